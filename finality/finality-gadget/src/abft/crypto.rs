@@ -1,5 +1,7 @@
+use dagestan_primitives::BlsSignature;
+
 use crate::{
-    crypto::{AuthorityPen, AuthorityVerifier, Signature},
+    crypto::{AggregateSignature, AuthorityPen, AuthorityVerifier, BlsPen, Signature},
     NodeCount, NodeIndex, SignatureSet,
 };
 
@@ -27,117 +29,88 @@ impl Keychain {
         }
     }
 
-    fn index(&self) -> NodeIndex {
+    pub(super) fn index(&self) -> NodeIndex {
         self.id
     }
 
-    fn node_count(&self) -> NodeCount {
+    pub(super) fn node_count(&self) -> NodeCount {
         self.authority_verifier.node_count()
     }
 
-    async fn sign(&self, msg: &[u8]) -> Signature {
+    pub(super) async fn sign(&self, msg: &[u8]) -> Signature {
         self.authority_pen.sign(msg).await
     }
 
-    fn verify<I: Into<NodeIndex>>(&self, msg: &[u8], sgn: &Signature, index: I) -> bool {
+    pub(super) fn verify<I: Into<NodeIndex>>(&self, msg: &[u8], sgn: &Signature, index: I) -> bool {
         self.authority_verifier.verify(msg, sgn, index.into())
     }
 
-    fn is_complete(&self, msg: &[u8], partial: &SignatureSet<Signature>) -> bool {
+    pub(super) fn is_complete(&self, msg: &[u8], partial: &SignatureSet<Signature>) -> bool {
         self.authority_verifier.is_complete(msg, partial)
     }
 }
 
-impl current_aleph_bft::Index for Keychain {
-    fn index(&self) -> current_aleph_bft::NodeIndex {
-        Keychain::index(self).into()
-    }
+/// BlsKeychain combines a BlsPen and an AuthorityVerifier with BLS keys into one object
+/// implementing the AlephBFT MultiKeychain trait, with multisignatures aggregated into a single
+/// BLS signature. Only used for signing block hashes in the aggregator.
+#[derive(Clone)]
+pub struct BlsKeychain {
+    id: NodeIndex,
+    bls_pen: BlsPen,
+    authority_verifier: AuthorityVerifier,
 }
 
-impl legacy_aleph_bft::Index for Keychain {
-    fn index(&self) -> legacy_aleph_bft::NodeIndex {
-        Keychain::index(self).into()
+impl BlsKeychain {
+    /// Constructs a new keychain from a BLS signing contraption and a verifier, which should know
+    /// the BLS keys of all the authorities, with the specified node index.
+    pub fn new(id: NodeIndex, authority_verifier: AuthorityVerifier, bls_pen: BlsPen) -> Self {
+        BlsKeychain {
+            id,
+            bls_pen,
+            authority_verifier,
+        }
     }
 }
 
-#[async_trait::async_trait]
-impl current_aleph_bft::Keychain for Keychain {
-    type Signature = Signature;
-
-    fn node_count(&self) -> current_aleph_bft::NodeCount {
-        Keychain::node_count(self).into()
-    }
-
-    async fn sign(&self, msg: &[u8]) -> Signature {
-        Keychain::sign(self, msg).await
-    }
-
-    fn verify(&self, msg: &[u8], sgn: &Signature, index: current_aleph_bft::NodeIndex) -> bool {
-        Keychain::verify(self, msg, sgn, index)
+impl current_aleph_bft::Index for BlsKeychain {
+    fn index(&self) -> current_aleph_bft::NodeIndex {
+        self.id.into()
     }
 }
 
 #[async_trait::async_trait]
-impl legacy_aleph_bft::Keychain for Keychain {
-    type Signature = Signature;
+impl current_aleph_bft::Keychain for BlsKeychain {
+    type Signature = BlsSignature;
 
-    fn node_count(&self) -> legacy_aleph_bft::NodeCount {
-        Keychain::node_count(self).into()
+    fn node_count(&self) -> current_aleph_bft::NodeCount {
+        self.authority_verifier.node_count().into()
     }
 
-    async fn sign(&self, msg: &[u8]) -> Signature {
-        Keychain::sign(self, msg).await
+    async fn sign(&self, msg: &[u8]) -> BlsSignature {
+        self.bls_pen.sign(msg)
     }
 
-    fn verify(&self, msg: &[u8], sgn: &Signature, index: legacy_aleph_bft::NodeIndex) -> bool {
-        Keychain::verify(self, msg, sgn, index)
+    fn verify(&self, msg: &[u8], sgn: &BlsSignature, index: current_aleph_bft::NodeIndex) -> bool {
+        self.authority_verifier.verify_bls(msg, sgn, index.into())
     }
 }
 
-impl current_aleph_bft::MultiKeychain for Keychain {
-    // Using `SignatureSet` is slow, but Substrate has not yet implemented aggregation.
-    // We probably should do this for them at some point.
-    type PartialMultisignature = SignatureSet<Signature>;
+impl current_aleph_bft::MultiKeychain for BlsKeychain {
+    type PartialMultisignature = AggregateSignature;
 
     fn bootstrap_multi(
         &self,
-        signature: &Signature,
+        signature: &BlsSignature,
         index: current_aleph_bft::NodeIndex,
     ) -> Self::PartialMultisignature {
-        current_aleph_bft::PartialMultisignature::add_signature(
-            SignatureSet(legacy_aleph_bft::SignatureSet::with_size(
-                legacy_aleph_bft::Keychain::node_count(self),
-            )),
-            signature,
-            index,
-        )
-    }
-
-    fn is_complete(&self, msg: &[u8], partial: &Self::PartialMultisignature) -> bool {
-        Keychain::is_complete(self, msg, partial)
-    }
-}
-
-impl legacy_aleph_bft::MultiKeychain for Keychain {
-    // Using `SignatureSet` is slow, but Substrate has not yet implemented aggregation.
-    // We probably should do this for them at some point.
-    type PartialMultisignature = SignatureSet<Signature>;
-
-    fn bootstrap_multi(
-        &self,
-        signature: &Signature,
-        index: legacy_aleph_bft::NodeIndex,
-    ) -> Self::PartialMultisignature {
-        legacy_aleph_bft::PartialMultisignature::add_signature(
-            SignatureSet(legacy_aleph_bft::SignatureSet::with_size(
-                legacy_aleph_bft::Keychain::node_count(self),
-            )),
-            signature,
-            index,
+        AggregateSignature::new(
+            self.authority_verifier.node_count(),
+            *signature,
+            index.into(),
         )
     }
 
     fn is_complete(&self, msg: &[u8], partial: &Self::PartialMultisignature) -> bool {
-        Keychain::is_complete(self, msg, partial)
+        self.authority_verifier.is_complete_aggregate(msg, partial)
     }
 }
@@ -1,37 +1,34 @@
-//! Main purpose of this module is to be able to use two different versions of the abft crate.
-//! Older version is referred to as 'Legacy' while newer as 'Current'.
+//! Main purpose of this module is to be able to use several different versions of the abft crate.
 //! We achieve this by hiding types & traits from abft crates behind our owns. In case of traits we
-//! implement both current and legacy ones. In case of types we implement trait `From` to be able
-//! convert them at the 'glueing' spot to the abft library. Current and legacy versions are marked
-//! by numbers. Whenever we upgrade to next version of abft we need to increment and mark each version
-//! version accordingly.
+//! implement the ones of every version. In case of types we implement trait `From` to be able
+//! convert them at the 'glueing' spot to the abft library. All of this is generated for every
+//! version by `aleph_bft_version`, so an upgrade takes adding the new crate and one invocation
+//! below, while the sessions that run it are chosen in `crate::versions`.
 
 mod common;
 mod crypto;
-mod current;
-mod legacy;
 mod network;
 mod traits;
 mod types;
+mod version;
 
 use std::fmt::Debug;
 
 use aleph_bft_crypto::{PartialMultisignature, Signature};
 use codec::{Decode, Encode};
-pub use crypto::Keychain;
-pub use current::{
-    create_dagestan_config as current_create_dagestan_config, run_member as run_current_member,
-    VERSION as CURRENT_VERSION,
-};
-pub use legacy::{
-    create_dagestan_config as legacy_create_dagestan_config, run_member as run_legacy_member,
-    VERSION as LEGACY_VERSION,
-};
-pub use network::{CurrentNetworkData, LegacyNetworkData, NetworkWrapper};
+pub use crypto::{BlsKeychain, Keychain};
+use dagestan_primitives::BlsSignature;
+pub use network::NetworkWrapper;
 pub use traits::{Hash, SpawnHandle, SpawnHandleT, Wrapper as HashWrapper};
 pub use types::{NodeCount, NodeIndex, Recipient};
+use version::aleph_bft_version;
 
-/// Wrapper for `SignatureSet` to be able to implement both legacy and current `PartialMultisignature` trait.
+use crate::crypto::AggregateSignature;
+
+aleph_bft_version!(v0_19, legacy_aleph_bft);
+aleph_bft_version!(v0_20, current_aleph_bft);
+
+/// Wrapper for `SignatureSet` to be able to implement the `PartialMultisignature` trait of every abft version.
 /// Inner `SignatureSet` is imported from `aleph_bft_crypto` with fixed version for compatibility reasons:
 /// this is also used in the justification which already exist in our chain history and we
 /// need to be careful with changing this.
@@ -72,26 +69,14 @@ impl<S: 'static> IntoIterator for SignatureSet<S> {
     }
 }
 
-impl<S: legacy_aleph_bft::Signature> legacy_aleph_bft::PartialMultisignature for SignatureSet<S> {
-    type Signature = S;
-
-    fn add_signature(
-        self,
-        signature: &Self::Signature,
-        index: legacy_aleph_bft::NodeIndex,
-    ) -> Self {
-        SignatureSet::add_signature(self, signature, index.into())
-    }
-}
-
-impl<S: legacy_aleph_bft::Signature> current_aleph_bft::PartialMultisignature for SignatureSet<S> {
-    type Signature = S;
+impl current_aleph_bft::PartialMultisignature for AggregateSignature {
+    type Signature = BlsSignature;
 
     fn add_signature(
         self,
         signature: &Self::Signature,
         index: current_aleph_bft::NodeIndex,
     ) -> Self {
-        SignatureSet::add_signature(self, signature, index.into())
+        AggregateSignature::add_signature(self, signature, index.into())
     }
 }
@@ -1,38 +1,12 @@
 use std::marker::PhantomData;
 
 use log::warn;
-use sp_runtime::traits::Block;
 
 use crate::{
-    abft::SignatureSet,
-    crypto::Signature,
-    data_io::{DagestanData, DagestanNetworkMessage},
     network::{data::Network, Data},
-    Hasher, Recipient,
+    Recipient,
 };
 
-pub type LegacyNetworkData<B> =
-    legacy_aleph_bft::NetworkData<Hasher, DagestanData<B>, Signature, SignatureSet<Signature>>;
-
-pub type CurrentNetworkData<B> =
-    current_aleph_bft::NetworkData<Hasher, DagestanData<B>, Signature, SignatureSet<Signature>>;
-
-impl<B: Block> DagestanNetworkMessage<B>
-    for legacy_aleph_bft::NetworkData<Hasher, DagestanData<B>, Signature, SignatureSet<Signature>>
-{
-    fn included_data(&self) -> Vec<DagestanData<B>> {
-        self.included_data()
-    }
-}
-
-impl<B: Block> DagestanNetworkMessage<B>
-    for current_aleph_bft::NetworkData<Hasher, DagestanData<B>, Signature, SignatureSet<Signature>>
-{
-    fn included_data(&self) -> Vec<DagestanData<B>> {
-        self.included_data()
-    }
-}
-
 /// A wrapper needed only because of type system theoretical constraints. Sadness.
 pub struct NetworkWrapper<D: Data, DN: Network<D>> {
     inner: DN,
@@ -49,7 +23,7 @@ impl<D: Data, DN: Network<D>> From<DN> for NetworkWrapper<D, DN> {
 }
 
 impl<D: Data, DN: Network<D>> NetworkWrapper<D, DN> {
-    fn send<R>(&self, data: D, recipient: R)
+    pub(super) fn send<R>(&self, data: D, recipient: R)
     where
         R: Into<Recipient>,
     {
@@ -58,29 +32,7 @@ impl<D: Data, DN: Network<D>> NetworkWrapper<D, DN> {
         }
     }
 
-    async fn next_event(&mut self) -> Option<D> {
+    pub(super) async fn next_event(&mut self) -> Option<D> {
         self.inner.next().await
     }
 }
-
-#[async_trait::async_trait]
-impl<D: Data, DN: Network<D>> current_aleph_bft::Network<D> for NetworkWrapper<D, DN> {
-    fn send(&self, data: D, recipient: current_aleph_bft::Recipient) {
-        NetworkWrapper::send(self, data, recipient)
-    }
-
-    async fn next_event(&mut self) -> Option<D> {
-        NetworkWrapper::next_event(self).await
-    }
-}
-
-#[async_trait::async_trait]
-impl<D: Data, DN: Network<D>> legacy_aleph_bft::Network<D> for NetworkWrapper<D, DN> {
-    fn send(&self, data: D, recipient: legacy_aleph_bft::Recipient) {
-        NetworkWrapper::send(self, data, recipient)
-    }
-
-    async fn next_event(&mut self) -> Option<D> {
-        NetworkWrapper::next_event(self).await
-    }
-}
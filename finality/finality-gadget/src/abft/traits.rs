@@ -1,51 +1,17 @@
-//! Implementations and definitions of traits used in legacy & current abft
+//! Implementations and definitions of traits used in every abft version
 
 use std::{cmp::Ordering, fmt::Debug, hash::Hash as StdHash, marker::PhantomData, pin::Pin};
 
 use codec::{Codec, Decode, Encode};
 use futures::{channel::oneshot, Future, TryFutureExt};
 use sc_service::SpawnTaskHandle;
-use sp_api::BlockT;
-use sp_blockchain::HeaderBackend;
 use sp_runtime::traits::Hash as SpHash;
 
-use crate::data_io::{DagestanData, DataProvider, OrderedDataInterpreter};
-
 /// A convenience trait for gathering all of the desired hash characteristics.
 pub trait Hash: AsRef<[u8]> + StdHash + Eq + Clone + Codec + Debug + Send + Sync {}
 
 impl<T: AsRef<[u8]> + StdHash + Eq + Clone + Codec + Debug + Send + Sync> Hash for T {}
 
-#[async_trait::async_trait]
-impl<B: BlockT> current_aleph_bft::DataProvider<DagestanData<B>> for DataProvider<B> {
-    async fn get_data(&mut self) -> Option<DagestanData<B>> {
-        DataProvider::get_data(self).await
-    }
-}
-
-#[async_trait::async_trait]
-impl<B: BlockT> legacy_aleph_bft::DataProvider<DagestanData<B>> for DataProvider<B> {
-    async fn get_data(&mut self) -> Option<DagestanData<B>> {
-        DataProvider::get_data(self).await
-    }
-}
-
-impl<B: BlockT, C: HeaderBackend<B> + Send + 'static>
-    current_aleph_bft::FinalizationHandler<DagestanData<B>> for OrderedDataInterpreter<B, C>
-{
-    fn data_finalized(&mut self, data: DagestanData<B>) {
-        OrderedDataInterpreter::data_finalized(self, data)
-    }
-}
-
-impl<B: BlockT, C: HeaderBackend<B> + Send + 'static>
-    legacy_aleph_bft::FinalizationHandler<DagestanData<B>> for OrderedDataInterpreter<B, C>
-{
-    fn data_finalized(&mut self, data: DagestanData<B>) {
-        OrderedDataInterpreter::data_finalized(self, data)
-    }
-}
-
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Wrapper<H: SpHash> {
     phantom: PhantomData<H>,
@@ -83,29 +49,13 @@ impl<O: Eq + Copy + Clone + Send + Sync + Debug + StdHash + Encode + Decode + As
 }
 
 impl<H: SpHash> Wrapper<H> {
-    fn hash(s: &[u8]) -> OrdForHash<H::Output> {
+    pub(super) fn hash(s: &[u8]) -> OrdForHash<H::Output> {
         OrdForHash {
             inner: <H as SpHash>::hash(s),
         }
     }
 }
 
-impl<H: SpHash> current_aleph_bft::Hasher for Wrapper<H> {
-    type Hash = OrdForHash<H::Output>;
-
-    fn hash(s: &[u8]) -> Self::Hash {
-        Wrapper::<H>::hash(s)
-    }
-}
-
-impl<H: SpHash> legacy_aleph_bft::Hasher for Wrapper<H> {
-    type Hash = OrdForHash<H::Output>;
-
-    fn hash(s: &[u8]) -> Self::Hash {
-        Wrapper::<H>::hash(s)
-    }
-}
-
 /// A wrapper for spawning tasks in a way compatible with AlephBFT.
 #[derive(Clone)]
 pub struct SpawnHandle(SpawnTaskHandle);
@@ -171,31 +121,3 @@ impl SpawnHandleT for SpawnHandle {
         Box::pin(rx.map_err(|_| ()))
     }
 }
-
-impl current_aleph_bft::SpawnHandle for SpawnHandle {
-    fn spawn(&self, name: &'static str, task: impl Future<Output = ()> + Send + 'static) {
-        SpawnHandleT::spawn(self, name, task)
-    }
-
-    fn spawn_essential(
-        &self,
-        name: &'static str,
-        task: impl Future<Output = ()> + Send + 'static,
-    ) -> current_aleph_bft::TaskHandle {
-        SpawnHandleT::spawn_essential(self, name, task)
-    }
-}
-
-impl legacy_aleph_bft::SpawnHandle for SpawnHandle {
-    fn spawn(&self, name: &'static str, task: impl Future<Output = ()> + Send + 'static) {
-        SpawnHandleT::spawn(self, name, task)
-    }
-
-    fn spawn_essential(
-        &self,
-        name: &'static str,
-        task: impl Future<Output = ()> + Send + 'static,
-    ) -> legacy_aleph_bft::TaskHandle {
-        SpawnHandleT::spawn_essential(self, name, task)
-    }
-}
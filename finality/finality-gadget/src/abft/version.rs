@@ -0,0 +1,604 @@
+//! Glue between our types and a single version of the AlephBFT crate.
+
+/// Generates a module with everything needed to run a session with the given version of the
+/// AlephBFT crate: implementations of its traits for our types, the network data it exchanges,
+/// its config, the member task and the decoding of the units it backs up. Supporting another
+/// version of AlephBFT takes adding it as a dependency and invoking this macro once more.
+macro_rules! aleph_bft_version {
+    ($name:ident, $aleph_bft:ident) => {
+        pub mod $name {
+            use codec::{Decode, Encode};
+            use futures::Future;
+            use log::debug;
+            use sp_api::BlockT;
+            use sp_blockchain::HeaderBackend;
+            use sp_core::H256;
+            use sp_runtime::traits::Hash as SpHash;
+
+            pub use $aleph_bft::Config;
+
+            use crate::{
+                abft::{
+                    common::unit_creation_delay_fn, traits::OrdForHash, HashWrapper,
+                    NetworkWrapper, SpawnHandle, SpawnHandleT,
+                },
+                crypto::Signature,
+                data_io::{
+                    DagestanData, DagestanNetworkMessage, DataProvider, OrderedDataInterpreter,
+                    Payload,
+                },
+                network::{data::Network, Data},
+                oneshot,
+                party::{
+                    backup::{ABFTBackup, BackupUnit},
+                    manager::{SubtaskCommon, Task},
+                },
+                Hasher, Keychain, NodeCount, NodeIndex, Recipient, SessionId, SignatureSet,
+                UnitCreationDelay,
+            };
+
+            pub type NetworkData<B, P = ()> = $aleph_bft::NetworkData<
+                Hasher,
+                DagestanData<B, P>,
+                Signature,
+                SignatureSet<Signature>,
+            >;
+
+            pub fn run_member<
+                B: BlockT,
+                C: HeaderBackend<B> + Send + 'static,
+                P: Payload,
+                ADN: Network<NetworkData<B, P>> + 'static,
+            >(
+                subtask_common: SubtaskCommon,
+                multikeychain: Keychain,
+                config: Config,
+                network: NetworkWrapper<NetworkData<B, P>, ADN>,
+                data_provider: impl $aleph_bft::DataProvider<DagestanData<B, P>> + Send + 'static,
+                ordered_data_interpreter: OrderedDataInterpreter<B, C, P>,
+                backup: ABFTBackup,
+            ) -> Task {
+                let SubtaskCommon {
+                    spawn_handle,
+                    session_id,
+                } = subtask_common;
+                let (stop, exit) = oneshot::channel();
+                let member_terminator = $aleph_bft::Terminator::create_root(exit, "member");
+                let local_io = $aleph_bft::LocalIO::new(
+                    data_provider,
+                    ordered_data_interpreter,
+                    backup.0,
+                    backup.1,
+                );
+
+                let task = {
+                    let spawn_handle = spawn_handle.clone();
+                    async move {
+                        debug!(target: "dagestan-party", "Running the member task for {:?}", session_id);
+                        $aleph_bft::run_session(
+                            config,
+                            local_io,
+                            network,
+                            multikeychain,
+                            spawn_handle,
+                            member_terminator,
+                        )
+                        .await;
+                        debug!(target: "dagestan-party", "Member task stopped for {:?}", session_id);
+                    }
+                };
+
+                let handle =
+                    spawn_handle.spawn_essential("dagestan/consensus_session_member", task);
+                Task::new(handle, stop)
+            }
+
+            // AlephBFT keeps the types of the units it backs up private, so we mirror their
+            // encoding to be able to inspect backups.
+            #[derive(Decode, Encode)]
+            struct ControlHash {
+                parents_mask: $aleph_bft::NodeSubset,
+                combined_hash: <Hasher as $aleph_bft::Hasher>::Hash,
+            }
+
+            #[derive(Decode, Encode)]
+            struct PreUnit {
+                creator: $aleph_bft::NodeIndex,
+                round: u16,
+                control_hash: ControlHash,
+            }
+
+            #[derive(Decode, Encode)]
+            struct FullUnit<B: BlockT, P: Payload> {
+                pre_unit: PreUnit,
+                data: Option<DagestanData<B, P>>,
+                session_id: u64,
+            }
+
+            #[derive(Decode, Encode)]
+            struct UncheckedSignedUnit<B: BlockT, P: Payload> {
+                full_unit: FullUnit<B, P>,
+                signature: Signature,
+            }
+
+            // The messages carrying units are private as well. We mirror only the ones carrying
+            // units of known creators, to learn who proposed the data of these units.
+            #[derive(Decode, Encode)]
+            enum UnitMessage<B: BlockT, P: Payload> {
+                #[codec(index = 0)]
+                NewUnit(UncheckedSignedUnit<B, P>),
+                #[codec(index = 2)]
+                ResponseCoord(UncheckedSignedUnit<B, P>),
+                #[codec(index = 4)]
+                ResponseParents(
+                    <Hasher as $aleph_bft::Hasher>::Hash,
+                    Vec<UncheckedSignedUnit<B, P>>,
+                ),
+            }
+
+            #[derive(Decode, Encode)]
+            enum NetworkDataInner<B: BlockT, P: Payload> {
+                #[codec(index = 0)]
+                Units(UnitMessage<B, P>),
+            }
+
+            /// Decodes a single unit, carrying payloads of type `P`, saved in the backup by this
+            /// version of AlephBFT.
+            pub fn decode_backup_unit<B: BlockT, P: Payload>(
+                input: &mut &[u8],
+            ) -> Result<BackupUnit, codec::Error> {
+                let UncheckedSignedUnit {
+                    full_unit,
+                    signature,
+                } = UncheckedSignedUnit::<B, P>::decode(input)?;
+                let hash = <Hasher as $aleph_bft::Hasher>::hash(&full_unit.encode());
+                Ok(BackupUnit {
+                    creator: full_unit.pre_unit.creator.into(),
+                    round: full_unit.pre_unit.round,
+                    session_id: full_unit.session_id,
+                    hash: H256::from_slice(hash.as_ref()),
+                    signature,
+                })
+            }
+
+            /// Encodes a unit without data the way this version of AlephBFT saves it in the
+            /// backup, signed with `pen`. Units differing only in `variant` are forks.
+            #[cfg(test)]
+            pub async fn encode_backup_unit<B: BlockT, P: Payload>(
+                pen: &crate::crypto::AuthorityPen,
+                creator: NodeIndex,
+                round: u16,
+                session_id: u64,
+                variant: u8,
+            ) -> Vec<u8> {
+                let full_unit = FullUnit::<B, P> {
+                    pre_unit: PreUnit {
+                        creator: creator.into(),
+                        round,
+                        control_hash: ControlHash {
+                            parents_mask: $aleph_bft::NodeSubset::with_size(
+                                $aleph_bft::NodeCount(0),
+                            ),
+                            combined_hash: <Hasher as $aleph_bft::Hasher>::hash(&[variant]),
+                        },
+                    },
+                    data: None,
+                    session_id,
+                };
+                let hash = <Hasher as $aleph_bft::Hasher>::hash(&full_unit.encode());
+                let signature = pen.sign(hash.as_ref()).await;
+                UncheckedSignedUnit {
+                    full_unit,
+                    signature,
+                }
+                .encode()
+            }
+
+            pub fn create_dagestan_config(
+                n_members: usize,
+                node_id: NodeIndex,
+                session_id: SessionId,
+                unit_creation_delay: UnitCreationDelay,
+            ) -> Config {
+                let mut config = $aleph_bft::default_config(
+                    n_members.into(),
+                    node_id.into(),
+                    session_id.0 as u64,
+                );
+                config.delay_config.unit_creation_delay =
+                    unit_creation_delay_fn(unit_creation_delay);
+
+                config
+            }
+
+            impl<B: BlockT, P: Payload> DagestanNetworkMessage<B>
+                for $aleph_bft::NetworkData<
+                    Hasher,
+                    DagestanData<B, P>,
+                    Signature,
+                    SignatureSet<Signature>,
+                >
+            {
+                type Payload = P;
+
+                fn included_data(&self) -> Vec<DagestanData<B, P>> {
+                    self.included_data()
+                }
+
+                // The units are not verified at this point, so the creators are only hints. The
+                // layout mirrored above is checked against the actual messages in the tests below.
+                fn data_with_creators(&self) -> Vec<(DagestanData<B, P>, NodeIndex)> {
+                    use NetworkDataInner::Units;
+                    let units = match NetworkDataInner::<B, P>::decode(&mut &self.encode()[..]) {
+                        Ok(Units(UnitMessage::NewUnit(unit)))
+                        | Ok(Units(UnitMessage::ResponseCoord(unit))) => vec![unit],
+                        Ok(Units(UnitMessage::ResponseParents(_, units))) => units,
+                        // The other messages carry no units of known creators.
+                        Err(_) => Vec::new(),
+                    };
+                    units
+                        .into_iter()
+                        .filter_map(|UncheckedSignedUnit { full_unit, .. }| {
+                            let creator: NodeIndex = full_unit.pre_unit.creator.into();
+                            full_unit.data.map(|data| (data, creator))
+                        })
+                        .collect()
+                }
+            }
+
+            #[async_trait::async_trait]
+            impl<B: BlockT, P: Payload> $aleph_bft::DataProvider<DagestanData<B, P>>
+                for DataProvider<B, P>
+            {
+                async fn get_data(&mut self) -> Option<DagestanData<B, P>> {
+                    DataProvider::get_data(self).await
+                }
+            }
+
+            impl<B: BlockT, C: HeaderBackend<B> + Send + 'static, P: Payload>
+                $aleph_bft::FinalizationHandler<DagestanData<B, P>>
+                for OrderedDataInterpreter<B, C, P>
+            {
+                fn data_finalized(&mut self, data: DagestanData<B, P>) {
+                    OrderedDataInterpreter::data_finalized(self, data)
+                }
+            }
+
+            impl<H: SpHash> $aleph_bft::Hasher for HashWrapper<H> {
+                type Hash = OrdForHash<H::Output>;
+
+                fn hash(s: &[u8]) -> Self::Hash {
+                    HashWrapper::<H>::hash(s)
+                }
+            }
+
+            impl $aleph_bft::SpawnHandle for SpawnHandle {
+                fn spawn(
+                    &self,
+                    name: &'static str,
+                    task: impl Future<Output = ()> + Send + 'static,
+                ) {
+                    SpawnHandleT::spawn(self, name, task)
+                }
+
+                fn spawn_essential(
+                    &self,
+                    name: &'static str,
+                    task: impl Future<Output = ()> + Send + 'static,
+                ) -> $aleph_bft::TaskHandle {
+                    SpawnHandleT::spawn_essential(self, name, task)
+                }
+            }
+
+            #[async_trait::async_trait]
+            impl<D: Data, DN: Network<D>> $aleph_bft::Network<D> for NetworkWrapper<D, DN> {
+                fn send(&self, data: D, recipient: $aleph_bft::Recipient) {
+                    NetworkWrapper::send(self, data, recipient)
+                }
+
+                async fn next_event(&mut self) -> Option<D> {
+                    NetworkWrapper::next_event(self).await
+                }
+            }
+
+            impl $aleph_bft::Index for Keychain {
+                fn index(&self) -> $aleph_bft::NodeIndex {
+                    Keychain::index(self).into()
+                }
+            }
+
+            #[async_trait::async_trait]
+            impl $aleph_bft::Keychain for Keychain {
+                type Signature = Signature;
+
+                fn node_count(&self) -> $aleph_bft::NodeCount {
+                    Keychain::node_count(self).into()
+                }
+
+                async fn sign(&self, msg: &[u8]) -> Signature {
+                    Keychain::sign(self, msg).await
+                }
+
+                fn verify(
+                    &self,
+                    msg: &[u8],
+                    sgn: &Signature,
+                    index: $aleph_bft::NodeIndex,
+                ) -> bool {
+                    Keychain::verify(self, msg, sgn, index)
+                }
+            }
+
+            impl $aleph_bft::MultiKeychain for Keychain {
+                // Using `SignatureSet` is slow, but Substrate has not yet implemented aggregation.
+                // We probably should do this for them at some point.
+                type PartialMultisignature = SignatureSet<Signature>;
+
+                fn bootstrap_multi(
+                    &self,
+                    signature: &Signature,
+                    index: $aleph_bft::NodeIndex,
+                ) -> Self::PartialMultisignature {
+                    SignatureSet::with_size(Keychain::node_count(self))
+                        .add_signature(signature, index.into())
+                }
+
+                fn is_complete(&self, msg: &[u8], partial: &Self::PartialMultisignature) -> bool {
+                    Keychain::is_complete(self, msg, partial)
+                }
+            }
+
+            impl<S: aleph_bft_crypto::Signature> $aleph_bft::PartialMultisignature
+                for SignatureSet<S>
+            {
+                type Signature = S;
+
+                fn add_signature(
+                    self,
+                    signature: &Self::Signature,
+                    index: $aleph_bft::NodeIndex,
+                ) -> Self {
+                    SignatureSet::add_signature(self, signature, index.into())
+                }
+            }
+
+            impl From<$aleph_bft::Recipient> for Recipient {
+                fn from(recipient: $aleph_bft::Recipient) -> Self {
+                    match recipient {
+                        $aleph_bft::Recipient::Everyone => Recipient::Everyone,
+                        $aleph_bft::Recipient::Node(id) => Recipient::Node(id.into()),
+                    }
+                }
+            }
+
+            impl From<Recipient> for $aleph_bft::Recipient {
+                fn from(recipient: Recipient) -> Self {
+                    match recipient {
+                        Recipient::Everyone => $aleph_bft::Recipient::Everyone,
+                        Recipient::Node(idx) => $aleph_bft::Recipient::Node(idx.into()),
+                    }
+                }
+            }
+
+            impl From<NodeCount> for $aleph_bft::NodeCount {
+                fn from(count: NodeCount) -> Self {
+                    $aleph_bft::NodeCount(count.0)
+                }
+            }
+
+            impl From<$aleph_bft::NodeCount> for NodeCount {
+                fn from(count: $aleph_bft::NodeCount) -> Self {
+                    Self(count.0)
+                }
+            }
+
+            impl From<NodeIndex> for $aleph_bft::NodeIndex {
+                fn from(idx: NodeIndex) -> Self {
+                    $aleph_bft::NodeIndex(idx.0)
+                }
+            }
+
+            impl From<$aleph_bft::NodeIndex> for NodeIndex {
+                fn from(idx: $aleph_bft::NodeIndex) -> Self {
+                    Self(idx.0)
+                }
+            }
+
+            #[cfg(test)]
+            mod tests {
+                use std::{io::Read, sync::Arc, time::Duration};
+
+                use futures::{
+                    channel::mpsc::{self, UnboundedSender},
+                    future::pending,
+                    StreamExt,
+                };
+                use sc_service::TaskManager;
+                use substrate_test_runtime_client::runtime::Block;
+                use tokio::{
+                    runtime::Handle,
+                    time::{sleep, timeout},
+                };
+
+                use super::{create_dagestan_config, decode_backup_unit, NetworkData};
+                use crate::{
+                    abft::SpawnHandle,
+                    data_io::{DagestanData, DagestanNetworkMessage, UnvalidatedDagestanProposal},
+                    network::mock::crypto_basics,
+                    oneshot,
+                    party::backup::{rotate, BackupStore, MemoryBackupStore},
+                    Keychain, SessionId, UnitCreationDelay,
+                };
+
+                const SESSION: u32 = 7;
+
+                struct EmptyDataProvider;
+
+                #[async_trait::async_trait]
+                impl $aleph_bft::DataProvider<DagestanData<Block>> for EmptyDataProvider {
+                    async fn get_data(&mut self) -> Option<DagestanData<Block>> {
+                        None
+                    }
+                }
+
+                struct ProposingDataProvider;
+
+                #[async_trait::async_trait]
+                impl $aleph_bft::DataProvider<DagestanData<Block>> for ProposingDataProvider {
+                    async fn get_data(&mut self) -> Option<DagestanData<Block>> {
+                        Some(DagestanData {
+                            head_proposal: UnvalidatedDagestanProposal::new(
+                                vec![[1; 32].into()],
+                                1,
+                            ),
+                            payload: (),
+                        })
+                    }
+                }
+
+                struct IgnoredFinalization;
+
+                impl $aleph_bft::FinalizationHandler<DagestanData<Block>> for IgnoredFinalization {
+                    fn data_finalized(&mut self, _: DagestanData<Block>) {}
+                }
+
+                struct SilentNetwork;
+
+                #[async_trait::async_trait]
+                impl $aleph_bft::Network<NetworkData<Block>> for SilentNetwork {
+                    fn send(&self, _: NetworkData<Block>, _: $aleph_bft::Recipient) {}
+
+                    async fn next_event(&mut self) -> Option<NetworkData<Block>> {
+                        pending().await
+                    }
+                }
+
+                struct CapturingNetwork(UnboundedSender<NetworkData<Block>>);
+
+                #[async_trait::async_trait]
+                impl $aleph_bft::Network<NetworkData<Block>> for CapturingNetwork {
+                    fn send(&self, data: NetworkData<Block>, _: $aleph_bft::Recipient) {
+                        let _ = self.0.unbounded_send(data);
+                    }
+
+                    async fn next_event(&mut self) -> Option<NetworkData<Block>> {
+                        pending().await
+                    }
+                }
+
+                #[tokio::test]
+                async fn finds_creators_of_data_in_sent_units() {
+                    // `TaskManager` can't be dropped for `SpawnTaskHandle` to work
+                    let task_manager = TaskManager::new(Handle::current(), None).unwrap();
+                    let (pens, verifier) = crypto_basics(1).await;
+                    let (node_id, pen) = pens[0].clone();
+                    let store = Arc::new(MemoryBackupStore::default());
+                    let (saver, loader) = rotate(store, SESSION).expect("backup should rotate");
+                    let (messages_tx, mut messages_rx) = mpsc::unbounded();
+                    let (stop, exit) = oneshot::channel();
+                    let session = tokio::spawn($aleph_bft::run_session(
+                        create_dagestan_config(
+                            1,
+                            node_id,
+                            SessionId(SESSION),
+                            UnitCreationDelay(10),
+                        ),
+                        $aleph_bft::LocalIO::new(
+                            ProposingDataProvider,
+                            IgnoredFinalization,
+                            saver,
+                            loader,
+                        ),
+                        CapturingNetwork(messages_tx),
+                        Keychain::new(node_id, verifier, pen),
+                        SpawnHandle::from(task_manager.spawn_handle()),
+                        $aleph_bft::Terminator::create_root(exit, "member"),
+                    ));
+
+                    let mut units_with_data = 0;
+                    timeout(Duration::from_secs(30), async {
+                        while units_with_data < 3 {
+                            let message = messages_rx.next().await.expect("session should run");
+                            let included_data = message.included_data();
+                            if included_data.is_empty() {
+                                continue;
+                            }
+                            // A single member sends only its own units, so all the data it sends
+                            // should come with it as the creator.
+                            let expected: Vec<_> = included_data
+                                .into_iter()
+                                .map(|data| (data, node_id))
+                                .collect();
+                            assert_eq!(message.data_with_creators(), expected);
+                            units_with_data += 1;
+                        }
+                    })
+                    .await
+                    .expect("units with data should be sent");
+                    stop.send(()).expect("session should be running");
+                    session.await.expect("session should stop");
+                }
+
+                #[tokio::test]
+                async fn decodes_units_saved_by_backup_saver() {
+                    // `TaskManager` can't be dropped for `SpawnTaskHandle` to work
+                    let task_manager = TaskManager::new(Handle::current(), None).unwrap();
+                    let (pens, verifier) = crypto_basics(1).await;
+                    let (node_id, pen) = pens[0].clone();
+                    let store = Arc::new(MemoryBackupStore::default());
+                    let (saver, loader) =
+                        rotate(store.clone(), SESSION).expect("backup should rotate");
+                    let (stop, exit) = oneshot::channel();
+                    let session = tokio::spawn($aleph_bft::run_session(
+                        create_dagestan_config(
+                            1,
+                            node_id,
+                            SessionId(SESSION),
+                            UnitCreationDelay(10),
+                        ),
+                        $aleph_bft::LocalIO::new(
+                            EmptyDataProvider,
+                            IgnoredFinalization,
+                            saver,
+                            loader,
+                        ),
+                        SilentNetwork,
+                        Keychain::new(node_id, verifier.clone(), pen),
+                        SpawnHandle::from(task_manager.spawn_handle()),
+                        $aleph_bft::Terminator::create_root(exit, "member"),
+                    ));
+
+                    let empty_length = store.read(SESSION, 0).unwrap().len();
+                    timeout(Duration::from_secs(30), async {
+                        while store.read(SESSION, 0).unwrap().len() == empty_length {
+                            sleep(Duration::from_millis(100)).await;
+                        }
+                    })
+                    .await
+                    .expect("units should be saved");
+                    sleep(Duration::from_millis(500)).await;
+                    stop.send(()).expect("session should be running");
+                    session.await.expect("session should stop");
+
+                    let (_, mut loader) = rotate(store, SESSION).expect("backup should rotate");
+                    let mut saved = Vec::new();
+                    loader.read_to_end(&mut saved).unwrap();
+                    let mut input = &saved[..];
+                    let mut rounds = Vec::new();
+                    while !input.is_empty() {
+                        let unit = decode_backup_unit::<Block, ()>(&mut input)
+                            .expect("saved units should decode");
+                        assert_eq!(unit.creator, node_id);
+                        assert_eq!(unit.session_id, SESSION as u64);
+                        assert!(verifier.verify(unit.hash.as_ref(), &unit.signature, unit.creator));
+                        rounds.push(unit.round);
+                    }
+                    assert!(!rounds.is_empty());
+                    assert_eq!(rounds, (0..rounds.len() as u16).collect::<Vec<_>>());
+                }
+            }
+        }
+    };
+}
+
+pub(super) use aleph_bft_version;
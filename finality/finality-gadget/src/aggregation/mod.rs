@@ -1,171 +1,33 @@
-//! Module to glue legacy and current version of the aggregator;
+//! Module to glue every version of the aggregator, possibly aggregating BLS signatures, behind a
+//! single trait.
 
 use std::{fmt::Debug, hash::Hash, marker::PhantomData, time::Instant};
 
-use current_aleph_aggregator::NetworkError as CurrentNetworkError;
-use legacy_aleph_aggregator::NetworkError as LegacyNetworkError;
 use sp_runtime::traits::Block;
 
 use crate::{
-    abft::SignatureSet,
-    crypto::Signature,
+    justification::DagestanJustification,
     metrics::Checkpoint,
-    mpsc,
     network::{
         data::{Network, SendError},
         Data,
     },
-    Keychain, Metrics,
+    Metrics,
 };
 
-pub type LegacyRmcNetworkData<B> =
-    legacy_aleph_aggregator::RmcNetworkData<<B as Block>::Hash, Signature, SignatureSet<Signature>>;
-pub type CurrentRmcNetworkData<B> = current_aleph_aggregator::RmcNetworkData<
-    <B as Block>::Hash,
-    Signature,
-    SignatureSet<Signature>,
->;
-
-pub type LegacySignableBlockHash<B> = legacy_aleph_aggregator::SignableHash<<B as Block>::Hash>;
-pub type LegacyRmc<'a, B> =
-    legacy_aleph_bft_rmc::ReliableMulticast<'a, LegacySignableBlockHash<B>, Keychain>;
-pub type LegacyAggregator<'a, B, N> = legacy_aleph_aggregator::IO<
-    <B as Block>::Hash,
-    LegacyRmcNetworkData<B>,
-    NetworkWrapper<LegacyRmcNetworkData<B>, N>,
-    SignatureSet<Signature>,
-    LegacyRmc<'a, B>,
-    Metrics<<B as Block>::Hash>,
->;
-
-pub type CurrentSignableBlockHash<B> = current_aleph_aggregator::SignableHash<<B as Block>::Hash>;
-pub type CurrentRmc<'a, B> =
-    current_aleph_bft_rmc::ReliableMulticast<'a, CurrentSignableBlockHash<B>, Keychain>;
-pub type CurrentAggregator<'a, B, N> = current_aleph_aggregator::IO<
-    <B as Block>::Hash,
-    CurrentRmcNetworkData<B>,
-    NetworkWrapper<CurrentRmcNetworkData<B>, N>,
-    SignatureSet<Signature>,
-    CurrentRmc<'a, B>,
-    Metrics<<B as Block>::Hash>,
->;
-
-enum EitherAggregator<'a, B, CN, LN>
-where
-    B: Block,
-    LN: Network<LegacyRmcNetworkData<B>>,
-    CN: Network<CurrentRmcNetworkData<B>>,
-    <B as Block>::Hash: AsRef<[u8]>,
-{
-    Current(CurrentAggregator<'a, B, CN>),
-    Legacy(LegacyAggregator<'a, B, LN>),
-}
-
-/// Wrapper on the aggregator, which is either current or legacy one. Depending on the inner variant
-/// it behaves runs the legacy one or the current.
-pub struct Aggregator<'a, B, CN, LN>
-where
-    B: Block,
-    LN: Network<LegacyRmcNetworkData<B>>,
-    CN: Network<CurrentRmcNetworkData<B>>,
-    <B as Block>::Hash: AsRef<[u8]>,
-{
-    agg: EitherAggregator<'a, B, CN, LN>,
-}
-
-impl<'a, B, CN, LN> Aggregator<'a, B, CN, LN>
-where
-    B: Block,
-    LN: Network<LegacyRmcNetworkData<B>>,
-    CN: Network<CurrentRmcNetworkData<B>>,
-    <B as Block>::Hash: AsRef<[u8]>,
-{
-    pub fn new_legacy(
-        multikeychain: &'a Keychain,
-        rmc_network: LN,
-        metrics: Option<Metrics<<B as Block>::Hash>>,
-    ) -> Self {
-        let (messages_for_rmc, messages_from_network) = mpsc::unbounded();
-        let (messages_for_network, messages_from_rmc) = mpsc::unbounded();
-        let scheduler = legacy_aleph_bft_rmc::DoublingDelayScheduler::new(
-            tokio::time::Duration::from_millis(500),
-        );
-        let rmc = legacy_aleph_bft_rmc::ReliableMulticast::new(
-            messages_from_network,
-            messages_for_network,
-            multikeychain,
-            legacy_aleph_bft::Keychain::node_count(multikeychain),
-            scheduler,
-        );
-        let aggregator = legacy_aleph_aggregator::BlockSignatureAggregator::new(metrics);
-        let aggregator_io = LegacyAggregator::<B, LN>::new(
-            messages_for_rmc,
-            messages_from_rmc,
-            NetworkWrapper::new(rmc_network),
-            rmc,
-            aggregator,
-        );
-
-        Self {
-            agg: EitherAggregator::Legacy(aggregator_io),
-        }
-    }
-
-    pub fn new_current(
-        multikeychain: &'a Keychain,
-        rmc_network: CN,
-        metrics: Option<Metrics<<B as Block>::Hash>>,
-    ) -> Self {
-        let (messages_for_rmc, messages_from_network) = mpsc::unbounded();
-        let (messages_for_network, messages_from_rmc) = mpsc::unbounded();
-        let scheduler = current_aleph_bft_rmc::DoublingDelayScheduler::new(
-            tokio::time::Duration::from_millis(500),
-        );
-        let rmc = current_aleph_bft_rmc::ReliableMulticast::new(
-            messages_from_network,
-            messages_for_network,
-            multikeychain,
-            current_aleph_bft::Keychain::node_count(multikeychain),
-            scheduler,
-        );
-        let aggregator = current_aleph_aggregator::BlockSignatureAggregator::new(metrics);
-        let aggregator_io = CurrentAggregator::<B, CN>::new(
-            messages_for_rmc,
-            messages_from_rmc,
-            NetworkWrapper::new(rmc_network),
-            rmc,
-            aggregator,
-        );
-
-        Self {
-            agg: EitherAggregator::Current(aggregator_io),
-        }
-    }
-
-    pub async fn start_aggregation(&mut self, h: <B as Block>::Hash) {
-        match &mut self.agg {
-            EitherAggregator::Current(agg) => agg.start_aggregation(h).await,
-            EitherAggregator::Legacy(agg) => agg.start_aggregation(h).await,
-        }
-    }
+/// Multisigns block hashes, regardless of the version of the aggregator doing it.
+#[async_trait::async_trait]
+pub trait BlockSignatureAggregator<B: Block>: Send {
+    async fn start_aggregation(&mut self, hash: B::Hash);
 
-    pub async fn next_multisigned_hash(
-        &mut self,
-    ) -> Option<(<B as Block>::Hash, SignatureSet<Signature>)> {
-        match &mut self.agg {
-            EitherAggregator::Current(agg) => agg.next_multisigned_hash().await,
-            EitherAggregator::Legacy(agg) => agg.next_multisigned_hash().await,
-        }
-    }
+    /// Returns the next multisigned hash, together with its justification.
+    async fn next_multisigned_hash(&mut self) -> Option<(B::Hash, DagestanJustification)>;
 
-    pub fn status_report(&self) {
-        match &self.agg {
-            EitherAggregator::Current(agg) => agg.status_report(),
-            EitherAggregator::Legacy(agg) => agg.status_report(),
-        }
-    }
+    fn status_report(&self);
 }
 
+pub type Aggregator<'a, B> = Box<dyn BlockSignatureAggregator<B> + Send + 'a>;
+
 pub struct NetworkWrapper<D: Data, N: Network<D>>(N, PhantomData<D>);
 
 impl<D: Data, N: Network<D>> NetworkWrapper<D, N> {
@@ -174,56 +36,167 @@ impl<D: Data, N: Network<D>> NetworkWrapper<D, N> {
     }
 }
 
-impl<H: Debug + Hash + Eq + Debug + Copy> legacy_aleph_aggregator::Metrics<H> for Metrics<H> {
-    fn report_aggregation_complete(&mut self, h: H) {
-        self.report_block(h, Instant::now(), Checkpoint::Aggregating);
-    }
-}
+/// Implements the traits of a version of the aggregator crate, used together with the given
+/// version of AlephBFT, for our types.
+macro_rules! aggregator_crate {
+    ($aggregator:ident, $aleph_bft:ident) => {
+        impl<H: Debug + Hash + Eq + Debug + Copy> $aggregator::Metrics<H> for Metrics<H> {
+            fn report_aggregation_complete(&mut self, h: H) {
+                self.report_block(h, Instant::now(), Checkpoint::Aggregating);
+            }
+        }
 
-impl<H: Debug + Hash + Eq + Debug + Copy> current_aleph_aggregator::Metrics<H> for Metrics<H> {
-    fn report_aggregation_complete(&mut self, h: H) {
-        self.report_block(h, Instant::now(), Checkpoint::Aggregating);
-    }
+        #[async_trait::async_trait]
+        impl<T, D> $aggregator::ProtocolSink<D> for NetworkWrapper<D, T>
+        where
+            T: Network<D>,
+            D: Data,
+        {
+            async fn next(&mut self) -> Option<D> {
+                self.0.next().await
+            }
+
+            fn send(
+                &self,
+                data: D,
+                recipient: $aleph_bft::Recipient,
+            ) -> Result<(), $aggregator::NetworkError> {
+                self.0.send(data, recipient.into()).map_err(|e| match e {
+                    SendError::SendFailed => $aggregator::NetworkError::SendFail,
+                })
+            }
+        }
+    };
 }
 
-#[async_trait::async_trait]
-impl<T, D> legacy_aleph_aggregator::ProtocolSink<D> for NetworkWrapper<D, T>
-where
-    T: Network<D>,
-    D: Data,
-{
-    async fn next(&mut self) -> Option<D> {
-        self.0.next().await
-    }
-
-    fn send(
-        &self,
-        data: D,
-        recipient: legacy_aleph_bft::Recipient,
-    ) -> Result<(), LegacyNetworkError> {
-        self.0.send(data, recipient.into()).map_err(|e| match e {
-            SendError::SendFailed => LegacyNetworkError::SendFail,
-        })
-    }
+/// Generates a module creating aggregators that multisign block hashes using the given crates and
+/// keychain, and turn the resulting multisignatures into the given kind of justification.
+macro_rules! aggregator_version {
+    (
+        $name:ident,
+        $aleph_bft:ident,
+        $rmc:ident,
+        $aggregator:ident,
+        $keychain:ty,
+        $signature:ty,
+        $multisignature:ty,
+        $justification:ident
+    ) => {
+        pub mod $name {
+            use sp_runtime::traits::Block;
+
+            use super::{Aggregator, BlockSignatureAggregator, NetworkWrapper};
+            use crate::{
+                justification::DagestanJustification, mpsc, network::data::Network, Metrics,
+            };
+
+            /// The keychain used to multisign block hashes.
+            pub type Keychain = $keychain;
+
+            pub type RmcNetworkData<B> =
+                $aggregator::RmcNetworkData<<B as Block>::Hash, $signature, $multisignature>;
+
+            type SignableBlockHash<B> = $aggregator::SignableHash<<B as Block>::Hash>;
+            type Rmc<'a, B> = $rmc::ReliableMulticast<'a, SignableBlockHash<B>, Keychain>;
+            type AggregatorIO<'a, B, N> = $aggregator::IO<
+                <B as Block>::Hash,
+                RmcNetworkData<B>,
+                NetworkWrapper<RmcNetworkData<B>, N>,
+                $multisignature,
+                Rmc<'a, B>,
+                Metrics<<B as Block>::Hash>,
+            >;
+
+            pub fn new<'a, B, N>(
+                multikeychain: &'a Keychain,
+                rmc_network: N,
+                metrics: Option<Metrics<<B as Block>::Hash>>,
+            ) -> Aggregator<'a, B>
+            where
+                B: Block,
+                N: Network<RmcNetworkData<B>> + 'static,
+            {
+                let (messages_for_rmc, messages_from_network) = mpsc::unbounded();
+                let (messages_for_network, messages_from_rmc) = mpsc::unbounded();
+                let scheduler =
+                    $rmc::DoublingDelayScheduler::new(tokio::time::Duration::from_millis(500));
+                let rmc = $rmc::ReliableMulticast::new(
+                    messages_from_network,
+                    messages_for_network,
+                    multikeychain,
+                    $aleph_bft::Keychain::node_count(multikeychain),
+                    scheduler,
+                );
+                let aggregator = $aggregator::BlockSignatureAggregator::new(metrics);
+                let aggregator_io = AggregatorIO::<B, N>::new(
+                    messages_for_rmc,
+                    messages_from_rmc,
+                    NetworkWrapper::new(rmc_network),
+                    rmc,
+                    aggregator,
+                );
+
+                Box::new(aggregator_io)
+            }
+
+            #[async_trait::async_trait]
+            impl<'a, B, N> BlockSignatureAggregator<B> for AggregatorIO<'a, B, N>
+            where
+                B: Block,
+                N: Network<RmcNetworkData<B>>,
+            {
+                async fn start_aggregation(&mut self, hash: <B as Block>::Hash) {
+                    AggregatorIO::start_aggregation(self, hash).await
+                }
+
+                async fn next_multisigned_hash(
+                    &mut self,
+                ) -> Option<(<B as Block>::Hash, DagestanJustification)> {
+                    AggregatorIO::next_multisigned_hash(self)
+                        .await
+                        .map(|(hash, multisignature)| {
+                            (hash, DagestanJustification::$justification(multisignature))
+                        })
+                }
+
+                fn status_report(&self) {
+                    AggregatorIO::status_report(self)
+                }
+            }
+        }
+    };
 }
 
-#[async_trait::async_trait]
-impl<T, D> current_aleph_aggregator::ProtocolSink<D> for NetworkWrapper<D, T>
-where
-    T: Network<D>,
-    D: Data,
-{
-    async fn next(&mut self) -> Option<D> {
-        self.0.next().await
-    }
-
-    fn send(
-        &self,
-        data: D,
-        recipient: current_aleph_bft::Recipient,
-    ) -> Result<(), CurrentNetworkError> {
-        self.0.send(data, recipient.into()).map_err(|e| match e {
-            SendError::SendFailed => CurrentNetworkError::SendFail,
-        })
-    }
-}
+aggregator_crate!(legacy_aleph_aggregator, legacy_aleph_bft);
+aggregator_crate!(current_aleph_aggregator, current_aleph_bft);
+
+aggregator_version!(
+    v0_19,
+    legacy_aleph_bft,
+    legacy_aleph_bft_rmc,
+    legacy_aleph_aggregator,
+    crate::Keychain,
+    crate::crypto::Signature,
+    crate::SignatureSet<crate::crypto::Signature>,
+    CommitteeMultisignature
+);
+aggregator_version!(
+    v0_20,
+    current_aleph_bft,
+    current_aleph_bft_rmc,
+    current_aleph_aggregator,
+    crate::Keychain,
+    crate::crypto::Signature,
+    crate::SignatureSet<crate::crypto::Signature>,
+    CommitteeMultisignature
+);
+aggregator_version!(
+    v0_20_bls,
+    current_aleph_bft,
+    current_aleph_bft_rmc,
+    current_aleph_aggregator,
+    crate::abft::BlsKeychain,
+    dagestan_primitives::BlsSignature,
+    crate::crypto::AggregateSignature,
+    AggregateSignature
+);
@@ -0,0 +1,227 @@
+//! Lists the units in the AlephBFT backup of a session kept in `--backup-saving-path` or in the
+//! client database, checks it for forks of our own units and incorrect signatures, and optionally
+//! truncates it to its last consistent prefix.
+use std::{path::PathBuf, process::exit, sync::Arc};
+
+use clap::{Parser, ValueEnum};
+use dagestan_primitives::{AuthorityId, BlockNumber};
+use finality_aleph::{
+    inspect_backup, truncate_backup, AuxBackupStore, BackupInspection, BackupStore,
+    FileBackupStore, InspectedUnit, NodeIndex,
+};
+use sc_client_db::{Backend, BlocksPruning, DatabaseSettings, DatabaseSource};
+use sp_core::{crypto::Ss58Codec, ed25519};
+use sp_runtime::{
+    generic::{Block as GenericBlock, Header as GenericHeader},
+    traits::BlakeTwo256,
+    OpaqueExtrinsic,
+};
+
+type Block = GenericBlock<GenericHeader<BlockNumber, BlakeTwo256>, OpaqueExtrinsic>;
+
+/// The cache size of the client database, in MiB, the same as the default of the node.
+const DATABASE_CACHE_SIZE: usize = 128;
+
+/// The canonicalization delay the node opens the client database with.
+const CANONICALIZATION_DELAY: u64 = 4096;
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Database {
+    #[value(name = "rocksdb")]
+    RocksDb,
+    #[value(name = "paritydb")]
+    ParityDb,
+}
+
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Config {
+    /// Path to the backup directory, i.e. the `--backup-saving-path` of the node.
+    #[arg(
+        long,
+        required_unless_present = "database_path",
+        conflicts_with = "database_path"
+    )]
+    backup_path: Option<PathBuf>,
+
+    /// Path to the client database of the node, e.g. `<base-path>/chains/<chain>/db/full`, to
+    /// inspect the backup of a node run without `--backup-saving-path`. The node has to be
+    /// stopped, as the database cannot be opened twice.
+    #[arg(long)]
+    database_path: Option<PathBuf>,
+
+    /// The backend of the client database.
+    #[arg(long, value_enum, default_value_t = Database::RocksDb)]
+    database: Database,
+
+    /// The session to inspect the backup of.
+    #[arg(long)]
+    session: u32,
+
+    /// The consensus version the session was run with: 1 for AlephBFT 0.19, 2 to 5 for 0.20.
+    /// Versions 4 and 5 order payloads, their units can be decoded only if the chain orders empty
+    /// payloads.
+    #[arg(long)]
+    consensus_version: u16,
+
+    /// SS58 addresses of the authorities of the session, in the order of their node indices.
+    /// Signatures of the units are verified only if these are given.
+    #[arg(long, value_delimiter = ',')]
+    authorities: Vec<String>,
+
+    /// Node index of the node the backup comes from. Forks of units of other nodes are reported,
+    /// but only forks of our own units make the backup inconsistent. Any fork does if not given.
+    #[arg(long)]
+    node_index: Option<usize>,
+
+    /// Truncate the backup to its last consistent prefix.
+    #[arg(long)]
+    truncate: bool,
+}
+
+fn authorities(addresses: &[String]) -> Result<Option<Vec<AuthorityId>>, String> {
+    if addresses.is_empty() {
+        return Ok(None);
+    }
+    addresses
+        .iter()
+        .map(|address| {
+            ed25519::Public::from_ss58check(address)
+                .map(AuthorityId::from)
+                .map_err(|e| format!("invalid address {}: {:?}", address, e))
+        })
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+fn open_database(path: PathBuf, database: Database) -> Result<Arc<Backend<Block>>, String> {
+    if !path.is_dir() {
+        return Err(format!("no database at {:?}", path));
+    }
+    let source = match database {
+        Database::RocksDb => DatabaseSource::RocksDb {
+            path: path.clone(),
+            cache_size: DATABASE_CACHE_SIZE,
+        },
+        Database::ParityDb => DatabaseSource::ParityDb { path: path.clone() },
+    };
+    let settings = DatabaseSettings {
+        trie_cache_maximum_size: None,
+        state_pruning: None,
+        source,
+        blocks_pruning: BlocksPruning::KeepFinalized,
+    };
+    Backend::new(settings, CANONICALIZATION_DELAY)
+        .map(Arc::new)
+        .map_err(|e| format!("could not open database at {:?}: {}", path, e))
+}
+
+fn open_store(config: &Config) -> Result<Box<dyn BackupStore>, String> {
+    if let Some(backup_path) = &config.backup_path {
+        let session_path = backup_path.join(config.session.to_string());
+        if !session_path.is_dir() {
+            return Err(format!(
+                "no backup for session {} at {:?}",
+                config.session, session_path
+            ));
+        }
+        return Ok(Box::new(FileBackupStore::new(backup_path.clone())));
+    }
+    let database_path = config
+        .database_path
+        .clone()
+        .ok_or("either the backup or the database path is required")?;
+    let store = AuxBackupStore::new(open_database(database_path, config.database)?);
+    let parts = store
+        .parts(config.session)
+        .map_err(|e| format!("could not read database: {}", e))?;
+    if parts.is_empty() {
+        return Err(format!(
+            "no backup for session {} in the database",
+            config.session
+        ));
+    }
+    Ok(Box::new(store))
+}
+
+fn print_units(inspection: &BackupInspection) {
+    let mut units: Vec<_> = inspection.units.iter().enumerate().collect();
+    units.sort_by_key(|(_, unit)| (unit.unit.round, unit.unit.creator));
+    for (position, unit) in units {
+        let mut notes = Vec::new();
+        if position >= inspection.consistent_units {
+            notes.push("past consistent prefix");
+        }
+        if unit.signature_valid == Some(false) {
+            notes.push("bad signature");
+        }
+        if unit.duplicate {
+            notes.push("duplicate");
+        }
+        if unit.fork {
+            notes.push("fork");
+        }
+        println!(
+            "round {:>4} creator {:>3} hash {:?} part {} session {}{}",
+            unit.unit.round,
+            unit.unit.creator.0,
+            unit.unit.hash,
+            unit.part,
+            unit.unit.session_id,
+            if notes.is_empty() {
+                String::new()
+            } else {
+                format!(" [{}]", notes.join(", "))
+            }
+        );
+    }
+}
+
+fn print_summary(inspection: &BackupInspection) {
+    let count = |check: fn(&InspectedUnit) -> bool| {
+        inspection.units.iter().filter(|unit| check(unit)).count()
+    };
+    println!("units: {}", inspection.units.len());
+    println!("duplicates: {}", count(|unit| unit.duplicate));
+    println!("forks: {}", count(|unit| unit.fork));
+    println!(
+        "bad signatures: {}",
+        count(|unit| unit.signature_valid == Some(false))
+    );
+    for (part, length) in &inspection.corrupted_records {
+        println!("part {}: corrupted record of {} bytes", part, length);
+    }
+    for (part, length) in &inspection.undecodable_data {
+        println!("part {}: {} bytes not decodable as units", part, length);
+    }
+    println!("consistent prefix: {} units", inspection.consistent_units);
+}
+
+fn inspect(config: Config) -> Result<(), String> {
+    let store = open_store(&config)?;
+    let inspection = inspect_backup::<Block, ()>(
+        store.as_ref(),
+        config.session,
+        config.consensus_version,
+        authorities(&config.authorities)?,
+        config.node_index.map(NodeIndex),
+    )
+    .map_err(|e| format!("could not inspect backup: {}", e))?;
+
+    print_units(&inspection);
+    print_summary(&inspection);
+
+    if config.truncate {
+        truncate_backup(store.as_ref(), config.session, &inspection)
+            .map_err(|e| format!("could not truncate backup: {}", e))?;
+        println!("truncated backup to {} units", inspection.consistent_units);
+    }
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = inspect(Config::parse()) {
+        eprintln!("{}", e);
+        exit(1);
+    }
+}
@@ -0,0 +1,84 @@
+//! Signs a block hash with an emergency finalizer key kept in a local keystore. The printed
+//! signature can be passed to the `dagestan_emergencyFinalize` RPC of a node.
+use std::{path::PathBuf, process::exit};
+
+use clap::Parser;
+use codec::Encode;
+use dagestan_primitives::{AuthorityId, AuthorityPair, KEY_TYPE};
+use sc_keystore::LocalKeystore;
+use sp_core::{
+    bytes::{from_hex, to_hex},
+    crypto::Ss58Codec,
+    ed25519, Pair, H256,
+};
+use sp_keystore::SyncCryptoStore;
+
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Config {
+    /// Path to the keystore holding the emergency finalizer key.
+    #[arg(long)]
+    keystore_path: PathBuf,
+
+    /// Password of the keystore, if it is encrypted.
+    #[arg(long)]
+    password: Option<String>,
+
+    /// SS58 address of the emergency finalizer key, needed only if the keystore holds more than
+    /// one Dagestan key.
+    #[arg(long)]
+    public: Option<String>,
+
+    /// Hex encoded hash of the block to finalize.
+    #[arg(long)]
+    hash: String,
+}
+
+fn public_key(keystore: &LocalKeystore, public: Option<String>) -> Result<AuthorityId, String> {
+    if let Some(address) = public {
+        return ed25519::Public::from_ss58check(&address)
+            .map(AuthorityId::from)
+            .map_err(|e| format!("invalid address {}: {:?}", address, e));
+    }
+    match SyncCryptoStore::ed25519_public_keys(keystore, KEY_TYPE).as_slice() {
+        [key] => Ok(AuthorityId::from(*key)),
+        [] => Err("the keystore holds no Dagestan keys".to_string()),
+        _ => Err("the keystore holds several Dagestan keys, choose one with --public".to_string()),
+    }
+}
+
+fn sign(config: Config) -> Result<String, String> {
+    let password = config
+        .password
+        .map(|password| password.parse())
+        .transpose()
+        .map_err(|_| "could not read the password".to_string())?;
+    let keystore = LocalKeystore::open(&config.keystore_path, password)
+        .map_err(|e| format!("could not open keystore: {}", e))?;
+    let public = public_key(&keystore, config.public)?;
+    let pair = keystore
+        .key_pair::<AuthorityPair>(&public)
+        .map_err(|e| format!("could not read key: {}", e))?
+        .ok_or_else(|| format!("no secret key for {:?}", public))?;
+
+    let hash = from_hex(&config.hash).map_err(|e| format!("invalid hash: {:?}", e))?;
+    if hash.len() != H256::len_bytes() {
+        return Err(format!(
+            "invalid hash length {}, expected {}",
+            hash.len(),
+            H256::len_bytes()
+        ));
+    }
+    let signature = pair.sign(&H256::from_slice(&hash).encode());
+    Ok(to_hex(&signature.encode(), false))
+}
+
+fn main() {
+    match sign(Config::parse()) {
+        Ok(signature) => println!("{}", signature),
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
+        }
+    }
+}
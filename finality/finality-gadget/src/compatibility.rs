@@ -1,6 +1,6 @@
 use codec::{Decode, Encode};
 
-#[derive(Encode, Eq, Decode, PartialEq, Debug, Copy, Clone)]
+#[derive(Encode, Eq, Decode, PartialEq, PartialOrd, Ord, Debug, Copy, Clone)]
 pub struct Version(pub u16);
 
 pub trait Versioned {
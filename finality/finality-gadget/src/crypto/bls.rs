@@ -0,0 +1,369 @@
+use std::sync::Arc;
+
+use blst::{
+    min_pk::{
+        AggregateSignature as RawAggregateSignature, PublicKey, SecretKey,
+        Signature as RawSignature,
+    },
+    BLST_ERROR,
+};
+use codec::{Compact, Decode, Encode, Error as CodecError, Input as CodecInput, Output};
+use dagestan_primitives::{BlsPublic, BlsSignature, BLS_KEY_TYPE, BLS_POP_DST};
+use log::{info, warn};
+use sp_core::ed25519::Public as SeedPublic;
+use sp_keystore::CryptoStore;
+
+use crate::{
+    abft::{NodeCount, NodeIndex},
+    crypto::Error,
+};
+
+/// Domain separation tag of the basic scheme with public keys in G1 and signatures in G2.
+const DST: &[u8] = b"BLS_SIG_BLS12381G2_XMD:SHA-256_SSWU_RO_NUL_";
+
+/// The message signed with a seed key to derive the BLS key. Seed keys never sign anything else,
+/// so the resulting signature, and thus the BLS key, stays secret.
+const KEY_DERIVATION_MESSAGE: &[u8] = b"dagestan-bls-key-derivation";
+
+/// The number of bytes of a bitmap with a bit for each of `size` members.
+pub fn bitmap_len(size: usize) -> usize {
+    (size + 7) / 8
+}
+
+/// Signs messages with a BLS key kept in the keystore. The keystore cannot hold BLS keys, so it
+/// holds a seed key of type `BLS_KEY_TYPE` instead, a randomly generated ed25519 key used for
+/// nothing but deriving the BLS key.
+#[derive(Clone)]
+pub struct BlsPen {
+    secret_key: SecretKey,
+    public: BlsPublic,
+}
+
+impl BlsPen {
+    /// Generates a new BLS key and keeps its seed key in the keystore.
+    pub async fn generate(keystore: Arc<dyn CryptoStore>) -> Result<Self, Error> {
+        let seed_key = keystore
+            .ed25519_generate_new(BLS_KEY_TYPE, None)
+            .await
+            .map_err(Error::Keystore)?;
+        Self::derive(seed_key, keystore.as_ref()).await
+    }
+
+    /// Loads the BLS key `registered` if the keystore holds it, otherwise any BLS key the keystore
+    /// holds, generating a new one if there are none.
+    pub async fn load(
+        registered: Option<BlsPublic>,
+        keystore: Arc<dyn CryptoStore>,
+    ) -> Result<Self, Error> {
+        let mut loaded = None;
+        for seed_key in keystore.ed25519_public_keys(BLS_KEY_TYPE).await {
+            let pen = Self::derive(seed_key, keystore.as_ref()).await?;
+            if Some(pen.public) == registered {
+                return Ok(pen);
+            }
+            loaded.get_or_insert(pen);
+        }
+        match loaded {
+            Some(pen) => Ok(pen),
+            None => Self::generate(keystore).await,
+        }
+    }
+
+    async fn derive(seed_key: SeedPublic, keystore: &dyn CryptoStore) -> Result<Self, Error> {
+        let seed = keystore
+            .sign_with(BLS_KEY_TYPE, &seed_key.into(), KEY_DERIVATION_MESSAGE)
+            .await
+            .map_err(Error::Keystore)?
+            .ok_or(Error::BlsKeyMissing)?;
+        let secret_key =
+            SecretKey::key_gen(&seed, &BLS_KEY_TYPE.0).map_err(|_| Error::Conversion)?;
+        let public = BlsPublic(secret_key.sk_to_pk().compress());
+        let pen = BlsPen { secret_key, public };
+        info!(target: "dagestan-party", "BLS key {:?} has proof of possession {:?}.", public, pen.proof_of_possession());
+        Ok(pen)
+    }
+
+    /// The proof of possession of the key, which has to accompany its registration on chain.
+    pub fn proof_of_possession(&self) -> BlsSignature {
+        BlsSignature(
+            self.secret_key
+                .sign(&self.public.0, BLS_POP_DST, &[])
+                .compress(),
+        )
+    }
+
+    /// Cryptographically signs the message.
+    pub fn sign(&self, msg: &[u8]) -> BlsSignature {
+        BlsSignature(self.secret_key.sign(msg, DST, &[]).compress())
+    }
+
+    pub fn public(&self) -> BlsPublic {
+        self.public
+    }
+}
+
+/// Verifies that the signature is a signature of the message by the given key.
+pub fn verify(public: &BlsPublic, msg: &[u8], signature: &BlsSignature) -> bool {
+    verify_aggregate(&[public], msg, signature)
+}
+
+/// Verifies that the signature is an aggregate of signatures of the message by all the given keys.
+/// This is only secure if every key was registered with a proof of possession of its secret key,
+/// otherwise a rogue key could be used to forge aggregates.
+pub fn verify_aggregate(publics: &[&BlsPublic], msg: &[u8], signature: &BlsSignature) -> bool {
+    if publics.is_empty() {
+        return false;
+    }
+    let publics = match publics
+        .iter()
+        .map(|public| PublicKey::key_validate(&public.0))
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(publics) => publics,
+        Err(_) => return false,
+    };
+    let signature = match RawSignature::sig_validate(&signature.0, true) {
+        Ok(signature) => signature,
+        Err(_) => return false,
+    };
+    let publics: Vec<_> = publics.iter().collect();
+    signature.fast_aggregate_verify(false, msg, DST, &publics) == BLST_ERROR::BLST_SUCCESS
+}
+
+fn aggregate(first: &BlsSignature, second: &BlsSignature) -> Option<BlsSignature> {
+    let first = RawSignature::from_bytes(&first.0).ok()?;
+    let second = RawSignature::from_bytes(&second.0).ok()?;
+    let mut aggregate = RawAggregateSignature::from_signature(&first);
+    aggregate.add_signature(&second, true).ok()?;
+    Some(BlsSignature(aggregate.to_signature().compress()))
+}
+
+/// A single BLS signature aggregated from signatures of some members of a committee, together with
+/// a bitmap of these members.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AggregateSignature {
+    size: NodeCount,
+    signers: Vec<u8>,
+    signature: BlsSignature,
+}
+
+impl AggregateSignature {
+    /// An aggregate of a single signature made by the member with the given index.
+    pub fn new(size: NodeCount, signature: BlsSignature, index: NodeIndex) -> Self {
+        let mut signers = vec![0; bitmap_len(size.0)];
+        if index.0 < size.0 {
+            signers[index.0 / 8] |= 1 << (index.0 % 8);
+        }
+        AggregateSignature {
+            size,
+            signers,
+            signature,
+        }
+    }
+
+    pub fn size(&self) -> NodeCount {
+        self.size
+    }
+
+    pub fn signature(&self) -> &BlsSignature {
+        &self.signature
+    }
+
+    pub fn contains(&self, index: NodeIndex) -> bool {
+        index.0 < self.size.0 && self.signers[index.0 / 8] & (1 << (index.0 % 8)) != 0
+    }
+
+    /// The indices of the members whose signatures are aggregated, in increasing order.
+    pub fn signers(&self) -> impl Iterator<Item = NodeIndex> + '_ {
+        (0..self.size.0)
+            .map(NodeIndex)
+            .filter(|index| self.contains(*index))
+    }
+
+    /// Aggregates the signature of the member with the given index, unless it is already included.
+    pub fn add_signature(mut self, signature: &BlsSignature, index: NodeIndex) -> Self {
+        if index.0 >= self.size.0 || self.contains(index) {
+            return self;
+        }
+        match aggregate(&self.signature, signature) {
+            Some(signature) => {
+                self.signature = signature;
+                self.signers[index.0 / 8] |= 1 << (index.0 % 8);
+            }
+            None => {
+                warn!(target: "dagestan-aggregator", "Malformed BLS signature of member {:?}, not aggregating it.", index)
+            }
+        }
+        self
+    }
+}
+
+impl Encode for AggregateSignature {
+    fn size_hint(&self) -> usize {
+        Compact(self.size.0 as u32).size_hint() + self.signers.len() + self.signature.size_hint()
+    }
+
+    fn encode_to<T: Output + ?Sized>(&self, dest: &mut T) {
+        // The length of the bitmap follows from the size, so it gets no length prefix.
+        Compact(self.size.0 as u32).encode_to(dest);
+        dest.write(&self.signers);
+        self.signature.encode_to(dest);
+    }
+}
+
+impl Decode for AggregateSignature {
+    fn decode<I: CodecInput>(input: &mut I) -> Result<Self, CodecError> {
+        let size = Compact::<u32>::decode(input)?.0 as usize;
+        let signers_len = bitmap_len(size);
+        if let Some(remaining) = input.remaining_len()? {
+            if remaining < signers_len {
+                return Err("Signer bitmap longer than the remaining input".into());
+            }
+        }
+        let mut signers = vec![0; signers_len];
+        input.read(&mut signers)?;
+        if size % 8 != 0 && signers[signers_len - 1] >> (size % 8) != 0 {
+            return Err("Signer bitmap marks members outside of the committee".into());
+        }
+        let signature = BlsSignature::decode(input)?;
+        Ok(AggregateSignature {
+            size: NodeCount(size),
+            signers,
+            signature,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dagestan_primitives::bls_crypto;
+    use sp_keystore::testing::KeyStore;
+
+    use super::*;
+
+    async fn generate_pens(count: usize) -> Vec<BlsPen> {
+        let keystore = Arc::new(KeyStore::new());
+        let mut pens = Vec::with_capacity(count);
+        for _ in 0..count {
+            pens.push(
+                BlsPen::generate(keystore.clone())
+                    .await
+                    .expect("the key should be generated"),
+            );
+        }
+        pens
+    }
+
+    fn aggregate_of(pens: &[BlsPen], signers: &[usize], msg: &[u8]) -> AggregateSignature {
+        let size = NodeCount(pens.len());
+        let first = signers[0];
+        signers[1..].iter().fold(
+            AggregateSignature::new(size, pens[first].sign(msg), NodeIndex(first)),
+            |aggregate, index| aggregate.add_signature(&pens[*index].sign(msg), NodeIndex(*index)),
+        )
+    }
+
+    #[tokio::test]
+    async fn loads_the_registered_key() {
+        let keystore = Arc::new(KeyStore::new());
+        let first = BlsPen::generate(keystore.clone()).await.unwrap();
+        let second = BlsPen::generate(keystore.clone()).await.unwrap();
+        assert_ne!(first.public(), second.public());
+
+        for pen in [&first, &second] {
+            let loaded = BlsPen::load(Some(pen.public()), keystore.clone())
+                .await
+                .unwrap();
+            assert_eq!(loaded.public(), pen.public());
+        }
+        let loaded = BlsPen::load(None, keystore.clone()).await.unwrap();
+        assert!([first.public(), second.public()].contains(&loaded.public()));
+        assert_eq!(keystore.ed25519_public_keys(BLS_KEY_TYPE).await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn generates_a_key_if_there_is_none() {
+        let keystore = Arc::new(KeyStore::new());
+        let generated = BlsPen::load(None, keystore.clone()).await.unwrap();
+        let loaded = BlsPen::load(None, keystore).await.unwrap();
+        assert_eq!(generated.public(), loaded.public());
+    }
+
+    #[tokio::test]
+    async fn produces_valid_proofs_of_possession() {
+        let pens = generate_pens(2).await;
+        let proof = pens[0].proof_of_possession();
+        assert!(bls_crypto::verify_proof_of_possession(
+            &pens[0].public().0,
+            &proof.0
+        ));
+        assert!(!bls_crypto::verify_proof_of_possession(
+            &pens[1].public().0,
+            &proof.0
+        ));
+        assert!(!bls_crypto::verify_proof_of_possession(
+            &pens[0].public().0,
+            &pens[0].sign(&pens[0].public().0).0
+        ));
+    }
+
+    #[tokio::test]
+    async fn verifies_aggregate_signatures() {
+        let pens = generate_pens(5).await;
+        let msg = b"test";
+        let aggregate = aggregate_of(&pens, &[0, 2, 3], msg);
+        let publics: Vec<_> = pens.iter().map(|pen| pen.public()).collect();
+
+        assert_eq!(
+            aggregate.signers().collect::<Vec<_>>(),
+            vec![NodeIndex(0), NodeIndex(2), NodeIndex(3)]
+        );
+        assert!(verify_aggregate(
+            &[&publics[0], &publics[2], &publics[3]],
+            msg,
+            aggregate.signature()
+        ));
+        assert!(!verify_aggregate(
+            &[&publics[0], &publics[1], &publics[3]],
+            msg,
+            aggregate.signature()
+        ));
+        assert!(!verify_aggregate(
+            &[&publics[0], &publics[2], &publics[3]],
+            b"not test",
+            aggregate.signature()
+        ));
+    }
+
+    #[tokio::test]
+    async fn ignores_repeated_signatures() {
+        let pens = generate_pens(3).await;
+        let msg = b"test";
+        let aggregate = aggregate_of(&pens, &[0, 1], msg);
+        let repeated = aggregate
+            .clone()
+            .add_signature(&pens[1].sign(msg), NodeIndex(1));
+        assert_eq!(aggregate, repeated);
+    }
+
+    #[tokio::test]
+    async fn codec_round_trips() {
+        let pens = generate_pens(10).await;
+        let aggregate = aggregate_of(&pens, &[1, 4, 9], b"test");
+        let encoded = aggregate.encode();
+        assert_eq!(encoded.len(), aggregate.size_hint());
+        assert_eq!(
+            AggregateSignature::decode(&mut encoded.as_slice()),
+            Ok(aggregate)
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_signers_outside_committee() {
+        let pens = generate_pens(3).await;
+        let mut encoded = aggregate_of(&pens, &[0, 1], b"test").encode();
+        // The bitmap directly follows the single byte of the compact size.
+        encoded[1] |= 1 << 5;
+        assert!(AggregateSignature::decode(&mut encoded.as_slice()).is_err());
+    }
+}
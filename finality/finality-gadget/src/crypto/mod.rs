@@ -0,0 +1,513 @@
+use std::{convert::TryInto, sync::Arc};
+
+use codec::{Decode, Encode};
+use dagestan_primitives::{AuthorityId, AuthoritySignature, BlsPublic, BlsSignature, KEY_TYPE};
+use futures::{executor::ThreadPool, future::BoxFuture};
+use log::warn;
+use parking_lot::{const_mutex, Mutex};
+use sp_core::{
+    crypto::KeyTypeId,
+    ed25519::{Public as RawPublic, Signature as RawSignature},
+    traits::{SpawnNamed, TaskExecutorExt},
+};
+use sp_keystore::{CryptoStore, Error as KeystoreError};
+use sp_runtime::RuntimeAppPublic;
+use sp_state_machine::BasicExternalities;
+
+use crate::abft::{NodeCount, NodeIndex, SignatureSet};
+
+mod bls;
+
+pub use bls::{bitmap_len, AggregateSignature, BlsPen};
+
+#[derive(Debug)]
+pub enum Error {
+    KeyMissing(AuthorityId),
+    BlsKeyMissing,
+    Keystore(KeystoreError),
+    Conversion,
+}
+
+#[derive(PartialEq, Eq, Clone, Debug, Hash, Decode, Encode)]
+pub struct Signature(AuthoritySignature);
+
+impl From<AuthoritySignature> for Signature {
+    fn from(authority_signature: AuthoritySignature) -> Signature {
+        Signature(authority_signature)
+    }
+}
+
+// This is here just for a compatibility hack, remove when removing legacy/v1 authentications.
+impl From<[u8; 64]> for Signature {
+    fn from(bytes: [u8; 64]) -> Signature {
+        Signature(RawSignature::from_raw(bytes).into())
+    }
+}
+
+/// Ties an authority identification and a cryptography keystore together for use in
+/// signing that requires an authority.
+#[derive(Clone)]
+pub struct AuthorityPen {
+    key_type_id: KeyTypeId,
+    authority_id: AuthorityId,
+    keystore: Arc<dyn CryptoStore>,
+}
+
+impl AuthorityPen {
+    /// Constructs a new authority cryptography keystore for the given ID and key type.
+    /// Will attempt to sign a test message to verify that signing works.
+    /// Returns errors if anything goes wrong during this attempt, otherwise we assume the
+    /// AuthorityPen will work for any future attempts at signing.
+    pub async fn new_with_key_type(
+        authority_id: AuthorityId,
+        keystore: Arc<dyn CryptoStore>,
+        key_type: KeyTypeId,
+    ) -> Result<Self, Error> {
+        // Check whether this signing setup works
+        let _: AuthoritySignature = keystore
+            .sign_with(key_type, &authority_id.clone().into(), b"test")
+            .await
+            .map_err(Error::Keystore)?
+            .ok_or_else(|| Error::KeyMissing(authority_id.clone()))?
+            .try_into()
+            .map_err(|_| Error::Conversion)?;
+        Ok(AuthorityPen {
+            key_type_id: key_type,
+            authority_id,
+            keystore,
+        })
+    }
+
+    /// Constructs a new authority cryptography keystore for the given ID and the dagestan key type.
+    /// Will attempt to sign a test message to verify that signing works.
+    /// Returns errors if anything goes wrong during this attempt, otherwise we assume the
+    /// AuthorityPen will work for any future attempts at signing.
+    pub async fn new(
+        authority_id: AuthorityId,
+        keystore: Arc<dyn CryptoStore>,
+    ) -> Result<Self, Error> {
+        Self::new_with_key_type(authority_id, keystore, KEY_TYPE).await
+    }
+
+    /// Cryptographically signs the message.
+    pub async fn sign(&self, msg: &[u8]) -> Signature {
+        Signature(
+            self.keystore
+                .sign_with(self.key_type_id, &self.authority_id.clone().into(), msg)
+                .await
+                .expect("the keystore works")
+                .expect("we have the required key")
+                .try_into()
+                .expect("the bytes encode a signature"),
+        )
+    }
+
+    /// Return the associated AuthorityId.
+    pub fn authority_id(&self) -> AuthorityId {
+        self.authority_id.clone()
+    }
+}
+
+/// Runs the tasks `sp_io` batch verification splits the signatures into.
+#[derive(Clone)]
+struct BatchVerificationExecutor(ThreadPool);
+
+impl SpawnNamed for BatchVerificationExecutor {
+    fn spawn_blocking(
+        &self,
+        _name: &'static str,
+        _group: Option<&'static str>,
+        future: BoxFuture<'static, ()>,
+    ) {
+        self.0.spawn_ok(future)
+    }
+
+    fn spawn(
+        &self,
+        _name: &'static str,
+        _group: Option<&'static str>,
+        future: BoxFuture<'static, ()>,
+    ) {
+        self.0.spawn_ok(future)
+    }
+}
+
+/// The executor shared by all the batch verifications, started with the first of them.
+fn batch_verification_executor() -> BatchVerificationExecutor {
+    static EXECUTOR: Mutex<Option<BatchVerificationExecutor>> = const_mutex(None);
+    EXECUTOR
+        .lock()
+        .get_or_insert_with(|| {
+            BatchVerificationExecutor(
+                ThreadPool::builder()
+                    .name_prefix("dagestan-batch-verify-")
+                    .create()
+                    .expect("we should be able to start threads"),
+            )
+        })
+        .clone()
+}
+
+/// Verify the signature given an authority id.
+pub fn verify(authority: &AuthorityId, message: &[u8], signature: &Signature) -> bool {
+    authority.verify(&message, &signature.0)
+}
+
+/// Holds the public authority keys for a session allowing for verification of messages from that
+/// session.
+#[derive(Clone)]
+pub struct AuthorityVerifier {
+    authorities: Vec<AuthorityId>,
+    bls_authorities: Option<Vec<BlsPublic>>,
+}
+
+impl AuthorityVerifier {
+    /// Constructs a new authority verifier from a set of public keys.
+    pub fn new(authorities: Vec<AuthorityId>) -> Self {
+        AuthorityVerifier {
+            authorities,
+            bls_authorities: None,
+        }
+    }
+
+    /// Adds the BLS keys of the authorities, in the same order as the authorities. The keys are
+    /// ignored unless there is exactly one for every authority.
+    pub fn with_bls_authorities(mut self, bls_authorities: Vec<BlsPublic>) -> Self {
+        if bls_authorities.len() == self.authorities.len() {
+            self.bls_authorities = Some(bls_authorities);
+        }
+        self
+    }
+
+    /// Whether every authority has a BLS key, so that the committee can aggregate signatures.
+    pub fn has_bls_authorities(&self) -> bool {
+        self.bls_authorities.is_some()
+    }
+
+    /// Verifies whether the message is correctly signed with the signature assumed to be made by a
+    /// node of the given index.
+    pub fn verify(&self, msg: &[u8], sgn: &Signature, index: NodeIndex) -> bool {
+        match self.authorities.get(index.0) {
+            Some(authority) => verify(authority, msg, sgn),
+            None => false,
+        }
+    }
+
+    /// Verifies whether the message is correctly signed with the BLS signature assumed to be made
+    /// by a node of the given index.
+    pub fn verify_bls(&self, msg: &[u8], sgn: &BlsSignature, index: NodeIndex) -> bool {
+        match self
+            .bls_authorities
+            .as_ref()
+            .and_then(|bls_authorities| bls_authorities.get(index.0))
+        {
+            Some(bls_authority) => bls::verify(bls_authority, msg, sgn),
+            None => false,
+        }
+    }
+
+    pub fn node_count(&self) -> NodeCount {
+        self.authorities.len().into()
+    }
+
+    fn threshold(&self) -> usize {
+        2 * self.node_count().0 / 3 + 1
+    }
+
+    /// Verifies all the signatures of the set at once with `sp_io` batch verification, which is
+    /// much faster than verifying them one by one, but does not tell which of them is bad. It checks
+    /// every signature exactly like `verify` does, so both accept the same signatures.
+    fn verify_batch(&self, msg: &[u8], partial: &SignatureSet<Signature>) -> bool {
+        let mut batch = Vec::new();
+        for (index, sgn) in partial.iter() {
+            let authority = match self.authorities.get(index.0) {
+                Some(authority) => authority,
+                None => return false,
+            };
+            let key: &RawPublic = authority.as_ref();
+            let sgn: &RawSignature = sgn.0.as_ref();
+            batch.push((key, sgn));
+        }
+        let mut externalities = BasicExternalities::default();
+        externalities.register_extension(TaskExecutorExt::new(batch_verification_executor()));
+        externalities.execute_with(|| {
+            sp_io::crypto::start_batch_verify();
+            for (key, sgn) in batch {
+                sp_io::crypto::ed25519_batch_verify(sgn, msg, key);
+            }
+            sp_io::crypto::finish_batch_verify()
+        })
+    }
+
+    /// Verifies whether the given signature set is a correct and complete multisignature of the
+    /// message. Completeness requires more than 2/3 of all authorities.
+    pub fn is_complete(&self, msg: &[u8], partial: &SignatureSet<Signature>) -> bool {
+        let signature_count = partial.iter().count();
+        if signature_count < self.threshold() {
+            return false;
+        }
+        if self.verify_batch(msg, partial) {
+            return true;
+        }
+        // Only checking the signatures one by one tells us who is to blame.
+        match partial.iter().find(|(i, sgn)| !self.verify(msg, sgn, *i)) {
+            Some((i, sgn)) => {
+                warn!(target: "dagestan-justification", "Bad signature {:?} of node {:?} in a multisignature.", sgn, i);
+                false
+            }
+            None => true,
+        }
+    }
+
+    /// Verifies whether the given aggregate is a correct and complete multisignature of the
+    /// message. Completeness requires more than 2/3 of all authorities, just as for signature sets.
+    pub fn is_complete_aggregate(&self, msg: &[u8], aggregate: &AggregateSignature) -> bool {
+        let bls_authorities = match &self.bls_authorities {
+            Some(bls_authorities) => bls_authorities,
+            None => return false,
+        };
+        if aggregate.size() != self.node_count() {
+            return false;
+        }
+        let signers: Vec<_> = aggregate
+            .signers()
+            .filter_map(|index| bls_authorities.get(index.0))
+            .collect();
+        if signers.len() < self.threshold() {
+            return false;
+        }
+        bls::verify_aggregate(&signers, msg, aggregate.signature())
+    }
+}
+
+/// Old format of signatures, needed for backwards compatibility.
+#[derive(PartialEq, Eq, Clone, Debug, Decode, Encode)]
+pub struct SignatureV1 {
+    pub _id: NodeIndex,
+    pub sgn: AuthoritySignature,
+}
+
+impl From<SignatureV1> for Signature {
+    fn from(sig_v1: SignatureV1) -> Signature {
+        Signature(sig_v1.sgn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sp_keystore::{testing::KeyStore, CryptoStore};
+
+    use super::*;
+    use crate::abft::NodeIndex;
+
+    async fn generate_keys(names: &[String]) -> (Vec<AuthorityPen>, AuthorityVerifier) {
+        let key_store = Arc::new(KeyStore::new());
+        let mut authority_ids = Vec::with_capacity(names.len());
+        for name in names {
+            let pk = key_store
+                .ed25519_generate_new(KEY_TYPE, Some(name))
+                .await
+                .unwrap();
+            authority_ids.push(AuthorityId::from(pk));
+        }
+        let mut pens = Vec::with_capacity(names.len());
+        for authority_id in authority_ids.clone() {
+            pens.push(
+                AuthorityPen::new(authority_id, key_store.clone())
+                    .await
+                    .expect("The keys should sign successfully"),
+            );
+        }
+        assert_eq!(
+            key_store.keys(KEY_TYPE).await.unwrap().len(),
+            3 * names.len()
+        );
+        (pens, AuthorityVerifier::new(authority_ids))
+    }
+
+    async fn prepare_test() -> (Vec<AuthorityPen>, AuthorityVerifier) {
+        let authority_names: Vec<_> = ["//Alice", "//Bob", "//Charlie"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        generate_keys(&authority_names).await
+    }
+
+    #[tokio::test]
+    async fn produces_and_verifies_correct_signatures() {
+        let (pens, verifier) = prepare_test().await;
+        let msg = b"test";
+        for (i, pen) in pens.into_iter().enumerate() {
+            let signature = pen.sign(msg).await;
+            assert!(verifier.verify(msg, &signature, NodeIndex(i)));
+        }
+    }
+
+    #[tokio::test]
+    async fn does_not_accept_signatures_from_wrong_sources() {
+        let (pens, verifier) = prepare_test().await;
+        let msg = b"test";
+        for pen in &pens[1..] {
+            let signature = pen.sign(msg).await;
+            assert!(!verifier.verify(msg, &signature, NodeIndex(0)));
+        }
+    }
+
+    #[tokio::test]
+    async fn does_not_accept_signatures_from_unknown_sources() {
+        let (pens, verifier) = prepare_test().await;
+        let msg = b"test";
+        for pen in &pens {
+            let signature = pen.sign(msg).await;
+            assert!(!verifier.verify(msg, &signature, NodeIndex(pens.len())));
+        }
+    }
+
+    #[tokio::test]
+    async fn does_not_accept_signatures_for_different_messages() {
+        let (pens, verifier) = prepare_test().await;
+        let msg = b"test";
+        let not_msg = b"not test";
+        for (i, pen) in pens.into_iter().enumerate() {
+            let signature = pen.sign(msg).await;
+            assert!(!verifier.verify(not_msg, &signature, NodeIndex(i)));
+        }
+    }
+
+    async fn multisignature(pens: &[AuthorityPen], msg: &[u8]) -> SignatureSet<Signature> {
+        let mut signature_set = SignatureSet::with_size(NodeCount(pens.len()));
+        for (i, pen) in pens.iter().enumerate() {
+            signature_set = signature_set.add_signature(&pen.sign(msg).await, NodeIndex(i));
+        }
+        signature_set
+    }
+
+    #[tokio::test]
+    async fn accepts_complete_multisignatures() {
+        let (pens, verifier) = prepare_test().await;
+        let msg = b"test";
+        let signature_set = multisignature(&pens, msg).await;
+        assert!(verifier.verify_batch(msg, &signature_set));
+        assert!(verifier.is_complete(msg, &signature_set));
+    }
+
+    #[tokio::test]
+    async fn rejects_multisignatures_with_a_bad_signature() {
+        let (pens, verifier) = prepare_test().await;
+        let msg = b"test";
+        let bad_signature = pens[0].sign(b"not test").await;
+        let signature_set = multisignature(&pens, msg).await.into_iter().fold(
+            SignatureSet::with_size(NodeCount(pens.len())),
+            |signature_set, (i, sgn)| match i {
+                NodeIndex(1) => signature_set.add_signature(&bad_signature, i),
+                _ => signature_set.add_signature(&sgn, i),
+            },
+        );
+        assert!(!verifier.verify_batch(msg, &signature_set));
+        assert!(!verifier.is_complete(msg, &signature_set));
+    }
+
+    #[test]
+    fn batch_verification_agrees_with_verification_of_single_signatures() {
+        // The identity as the key, a point of order 2 as the commitment and a zero scalar make a
+        // signature of any message under the cofactored equation of ZIP-215, but not under the
+        // cofactorless one.
+        let mut key = [0u8; 32];
+        key[0] = 1;
+        let mut signature = [0u8; 64];
+        signature[0] = 0xec;
+        signature[1..31].fill(0xff);
+        signature[31] = 0x7f;
+        let msg = b"test";
+        assert!(ed25519_zebra::VerificationKey::try_from(key)
+            .and_then(|key| key.verify(&ed25519_zebra::Signature::from(signature), msg))
+            .is_ok());
+
+        let verifier = AuthorityVerifier::new(vec![AuthorityId::from(RawPublic::from_raw(key))]);
+        let signature = Signature::from(signature);
+        let signature_set =
+            SignatureSet::with_size(NodeCount(1)).add_signature(&signature, NodeIndex(0));
+        assert_eq!(
+            verifier.verify_batch(msg, &signature_set),
+            verifier.verify(msg, &signature, NodeIndex(0))
+        );
+        assert_eq!(
+            verifier.is_complete(msg, &signature_set),
+            verifier.verify(msg, &signature, NodeIndex(0))
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_incomplete_multisignatures() {
+        let (pens, verifier) = prepare_test().await;
+        let msg = b"test";
+        let signature_set = multisignature(&pens[..2], msg).await;
+        assert!(!verifier.is_complete(msg, &signature_set));
+    }
+
+    async fn generate_bls_keys(count: usize) -> (Vec<BlsPen>, AuthorityVerifier) {
+        let key_store = Arc::new(KeyStore::new());
+        let mut authority_ids = Vec::with_capacity(count);
+        let mut pens = Vec::with_capacity(count);
+        for _ in 0..count {
+            let authority_id = AuthorityId::from(
+                key_store
+                    .ed25519_generate_new(KEY_TYPE, None)
+                    .await
+                    .unwrap(),
+            );
+            pens.push(
+                BlsPen::generate(key_store.clone())
+                    .await
+                    .expect("The keys should be generated successfully"),
+            );
+            authority_ids.push(authority_id);
+        }
+        let bls_authorities = pens.iter().map(|pen| pen.public()).collect();
+        (
+            pens,
+            AuthorityVerifier::new(authority_ids).with_bls_authorities(bls_authorities),
+        )
+    }
+
+    fn aggregate(pens: &[BlsPen], signers: &[usize], msg: &[u8]) -> AggregateSignature {
+        signers.iter().skip(1).fold(
+            AggregateSignature::new(
+                NodeCount(pens.len()),
+                pens[signers[0]].sign(msg),
+                NodeIndex(signers[0]),
+            ),
+            |aggregate, i| aggregate.add_signature(&pens[*i].sign(msg), NodeIndex(*i)),
+        )
+    }
+
+    #[tokio::test]
+    async fn verifies_bls_signatures() {
+        let (pens, verifier) = generate_bls_keys(3).await;
+        let msg = b"test";
+        for (i, pen) in pens.iter().enumerate() {
+            let signature = pen.sign(msg);
+            assert!(verifier.verify_bls(msg, &signature, NodeIndex(i)));
+            assert!(!verifier.verify_bls(msg, &signature, NodeIndex((i + 1) % pens.len())));
+        }
+    }
+
+    #[tokio::test]
+    async fn accepts_only_complete_aggregates() {
+        let (pens, verifier) = generate_bls_keys(4).await;
+        let msg = b"test";
+        assert!(verifier.is_complete_aggregate(msg, &aggregate(&pens, &[0, 1, 3], msg)));
+        assert!(verifier.is_complete_aggregate(msg, &aggregate(&pens, &[0, 1, 2, 3], msg)));
+        assert!(!verifier.is_complete_aggregate(msg, &aggregate(&pens, &[1, 2], msg)));
+        assert!(!verifier.is_complete_aggregate(b"not test", &aggregate(&pens, &[0, 1, 3], msg)));
+    }
+
+    #[tokio::test]
+    async fn does_not_accept_aggregates_without_bls_keys() {
+        let (pens, verifier) = generate_bls_keys(3).await;
+        let verifier = AuthorityVerifier::new(verifier.authorities);
+        let msg = b"test";
+        assert!(!verifier.has_bls_authorities());
+        assert!(!verifier.is_complete_aggregate(msg, &aggregate(&pens, &[0, 1, 2], msg)));
+    }
+}
@@ -0,0 +1,23 @@
+use codec::{Decode, Encode};
+use sp_runtime::traits::Block as BlockT;
+
+use crate::NodeIndex;
+
+/// The largest number of blocks sent in a single response to a block fetch request.
+pub const MAX_FETCHED_BLOCKS: usize = dagestan_primitives::MAX_DATA_BRANCH_LEN_LIMIT as usize;
+
+/// Messages exchanged over the session network to fetch the branches of pending proposals
+/// directly from the members that proposed them, as they are the most likely to have the blocks.
+#[derive(Clone, Debug, Encode, Decode)]
+pub enum BlockFetchMessage<B: BlockT> {
+    /// Asks for the block with the given hash and its ancestors, `branch_len` blocks in total. The
+    /// network does not tell who sent a message, so the request names the member to respond to.
+    Request {
+        requester: NodeIndex,
+        hash: B::Hash,
+        branch_len: u32,
+    },
+    /// The requested blocks, starting from the oldest one. Contains fewer blocks than requested
+    /// if the responder does not have them all, but never more than `MAX_FETCHED_BLOCKS`.
+    Response(Vec<B>),
+}
@@ -9,7 +9,7 @@ use crate::{
     data_io::{
         chain_info::{AuxFinalizationChainInfoProvider, CachedChainInfoProvider},
         status_provider::get_proposal_status,
-        DagestanData, ChainInfoProvider,
+        ChainInfoProvider, DagestanData, Payload, PayloadSink, UnvalidatedDagestanProposal,
     },
     mpsc::TrySendError,
     BlockHashNum, SessionBoundaries,
@@ -20,12 +20,14 @@ type InterpretersChainInfoProvider<B, C> =
 
 /// Takes as input ordered `DagestanData` from `AlephBFT` and pushes blocks that should be finalized
 /// to an output channel. The other end of the channel is held by the aggregator whose goal is to
-/// create multisignatures under the finalized blocks.
-pub struct OrderedDataInterpreter<B: BlockT, C: HeaderBackend<B>> {
+/// create multisignatures under the finalized blocks. The payloads of the data are passed to the
+/// payload sink, in the same order.
+pub struct OrderedDataInterpreter<B: BlockT, C: HeaderBackend<B>, P: Payload = ()> {
     blocks_to_finalize_tx: mpsc::UnboundedSender<BlockHashNum<B>>,
     chain_info_provider: InterpretersChainInfoProvider<B, C>,
     last_finalized_by_dagestan: BlockHashNum<B>,
     session_boundaries: SessionBoundaries<B>,
+    payload_sink: Box<dyn PayloadSink<Payload = P>>,
 }
 
 fn get_last_block_prev_session<B: BlockT, C: HeaderBackend<B>>(
@@ -46,11 +48,12 @@ fn get_last_block_prev_session<B: BlockT, C: HeaderBackend<B>>(
     }
 }
 
-impl<B: BlockT, C: HeaderBackend<B>> OrderedDataInterpreter<B, C> {
+impl<B: BlockT, C: HeaderBackend<B>, P: Payload> OrderedDataInterpreter<B, C, P> {
     pub fn new(
         blocks_to_finalize_tx: mpsc::UnboundedSender<BlockHashNum<B>>,
         client: Arc<C>,
         session_boundaries: SessionBoundaries<B>,
+        payload_sink: impl PayloadSink<Payload = P>,
     ) -> Self {
         let last_finalized_by_dagestan =
             get_last_block_prev_session(session_boundaries.clone(), client.clone());
@@ -64,6 +67,7 @@ impl<B: BlockT, C: HeaderBackend<B>> OrderedDataInterpreter<B, C> {
             chain_info_provider,
             last_finalized_by_dagestan,
             session_boundaries,
+            payload_sink: Box::new(payload_sink),
         }
     }
 
@@ -82,8 +86,10 @@ impl<B: BlockT, C: HeaderBackend<B>> OrderedDataInterpreter<B, C> {
         self.blocks_to_finalize_tx.unbounded_send(block)
     }
 
-    pub fn blocks_to_finalize_from_data(&mut self, new_data: DagestanData<B>) -> Vec<BlockHashNum<B>> {
-        let unvalidated_proposal = new_data.head_proposal;
+    pub fn blocks_to_finalize_from_data(
+        &mut self,
+        unvalidated_proposal: UnvalidatedDagestanProposal<B>,
+    ) -> Vec<BlockHashNum<B>> {
         let proposal = match unvalidated_proposal.validate_bounds(&self.session_boundaries) {
             Ok(proposal) => proposal,
             Err(error) => {
@@ -112,8 +118,13 @@ impl<B: BlockT, C: HeaderBackend<B>> OrderedDataInterpreter<B, C> {
         }
     }
 
-    pub fn data_finalized(&mut self, data: DagestanData<B>) {
-        for block in self.blocks_to_finalize_from_data(data) {
+    pub fn data_finalized(&mut self, data: DagestanData<B, P>) {
+        let DagestanData {
+            head_proposal,
+            payload,
+        } = data;
+        self.payload_sink.payload_ordered(payload);
+        for block in self.blocks_to_finalize_from_data(head_proposal) {
             self.set_last_finalized(block.clone());
             self.chain_info_provider()
                 .inner()
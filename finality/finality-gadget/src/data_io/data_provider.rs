@@ -1,9 +1,10 @@
 use std::{sync::Arc, time::Duration};
 
-use futures::channel::oneshot;
-use log::{debug, warn};
+use futures::{channel::oneshot, StreamExt};
+use futures_timer::Delay;
+use log::{debug, error, trace, warn};
 use parking_lot::Mutex;
-use sc_client_api::HeaderBackend;
+use sc_client_api::{BlockchainEvents, HeaderBackend};
 use sp_consensus::SelectChain;
 use sp_runtime::{
     generic::BlockId,
@@ -12,7 +13,10 @@ use sp_runtime::{
 };
 
 use crate::{
-    data_io::{proposal::UnvalidatedDagestanProposal, DagestanData, MAX_DATA_BRANCH_LEN},
+    data_io::{
+        fits_size_limit, proposal::UnvalidatedDagestanProposal, DagestanData, Payload,
+        PayloadProvider, MAX_PAYLOAD_SIZE,
+    },
     metrics::Checkpoint,
     BlockHashNum, Metrics, SessionBoundaries,
 };
@@ -62,6 +66,7 @@ pub fn get_proposal<B, C>(
     client: &C,
     best_block: BlockHashNum<B>,
     finalized_block: BlockHashNum<B>,
+    max_branch_len: usize,
 ) -> Result<DagestanData<B>, ()>
 where
     B: BlockT,
@@ -70,9 +75,7 @@ where
     let mut curr_block = best_block;
     let mut branch: Vec<B::Hash> = Vec::new();
     while curr_block.num > finalized_block.num {
-        if curr_block.num - finalized_block.num
-            <= <NumberFor<B>>::saturated_from(MAX_DATA_BRANCH_LEN)
-        {
+        if curr_block.num - finalized_block.num <= <NumberFor<B>>::saturated_from(max_branch_len) {
             branch.push(curr_block.hash);
         }
         curr_block = get_parent(client, &curr_block).expect("block of num >= 1 must have a parent")
@@ -83,6 +86,7 @@ where
         branch.reverse();
         Ok(DagestanData {
             head_proposal: UnvalidatedDagestanProposal::new(branch, num_last),
+            payload: (),
         })
     } else {
         // By backtracking from the best block we reached a block conflicting with best finalized.
@@ -92,16 +96,23 @@ where
     }
 }
 
-const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_millis(100);
+const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_millis(1000);
+const DEFAULT_DEBOUNCE_INTERVAL: Duration = Duration::from_millis(10);
 
 pub struct ChainTrackerConfig {
+    /// How often the chain is checked when no notifications arrive, only a safety net in case some
+    /// notification was missed.
     pub refresh_interval: Duration,
+    /// How long to wait after a notification before checking the chain, so that a burst of
+    /// notifications results in a single check.
+    pub debounce_interval: Duration,
 }
 
 impl Default for ChainTrackerConfig {
     fn default() -> ChainTrackerConfig {
         ChainTrackerConfig {
             refresh_interval: DEFAULT_REFRESH_INTERVAL,
+            debounce_interval: DEFAULT_DEBOUNCE_INTERVAL,
         }
     }
 }
@@ -113,13 +124,14 @@ struct ChainInfo<B: BlockT> {
 }
 
 /// ChainTracker keeps track of the best_block in a given session and allows to generate `DagestanData`.
-/// Internally it frequently updates a `data_to_propose` field that is shared with a `DataProvider`, which
+/// Internally it updates a `data_to_propose` field that is shared with a `DataProvider`, which
 /// in turn is a tiny wrapper around this single shared resource that takes out `data_to_propose` whenever
-/// `get_data` is called.
+/// `get_data` is called. The updates are triggered by block import and finality notifications, with
+/// periodic refreshes as a fallback.
 pub struct ChainTracker<B, SC, C>
 where
     B: BlockT,
-    C: HeaderBackend<B> + 'static,
+    C: HeaderBackend<B> + BlockchainEvents<B> + 'static,
     SC: SelectChain<B> + 'static,
 {
     select_chain: SC,
@@ -133,16 +145,17 @@ where
 impl<B, SC, C> ChainTracker<B, SC, C>
 where
     B: BlockT,
-    C: HeaderBackend<B> + 'static,
+    C: HeaderBackend<B> + BlockchainEvents<B> + 'static,
     SC: SelectChain<B> + 'static,
 {
-    pub fn new(
+    pub fn new<P: Payload>(
         select_chain: SC,
         client: Arc<C>,
         session_boundaries: SessionBoundaries<B>,
         config: ChainTrackerConfig,
+        payload_provider: impl PayloadProvider<Payload = P>,
         metrics: Option<Metrics<<B::Header as HeaderT>::Hash>>,
-    ) -> (Self, DataProvider<B>) {
+    ) -> (Self, DataProvider<B, P>) {
         let data_to_propose = Arc::new(Mutex::new(None));
         (
             ChainTracker {
@@ -155,6 +168,7 @@ where
             },
             DataProvider {
                 data_to_propose,
+                payload_provider: Box::new(payload_provider),
                 metrics,
             },
         )
@@ -207,6 +221,7 @@ where
             &*self.client,
             best_block_in_session.clone(),
             finalized_block,
+            self.session_boundaries.max_branch_len(),
         ) {
             *self.data_to_propose.lock() = Some(proposal);
         }
@@ -270,15 +285,34 @@ where
 
     pub async fn run(mut self, mut exit: oneshot::Receiver<()>) {
         let mut best_block_in_session: Option<BlockHashNum<B>> = None;
+        let mut import_stream = self.client.import_notification_stream();
+        let mut finality_stream = self.client.finality_notification_stream();
+        // Check the chain right away, later only after notifications or a full refresh interval.
+        let mut refresh_clock = Delay::new(Duration::ZERO);
+        let mut debouncing = false;
         loop {
-            let delay = futures_timer::Delay::new(self.config.refresh_interval);
             tokio::select! {
-                _ = delay => {
+                _ = &mut refresh_clock => {
                     best_block_in_session = self.get_best_block_in_session(best_block_in_session).await;
                     if let Some(best_block) = &best_block_in_session {
                         self.update_data(best_block);
                     }
-
+                    refresh_clock = Delay::new(self.config.refresh_interval);
+                    debouncing = false;
+                }
+                Some(block) = import_stream.next() => {
+                    if block.is_new_best && !debouncing {
+                        trace!(target: "dagestan-data-store", "New best block {:?} imported, scheduling a chain refresh.", block.hash);
+                        refresh_clock = Delay::new(self.config.debounce_interval);
+                        debouncing = true;
+                    }
+                }
+                Some(block) = finality_stream.next() => {
+                    if !debouncing {
+                        trace!(target: "dagestan-data-store", "Block {:?} finalized, scheduling a chain refresh.", block.hash);
+                        refresh_clock = Delay::new(self.config.debounce_interval);
+                        debouncing = true;
+                    }
                 }
                 _ = &mut exit => {
                     debug!(target: "dagestan-data-store", "Task for refreshing best chain received exit signal. Terminating.");
@@ -290,9 +324,9 @@ where
 }
 
 /// Provides data to AlephBFT for ordering.
-#[derive(Clone)]
-pub struct DataProvider<B: BlockT> {
+pub struct DataProvider<B: BlockT, P: Payload = ()> {
     data_to_propose: Arc<Mutex<Option<DagestanData<B>>>>,
+    payload_provider: Box<dyn PayloadProvider<Payload = P>>,
     metrics: Option<Metrics<<B::Header as HeaderT>::Hash>>,
 }
 
@@ -303,10 +337,30 @@ pub struct DataProvider<B: BlockT> {
 // 2. If the node does not know of any block in session `k` or if `best_block` is equal to the last finalized block
 //    then the node proposes `Empty`, otherwise the node proposes a branch extending from one block above
 //    last finalized till `best_block` with the restriction that the branch must be truncated to length
-//    at most MAX_DATA_BRANCH_LEN.
-impl<B: BlockT> DataProvider<B> {
-    pub async fn get_data(&mut self) -> Option<DagestanData<B>> {
-        let data_to_propose = (*self.data_to_propose.lock()).take();
+//    at most the maximal branch length of session `k`.
+// 3. Whenever the node proposes a branch, it also attaches the next payload from its payload provider,
+//    unless the payload is bigger than `MAX_PAYLOAD_SIZE`. Payloads are only carried together with
+//    branches, so the provider is not asked for them while there is nothing to propose.
+impl<B: BlockT, P: Payload> DataProvider<B, P> {
+    fn next_payload(&mut self) -> P {
+        let payload = self.payload_provider.next_payload();
+        match fits_size_limit(&payload) {
+            true => payload,
+            false => {
+                error!(target: "dagestan-data-store", "Dropping a payload bigger than {} bytes, it would not be accepted by other nodes.", MAX_PAYLOAD_SIZE);
+                P::default()
+            }
+        }
+    }
+
+    pub async fn get_data(&mut self) -> Option<DagestanData<B, P>> {
+        let head_proposal = (*self.data_to_propose.lock())
+            .take()
+            .map(|data| data.head_proposal);
+        let data_to_propose = head_proposal.map(|head_proposal| DagestanData {
+            head_proposal,
+            payload: self.next_payload(),
+        });
 
         if let Some(data) = &data_to_propose {
             if let Some(m) = &self.metrics {
@@ -336,7 +390,8 @@ mod tests {
     use crate::{
         data_io::{
             data_provider::{ChainTracker, ChainTrackerConfig},
-            DataProvider, MAX_DATA_BRANCH_LEN,
+            DataProvider, NoPayload, Payload, PayloadProvider, DEFAULT_MAX_DATA_BRANCH_LEN,
+            MAX_PAYLOAD_SIZE,
         },
         testing::{client_chain_builder::ClientChainBuilder, mocks::dagestan_data_from_blocks},
         SessionBoundaries, SessionId, SessionPeriod,
@@ -346,12 +401,28 @@ mod tests {
     // The lower the interval the less time the tests take, however setting this too low might cause
     // the tests to fail. Even though 1ms works with no issues, we set it to 5ms for safety.
     const REFRESH_INTERVAL: Duration = Duration::from_millis(5);
+    // Long enough for no refresh to happen during a test, unless triggered by a notification.
+    const NEVER: Duration = Duration::from_secs(3600);
+
+    // Hands out the queued payloads, the last one first.
+    struct QueuedPayloads(Vec<Vec<u8>>);
+
+    impl PayloadProvider for QueuedPayloads {
+        type Payload = Vec<u8>;
 
-    fn prepare_chain_tracker_test() -> (
+        fn next_payload(&mut self) -> Vec<u8> {
+            self.0.pop().unwrap_or_default()
+        }
+    }
+
+    fn prepare_chain_tracker_test<P: Payload>(
+        config: ChainTrackerConfig,
+        payload_provider: impl PayloadProvider<Payload = P>,
+    ) -> (
         impl Future<Output = ()>,
         oneshot::Sender<()>,
         ClientChainBuilder,
-        DataProvider<Block>,
+        DataProvider<Block, P>,
     ) {
         let (client, select_chain) = TestClientBuilder::new().build_with_longest_chain();
         let client = Arc::new(client);
@@ -360,12 +431,14 @@ mod tests {
             ClientChainBuilder::new(client.clone(), Arc::new(TestClientBuilder::new().build()));
         let session_boundaries = SessionBoundaries::new(SessionId(0), SessionPeriod(SESSION_LEN));
 
-        let config = ChainTrackerConfig {
-            refresh_interval: REFRESH_INTERVAL,
-        };
-
-        let (chain_tracker, data_provider) =
-            ChainTracker::new(select_chain, client, session_boundaries, config, None);
+        let (chain_tracker, data_provider) = ChainTracker::new(
+            select_chain,
+            client,
+            session_boundaries,
+            config,
+            payload_provider,
+            None,
+        );
 
         let (exit_chain_tracker_tx, exit_chain_tracker_rx) = oneshot::channel();
         (
@@ -388,7 +461,24 @@ mod tests {
         F: Future,
         S: FnOnce(ClientChainBuilder, DataProvider<Block>) -> F,
     {
-        let (task_handle, exit, chain_builder, data_provider) = prepare_chain_tracker_test();
+        let config = ChainTrackerConfig {
+            refresh_interval: REFRESH_INTERVAL,
+            debounce_interval: REFRESH_INTERVAL,
+        };
+        run_test_with_config(config, NoPayload, scenario).await;
+    }
+
+    async fn run_test_with_config<P, F, S>(
+        config: ChainTrackerConfig,
+        payload_provider: impl PayloadProvider<Payload = P>,
+        scenario: S,
+    ) where
+        P: Payload,
+        F: Future,
+        S: FnOnce(ClientChainBuilder, DataProvider<Block, P>) -> F,
+    {
+        let (task_handle, exit, chain_builder, data_provider) =
+            prepare_chain_tracker_test(config, payload_provider);
         let chain_tracker_handle = tokio::spawn(task_handle);
 
         scenario(chain_builder, data_provider).await;
@@ -411,13 +501,14 @@ mod tests {
             );
 
             let blocks = chain_builder
-                .initialize_single_branch_and_import(2 * MAX_DATA_BRANCH_LEN)
+                .initialize_single_branch_and_import(2 * DEFAULT_MAX_DATA_BRANCH_LEN)
                 .await;
 
             sleep_enough().await;
 
             let data = data_provider.get_data().await.unwrap();
-            let expected_data = dagestan_data_from_blocks(blocks[..MAX_DATA_BRANCH_LEN].to_vec());
+            let expected_data =
+                dagestan_data_from_blocks(blocks[..DEFAULT_MAX_DATA_BRANCH_LEN].to_vec());
             assert_eq!(data, expected_data);
         })
         .await;
@@ -427,14 +518,15 @@ mod tests {
     async fn proposal_changes_with_finalization() {
         run_test(|mut chain_builder, mut data_provider| async move {
             let blocks = chain_builder
-                .initialize_single_branch_and_import(3 * MAX_DATA_BRANCH_LEN)
+                .initialize_single_branch_and_import(3 * DEFAULT_MAX_DATA_BRANCH_LEN)
                 .await;
-            for height in 1..(2 * MAX_DATA_BRANCH_LEN) {
+            for height in 1..(2 * DEFAULT_MAX_DATA_BRANCH_LEN) {
                 chain_builder.finalize_block(&blocks[height - 1].header.hash());
                 sleep_enough().await;
                 let data = data_provider.get_data().await.unwrap();
-                let expected_data =
-                    dagestan_data_from_blocks(blocks[height..(MAX_DATA_BRANCH_LEN + height)].to_vec());
+                let expected_data = dagestan_data_from_blocks(
+                    blocks[height..(DEFAULT_MAX_DATA_BRANCH_LEN + height)].to_vec(),
+                );
                 assert_eq!(data, expected_data);
             }
             chain_builder.finalize_block(&blocks.last().unwrap().header.hash());
@@ -448,17 +540,82 @@ mod tests {
         .await;
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn follows_chain_notifications_without_polling() {
+        let config = ChainTrackerConfig {
+            refresh_interval: NEVER,
+            debounce_interval: REFRESH_INTERVAL,
+        };
+        run_test_with_config(
+            config,
+            NoPayload,
+            |mut chain_builder, mut data_provider| async move {
+                let blocks = chain_builder
+                    .initialize_single_branch_and_import(2 * DEFAULT_MAX_DATA_BRANCH_LEN)
+                    .await;
+                sleep_enough().await;
+                let data = data_provider.get_data().await.unwrap();
+                let expected_data =
+                    dagestan_data_from_blocks(blocks[..DEFAULT_MAX_DATA_BRANCH_LEN].to_vec());
+                assert_eq!(data, expected_data);
+
+                chain_builder.finalize_block(&blocks[0].header.hash());
+                sleep_enough().await;
+                let data = data_provider.get_data().await.unwrap();
+                let expected_data = dagestan_data_from_blocks(
+                    blocks[1..(DEFAULT_MAX_DATA_BRANCH_LEN + 1)].to_vec(),
+                );
+                assert_eq!(data, expected_data);
+            },
+        )
+        .await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn attaches_payloads_within_size_limit() {
+        let config = ChainTrackerConfig {
+            refresh_interval: REFRESH_INTERVAL,
+            debounce_interval: REFRESH_INTERVAL,
+        };
+        let payloads = QueuedPayloads(vec![vec![0; MAX_PAYLOAD_SIZE], vec![1, 2, 3]]);
+        run_test_with_config(
+            config,
+            payloads,
+            |mut chain_builder, mut data_provider| async move {
+                let blocks = chain_builder
+                    .initialize_single_branch_and_import(2 * DEFAULT_MAX_DATA_BRANCH_LEN)
+                    .await;
+                sleep_enough().await;
+                let data = data_provider.get_data().await.unwrap();
+                assert_eq!(
+                    data.head_proposal,
+                    dagestan_data_from_blocks(blocks[..DEFAULT_MAX_DATA_BRANCH_LEN].to_vec())
+                        .head_proposal
+                );
+                assert_eq!(data.payload, vec![1, 2, 3]);
+
+                // The next payload is too big to be ordered, so it gets replaced by an empty one.
+                chain_builder.finalize_block(&blocks[0].header.hash());
+                sleep_enough().await;
+                let data = data_provider.get_data().await.unwrap();
+                assert!(data.payload.is_empty());
+            },
+        )
+        .await;
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn returns_empty_proposal_above_session_end() {
         run_test(|mut chain_builder, mut data_provider| async move {
             let blocks = chain_builder
                 .initialize_single_branch_and_import(
-                    (SESSION_LEN as usize) + 3 * MAX_DATA_BRANCH_LEN,
+                    (SESSION_LEN as usize) + 3 * DEFAULT_MAX_DATA_BRANCH_LEN,
                 )
                 .await;
             sleep_enough().await;
             let data = data_provider.get_data().await.unwrap();
-            let expected_data = dagestan_data_from_blocks(blocks[0..MAX_DATA_BRANCH_LEN].to_vec());
+            let expected_data =
+                dagestan_data_from_blocks(blocks[0..DEFAULT_MAX_DATA_BRANCH_LEN].to_vec());
             assert_eq!(data, expected_data);
 
             // Finalize a block beyond the last block in the session.
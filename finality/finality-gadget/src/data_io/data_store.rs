@@ -1,9 +1,10 @@
 use std::{
+    cmp::min,
     collections::{hash_map::Entry::Occupied, BTreeMap, HashMap, HashSet},
     default::Default,
     hash::{Hash, Hasher},
     sync::Arc,
-    time::{self, Duration},
+    time::{self, Duration, Instant},
 };
 
 use futures::{
@@ -16,28 +17,42 @@ use futures::{
 use futures_timer::Delay;
 use log::{debug, error, info, trace, warn};
 use lru::LruCache;
-use sc_client_api::{BlockchainEvents, HeaderBackend};
-use sp_runtime::traits::{Block as BlockT, Header as HeaderT, NumberFor, One};
+use sc_client_api::{BlockBackend, BlockchainEvents, HeaderBackend};
+use sp_runtime::{
+    generic::BlockId,
+    traits::{Block as BlockT, Header as HeaderT, NumberFor, One},
+};
 
 use crate::{
     data_io::{
+        block_fetch::{BlockFetchMessage, MAX_FETCHED_BLOCKS},
         chain_info::{CachedChainInfoProvider, ChainInfoProvider},
-        proposal::{DagestanProposal, ProposalStatus},
+        fits_size_limit,
+        proposal::{DagestanProposal, PendingProposalStatus, ProposalStatus},
         status_provider::get_proposal_status,
-        DagestanNetworkMessage,
+        DagestanNetworkMessage, MAX_PAYLOAD_SIZE,
     },
     network::{
         data::{
             component::{Network as ComponentNetwork, Receiver, SimpleNetwork},
             Network as DataNetwork,
         },
-        RequestBlocks,
+        ImportBlocks, RequestBlocks,
     },
-    BlockHashNum, SessionBoundaries,
+    BlockHashNum, Metrics, NodeIndex, Recipient, SessionBoundaries,
 };
 
 type MessageId = u64;
 
+fn pending_status_label(status: &PendingProposalStatus) -> &'static str {
+    use PendingProposalStatus::*;
+    match status {
+        PendingTopBlock => "pending_top_block",
+        TopBlockImportedButIncorrectBranch => "incorrect_branch",
+        TopBlockImportedButNotFinalizedAncestor => "not_finalized_ancestor",
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum ChainEvent<B: BlockT> {
     Imported(BlockHashNum<B>),
@@ -80,6 +95,10 @@ pub struct PendingProposalInfo<B: BlockT> {
     // When was the first message containing this data item encountered.
     first_occurrence: time::SystemTime,
     status: ProposalStatus<B>,
+    // The member whose unit introduced this data item, if known.
+    creator: Option<NodeIndex>,
+    // Whether we already asked the creator for the blocks of this data item.
+    block_fetch_requested: bool,
 }
 
 impl<B: BlockT> PendingProposalInfo<B> {
@@ -88,6 +107,8 @@ impl<B: BlockT> PendingProposalInfo<B> {
             messages: HashSet::new(),
             first_occurrence: time::SystemTime::now(),
             status,
+            creator: None,
+            block_fetch_requested: false,
         }
     }
 }
@@ -117,6 +138,14 @@ pub struct DataStoreConfig {
     // Specifies how much time must pass from receiving a given proposal for the first time, till we
     // perform a request for either a block or a justification required to let this proposal through.
     pub request_block_after: Duration,
+    // How often the sizes of the pending sets are reported to metrics, counting them takes time
+    // linear in their size.
+    pub metrics_report_interval: Duration,
+    // How many block fetch requests naming a given member we answer per
+    // `block_fetch_response_period`. The requester is not authenticated, so this bounds how many
+    // blocks anyone can make us send to a member.
+    pub max_block_fetch_responses: usize,
+    pub block_fetch_response_period: Duration,
 }
 
 impl Default for DataStoreConfig {
@@ -128,6 +157,9 @@ impl Default for DataStoreConfig {
             available_proposals_cache_capacity: 8000,
             periodic_maintenance_interval: Duration::from_secs(25),
             request_block_after: Duration::from_secs(20),
+            metrics_report_interval: Duration::from_secs(5),
+            max_block_fetch_responses: 20,
+            block_fetch_response_period: Duration::from_secs(25),
         }
     }
 }
@@ -144,6 +176,9 @@ impl Default for DataStoreConfig {
 //    some block `b` of number `num` is finalized and `hash(b) != h`. This is simply a situation in which the proposal
 //    no matter whether honest or not, cannot possibly be applied, as a conflicting block was already finalized.
 // It is possible that both 1) and 2) might be true for some proposals, but that's fine.
+// Payloads carried by `DagestanData<B>` are self-contained, so they are available as soon as the message
+// is. We only require their encoding to take at most `MAX_PAYLOAD_SIZE` bytes, messages containing bigger
+// payloads are dropped.
 //
 // The way DataStore works internally, is roughly as follows:
 // 1) We keep receiving and caching imported and finalized blocks via appropriate subscriptions from the client. It is
@@ -169,19 +204,27 @@ impl Default for DataStoreConfig {
 // 5) Periodically, every `config.periodic_maintenance_interval` time we run "maintenance" which has two purposes:
 //    a) To bump long-pending proposals that for some reason are still in the data store -- maybe because some blocks
 //       were missed by the block import subscription.
-//    b) To explicitly request blocks that are the cause of some proposals pending for a long time.
+//    b) To explicitly request blocks that are the cause of some proposals pending for a long time. We first ask
+//       the member whose unit introduced the proposal, as it is the most likely to have the blocks, sending a block
+//       fetch request over the session network. Only if the blocks are still missing at the next maintenance, we
+//       request them from sync. The blocks we receive in response are passed to the block importer.
+// 6) We respond to the block fetch requests of other members with the blocks we have. The requester named in a
+//    request is not authenticated, so we only send a limited number of responses to each member per period.
 
 /// This component is used for filtering available data for Dagestan Network.
 /// It needs to be started by calling the run method.
-pub struct DataStore<B, C, RB, Message, R>
+pub struct DataStore<B, C, RB, BI, Message, R, BN>
 where
     B: BlockT,
-    C: HeaderBackend<B> + BlockchainEvents<B> + Send + Sync + 'static,
+    C: HeaderBackend<B> + BlockBackend<B> + BlockchainEvents<B> + Send + Sync + 'static,
     RB: RequestBlocks<B> + 'static,
+    BI: ImportBlocks<B>,
     Message:
         DagestanNetworkMessage<B> + std::fmt::Debug + Send + Sync + Clone + codec::Codec + 'static,
     R: Receiver<Message>,
+    BN: DataNetwork<BlockFetchMessage<B>>,
 {
+    node_id: NodeIndex,
     next_free_id: MessageId,
     pending_proposals: HashMap<DagestanProposal<B>, PendingProposalInfo<B>>,
     event_triggers: HashMap<ChainEvent<B>, HashSet<DagestanProposal<B>>>,
@@ -195,27 +238,42 @@ where
     session_boundaries: SessionBoundaries<B>,
     client: Arc<C>,
     block_requester: RB,
+    block_importer: BI,
     config: DataStoreConfig,
     messages_from_network: R,
     messages_for_dagestan: UnboundedSender<Message>,
+    block_fetch_network: BN,
+    // When the current response period of a requester started and how many of its requests we
+    // answered since then.
+    block_fetch_responses: HashMap<NodeIndex, (Instant, usize)>,
+    metrics: Option<Metrics<<B::Header as HeaderT>::Hash>>,
 }
 
-impl<B, C, RB, Message, R> DataStore<B, C, RB, Message, R>
+impl<B, C, RB, BI, Message, R, BN> DataStore<B, C, RB, BI, Message, R, BN>
 where
     B: BlockT,
-    C: HeaderBackend<B> + BlockchainEvents<B> + Send + Sync + 'static,
+    C: HeaderBackend<B> + BlockBackend<B> + BlockchainEvents<B> + Send + Sync + 'static,
     RB: RequestBlocks<B> + 'static,
+    BI: ImportBlocks<B>,
     Message:
         DagestanNetworkMessage<B> + std::fmt::Debug + Send + Sync + Clone + codec::Codec + 'static,
     R: Receiver<Message>,
+    BN: DataNetwork<BlockFetchMessage<B>>,
 {
-    /// Returns a struct to be run and a network that outputs messages filtered as appropriate
+    /// Returns a struct to be run and a network that outputs messages filtered as appropriate.
+    /// The blocks of pending proposals are fetched from other members over `block_fetch_network`
+    /// and imported with `block_importer`.
+    #[allow(clippy::too_many_arguments)]
     pub fn new<N: ComponentNetwork<Message, R = R>>(
+        node_id: NodeIndex,
         session_boundaries: SessionBoundaries<B>,
         client: Arc<C>,
         block_requester: RB,
+        block_importer: BI,
         config: DataStoreConfig,
         component_network: N,
+        block_fetch_network: BN,
+        metrics: Option<Metrics<<B::Header as HeaderT>::Hash>>,
     ) -> (Self, impl DataNetwork<Message>) {
         let (messages_for_dagestan, messages_from_data_store) = mpsc::unbounded();
         let (messages_to_network, messages_from_network) = component_network.into();
@@ -225,6 +283,7 @@ where
         let highest_finalized_num = status.finalized_number;
         (
             DataStore {
+                node_id,
                 next_free_id: 0,
                 pending_proposals: HashMap::new(),
                 event_triggers: HashMap::new(),
@@ -236,9 +295,13 @@ where
                 session_boundaries,
                 client,
                 block_requester,
+                block_importer,
                 config,
                 messages_from_network,
                 messages_for_dagestan,
+                block_fetch_network,
+                block_fetch_responses: HashMap::new(),
+                metrics,
             },
             SimpleNetwork::new(messages_from_data_store, messages_to_network),
         )
@@ -246,6 +309,7 @@ where
 
     pub async fn run(&mut self, mut exit: oneshot::Receiver<()>) {
         let mut maintenance_clock = Delay::new(self.config.periodic_maintenance_interval);
+        let mut metrics_clock = Delay::new(self.config.metrics_report_interval);
         let mut import_stream = self.client.import_notification_stream();
         let mut finality_stream = self.client.finality_notification_stream();
         loop {
@@ -256,6 +320,10 @@ where
                     trace!(target: "dagestan-data-store", "Received message at Data Store {:?}", message);
                     self.on_message_received(message);
                 }
+                Some(message) = self.block_fetch_network.next() => {
+                    trace!(target: "dagestan-data-store", "Received block fetch message at Data Store {:?}", message);
+                    self.on_block_fetch_message(message);
+                }
                 Some(block) = &mut import_stream.next() => {
                     trace!(target: "dagestan-data-store", "Block import notification at Data Store for block {:?}", block);
                     self.on_block_imported((block.header.hash(), *block.header.number()).into());
@@ -268,12 +336,58 @@ where
                     self.run_maintenance();
                     maintenance_clock = Delay::new(self.config.periodic_maintenance_interval);
                 }
+                _ = &mut metrics_clock => {
+                    self.report_metrics();
+                    metrics_clock = Delay::new(self.config.metrics_report_interval);
+                }
                 _ = &mut exit => {
                     debug!(target: "dagestan-data-store", "Data Store task received exit signal. Terminating.");
                     break;
                 }
             }
         }
+        self.reset_metrics();
+    }
+
+    fn report_metrics(&self) {
+        let metrics = match &self.metrics {
+            Some(metrics) => metrics.data_store(),
+            None => return,
+        };
+        use PendingProposalStatus::*;
+        let mut pending_proposals = HashMap::from([
+            (PendingTopBlock, 0),
+            (TopBlockImportedButIncorrectBranch, 0),
+            (TopBlockImportedButNotFinalizedAncestor, 0),
+        ]);
+        for info in self.pending_proposals.values() {
+            if let ProposalStatus::Pending(status) = &info.status {
+                *pending_proposals.entry(status.clone()).or_default() += 1;
+            }
+        }
+        for (status, count) in pending_proposals {
+            metrics.set_pending_proposals(pending_status_label(&status), count);
+        }
+        metrics.set_pending_messages(self.pending_messages.len());
+        metrics.set_cached_available_proposals(self.available_proposals_cache.len());
+    }
+
+    // Zeroes the gauges, so that they do not keep reporting the state of a session that is over.
+    fn reset_metrics(&self) {
+        let metrics = match &self.metrics {
+            Some(metrics) => metrics.data_store(),
+            None => return,
+        };
+        use PendingProposalStatus::*;
+        for status in [
+            PendingTopBlock,
+            TopBlockImportedButIncorrectBranch,
+            TopBlockImportedButNotFinalizedAncestor,
+        ] {
+            metrics.set_pending_proposals(pending_status_label(&status), 0);
+        }
+        metrics.set_pending_messages(0);
+        metrics.set_cached_available_proposals(0);
     }
 
     // Updates our highest known and highest finalized block info directly from the client.
@@ -285,6 +399,7 @@ where
 
     fn run_maintenance(&mut self) {
         self.update_highest_finalized();
+        self.prune_block_fetch_responses();
 
         let proposals_with_timestamps: Vec<_> = self
             .pending_proposals
@@ -316,9 +431,15 @@ where
 
             let block = proposal.top_block();
             if !self.chain_info_provider.is_block_imported(&block) {
+                if self.request_block_fetch(&proposal) {
+                    continue;
+                }
                 debug!(target: "dagestan-data-store", "Requesting a stale block {:?} after it has been missing for {:?} secs.", block, time_waiting.as_secs());
                 self.block_requester
                     .request_stale_block(block.hash, block.num);
+                if let Some(metrics) = &self.metrics {
+                    metrics.data_store().report_block_request();
+                }
                 continue;
             }
             // The top block (thus the whole branch, in the honest case) has been imported. What's holding us
@@ -346,8 +467,143 @@ where
                         after it has been missing for {:?} secs.", parent_num, parent_hash, time_waiting.as_secs());
                 self.block_requester
                     .request_justification(&parent_hash, parent_num);
+                if let Some(metrics) = &self.metrics {
+                    metrics.data_store().report_justification_request();
+                }
+            }
+        }
+    }
+
+    // Asks the member that proposed `proposal` for its branch, unless we already did or the creator
+    // of the proposal is unknown. Returns whether the request was sent, if not we should turn to sync.
+    fn request_block_fetch(&mut self, proposal: &DagestanProposal<B>) -> bool {
+        let creator = match self.pending_proposals.get_mut(proposal) {
+            Some(info) if !info.block_fetch_requested => {
+                info.block_fetch_requested = true;
+                info.creator
+            }
+            _ => None,
+        };
+        let creator = match creator {
+            Some(creator) if creator != self.node_id => creator,
+            _ => return false,
+        };
+        let request = BlockFetchMessage::Request {
+            requester: self.node_id,
+            hash: proposal.top_block().hash,
+            branch_len: proposal.len() as u32,
+        };
+        match self
+            .block_fetch_network
+            .send(request, Recipient::Node(creator))
+        {
+            Ok(()) => {
+                debug!(target: "dagestan-data-store", "Requesting the blocks of {:?} from its creator {:?}.", proposal, creator);
+                true
+            }
+            Err(e) => {
+                warn!(target: "dagestan-data-store", "Failed to request the blocks of {:?} from its creator {:?}: {:?}.", proposal, creator, e);
+                false
+            }
+        }
+    }
+
+    fn on_block_fetch_message(&mut self, message: BlockFetchMessage<B>) {
+        match message {
+            BlockFetchMessage::Request {
+                requester,
+                hash,
+                branch_len,
+            } => self.on_block_fetch_request(requester, hash, branch_len),
+            BlockFetchMessage::Response(blocks) => self.on_block_fetch_response(blocks),
+        }
+    }
+
+    // Responds with the requested block and as many of its ancestors as we have, up to the
+    // requested branch length. Requests naming us are dropped, as are the ones over the response
+    // limit of the requester.
+    fn on_block_fetch_request(&mut self, requester: NodeIndex, hash: B::Hash, branch_len: u32) {
+        if requester == self.node_id {
+            debug!(target: "dagestan-data-store", "Dropping a block fetch request for {:?} naming us as the requester.", hash);
+            return;
+        }
+        let mut blocks = Vec::new();
+        let mut next_hash = hash;
+        while blocks.len() < min(branch_len as usize, MAX_FETCHED_BLOCKS) {
+            match self.client.block(&BlockId::Hash(next_hash)) {
+                Ok(Some(signed_block)) => {
+                    next_hash = *signed_block.block.header().parent_hash();
+                    blocks.push(signed_block.block);
+                }
+                _ => break,
             }
         }
+        if blocks.is_empty() {
+            debug!(target: "dagestan-data-store", "Not responding to the block fetch request of {:?} for the unknown block {:?}.", requester, hash);
+            return;
+        }
+        if !self.note_block_fetch_response(requester) {
+            debug!(target: "dagestan-data-store", "Not responding to the block fetch request of {:?} for {:?}, too many requests.", requester, hash);
+            return;
+        }
+        blocks.reverse();
+        if let Err(e) = self.block_fetch_network.send(
+            BlockFetchMessage::Response(blocks),
+            Recipient::Node(requester),
+        ) {
+            warn!(target: "dagestan-data-store", "Failed to respond to the block fetch request of {:?}: {:?}.", requester, e);
+        }
+    }
+
+    // Counts a response to `requester`, returns false if it would exceed the limit of the current
+    // response period.
+    fn note_block_fetch_response(&mut self, requester: NodeIndex) -> bool {
+        let now = Instant::now();
+        let period = self.config.block_fetch_response_period;
+        let (period_start, responses) = self
+            .block_fetch_responses
+            .entry(requester)
+            .or_insert((now, 0));
+        if now.duration_since(*period_start) >= period {
+            *period_start = now;
+            *responses = 0;
+        }
+        if *responses >= self.config.max_block_fetch_responses {
+            return false;
+        }
+        *responses += 1;
+        true
+    }
+
+    fn prune_block_fetch_responses(&mut self) {
+        let period = self.config.block_fetch_response_period;
+        self.block_fetch_responses
+            .retain(|_, (period_start, _)| period_start.elapsed() < period);
+    }
+
+    // Passes the fetched blocks on to be imported, as long as they form the branch of a proposal
+    // whose blocks we requested.
+    fn on_block_fetch_response(&self, blocks: Vec<B>) {
+        let top_hash = match blocks.last() {
+            Some(block) => block.header().hash(),
+            None => return,
+        };
+        let requested = self.pending_proposals.iter().any(|(proposal, info)| {
+            info.block_fetch_requested && proposal.top_block().hash == top_hash
+        });
+        if !requested || blocks.len() > MAX_FETCHED_BLOCKS {
+            debug!(target: "dagestan-data-store", "Ignoring {} fetched blocks up to {:?}, we did not request them.", blocks.len(), top_hash);
+            return;
+        }
+        let is_branch = blocks
+            .windows(2)
+            .all(|pair| pair[1].header().parent_hash() == &pair[0].header().hash());
+        if !is_branch {
+            warn!(target: "dagestan-data-store", "Ignoring fetched blocks up to {:?}, they do not form a branch.", top_hash);
+            return;
+        }
+        debug!(target: "dagestan-data-store", "Importing {} fetched blocks up to {:?}.", blocks.len(), top_hash);
+        self.block_importer.import_blocks(blocks);
     }
 
     fn register_block_import_trigger(
@@ -607,13 +863,21 @@ where
     // Checks if we have exceeded the maximum number of pending messages or proposals.
     // If so, we prune messages until the limits are satisfied again.
     fn prune_pending_messages(&mut self) {
-        while self.pending_messages.len() > self.config.max_messages_pending
-            || self.pending_proposals.len() > self.config.max_proposals_pending
-        {
+        loop {
+            let exceeded_limit = if self.pending_messages.len() > self.config.max_messages_pending {
+                "messages"
+            } else if self.pending_proposals.len() > self.config.max_proposals_pending {
+                "proposals"
+            } else {
+                break;
+            };
             if !self.prune_single_message() {
                 warn!(target: "dagestan-data-store", "Message pruning in DataStore failed. Moving on.");
                 break;
             }
+            if let Some(metrics) = &self.metrics {
+                metrics.data_store().report_pruned_message(exceeded_limit);
+            }
         }
     }
 
@@ -633,6 +897,11 @@ where
     fn on_message_received(&mut self, message: Message) {
         let mut proposals = Vec::new();
         for data in message.included_data() {
+            if !fits_size_limit(&data.payload) {
+                warn!(target: "dagestan-data-store", "Message {:?} dropped as it contains \
+                        a payload bigger than {} bytes.", message, MAX_PAYLOAD_SIZE);
+                return;
+            }
             let unvalidated_proposal = data.head_proposal;
             match unvalidated_proposal.validate_bounds(&self.session_boundaries) {
                 Ok(proposal) => proposals.push(proposal),
@@ -653,7 +922,22 @@ where
         if message_info.pending_proposals.is_empty() {
             self.on_message_dependencies_resolved(message);
         } else {
+            self.record_proposal_creators(&message);
             self.pending_messages.insert(message_id, message_info);
         }
     }
+
+    // Remembers the members that proposed the pending proposals of the message, the first ones
+    // we learn about are kept.
+    fn record_proposal_creators(&mut self, message: &Message) {
+        for (data, creator) in message.data_with_creators() {
+            let proposal = match data.head_proposal.validate_bounds(&self.session_boundaries) {
+                Ok(proposal) => proposal,
+                Err(_) => continue,
+            };
+            if let Some(info) = self.pending_proposals.get_mut(&proposal) {
+                info.creator.get_or_insert(creator);
+            }
+        }
+    }
 }
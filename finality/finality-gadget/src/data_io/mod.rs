@@ -6,48 +6,69 @@ use std::{
 use codec::{Decode, Encode};
 use sp_runtime::traits::Block as BlockT;
 
+use crate::NodeIndex;
+
+mod block_fetch;
 mod chain_info;
 mod data_interpreter;
 mod data_provider;
 mod data_store;
+mod payload;
 mod proposal;
 mod status_provider;
 
+pub use block_fetch::{BlockFetchMessage, MAX_FETCHED_BLOCKS};
 pub use chain_info::ChainInfoProvider;
 pub use data_interpreter::OrderedDataInterpreter;
 pub use data_provider::{ChainTracker, DataProvider};
 pub use data_store::{DataStore, DataStoreConfig};
+pub use payload::{
+    fits_size_limit, NoPayload, Payload, PayloadProvider, PayloadSink, MAX_PAYLOAD_SIZE,
+};
 pub use proposal::UnvalidatedDagestanProposal;
 
-// Maximum number of blocks above the last finalized allowed in an AlephBFT proposal.
-pub const MAX_DATA_BRANCH_LEN: usize = 7;
+// Maximum number of blocks above the last finalized allowed in an AlephBFT proposal, in sessions
+// that do not configure it through the runtime.
+pub const DEFAULT_MAX_DATA_BRANCH_LEN: usize =
+    dagestan_primitives::DEFAULT_MAX_DATA_BRANCH_LEN as usize;
 
-/// The data ordered by the Dagestan consensus.
+/// The data ordered by the Dagestan consensus. The empty payload encodes to nothing, so data
+/// without payloads is encoded exactly as before payloads were introduced.
 #[derive(Clone, Debug, Encode, Decode)]
-pub struct DagestanData<B: BlockT> {
+pub struct DagestanData<B: BlockT, P: Payload = ()> {
     pub head_proposal: UnvalidatedDagestanProposal<B>,
+    pub payload: P,
 }
 
 // Need to be implemented manually, as deriving does not work (`BlockT` is not `Hash`).
-impl<B: BlockT> Hash for DagestanData<B> {
+impl<B: BlockT, P: Payload> Hash for DagestanData<B, P> {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.head_proposal.hash(state);
+        self.payload.hash(state);
     }
 }
 
 // Clippy does not allow deriving PartialEq when implementing Hash manually
-impl<B: BlockT> PartialEq for DagestanData<B> {
+impl<B: BlockT, P: Payload> PartialEq for DagestanData<B, P> {
     fn eq(&self, other: &Self) -> bool {
-        self.head_proposal.eq(&other.head_proposal)
+        self.head_proposal.eq(&other.head_proposal) && self.payload.eq(&other.payload)
     }
 }
 
-impl<B: BlockT> Eq for DagestanData<B> {}
+impl<B: BlockT, P: Payload> Eq for DagestanData<B, P> {}
 
 /// A trait allowing to check the data contained in an AlephBFT network message, for the purpose of
 /// data availability checks.
 pub trait DagestanNetworkMessage<B: BlockT>: Clone + Debug {
-    fn included_data(&self) -> Vec<DagestanData<B>>;
+    type Payload: Payload;
+
+    fn included_data(&self) -> Vec<DagestanData<B, Self::Payload>>;
+
+    /// The data of the units carried by the message, together with the creators of these units.
+    /// Might miss some of the included data, e.g. the data of units contained in alerts.
+    /// Finding the creators takes decoding the whole message, so it should only be done for
+    /// messages that cannot be passed on right away.
+    fn data_with_creators(&self) -> Vec<(DagestanData<B, Self::Payload>, NodeIndex)>;
 }
 
 #[derive(Clone, Debug)]
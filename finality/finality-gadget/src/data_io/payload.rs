@@ -0,0 +1,82 @@
+use std::{fmt::Debug, hash::Hash};
+
+use codec::{Codec, Encode};
+
+/// The maximal size of an encoded payload. Messages containing data with bigger payloads are
+/// considered unavailable and dropped, and we never propose such payloads ourselves.
+pub const MAX_PAYLOAD_SIZE: usize = 64 * 1024;
+
+/// Application data ordered by Dagestan alongside the head proposals, e.g. the inputs of an
+/// off-chain sequencer. The default value means there is nothing to order.
+///
+/// Payloads have to be self-contained -- contrary to head proposals they do not refer to anything
+/// outside the unit, so the only data availability condition they are subject to is the size
+/// limit, checked by `fits_size_limit`.
+pub trait Payload: Clone + Debug + Default + Hash + Eq + Codec + Send + Sync + 'static {}
+
+impl<T: Clone + Debug + Default + Hash + Eq + Codec + Send + Sync + 'static> Payload for T {}
+
+/// Whether the payload is small enough to be ordered.
+pub fn fits_size_limit<P: Payload>(payload: &P) -> bool {
+    payload.encoded_size() <= MAX_PAYLOAD_SIZE
+}
+
+/// Provides the payloads to include in our units. Called whenever AlephBFT asks for data, so it
+/// should return immediately.
+pub trait PayloadProvider: Send + Sync + 'static {
+    type Payload: Payload;
+
+    fn next_payload(&mut self) -> Self::Payload;
+}
+
+impl<T: PayloadProvider + ?Sized> PayloadProvider for Box<T> {
+    type Payload = T::Payload;
+
+    fn next_payload(&mut self) -> Self::Payload {
+        (**self).next_payload()
+    }
+}
+
+/// Receives the payloads in the order decided by Dagestan, one for every ordered piece of data.
+pub trait PayloadSink: Send + Sync + 'static {
+    type Payload: Payload;
+
+    fn payload_ordered(&mut self, payload: Self::Payload);
+}
+
+impl<T: PayloadSink + ?Sized> PayloadSink for Box<T> {
+    type Payload = T::Payload;
+
+    fn payload_ordered(&mut self, payload: Self::Payload) {
+        (**self).payload_ordered(payload)
+    }
+}
+
+/// Used when there are no payloads to order, which is the default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoPayload;
+
+impl PayloadProvider for NoPayload {
+    type Payload = ();
+
+    fn next_payload(&mut self) {}
+}
+
+impl PayloadSink for NoPayload {
+    type Payload = ();
+
+    fn payload_ordered(&mut self, _payload: ()) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fits_size_limit, MAX_PAYLOAD_SIZE};
+
+    #[test]
+    fn payloads_over_the_limit_do_not_fit() {
+        assert!(fits_size_limit(&()));
+        // The length prefix of a vector takes a few bytes on its own.
+        assert!(fits_size_limit(&vec![0u8; MAX_PAYLOAD_SIZE - 4]));
+        assert!(!fits_size_limit(&vec![0u8; MAX_PAYLOAD_SIZE]));
+    }
+}
@@ -10,7 +10,7 @@ use sp_runtime::{
     SaturatedConversion,
 };
 
-use crate::{data_io::MAX_DATA_BRANCH_LEN, BlockHashNum, SessionBoundaries};
+use crate::{BlockHashNum, SessionBoundaries};
 
 /// Represents a proposal we obtain from another node. Note that since the proposal might come from
 /// a malicious node there is no guarantee that the block hashes in the proposal correspond to real blocks
@@ -82,7 +82,7 @@ impl<B: BlockT> UnvalidatedDagestanProposal<B> {
     ) -> Result<DagestanProposal<B>, ValidationError<B>> {
         use ValidationError::*;
 
-        if self.branch.len() > MAX_DATA_BRANCH_LEN {
+        if self.branch.len() > session_boundaries.max_branch_len() {
             return Err(BranchTooLong {
                 branch_size: self.branch.len(),
             });
@@ -221,7 +221,7 @@ impl<B: BlockT> DagestanProposal<B> {
     }
 }
 
-#[derive(PartialEq, Eq, Clone, Debug)]
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
 pub enum PendingProposalStatus {
     PendingTopBlock,
     TopBlockImportedButIncorrectBranch,
@@ -241,7 +241,9 @@ mod tests {
     use substrate_test_runtime_client::runtime::Block;
 
     use super::{UnvalidatedDagestanProposal, ValidationError::*};
-    use crate::{data_io::MAX_DATA_BRANCH_LEN, SessionBoundaries, SessionId, SessionPeriod};
+    use crate::{
+        data_io::DEFAULT_MAX_DATA_BRANCH_LEN, SessionBoundaries, SessionId, SessionPeriod,
+    };
 
     #[test]
     fn proposal_with_empty_branch_is_invalid() {
@@ -258,7 +260,7 @@ mod tests {
     fn too_long_proposal_is_invalid() {
         let session_boundaries = SessionBoundaries::<Block>::new(SessionId(1), SessionPeriod(20));
         let session_end = session_boundaries.last_block();
-        let branch = vec![H256::default(); MAX_DATA_BRANCH_LEN + 1];
+        let branch = vec![H256::default(); DEFAULT_MAX_DATA_BRANCH_LEN + 1];
         let branch_size = branch.len();
         let proposal = UnvalidatedDagestanProposal::new(branch, session_end);
         assert_eq!(
@@ -316,12 +318,35 @@ mod tests {
     fn valid_proposal_is_validated_positively() {
         let session_boundaries = SessionBoundaries::<Block>::new(SessionId(0), SessionPeriod(20));
 
-        let branch = vec![H256::default(); MAX_DATA_BRANCH_LEN];
-        let proposal = UnvalidatedDagestanProposal::new(branch, (MAX_DATA_BRANCH_LEN + 1) as u64);
+        let branch = vec![H256::default(); DEFAULT_MAX_DATA_BRANCH_LEN];
+        let proposal =
+            UnvalidatedDagestanProposal::new(branch, (DEFAULT_MAX_DATA_BRANCH_LEN + 1) as u64);
         assert!(proposal.validate_bounds(&session_boundaries).is_ok());
 
         let branch = vec![H256::default(); 1];
-        let proposal = UnvalidatedDagestanProposal::new(branch, (MAX_DATA_BRANCH_LEN + 1) as u64);
+        let proposal =
+            UnvalidatedDagestanProposal::new(branch, (DEFAULT_MAX_DATA_BRANCH_LEN + 1) as u64);
+        assert!(proposal.validate_bounds(&session_boundaries).is_ok());
+    }
+
+    #[test]
+    fn branch_length_limit_follows_session_configuration() {
+        let max_branch_len = 2 * DEFAULT_MAX_DATA_BRANCH_LEN;
+        let session_boundaries = SessionBoundaries::<Block>::new(SessionId(1), SessionPeriod(40))
+            .with_max_branch_len(max_branch_len);
+        let session_end = session_boundaries.last_block();
+
+        let branch = vec![H256::default(); max_branch_len];
+        let proposal = UnvalidatedDagestanProposal::new(branch, session_end);
         assert!(proposal.validate_bounds(&session_boundaries).is_ok());
+
+        let branch = vec![H256::default(); max_branch_len + 1];
+        let proposal = UnvalidatedDagestanProposal::new(branch, session_end);
+        assert_eq!(
+            proposal.validate_bounds(&session_boundaries),
+            Err(BranchTooLong {
+                branch_size: max_branch_len + 1
+            })
+        );
     }
 }
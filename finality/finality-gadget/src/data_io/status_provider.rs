@@ -169,7 +169,7 @@ mod tests {
                 ProposalStatus::{self, *},
             },
             status_provider::get_proposal_status,
-            ChainInfoCacheConfig, MAX_DATA_BRANCH_LEN,
+            ChainInfoCacheConfig, DEFAULT_MAX_DATA_BRANCH_LEN,
         },
         testing::{
             client_chain_builder::ClientChainBuilder, mocks::unvalidated_proposal_from_headers,
@@ -241,7 +241,7 @@ mod tests {
         cached_cip: &mut TestCachedChainInfo,
         aux_cip: &mut TestAuxChainInfo,
     ) {
-        for len in 1..=MAX_DATA_BRANCH_LEN {
+        for len in 1..=DEFAULT_MAX_DATA_BRANCH_LEN {
             let blocks_branch = blocks[0..len].to_vec();
             let proposal = proposal_from_blocks(blocks_branch);
             verify_proposal_status(
@@ -257,13 +257,16 @@ mod tests {
     async fn correct_proposals_are_finalizable_even_with_forks() {
         let (mut chain_builder, mut cached_cip, mut aux_cip) = prepare_proposal_test();
         let blocks = chain_builder
-            .initialize_single_branch_and_import(MAX_DATA_BRANCH_LEN * 10)
+            .initialize_single_branch_and_import(DEFAULT_MAX_DATA_BRANCH_LEN * 10)
             .await;
 
         verify_proposal_of_all_lens_finalizable(blocks.clone(), &mut cached_cip, &mut aux_cip);
 
         let _fork = chain_builder
-            .build_and_import_branch_above(&blocks[2].header.hash(), MAX_DATA_BRANCH_LEN * 10)
+            .build_and_import_branch_above(
+                &blocks[2].header.hash(),
+                DEFAULT_MAX_DATA_BRANCH_LEN * 10,
+            )
             .await;
 
         verify_proposal_of_all_lens_finalizable(blocks.clone(), &mut cached_cip, &mut aux_cip);
@@ -273,14 +276,17 @@ mod tests {
     async fn not_finalized_ancestors_handled_correctly() {
         let (mut chain_builder, mut cached_cip, mut aux_cip) = prepare_proposal_test();
         let blocks = chain_builder
-            .initialize_single_branch_and_import(MAX_DATA_BRANCH_LEN * 10)
+            .initialize_single_branch_and_import(DEFAULT_MAX_DATA_BRANCH_LEN * 10)
             .await;
 
         let fork = chain_builder
-            .build_and_import_branch_above(&blocks[2].header.hash(), MAX_DATA_BRANCH_LEN * 10)
+            .build_and_import_branch_above(
+                &blocks[2].header.hash(),
+                DEFAULT_MAX_DATA_BRANCH_LEN * 10,
+            )
             .await;
 
-        for len in 1..=MAX_DATA_BRANCH_LEN {
+        for len in 1..=DEFAULT_MAX_DATA_BRANCH_LEN {
             let blocks_branch = blocks[1..(len + 1)].to_vec();
             let proposal = proposal_from_blocks(blocks_branch);
             verify_proposal_status(
@@ -304,7 +310,7 @@ mod tests {
     async fn incorrect_branch_handled_correctly() {
         let (mut chain_builder, mut cached_cip, mut aux_cip) = prepare_proposal_test();
         let blocks = chain_builder
-            .initialize_single_branch_and_import(MAX_DATA_BRANCH_LEN * 10)
+            .initialize_single_branch_and_import(DEFAULT_MAX_DATA_BRANCH_LEN * 10)
             .await;
 
         let incorrect_branch = vec![
@@ -337,10 +343,10 @@ mod tests {
     async fn pending_top_block_handled_correctly() {
         let (mut chain_builder, mut cached_cip, mut aux_cip) = prepare_proposal_test();
         let blocks = chain_builder
-            .initialize_single_branch(MAX_DATA_BRANCH_LEN * 10)
+            .initialize_single_branch(DEFAULT_MAX_DATA_BRANCH_LEN * 10)
             .await;
 
-        for len in 1..=MAX_DATA_BRANCH_LEN {
+        for len in 1..=DEFAULT_MAX_DATA_BRANCH_LEN {
             let blocks_branch = blocks[0..len].to_vec();
             let proposal = proposal_from_blocks(blocks_branch);
             verify_proposal_status(
@@ -359,14 +365,14 @@ mod tests {
     async fn hopeless_forks_handled_correctly() {
         let (mut chain_builder, mut cached_cip, mut aux_cip) = prepare_proposal_test();
         let blocks = chain_builder
-            .initialize_single_branch_and_import(MAX_DATA_BRANCH_LEN * 10)
+            .initialize_single_branch_and_import(DEFAULT_MAX_DATA_BRANCH_LEN * 10)
             .await;
 
         let fork = chain_builder
-            .build_branch_above(&blocks[2].header.hash(), MAX_DATA_BRANCH_LEN * 10)
+            .build_branch_above(&blocks[2].header.hash(), DEFAULT_MAX_DATA_BRANCH_LEN * 10)
             .await;
 
-        for len in 1..=MAX_DATA_BRANCH_LEN {
+        for len in 1..=DEFAULT_MAX_DATA_BRANCH_LEN {
             let fork_branch = fork[0..len].to_vec();
             let proposal = proposal_from_blocks(fork_branch);
             verify_proposal_status(
@@ -379,7 +385,7 @@ mod tests {
 
         chain_builder.finalize_block(&blocks[2].header.hash());
 
-        for len in 1..=MAX_DATA_BRANCH_LEN {
+        for len in 1..=DEFAULT_MAX_DATA_BRANCH_LEN {
             let fork_branch = fork[0..len].to_vec();
             let proposal = proposal_from_blocks(fork_branch);
             verify_proposal_status(
@@ -392,7 +398,7 @@ mod tests {
 
         chain_builder.finalize_block(&blocks[3].header.hash());
 
-        for len in 1..=MAX_DATA_BRANCH_LEN {
+        for len in 1..=DEFAULT_MAX_DATA_BRANCH_LEN {
             let fork_branch = fork[0..len].to_vec();
             let proposal = proposal_from_blocks(fork_branch);
             verify_proposal_status(&mut cached_cip, &mut aux_cip, &proposal, Ignore);
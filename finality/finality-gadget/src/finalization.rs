@@ -1,18 +1,21 @@
 use core::result::Result;
 use std::{marker::PhantomData, sync::Arc};
 
+use dagestan_primitives::{engine_id, DagestanSessionApi, Version, DEFAULT_FINALITY_VERSION};
 use log::{debug, warn};
 use sc_client_api::{Backend, Finalizer, HeaderBackend, LockImportRun};
-use sp_api::{BlockId, NumberFor};
+use sp_api::{BlockId, NumberFor, ProvideRuntimeApi};
 use sp_blockchain::Error;
-use sp_runtime::{traits::Block, Justification};
+use sp_runtime::traits::Block;
+
+use crate::justification::{versioned_encode, DagestanJustification};
 
 pub trait BlockFinalizer<B: Block> {
     fn finalize_block(
         &self,
         hash: B::Hash,
         block_number: NumberFor<B>,
-        justification: Option<Justification>,
+        justification: Option<DagestanJustification>,
     ) -> Result<(), Error>;
 }
 
@@ -20,7 +23,8 @@ pub struct DagestanFinalizer<B, BE, C>
 where
     B: Block,
     BE: Backend<B>,
-    C: HeaderBackend<B> + LockImportRun<B, BE> + Finalizer<B, BE>,
+    C: HeaderBackend<B> + LockImportRun<B, BE> + Finalizer<B, BE> + ProvideRuntimeApi<B>,
+    C::Api: DagestanSessionApi<B>,
 {
     client: Arc<C>,
     phantom: PhantomData<(B, BE)>,
@@ -30,7 +34,8 @@ impl<B, BE, C> DagestanFinalizer<B, BE, C>
 where
     B: Block,
     BE: Backend<B>,
-    C: HeaderBackend<B> + LockImportRun<B, BE> + Finalizer<B, BE>,
+    C: HeaderBackend<B> + LockImportRun<B, BE> + Finalizer<B, BE> + ProvideRuntimeApi<B>,
+    C::Api: DagestanSessionApi<B>,
 {
     pub(crate) fn new(client: Arc<C>) -> Self {
         DagestanFinalizer {
@@ -38,20 +43,38 @@ where
             phantom: PhantomData,
         }
     }
+
+    /// The finality version in the state of the given block, which determines how its
+    /// justification is stored.
+    fn finality_version(&self, hash: B::Hash) -> Version {
+        self.client
+            .runtime_api()
+            .finality_version(&BlockId::Hash(hash))
+            // Runtimes without the finality version predate every change of the storage format.
+            .unwrap_or(DEFAULT_FINALITY_VERSION)
+    }
 }
 
 impl<B, BE, C> BlockFinalizer<B> for DagestanFinalizer<B, BE, C>
 where
     B: Block,
     BE: Backend<B>,
-    C: HeaderBackend<B> + LockImportRun<B, BE> + Finalizer<B, BE>,
+    C: HeaderBackend<B> + LockImportRun<B, BE> + Finalizer<B, BE> + ProvideRuntimeApi<B>,
+    C::Api: DagestanSessionApi<B>,
 {
     fn finalize_block(
         &self,
         hash: B::Hash,
         block_number: NumberFor<B>,
-        justification: Option<Justification>,
+        justification: Option<DagestanJustification>,
     ) -> Result<(), Error> {
+        let finality_version = self.finality_version(hash);
+        let justification = justification.map(|justification| {
+            (
+                engine_id(finality_version),
+                versioned_encode(justification, finality_version),
+            )
+        });
         let status = self.client.info();
         if status.finalized_number >= block_number {
             warn!(target: "dagestan-finality", "trying to finalize a block with hash {} and number {}
@@ -1,24 +1,37 @@
 use std::{collections::HashMap, marker::PhantomData, sync::Arc, time::Instant};
 
-use dagestan_primitives::DAGESTAN_ENGINE_ID;
+use dagestan_primitives::is_dagestan_engine_id;
 use futures::channel::mpsc::{TrySendError, UnboundedSender};
 use log::{debug, warn};
-use sc_client_api::backend::Backend;
+use sc_client_api::{backend::Backend, BlockBackend, HeaderBackend};
 use sc_consensus::{
     BlockCheckParams, BlockImport, BlockImportParams, ImportResult, JustificationImport,
 };
 use sp_api::TransactionFor;
 use sp_consensus::Error as ConsensusError;
 use sp_runtime::{
+    generic::BlockId,
     traits::{Block as BlockT, Header, NumberFor},
     Justification,
 };
 
 use crate::{
-    justification::{backwards_compatible_decode, DecodeError, JustificationNotification},
+    justification::{
+        backwards_compatible_decode, find_dagestan_justification, DagestanJustification,
+        DecodeError, JustificationNotification, Verifier,
+    },
+    last_block_of_session,
     metrics::{Checkpoint, Metrics},
+    nodes::JustificationVerifier,
+    session_id_from_block_num,
+    session_map::ReadOnlySessionMap,
+    SessionId, SessionPeriod,
 };
 
+/// How many of the most recent sessions are checked for missing justifications on start by
+/// default, unless a session end with a justification is found earlier.
+const DEFAULT_SESSIONS_CHECKED_ON_START: u32 = 1024;
+
 pub struct DagestanBlockImport<Block, Be, I>
 where
     Block: BlockT,
@@ -28,9 +41,41 @@ where
     inner: Arc<I>,
     justification_tx: UnboundedSender<JustificationNotification<Block>>,
     metrics: Option<Metrics<<Block::Header as Header>::Hash>>,
+    session_period: SessionPeriod,
+    sessions_checked_on_start: u32,
+    session_map: Option<ReadOnlySessionMap>,
     _phantom: PhantomData<Be>,
 }
 
+/// What the import knows about a justification.
+#[derive(Debug, PartialEq, Eq)]
+enum JustificationStatus {
+    Valid,
+    Invalid,
+    /// The authorities of the session of the block are not known yet.
+    NotYetVerifiable,
+}
+
+fn check_justification<Block: BlockT>(
+    session_map: &ReadOnlySessionMap,
+    session_period: SessionPeriod,
+    justification: &DagestanJustification,
+    hash: Block::Hash,
+    number: NumberFor<Block>,
+) -> JustificationStatus {
+    let session = session_id_from_block_num::<Block>(number, session_period);
+    let (authority_data, finality_version) =
+        match session_map.try_get_with_finality_version(session) {
+            Some(session_data) => session_data,
+            None => return JustificationStatus::NotYetVerifiable,
+        };
+    let verifier = JustificationVerifier::new(authority_data, finality_version);
+    match Verifier::<Block>::verify(&verifier, justification, hash) {
+        true => JustificationStatus::Valid,
+        false => JustificationStatus::Invalid,
+    }
+}
+
 #[derive(Debug)]
 enum SendJustificationError<Block>
 where
@@ -39,6 +84,7 @@ where
     Send(TrySendError<JustificationNotification<Block>>),
     Consensus(Box<ConsensusError>),
     Decode(DecodeError),
+    Invalid,
 }
 
 impl<Block: BlockT> From<DecodeError> for SendJustificationError<Block> {
@@ -53,33 +99,127 @@ where
     Be: Backend<Block>,
     I: crate::ClientForDagestan<Block, Be>,
 {
+    /// The `session_period` has to be the session period of the chain, it is used to find the
+    /// session end blocks that were imported without a justification. This is a breaking change,
+    /// the block import used to be created without it.
     pub fn new(
         inner: Arc<I>,
         justification_tx: UnboundedSender<JustificationNotification<Block>>,
         metrics: Option<Metrics<<Block::Header as Header>::Hash>>,
+        session_period: SessionPeriod,
     ) -> DagestanBlockImport<Block, Be, I> {
         DagestanBlockImport {
             inner,
             justification_tx,
             metrics,
+            session_period,
+            sessions_checked_on_start: DEFAULT_SESSIONS_CHECKED_ON_START,
+            session_map: None,
             _phantom: PhantomData,
         }
     }
 
-    fn send_justification(
+    /// Sets how many of the most recent sessions are checked for missing justifications on start.
+    pub fn with_sessions_checked_on_start(mut self, sessions: u32) -> Self {
+        self.sessions_checked_on_start = sessions;
+        self
+    }
+
+    /// Makes the import reject invalid justifications, so that the sync can punish the peers
+    /// sending them, using the session map the gadget keeps up to date, see
+    /// `DagestanConfig::session_map`. Valid justifications are still verified again by the
+    /// justification handler.
+    pub fn with_verification(mut self, session_map: ReadOnlySessionMap) -> Self {
+        self.session_map = Some(session_map);
+        self
+    }
+
+    fn justification_status(
+        &self,
+        justification: &DagestanJustification,
+        hash: Block::Hash,
+        number: NumberFor<Block>,
+    ) -> JustificationStatus {
+        match &self.session_map {
+            Some(session_map) => check_justification::<Block>(
+                session_map,
+                self.session_period,
+                justification,
+                hash,
+                number,
+            ),
+            None => JustificationStatus::NotYetVerifiable,
+        }
+    }
+
+    /// The last blocks of sessions, on the best chain and thus finalized ones included, that have
+    /// no Dagestan justification stored, in increasing order. Goes down from the best block until
+    /// the last session end that has a justification, as the earlier ones were checked on previous
+    /// starts, but checks at most `sessions_checked_on_start` sessions.
+    fn missing_session_end_justifications(&self) -> Vec<(Block::Hash, NumberFor<Block>)> {
+        let info = self.inner.info();
+        let best_session =
+            session_id_from_block_num::<Block>(info.best_number, self.session_period);
+        let session_ends = (0..=best_session.0)
+            .rev()
+            .map(|session| last_block_of_session::<Block>(SessionId(session), self.session_period))
+            .skip_while(|number| *number > info.best_number)
+            .take(self.sessions_checked_on_start as usize);
+        let mut missing = Vec::new();
+        for number in session_ends {
+            let hash = match self.inner.hash(number) {
+                Ok(Some(hash)) => hash,
+                // Blocks below a warp sync target might not be there yet.
+                Ok(None) => break,
+                Err(e) => {
+                    warn!(target: "dagestan-justification", "Could not get the hash of block {:?}: {}", number, e);
+                    break;
+                }
+            };
+            match self.inner.justifications(&BlockId::Hash(hash)) {
+                Ok(justifications) => {
+                    if justifications
+                        .as_ref()
+                        .and_then(find_dagestan_justification)
+                        .is_some()
+                    {
+                        break;
+                    }
+                    missing.push((hash, number));
+                }
+                Err(e) => {
+                    warn!(target: "dagestan-justification", "Could not get the justifications of block {:?}: {}", number, e);
+                    break;
+                }
+            }
+        }
+        missing.reverse();
+        missing
+    }
+
+    async fn send_justification(
         &mut self,
         hash: Block::Hash,
         number: NumberFor<Block>,
         justification: Justification,
     ) -> Result<(), SendJustificationError<Block>> {
         debug!(target: "dagestan-justification", "Importing justification for block {:?}", number);
-        if justification.0 != DAGESTAN_ENGINE_ID {
+        if !is_dagestan_engine_id(justification.0) {
             return Err(SendJustificationError::Consensus(Box::new(
                 ConsensusError::ClientImport("Dagestan can import only Dagestan justifications.".into()),
             )));
         }
         let justification_raw = justification.1;
         let dagestan_justification = backwards_compatible_decode(justification_raw)?;
+        match self.justification_status(&dagestan_justification, hash, number) {
+            JustificationStatus::Valid => (),
+            JustificationStatus::Invalid => return Err(SendJustificationError::Invalid),
+            // The handler keeps justifications of future sessions until it can verify them, and
+            // drops them if there are too many, in which case it requests them again later.
+            JustificationStatus::NotYetVerifiable => {
+                debug!(target: "dagestan-justification", "Leaving the justification of block {:?} to be verified by the handler", number)
+            }
+        }
 
         self.justification_tx
             .unbounded_send(JustificationNotification {
@@ -102,6 +242,9 @@ where
             inner: self.inner.clone(),
             justification_tx: self.justification_tx.clone(),
             metrics: self.metrics.clone(),
+            session_period: self.session_period,
+            sessions_checked_on_start: self.sessions_checked_on_start,
+            session_map: self.session_map.clone(),
             _phantom: PhantomData,
         }
     }
@@ -143,21 +286,31 @@ where
         debug!(target: "dagestan-justification", "Importing block {:?} {:?} {:?}", number, block.header.hash(), block.post_hash());
         let import_result = self.inner.import_block(block, cache).await;
 
-        let imported_aux = match import_result {
+        let mut imported_aux = match import_result {
             Ok(ImportResult::Imported(aux)) => aux,
             Ok(r) => return Ok(r),
             Err(e) => return Err(e),
         };
 
-        if let Some(justification) =
-            justifications.and_then(|just| just.into_justification(DAGESTAN_ENGINE_ID))
+        if let Some(justification) = justifications
+            .as_ref()
+            .and_then(find_dagestan_justification)
         {
             debug!(target: "dagestan-justification", "Got justification along imported block {:?}", number);
 
-            if let Err(e) =
-                self.send_justification(post_hash, number, (DAGESTAN_ENGINE_ID, justification))
+            match self
+                .send_justification(post_hash, number, justification)
+                .await
             {
-                warn!(target: "dagestan-justification", "Error while receiving justification for block {:?}: {:?}", post_hash, e);
+                Ok(()) => (),
+                Err(SendJustificationError::Invalid) => {
+                    warn!(target: "dagestan-justification", "Dropping invalid justification imported along block {:?}", post_hash);
+                    // Makes the sync lower the reputation of the peer that sent the block.
+                    imported_aux.bad_justification = true;
+                }
+                Err(e) => {
+                    warn!(target: "dagestan-justification", "Error while receiving justification for block {:?}: {:?}", post_hash, e);
+                }
             }
         }
 
@@ -180,7 +333,11 @@ where
 
     async fn on_start(&mut self) -> Vec<(Block::Hash, NumberFor<Block>)> {
         debug!(target: "dagestan-justification", "On start called");
-        Vec::new()
+        let missing = self.missing_session_end_justifications();
+        if !missing.is_empty() {
+            debug!(target: "dagestan-justification", "Requesting justifications of {} session end blocks imported without one", missing.len());
+        }
+        missing
     }
 
     async fn import_justification(
@@ -191,6 +348,7 @@ where
     ) -> Result<(), Self::Error> {
         debug!(target: "dagestan-justification", "import_justification called on {:?}", justification);
         self.send_justification(hash, number, justification)
+            .await
             .map_err(|error| match error {
                 SendJustificationError::Send(_) => ConsensusError::ClientImport(String::from(
                     "Could not send justification to ConsensusParty",
@@ -200,6 +358,201 @@ where
                     warn!(target: "dagestan-justification", "Justification for block {:?} decoded incorrectly: {}", number, e);
                     ConsensusError::ClientImport(String::from("Could not decode justification"))
                 }
+                SendJustificationError::Invalid => {
+                    warn!(target: "dagestan-justification", "Justification for block {:?} is invalid", number);
+                    ConsensusError::ClientImport(String::from("Invalid justification"))
+                }
             })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use codec::Encode;
+    use dagestan_primitives::{
+        AuthorityPair, SessionAuthorityData, DAGESTAN_ENGINE_ID, DEFAULT_FINALITY_VERSION,
+    };
+    use futures::channel::mpsc::{self, UnboundedReceiver};
+    use sc_block_builder::BlockBuilderProvider;
+    use sc_client_api::HeaderBackend;
+    use sc_consensus::{BlockImport, BlockImportParams, ForkChoiceStrategy, ImportResult};
+    use sp_consensus::BlockOrigin;
+    use sp_core::Pair;
+    use sp_runtime::{generic::BlockId, traits::Block as BlockT, Justifications};
+    use substrate_test_runtime_client::{
+        runtime::Block, Backend, ClientBlockImportExt, ClientExt, DefaultTestClientBuilderExt,
+        TestClient, TestClientBuilder, TestClientBuilderExt,
+    };
+
+    use super::DagestanBlockImport;
+    use crate::{
+        abft::{NodeCount, NodeIndex, SignatureSet},
+        crypto::Signature,
+        justification::versioned_encode,
+        session_map::SharedSessionMap,
+        DagestanJustification, JustificationNotification, SessionId, SessionPeriod,
+    };
+
+    type TestBlockImport = DagestanBlockImport<Block, Backend, TestClient>;
+
+    fn pairs(seeds: &[u8]) -> Vec<AuthorityPair> {
+        seeds
+            .iter()
+            .map(|seed| AuthorityPair::from_seed(&[*seed; 32]))
+            .collect()
+    }
+
+    async fn setup(
+        authorities: Option<&[AuthorityPair]>,
+    ) -> (
+        TestBlockImport,
+        UnboundedReceiver<JustificationNotification<Block>>,
+        Arc<TestClient>,
+    ) {
+        let client = Arc::new(TestClientBuilder::new().build());
+        let (justification_tx, justification_rx) = mpsc::unbounded();
+        let mut session_map = SharedSessionMap::new();
+        if let Some(authorities) = authorities {
+            let authority_data = SessionAuthorityData::new(
+                authorities.iter().map(|pair| pair.public()).collect(),
+                None,
+            );
+            session_map
+                .update(SessionId(0), authority_data, DEFAULT_FINALITY_VERSION)
+                .await;
+        }
+        let block_import =
+            DagestanBlockImport::new(client.clone(), justification_tx, None, SessionPeriod(10))
+                .with_verification(session_map.read_only());
+        (block_import, justification_rx, client)
+    }
+
+    /// Imports a new block with a justification signed by the given authorities. Returns whether
+    /// the import marked the justification as bad.
+    async fn import_justified_block(
+        block_import: &mut TestBlockImport,
+        client: &Arc<TestClient>,
+        signers: &[AuthorityPair],
+    ) -> bool {
+        let block = client
+            .new_block(Default::default())
+            .expect("the test client should build blocks")
+            .build()
+            .expect("the block should build")
+            .block;
+        let message = block.hash().encode();
+        let signatures = signers.iter().enumerate().fold(
+            SignatureSet::with_size(NodeCount(signers.len())),
+            |signatures, (index, pair)| {
+                signatures.add_signature(&Signature::from(pair.sign(&message)), NodeIndex(index))
+            },
+        );
+        let justification = versioned_encode(
+            DagestanJustification::CommitteeMultisignature(signatures),
+            DEFAULT_FINALITY_VERSION,
+        );
+        let (header, extrinsics) = block.deconstruct();
+        let mut params = BlockImportParams::new(BlockOrigin::NetworkBroadcast, header);
+        params.body = Some(extrinsics);
+        params.justifications = Some(Justifications::from((DAGESTAN_ENGINE_ID, justification)));
+        params.fork_choice = Some(ForkChoiceStrategy::LongestChain);
+        match block_import.import_block(params, HashMap::new()).await {
+            Ok(ImportResult::Imported(aux)) => aux.bad_justification,
+            _ => panic!("the block should be imported"),
+        }
+    }
+
+    /// Imports `count` blocks directly into the client, the ones with the given numbers with
+    /// a Dagestan justification.
+    async fn import_blocks(client: &Arc<TestClient>, count: u32, justified: &[u32]) {
+        let mut client = client.clone();
+        for number in 1..=count {
+            let block = client
+                .new_block(Default::default())
+                .expect("the test client should build blocks")
+                .build()
+                .expect("the block should build")
+                .block;
+            let result = if justified.contains(&number) {
+                let justifications = Justifications::from((DAGESTAN_ENGINE_ID, Vec::new()));
+                client
+                    .import_justified(BlockOrigin::Own, block, justifications)
+                    .await
+            } else {
+                client.import(BlockOrigin::Own, block).await
+            };
+            result.expect("the block should be imported");
+        }
+    }
+
+    fn missing_numbers(block_import: &TestBlockImport) -> Vec<u32> {
+        block_import
+            .missing_session_end_justifications()
+            .into_iter()
+            .map(|(_, number)| number)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn finds_missing_session_end_justifications_down_to_last_justified_one() {
+        let (block_import, _justification_rx, client) = setup(None).await;
+        import_blocks(&client, 35, &[9]).await;
+        let hash = client.hash(25).unwrap().unwrap();
+        client
+            .finalize_block(BlockId::Hash(hash), None)
+            .expect("the block should be finalized");
+
+        assert_eq!(missing_numbers(&block_import), vec![19, 29]);
+    }
+
+    #[tokio::test]
+    async fn finds_missing_session_end_justifications_of_all_sessions() {
+        let (block_import, _justification_rx, client) = setup(None).await;
+        import_blocks(&client, 35, &[]).await;
+
+        assert_eq!(missing_numbers(&block_import), vec![9, 19, 29]);
+    }
+
+    #[tokio::test]
+    async fn checks_configured_number_of_sessions_on_start() {
+        let (block_import, _justification_rx, client) = setup(None).await;
+        let block_import = block_import.with_sessions_checked_on_start(2);
+        import_blocks(&client, 35, &[]).await;
+
+        assert_eq!(missing_numbers(&block_import), vec![19, 29]);
+    }
+
+    #[tokio::test]
+    async fn passes_valid_justification_to_handler() {
+        let authorities = pairs(&[1, 2, 3]);
+        let (mut block_import, mut justification_rx, client) = setup(Some(&authorities)).await;
+
+        assert!(!import_justified_block(&mut block_import, &client, &authorities).await);
+        assert!(matches!(
+            justification_rx.try_next(),
+            Ok(Some(notification)) if notification.number == 1
+        ));
+    }
+
+    #[tokio::test]
+    async fn marks_invalid_justification_as_bad() {
+        let authorities = pairs(&[1, 2, 3]);
+        let (mut block_import, mut justification_rx, client) = setup(Some(&authorities)).await;
+
+        assert!(import_justified_block(&mut block_import, &client, &pairs(&[4, 5, 6])).await);
+        assert!(justification_rx.try_next().is_err());
+    }
+
+    #[tokio::test]
+    async fn leaves_not_yet_verifiable_justification_to_handler() {
+        let (mut block_import, mut justification_rx, client) = setup(None).await;
+
+        assert!(!import_justified_block(&mut block_import, &client, &pairs(&[4, 5, 6])).await);
+        assert!(matches!(
+            justification_rx.try_next(),
+            Ok(Some(notification)) if notification.number == 1
+        ));
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::BTreeMap;
+
+use sp_api::BlockT;
+
+use crate::{justification::JustificationNotification, SessionId};
+
+/// How many sessions past the current one we keep justifications for.
+const MAX_SESSIONS_AHEAD: u32 = 8;
+/// How many justifications we keep for a single session.
+const MAX_PER_SESSION: usize = 32;
+/// How many different justifications we keep for a single block. One is enough to finalize it, the
+/// others are redundant or forged, but we cannot tell which is which before their session comes.
+const MAX_PER_BLOCK: usize = 4;
+
+/// Unverified justifications of blocks from sessions later than the one we are finalizing in, kept
+/// until finalization reaches their session and its verifier becomes usable.
+pub struct FutureJustifications<B: BlockT> {
+    sessions: BTreeMap<SessionId, Vec<JustificationNotification<B>>>,
+    max_sessions_ahead: u32,
+    max_per_session: usize,
+    max_per_block: usize,
+}
+
+impl<B: BlockT> FutureJustifications<B> {
+    pub fn new() -> Self {
+        Self::with_limits(MAX_SESSIONS_AHEAD, MAX_PER_SESSION, MAX_PER_BLOCK)
+    }
+
+    fn with_limits(max_sessions_ahead: u32, max_per_session: usize, max_per_block: usize) -> Self {
+        FutureJustifications {
+            sessions: BTreeMap::new(),
+            max_sessions_ahead,
+            max_per_session,
+            max_per_block,
+        }
+    }
+
+    /// Keeps the justification of a block from `session` while finalizing in `current_session`.
+    /// Different justifications of the same block are all kept, so that a forged one cannot keep
+    /// out the genuine one, up to the maximal number per block, above which the oldest ones are
+    /// dropped. When a session already has the maximal number of justifications, the oldest one of
+    /// a block with several is dropped, and otherwise the one for the lowest block, as finalizing
+    /// a higher block finalizes the lower ones as well.
+    /// Returns whether the justification was kept.
+    pub fn insert(
+        &mut self,
+        current_session: SessionId,
+        session: SessionId,
+        notification: JustificationNotification<B>,
+    ) -> bool {
+        if session <= current_session
+            || session.0 > current_session.0.saturating_add(self.max_sessions_ahead)
+        {
+            return false;
+        }
+        let buffered = self.sessions.entry(session).or_default();
+        if buffered.iter().any(|buffered| {
+            buffered.hash == notification.hash
+                && buffered.justification == notification.justification
+        }) {
+            return false;
+        }
+        let hash = notification.hash;
+        // Justifications of the same block are kept in the order they came in.
+        let mut position =
+            buffered.partition_point(|buffered| buffered.number <= notification.number);
+        buffered.insert(position, notification);
+        let same_block: Vec<_> = (0..buffered.len())
+            .filter(|index| buffered[*index].hash == hash)
+            .collect();
+        if same_block.len() > self.max_per_block {
+            buffered.remove(same_block[0]);
+            position -= 1;
+        }
+        if buffered.len() > self.max_per_session {
+            let evicted = Self::redundant(buffered).unwrap_or(0);
+            buffered.remove(evicted);
+            return evicted != position;
+        }
+        true
+    }
+
+    /// The oldest justification of the lowest block with several justifications, if there is one.
+    fn redundant(buffered: &[JustificationNotification<B>]) -> Option<usize> {
+        buffered
+            .iter()
+            .enumerate()
+            .find_map(|(index, notification)| {
+                buffered[index + 1..]
+                    .iter()
+                    .any(|other| other.hash == notification.hash)
+                    .then_some(index)
+            })
+    }
+
+    /// Removes and returns the justifications from `session`, ordered by block number. The ones
+    /// from earlier sessions are dropped, as they can no longer be verified with the verifier of
+    /// `session` and their blocks are finalized by now anyway.
+    pub fn take(&mut self, session: SessionId) -> Vec<JustificationNotification<B>> {
+        let mut later = self
+            .sessions
+            .split_off(&SessionId(session.0.saturating_add(1)));
+        let taken = self.sessions.remove(&session).unwrap_or_default();
+        std::mem::swap(&mut self.sessions, &mut later);
+        taken
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.values().map(Vec::len).sum()
+    }
+}
+
+impl<B: BlockT> Default for FutureJustifications<B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FutureJustifications;
+    use crate::{
+        justification::{DagestanJustification, JustificationNotification},
+        testing::mocks::{TBlock, THash},
+        SessionId, SignatureSet,
+    };
+
+    fn notification(number: u64) -> JustificationNotification<TBlock> {
+        variant(number, 0)
+    }
+
+    /// A justification of the block with the given number, different for every `variant`.
+    fn variant(number: u64, variant: usize) -> JustificationNotification<TBlock> {
+        JustificationNotification {
+            justification: DagestanJustification::CommitteeMultisignature(SignatureSet::with_size(
+                variant.into(),
+            )),
+            hash: THash::from_low_u64_be(number),
+            number,
+        }
+    }
+
+    fn variants(notifications: Vec<JustificationNotification<TBlock>>) -> Vec<(u64, usize)> {
+        notifications
+            .into_iter()
+            .map(|notification| match notification.justification {
+                DagestanJustification::CommitteeMultisignature(signature_set) => {
+                    (notification.number, signature_set.size().0)
+                }
+                _ => panic!("only multisignatures are buffered in tests"),
+            })
+            .collect()
+    }
+
+    fn numbers(notifications: Vec<JustificationNotification<TBlock>>) -> Vec<u64> {
+        notifications
+            .into_iter()
+            .map(|notification| notification.number)
+            .collect()
+    }
+
+    #[test]
+    fn keeps_only_future_sessions_within_limit() {
+        let mut buffer = FutureJustifications::with_limits(2, 10, 4);
+
+        assert!(!buffer.insert(SessionId(3), SessionId(2), notification(25)));
+        assert!(!buffer.insert(SessionId(3), SessionId(3), notification(35)));
+        assert!(buffer.insert(SessionId(3), SessionId(4), notification(45)));
+        assert!(buffer.insert(SessionId(3), SessionId(5), notification(55)));
+        assert!(!buffer.insert(SessionId(3), SessionId(6), notification(65)));
+        assert_eq!(buffer.len(), 2);
+    }
+
+    #[test]
+    fn ignores_repeated_justifications() {
+        let mut buffer = FutureJustifications::with_limits(2, 10, 4);
+
+        assert!(buffer.insert(SessionId(0), SessionId(1), notification(15)));
+        assert!(!buffer.insert(SessionId(0), SessionId(1), notification(15)));
+        assert_eq!(buffer.len(), 1);
+    }
+
+    #[test]
+    fn keeps_different_justifications_of_a_block() {
+        let mut buffer = FutureJustifications::with_limits(2, 10, 4);
+
+        // A forged justification arriving first does not keep out the genuine one.
+        assert!(buffer.insert(SessionId(0), SessionId(1), variant(15, 7)));
+        assert!(buffer.insert(SessionId(0), SessionId(1), variant(15, 0)));
+        assert!(!buffer.insert(SessionId(0), SessionId(1), variant(15, 7)));
+
+        assert_eq!(variants(buffer.take(SessionId(1))), vec![(15, 7), (15, 0)]);
+    }
+
+    #[test]
+    fn keeps_latest_justifications_of_a_block() {
+        let mut buffer = FutureJustifications::with_limits(2, 10, 2);
+
+        for number in 1..=3 {
+            assert!(buffer.insert(SessionId(0), SessionId(1), variant(15, number)));
+        }
+        assert!(buffer.insert(SessionId(0), SessionId(1), variant(16, 0)));
+
+        assert_eq!(
+            variants(buffer.take(SessionId(1))),
+            vec![(15, 2), (15, 3), (16, 0)]
+        );
+    }
+
+    #[test]
+    fn drops_redundant_justifications_of_full_session_first() {
+        let mut buffer = FutureJustifications::with_limits(2, 3, 4);
+
+        assert!(buffer.insert(SessionId(0), SessionId(1), variant(13, 0)));
+        assert!(buffer.insert(SessionId(0), SessionId(1), variant(15, 7)));
+        assert!(buffer.insert(SessionId(0), SessionId(1), variant(15, 8)));
+        assert!(buffer.insert(SessionId(0), SessionId(1), variant(14, 0)));
+        assert_eq!(
+            variants(buffer.take(SessionId(1))),
+            vec![(13, 0), (14, 0), (15, 8)]
+        );
+
+        assert!(buffer.insert(SessionId(0), SessionId(2), variant(25, 7)));
+        assert!(buffer.insert(SessionId(0), SessionId(2), variant(25, 8)));
+        assert!(buffer.insert(SessionId(0), SessionId(2), variant(26, 0)));
+        // The genuine justification replaces the oldest redundant one.
+        assert!(buffer.insert(SessionId(0), SessionId(2), variant(25, 0)));
+        assert_eq!(
+            variants(buffer.take(SessionId(2))),
+            vec![(25, 8), (25, 0), (26, 0)]
+        );
+    }
+
+    #[test]
+    fn drops_lowest_blocks_of_full_session() {
+        let mut buffer = FutureJustifications::with_limits(2, 2, 4);
+
+        assert!(buffer.insert(SessionId(0), SessionId(1), notification(13)));
+        assert!(buffer.insert(SessionId(0), SessionId(1), notification(15)));
+        assert!(buffer.insert(SessionId(0), SessionId(1), notification(14)));
+        assert!(!buffer.insert(SessionId(0), SessionId(1), notification(12)));
+
+        assert_eq!(numbers(buffer.take(SessionId(1))), vec![14, 15]);
+    }
+
+    #[test]
+    fn returns_given_session_in_block_order() {
+        let mut buffer = FutureJustifications::with_limits(4, 10, 4);
+
+        for (session, number) in [(3, 37), (2, 29), (1, 19), (2, 21), (1, 11), (4, 40)] {
+            assert!(buffer.insert(SessionId(0), SessionId(session), notification(number)));
+        }
+
+        assert_eq!(numbers(buffer.take(SessionId(2))), vec![21, 29]);
+        assert!(buffer.take(SessionId(2)).is_empty());
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(numbers(buffer.take(SessionId(4))), vec![40]);
+        assert_eq!(buffer.len(), 0);
+    }
+
+    #[test]
+    fn drops_earlier_sessions() {
+        let mut buffer = FutureJustifications::with_limits(4, 10, 4);
+
+        for (session, number) in [(1, 11), (2, 21), (3, 31)] {
+            assert!(buffer.insert(SessionId(0), SessionId(session), notification(number)));
+        }
+
+        assert_eq!(numbers(buffer.take(SessionId(2))), vec![21]);
+        assert!(buffer.take(SessionId(1)).is_empty());
+        assert_eq!(numbers(buffer.take(SessionId(3))), vec![31]);
+    }
+}
@@ -3,17 +3,26 @@ use std::{
     mem::size_of,
 };
 
-use codec::{Decode, DecodeAll, Encode, Error as CodecError, Input as CodecInput};
+use codec::{Compact, Decode, DecodeAll, Encode, Error as CodecError, Input as CodecInput};
+use dagestan_primitives::{
+    AuthoritySignature, Version as FinalityVersion, COMPACT_JUSTIFICATION_FINALITY_VERSION,
+    DAGESTAN_ENGINE_ID, LEGACY_DAGESTAN_ENGINE_ID,
+};
 use log::warn;
+use sp_runtime::{Justification, Justifications};
 
 use crate::{
-    abft::SignatureSet,
-    crypto::{Signature, SignatureV1},
+    abft::{NodeCount, NodeIndex, SignatureSet},
+    crypto::{bitmap_len, AggregateSignature, Signature, SignatureV1},
     justification::DagestanJustification,
     Version,
 };
 
 type ByteCount = u16;
+type CompactByteCount = Compact<u32>;
+
+/// The first version that stores the payload length as a `CompactByteCount`.
+const FIRST_COMPACT_LENGTH_VERSION: Version = Version(4);
 
 /// Old format of justifications, needed for backwards compatibility.
 /// Used an old format of signature which unnecessarily contained the signer ID.
@@ -48,6 +57,135 @@ impl From<DagestanJustificationV2> for DagestanJustification {
     }
 }
 
+/// Signatures of a committee stored densely: a bitmap of the members who signed, followed by
+/// their signatures in the order of their indices.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct CompactSignatureSet {
+    size: NodeCount,
+    signers: Vec<u8>,
+    signatures: Vec<Signature>,
+}
+
+impl From<SignatureSet<Signature>> for CompactSignatureSet {
+    fn from(signature_set: SignatureSet<Signature>) -> Self {
+        let size = signature_set.size();
+        let mut signers = vec![0; bitmap_len(size.0)];
+        let mut signatures = Vec::new();
+        for (NodeIndex(index), signature) in signature_set.iter() {
+            signers[index / 8] |= 1 << (index % 8);
+            signatures.push(signature.clone());
+        }
+        CompactSignatureSet {
+            size,
+            signers,
+            signatures,
+        }
+    }
+}
+
+impl From<CompactSignatureSet> for SignatureSet<Signature> {
+    fn from(compact: CompactSignatureSet) -> Self {
+        let indices = (0..compact.size.0)
+            .filter(|index| compact.signers[index / 8] & (1 << (index % 8)) != 0)
+            .map(NodeIndex);
+        indices.zip(compact.signatures.iter()).fold(
+            SignatureSet::with_size(compact.size),
+            |signature_set, (index, signature)| signature_set.add_signature(signature, index),
+        )
+    }
+}
+
+impl Encode for CompactSignatureSet {
+    fn size_hint(&self) -> usize {
+        Compact(self.size.0 as u32).size_hint()
+            + self.signers.len()
+            + self
+                .signatures
+                .iter()
+                .map(|signature| signature.size_hint())
+                .sum::<usize>()
+    }
+
+    fn encode_to<T: codec::Output + ?Sized>(&self, dest: &mut T) {
+        // Both the bitmap and the number of signatures follow from the size, so neither gets a
+        // length prefix.
+        Compact(self.size.0 as u32).encode_to(dest);
+        dest.write(&self.signers);
+        for signature in &self.signatures {
+            signature.encode_to(dest);
+        }
+    }
+}
+
+impl Decode for CompactSignatureSet {
+    fn decode<I: CodecInput>(input: &mut I) -> Result<Self, CodecError> {
+        let size = Compact::<u32>::decode(input)?.0 as usize;
+        let signers_len = bitmap_len(size);
+        if let Some(remaining) = input.remaining_len()? {
+            if remaining < signers_len {
+                return Err("Signer bitmap longer than the remaining input".into());
+            }
+        }
+        let mut signers = vec![0; signers_len];
+        input.read(&mut signers)?;
+        if size % 8 != 0 && signers[signers_len - 1] >> (size % 8) != 0 {
+            return Err("Signer bitmap marks members outside of the committee".into());
+        }
+        let signature_count = signers
+            .iter()
+            .map(|byte| byte.count_ones() as usize)
+            .sum::<usize>();
+        let signatures = (0..signature_count)
+            .map(|_| Signature::decode(input))
+            .collect::<Result<_, _>>()?;
+        Ok(CompactSignatureSet {
+            size: NodeCount(size),
+            signers,
+            signatures,
+        })
+    }
+}
+
+/// Compact format of justifications, without an `Option` for every member of the committee.
+#[derive(Clone, Encode, Decode, Debug, PartialEq, Eq)]
+enum DagestanJustificationV4 {
+    CommitteeMultisignature(CompactSignatureSet),
+    EmergencySignature(AuthoritySignature),
+    AggregateSignature(AggregateSignature),
+}
+
+impl From<DagestanJustification> for DagestanJustificationV4 {
+    fn from(justification: DagestanJustification) -> Self {
+        match justification {
+            DagestanJustification::CommitteeMultisignature(signature_set) => {
+                DagestanJustificationV4::CommitteeMultisignature(signature_set.into())
+            }
+            DagestanJustification::EmergencySignature(signature) => {
+                DagestanJustificationV4::EmergencySignature(signature)
+            }
+            DagestanJustification::AggregateSignature(aggregate) => {
+                DagestanJustificationV4::AggregateSignature(aggregate)
+            }
+        }
+    }
+}
+
+impl From<DagestanJustificationV4> for DagestanJustification {
+    fn from(justification: DagestanJustificationV4) -> Self {
+        match justification {
+            DagestanJustificationV4::CommitteeMultisignature(compact) => {
+                DagestanJustification::CommitteeMultisignature(compact.into())
+            }
+            DagestanJustificationV4::EmergencySignature(signature) => {
+                DagestanJustification::EmergencySignature(signature)
+            }
+            DagestanJustificationV4::AggregateSignature(aggregate) => {
+                DagestanJustification::AggregateSignature(aggregate)
+            }
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 enum VersionedDagestanJustification {
     // Most likely from the future.
@@ -55,12 +193,15 @@ enum VersionedDagestanJustification {
     V1(DagestanJustificationV1),
     V2(DagestanJustificationV2),
     V3(DagestanJustification),
+    V4(DagestanJustification),
 }
 
 fn encode_with_version(version: Version, payload: &[u8]) -> Vec<u8> {
-    // This will produce rubbish if we ever try encodings that have more than u16::MAX bytes. We
-    // expect this won't happen, since we will switch to proper multisignatures before proofs get
-    // that big.
+    if version >= FIRST_COMPACT_LENGTH_VERSION {
+        return encode_with_compact_length(version, payload);
+    }
+    // Before V4 this will produce rubbish if we ever try encodings that have more than u16::MAX
+    // bytes, which is why newer versions store the length compactly.
     // We do not have a guarantee that size_hint is implemented for DagestanJustification, so we need
     // to compute actual size to place it in the encoded data.
     let size = payload.len().try_into().unwrap_or_else(|_| {
@@ -84,11 +225,46 @@ fn encode_with_version(version: Version, payload: &[u8]) -> Vec<u8> {
     result
 }
 
+fn encode_with_compact_length(version: Version, payload: &[u8]) -> Vec<u8> {
+    let size: CompactByteCount = Compact(
+        payload
+            .len()
+            .try_into()
+            .expect("justifications are much smaller than 4GiB"),
+    );
+
+    let mut result = Vec::with_capacity(version.size_hint() + size.size_hint() + payload.len());
+
+    version.encode_to(&mut result);
+    size.encode_to(&mut result);
+    result.extend_from_slice(payload);
+
+    result
+}
+
+/// Reads exactly `num_bytes` of payload, failing instead of allocating if the input is shorter.
+fn read_payload<I: CodecInput>(input: &mut I, num_bytes: usize) -> Result<Vec<u8>, CodecError> {
+    if let Some(remaining) = input.remaining_len()? {
+        if remaining < num_bytes {
+            return Err("Versioned justification payload truncated".into());
+        }
+    }
+    let mut payload = vec![0; num_bytes];
+    input.read(payload.as_mut_slice())?;
+    Ok(payload)
+}
+
 impl Encode for VersionedDagestanJustification {
     fn size_hint(&self) -> usize {
         use VersionedDagestanJustification::*;
         let version_size = size_of::<Version>();
-        let byte_count_size = size_of::<ByteCount>();
+        let byte_count_size = match self {
+            Other(version, _) if *version >= FIRST_COMPACT_LENGTH_VERSION => {
+                size_of::<CompactByteCount>()
+            }
+            V4(_) => size_of::<CompactByteCount>(),
+            _ => size_of::<ByteCount>(),
+        };
         version_size
             + byte_count_size
             + match self {
@@ -96,6 +272,9 @@ impl Encode for VersionedDagestanJustification {
                 V1(justification) => justification.size_hint(),
                 V2(justification) => justification.size_hint(),
                 V3(justification) => justification.size_hint(),
+                V4(justification) => {
+                    DagestanJustificationV4::from(justification.clone()).size_hint()
+                }
             }
     }
 
@@ -106,6 +285,10 @@ impl Encode for VersionedDagestanJustification {
             V1(justification) => encode_with_version(Version(1), &justification.encode()),
             V2(justification) => encode_with_version(Version(2), &justification.encode()),
             V3(justification) => encode_with_version(Version(3), &justification.encode()),
+            V4(justification) => encode_with_version(
+                Version(4),
+                &DagestanJustificationV4::from(justification.clone()).encode(),
+            ),
         }
     }
 }
@@ -114,6 +297,17 @@ impl Decode for VersionedDagestanJustification {
     fn decode<I: CodecInput>(input: &mut I) -> Result<Self, CodecError> {
         use VersionedDagestanJustification::*;
         let version = Version::decode(input)?;
+        if version >= FIRST_COMPACT_LENGTH_VERSION {
+            let num_bytes = CompactByteCount::decode(input)?.0 as usize;
+            let payload = read_payload(input, num_bytes)?;
+            return match version {
+                Version(4) => Ok(V4(DagestanJustificationV4::decode_all(
+                    &mut payload.as_slice(),
+                )?
+                .into())),
+                _ => Ok(Other(version, payload)),
+            };
+        }
         let num_bytes = ByteCount::decode(input)?;
         match version {
             Version(1) => Ok(V1(DagestanJustificationV1::decode(input)?)),
@@ -181,7 +375,7 @@ pub fn backwards_compatible_decode(
             match justification {
                 V1(justification) => Ok(justification.into()),
                 V2(justification) => Ok(justification.into()),
-                V3(justification) => Ok(justification),
+                V3(justification) | V4(justification) => Ok(justification),
                 Other(version, _) => {
                     // it is a coincidence that sometimes pre-compatibility legacy justification second word,
                     // which is in VersionedDagestanJustification byte_count_size, can be small enough
@@ -196,27 +390,65 @@ pub fn backwards_compatible_decode(
     }
 }
 
-/// Encodes the justification in a way that is forwards compatible with future versions.
-pub fn versioned_encode(justification: DagestanJustification) -> Vec<u8> {
-    VersionedDagestanJustification::V3(justification).encode()
+/// Encodes the justification in a way that is forwards compatible with future versions, using the
+/// newest format supported by the given finality version.
+pub fn versioned_encode(
+    justification: DagestanJustification,
+    finality_version: FinalityVersion,
+) -> Vec<u8> {
+    match finality_version >= COMPACT_JUSTIFICATION_FINALITY_VERSION {
+        true => VersionedDagestanJustification::V4(justification),
+        false => VersionedDagestanJustification::V3(justification),
+    }
+    .encode()
+}
+
+/// Returns the Dagestan justification among `justifications`, together with its engine id.
+/// Justifications stored under the dedicated engine id take precedence over legacy ones.
+pub fn find_dagestan_justification(justifications: &Justifications) -> Option<Justification> {
+    [DAGESTAN_ENGINE_ID, LEGACY_DAGESTAN_ENGINE_ID]
+        .into_iter()
+        .find_map(|engine_id| {
+            justifications
+                .get(engine_id)
+                .map(|justification| (engine_id, justification.clone()))
+        })
 }
 
 #[cfg(test)]
 mod test {
-    use dagestan_primitives::{AuthorityPair, AuthoritySignature};
+    use dagestan_primitives::{
+        AuthorityPair, AuthoritySignature, BlsSignature, COMPACT_JUSTIFICATION_FINALITY_VERSION,
+        DAGESTAN_ENGINE_ID, DEFAULT_FINALITY_VERSION, LEGACY_DAGESTAN_ENGINE_ID,
+    };
     use codec::{Decode, Encode};
     use sp_core::Pair;
+    use sp_runtime::Justifications;
 
     use super::{
-        backwards_compatible_decode, versioned_encode, DagestanJustificationV1, DagestanJustificationV2,
-        VersionedDagestanJustification,
+        backwards_compatible_decode, find_dagestan_justification, versioned_encode,
+        CompactSignatureSet, DagestanJustificationV1, DagestanJustificationV2,
+        DagestanJustificationV4, VersionedDagestanJustification,
     };
     use crate::{
-        crypto::{Signature, SignatureV1},
+        crypto::{AggregateSignature, Signature, SignatureV1},
         justification::DagestanJustification,
-        NodeCount, SignatureSet, Version,
+        NodeCount, NodeIndex, SignatureSet, Version,
     };
 
+    fn committee_justification(
+        size: usize,
+        signers: impl Iterator<Item = usize>,
+    ) -> DagestanJustification {
+        let pair = AuthorityPair::from_seed(&[7; 32]);
+        let signature: Signature = pair.sign(&[0u8, 0u8, 0u8, 0u8]).into();
+        let signature_set = signers.fold(
+            SignatureSet::with_size(NodeCount(size)),
+            |signature_set, index| signature_set.add_signature(&signature, NodeIndex(index)),
+        );
+        DagestanJustification::CommitteeMultisignature(signature_set)
+    }
+
     #[test]
     fn correctly_decodes_v1() {
         let mut signature_set: SignatureSet<SignatureV1> = SignatureSet::with_size(7.into());
@@ -271,11 +503,104 @@ mod test {
 
         let just_v3 = DagestanJustification::CommitteeMultisignature(signature_set);
         // Here we use `versioned_encode` since we never sent plain v3 justifications.
-        let encoded_just = versioned_encode(just_v3.clone());
+        let encoded_just = versioned_encode(just_v3.clone(), DEFAULT_FINALITY_VERSION);
         let decoded = backwards_compatible_decode(encoded_just);
         assert_eq!(decoded, Ok(just_v3));
     }
 
+    #[test]
+    fn correctly_decodes_v4_committee() {
+        let just_v4 = committee_justification(7, [0, 2, 3, 6].into_iter());
+        let encoded_just =
+            versioned_encode(just_v4.clone(), COMPACT_JUSTIFICATION_FINALITY_VERSION);
+        let decoded = backwards_compatible_decode(encoded_just);
+        assert_eq!(decoded, Ok(just_v4));
+    }
+
+    #[test]
+    fn correctly_decodes_v4_emergency() {
+        let just_v4 = DagestanJustification::EmergencySignature(
+            AuthorityPair::from_seed(&[3; 32]).sign(&[0u8, 0u8, 0u8, 0u8]),
+        );
+        let encoded_just =
+            versioned_encode(just_v4.clone(), COMPACT_JUSTIFICATION_FINALITY_VERSION);
+        let decoded = backwards_compatible_decode(encoded_just);
+        assert_eq!(decoded, Ok(just_v4));
+    }
+
+    #[test]
+    fn correctly_decodes_v4_aggregate() {
+        let just_v4 = DagestanJustification::AggregateSignature(AggregateSignature::new(
+            NodeCount(5),
+            BlsSignature([3; 96]),
+            NodeIndex(2),
+        ));
+        let encoded_just =
+            versioned_encode(just_v4.clone(), COMPACT_JUSTIFICATION_FINALITY_VERSION);
+        let decoded = backwards_compatible_decode(encoded_just);
+        assert_eq!(decoded, Ok(just_v4));
+    }
+
+    #[test]
+    fn v4_handles_committees_too_big_for_v3() {
+        let just_v4 = committee_justification(1200, 0..1200);
+        let encoded_just =
+            versioned_encode(just_v4.clone(), COMPACT_JUSTIFICATION_FINALITY_VERSION);
+        assert!(encoded_just.len() > u16::MAX as usize);
+        let decoded = backwards_compatible_decode(encoded_just);
+        assert_eq!(decoded, Ok(just_v4));
+    }
+
+    #[test]
+    fn v4_is_smaller_than_v3_for_sparse_signatures() {
+        let justification = committee_justification(100, (0..100).step_by(3));
+        let v3 = versioned_encode(justification.clone(), DEFAULT_FINALITY_VERSION);
+        let v4 = versioned_encode(justification, COMPACT_JUSTIFICATION_FINALITY_VERSION);
+        assert!(v4.len() < v3.len());
+    }
+
+    #[test]
+    fn versioned_encode_switches_format_with_finality_version() {
+        let justification = committee_justification(4, 0..3);
+        let v3 = versioned_encode(
+            justification.clone(),
+            COMPACT_JUSTIFICATION_FINALITY_VERSION - 1,
+        );
+        let v4 = versioned_encode(justification, COMPACT_JUSTIFICATION_FINALITY_VERSION);
+        assert_eq!(Version::decode(&mut v3.as_slice()), Ok(Version(3)));
+        assert_eq!(Version::decode(&mut v4.as_slice()), Ok(Version(4)));
+    }
+
+    #[test]
+    fn v4_rejects_truncated_input() {
+        let encoded = versioned_encode(
+            committee_justification(7, [1, 4, 5].into_iter()),
+            COMPACT_JUSTIFICATION_FINALITY_VERSION,
+        );
+        for len in [3, 4, encoded.len() / 2, encoded.len() - 1] {
+            assert!(
+                VersionedDagestanJustification::decode(&mut &encoded[..len]).is_err(),
+                "decoded a justification truncated to {} bytes",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn v4_rejects_signers_outside_committee() {
+        let signature: Signature = AuthorityPair::from_seed(&[7; 32])
+            .sign(&[0u8, 0u8, 0u8, 0u8])
+            .into();
+        let payload = DagestanJustificationV4::CommitteeMultisignature(CompactSignatureSet {
+            size: NodeCount(3),
+            signers: vec![0b0000_1001],
+            signatures: vec![signature.clone(), signature],
+        })
+        .encode();
+        let encoded = VersionedDagestanJustification::Other(Version(4), payload).encode();
+        assert!(VersionedDagestanJustification::decode(&mut encoded.as_slice()).is_err());
+    }
+
     #[test]
     fn correctly_decodes_other() {
         let other = VersionedDagestanJustification::Other(Version(43), vec![21, 37]);
@@ -295,6 +620,9 @@ mod test {
             Ok(DagestanJustification::EmergencySignature(_)) => {
                 panic!("decoded V1 as emergency signature")
             }
+            Ok(DagestanJustification::AggregateSignature(_)) => {
+                panic!("decoded V1 as aggregate signature")
+            }
             Err(e) => panic!("decoding V1 failed: {}", e),
         }
     }
@@ -380,4 +708,26 @@ mod test {
             10,
         );
     }
+
+    #[test]
+    fn finds_justification_under_either_engine_id() {
+        let legacy = (LEGACY_DAGESTAN_ENGINE_ID, vec![1]);
+        let dedicated = (DAGESTAN_ENGINE_ID, vec![2]);
+
+        let justifications = Justifications::from(legacy.clone());
+        assert_eq!(
+            find_dagestan_justification(&justifications),
+            Some(legacy.clone())
+        );
+
+        let mut justifications = Justifications::from(legacy);
+        justifications.append(dedicated.clone());
+        assert_eq!(
+            find_dagestan_justification(&justifications),
+            Some(dedicated)
+        );
+
+        let justifications = Justifications::from((*b"BABE", vec![3]));
+        assert_eq!(find_dagestan_justification(&justifications), None);
+    }
 }
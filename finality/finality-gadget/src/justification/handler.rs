@@ -2,7 +2,7 @@ use std::time::{Duration, Instant};
 
 use futures::{channel::mpsc, Stream, StreamExt};
 use futures_timer::Delay;
-use log::{debug, error};
+use log::{debug, error, trace};
 use sp_api::BlockT;
 use sp_runtime::traits::Header;
 use tokio::time::timeout;
@@ -10,10 +10,13 @@ use tokio::time::timeout;
 use crate::{
     finalization::BlockFinalizer,
     justification::{
-        requester::BlockRequester, JustificationHandlerConfig, JustificationNotification,
-        JustificationRequestScheduler, SessionInfo, SessionInfoProvider, Verifier,
+        buffer::FutureJustifications, requester::BlockRequester, JustificationHandlerConfig,
+        JustificationNotification, JustificationRequestScheduler, SessionInfo, SessionInfoProvider,
+        Verifier,
     },
-    network, BlockchainBackend, Metrics, STATUS_REPORT_INTERVAL,
+    network,
+    rpc::RpcLink,
+    BlockchainBackend, Metrics, SessionId, STATUS_REPORT_INTERVAL,
 };
 
 pub struct JustificationHandler<B, V, RB, S, SI, F, BB>
@@ -28,6 +31,7 @@ where
 {
     session_info_provider: SI,
     block_requester: BlockRequester<B, RB, S, F, V, BB>,
+    future_justifications: FutureJustifications<B>,
     verifier_timeout: Duration,
     notification_timeout: Duration,
 }
@@ -42,6 +46,7 @@ where
     F: BlockFinalizer<B>,
     BB: BlockchainBackend<B> + 'static,
 {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         session_info_provider: SI,
         block_requester: RB,
@@ -50,6 +55,7 @@ where
         justification_request_scheduler: S,
         metrics: Option<Metrics<<B::Header as Header>::Hash>>,
         justification_handler_config: JustificationHandlerConfig,
+        rpc_link: RpcLink<B>,
     ) -> Self {
         Self {
             session_info_provider,
@@ -59,7 +65,9 @@ where
                 finalizer,
                 justification_request_scheduler,
                 metrics,
+                rpc_link,
             ),
+            future_justifications: FutureJustifications::new(),
             verifier_timeout: justification_handler_config.verifier_timeout,
             notification_timeout: justification_handler_config.notification_timeout,
         }
@@ -69,10 +77,15 @@ where
         mut self,
         authority_justification_rx: mpsc::UnboundedReceiver<JustificationNotification<B>>,
         import_justification_rx: mpsc::UnboundedReceiver<JustificationNotification<B>>,
+        rpc_justification_rx: mpsc::UnboundedReceiver<JustificationNotification<B>>,
     ) {
         let import_stream = wrap_channel_with_logging(import_justification_rx, "import");
         let authority_stream = wrap_channel_with_logging(authority_justification_rx, "aggregator");
-        let mut notification_stream = futures::stream::select(import_stream, authority_stream);
+        let rpc_stream = wrap_channel_with_logging(rpc_justification_rx, "rpc");
+        let mut notification_stream = futures::stream::select(
+            futures::stream::select(import_stream, authority_stream),
+            rpc_stream,
+        );
         let mut last_status_report = Instant::now();
 
         loop {
@@ -92,12 +105,26 @@ where
             }
             let verifier = verifier.expect("We loop until this is some.");
 
+            for notification in self.future_justifications.take(current_session) {
+                let last_finalized_number = self.block_requester.finalized_number();
+                self.block_requester.handle_justification_notification(
+                    notification,
+                    &verifier,
+                    last_finalized_number,
+                    stop_h,
+                );
+            }
+
             match timeout(self.notification_timeout, notification_stream.next()).await {
+                Ok(Some(notification)) if notification.number > stop_h => {
+                    self.keep_future_justification(notification, current_session)
+                        .await;
+                }
                 Ok(Some(notification)) => {
                     self.block_requester.handle_justification_notification(
                         notification,
-                        verifier,
-                        last_finalized_number,
+                        &verifier,
+                        self.block_requester.finalized_number(),
                         stop_h,
                     );
                 }
@@ -106,6 +133,7 @@ where
             }
 
             self.block_requester.request_justification(stop_h);
+            self.block_requester.publish_status();
             if Instant::now().saturating_duration_since(last_status_report)
                 >= STATUS_REPORT_INTERVAL
             {
@@ -114,6 +142,30 @@ where
             }
         }
     }
+
+    async fn keep_future_justification(
+        &mut self,
+        notification: JustificationNotification<B>,
+        current_session: SessionId,
+    ) {
+        let number = notification.number;
+        let session = self
+            .session_info_provider
+            .for_block_num(number)
+            .await
+            .current_session;
+        match self
+            .future_justifications
+            .insert(current_session, session, notification)
+        {
+            true => {
+                trace!(target: "dagestan-justification", "Keeping justification for block {:?} of session {:?} until session {:?} ends. {} justifications kept.", number, session, current_session, self.future_justifications.len())
+            }
+            false => {
+                debug!(target: "dagestan-justification", "Not keeping justification for block {:?} of session {:?}, current session {:?}.", number, session, current_session)
+            }
+        }
+    }
 }
 
 fn wrap_channel_with_logging<B: BlockT>(
@@ -4,27 +4,37 @@ use dagestan_primitives::AuthoritySignature;
 use codec::{Decode, Encode};
 use sp_api::{BlockT, NumberFor};
 
-use crate::{crypto::Signature, SessionId};
+use crate::{
+    crypto::{AggregateSignature, Signature},
+    SessionId,
+};
 
+mod buffer;
 mod compatibility;
 mod handler;
 mod requester;
 mod scheduler;
 
-pub use compatibility::{backwards_compatible_decode, versioned_encode, Error as DecodeError};
+pub use compatibility::{
+    backwards_compatible_decode, find_dagestan_justification, versioned_encode,
+    Error as DecodeError,
+};
 pub use handler::JustificationHandler;
 pub use scheduler::{
-    JustificationRequestScheduler, JustificationRequestSchedulerImpl, SchedulerActions,
+    AdaptiveJustificationRequestScheduler, JustificationRequestScheduler,
+    JustificationRequestSchedulerImpl, RequestTries, SchedulerActions,
 };
 
 use crate::abft::SignatureSet;
 
-/// A proof of block finality, currently in the form of a sufficiently long list of signatures or a
-/// sudo signature of a block for emergency finalization.
+/// A proof of block finality, currently in the form of a sufficiently long list of signatures, a
+/// single BLS signature aggregated from sufficiently many signatures, or a sudo signature of a
+/// block for emergency finalization.
 #[derive(Clone, Encode, Decode, Debug, PartialEq, Eq)]
 pub enum DagestanJustification {
     CommitteeMultisignature(SignatureSet<Signature>),
     EmergencySignature(AuthoritySignature),
+    AggregateSignature(AggregateSignature),
 }
 
 pub trait Verifier<B: BlockT> {
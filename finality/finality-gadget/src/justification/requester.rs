@@ -1,6 +1,5 @@
 use std::{fmt, marker::PhantomData, time::Instant};
 
-use dagestan_primitives::DAGESTAN_ENGINE_ID;
 use log::{debug, error, info, warn};
 use sc_client_api::blockchain::Info;
 use sp_api::{BlockId, BlockT, NumberFor};
@@ -9,11 +8,13 @@ use sp_runtime::traits::{Header, One, Saturating};
 use crate::{
     finalization::BlockFinalizer,
     justification::{
-        scheduler::SchedulerActions, versioned_encode, JustificationNotification,
-        JustificationRequestScheduler, Verifier,
+        scheduler::{RequestTries, SchedulerActions},
+        JustificationNotification, JustificationRequestScheduler, Verifier,
     },
     metrics::Checkpoint,
-    network, BlockHashNum, BlockchainBackend, Metrics,
+    network,
+    rpc::{RequesterStatus, RpcLink},
+    BlockHashNum, BlockchainBackend, Metrics,
 };
 
 /// Threshold for how many tries are needed so that JustificationRequestStatus is logged
@@ -68,9 +69,30 @@ impl<B: BlockT> JustificationRequestStatus<B> {
         self.children_tries = 0;
     }
 
+    fn tries(&self) -> RequestTries {
+        RequestTries {
+            block: self.block_tries,
+            children: self.children_tries,
+        }
+    }
+
     fn should_report(&self) -> bool {
         self.block_tries >= self.report_threshold || self.children_tries >= self.report_threshold
     }
+
+    fn rpc_status(
+        &self,
+        finalized_number: NumberFor<B>,
+    ) -> RequesterStatus<B::Hash, NumberFor<B>> {
+        RequesterStatus {
+            finalized_number,
+            requested_block: self.block_hash_number.as_ref().map(|hn| (hn.hash, hn.num)),
+            block_tries: self.block_tries,
+            children_of: self.parent,
+            children_count: self.n_children,
+            children_tries: self.children_tries,
+        }
+    }
 }
 
 impl<B: BlockT> fmt::Display for JustificationRequestStatus<B> {
@@ -112,6 +134,7 @@ where
     justification_request_scheduler: S,
     metrics: Option<Metrics<<B::Header as Header>::Hash>>,
     request_status: JustificationRequestStatus<B>,
+    rpc_link: RpcLink<B>,
     _phantom: PhantomData<V>,
 }
 
@@ -130,6 +153,7 @@ where
         finalizer: F,
         justification_request_scheduler: S,
         metrics: Option<Metrics<<B::Header as Header>::Hash>>,
+        rpc_link: RpcLink<B>,
     ) -> Self {
         BlockRequester {
             block_requester,
@@ -138,6 +162,7 @@ where
             justification_request_scheduler,
             metrics,
             request_status: JustificationRequestStatus::new(),
+            rpc_link,
             _phantom: PhantomData,
         }
     }
@@ -145,7 +170,7 @@ where
     pub fn handle_justification_notification(
         &mut self,
         notification: JustificationNotification<B>,
-        verifier: V,
+        verifier: &V,
         last_finalized: NumberFor<B>,
         stop_h: NumberFor<B>,
     ) {
@@ -166,11 +191,9 @@ where
         };
 
         debug!(target: "dagestan-justification", "Finalizing block {:?} {:?}", number, hash);
-        let finalization_res = self.finalizer.finalize_block(
-            hash,
-            number,
-            Some((DAGESTAN_ENGINE_ID, versioned_encode(justification))),
-        );
+        let finalization_res = self
+            .finalizer
+            .finalize_block(hash, number, Some(justification.clone()));
         match finalization_res {
             Ok(()) => {
                 self.justification_request_scheduler.on_block_finalized();
@@ -179,6 +202,11 @@ where
                 if let Some(metrics) = &self.metrics {
                     metrics.report_block(hash, Instant::now(), Checkpoint::Finalized);
                 }
+                self.rpc_link.notify_justification(JustificationNotification {
+                    justification,
+                    hash,
+                    number,
+                });
             }
             Err(e) => {
                 error!(target: "dagestan-justification", "Fail in finalization of {:?} {:?} -- {:?}", number, hash, e);
@@ -192,16 +220,32 @@ where
         }
     }
 
+    /// Shares the current status with the RPC.
+    pub fn publish_status(&self) {
+        self.rpc_link
+            .report_requester_status(self.request_status.rpc_status(self.finalized_number()));
+    }
+
     pub fn request_justification(&mut self, wanted: NumberFor<B>) {
-        match self.justification_request_scheduler.schedule_action() {
+        match self
+            .justification_request_scheduler
+            .schedule_action(self.request_status.tries())
+        {
             SchedulerActions::Request => {
                 let info = self.blockchain_backend.info();
                 self.request_children(&info);
                 self.request_wanted(wanted, &info);
             }
+            SchedulerActions::RequestStale => {
+                let info = self.blockchain_backend.info();
+                self.request_children(&info);
+                self.request_wanted(wanted, &info);
+                self.request_stale_blocks(&info);
+            }
             SchedulerActions::ClearQueue => {
                 debug!(target: "dagestan-justification", "Clearing justification request queue");
                 self.block_requester.clear_justification_requests();
+                self.request_status.reset();
             }
             SchedulerActions::Wait => (),
         }
@@ -239,6 +283,21 @@ where
         }
     }
 
+    // When justification requests keep failing, we additionally ask for the blocks themselves,
+    // which makes sync download them again, together with their justifications.
+    fn request_stale_blocks(&mut self, info: &Info<B>) {
+        let finalized_number = info.finalized_number;
+        for child in self.blockchain_backend.children(info.finalized_hash) {
+            debug!(target: "dagestan-justification", "Requesting block {:?} {:?} missing a justification.", finalized_number + NumberFor::<B>::one(), child);
+            self.block_requester
+                .request_stale_block(child, finalized_number + NumberFor::<B>::one());
+        }
+        if let Some(hn) = &self.request_status.block_hash_number {
+            debug!(target: "dagestan-justification", "Requesting block {:?} {:?} missing a justification.", hn.num, hn.hash);
+            self.block_requester.request_stale_block(hn.hash, hn.num);
+        }
+    }
+
     // This request is important in the case when we are far behind and want to catch up.
     fn request_wanted(&mut self, mut top_wanted: NumberFor<B>, info: &Info<B>) {
         let best_number = info.best_number;
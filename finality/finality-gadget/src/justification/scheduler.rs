@@ -1,20 +1,42 @@
 use std::{
-    cmp::min,
+    cmp::{max, min},
     time::{Duration, Instant},
 };
 
+use rand::Rng;
+
 use crate::{MillisecsPerBlock, SessionPeriod};
 
+/// Tries after which we also ask for the blocks missing justifications, as if they were on a stale
+/// fork, so that they get downloaded again together with their justifications.
+const STALE_BLOCK_TRIES: u32 = 3;
+/// Tries after which we clear the whole justification request queue.
+const CLEAR_QUEUE_TRIES: u32 = 6;
+/// The newest finalization counts with weight `1 / LATENCY_AVERAGE_WINDOW` in the learned latency.
+const LATENCY_AVERAGE_WINDOW: u32 = 8;
+
 pub enum SchedulerActions {
     ClearQueue,
     Request,
+    /// Request the justifications and the blocks missing them.
+    RequestStale,
     Wait,
 }
 
+/// How many times in a row the same justifications were requested without anything getting
+/// finalized.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RequestTries {
+    /// Requests for the block we want to finalize up to.
+    pub block: u32,
+    /// Requests for the children of the last finalized block.
+    pub children: u32,
+}
+
 /// Bunch of methods for managing frequency of sending justification requests.
 pub trait JustificationRequestScheduler {
     /// Decides whether we can request new justification.
-    fn schedule_action(&mut self) -> SchedulerActions;
+    fn schedule_action(&mut self, tries: RequestTries) -> SchedulerActions;
     /// Notice block finalization.
     fn on_block_finalized(&mut self);
     /// Notice request sending.
@@ -58,7 +80,7 @@ impl JustificationRequestSchedulerImpl {
 }
 
 impl JustificationRequestScheduler for JustificationRequestSchedulerImpl {
-    fn schedule_action(&mut self) -> SchedulerActions {
+    fn schedule_action(&mut self, _tries: RequestTries) -> SchedulerActions {
         let now = Instant::now();
         if self.enough_time_elapsed() {
             self.attempt += 1;
@@ -84,3 +106,159 @@ impl JustificationRequestScheduler for JustificationRequestSchedulerImpl {
         self.last_request_time = Instant::now();
     }
 }
+
+/// Requests justifications once finalization takes longer than it usually does, backing off
+/// exponentially with jitter while nothing gets finalized. How far the requests escalate depends on
+/// how many times the requester already asked for the same justifications.
+pub struct AdaptiveJustificationRequestScheduler {
+    last_request_time: Instant,
+    last_finalization_time: Instant,
+    /// Moving average of the time between consecutive finalizations.
+    finalization_latency: Duration,
+    delay: Duration,
+    min_delay: Duration,
+    max_delay: Duration,
+}
+
+impl AdaptiveJustificationRequestScheduler {
+    pub fn new(session_period: &SessionPeriod, millisecs_per_block: &MillisecsPerBlock) -> Self {
+        let block_time = Duration::from_millis(millisecs_per_block.0);
+        let min_delay = block_time / 2;
+        // Even when stuck, we ask a few times per session, so we do not fall behind for long.
+        let max_delay = max(min_delay, block_time.saturating_mul(session_period.0) / 4);
+        Self {
+            last_request_time: Instant::now(),
+            last_finalization_time: Instant::now(),
+            finalization_latency: min(2 * block_time, max_delay),
+            delay: Duration::ZERO,
+            min_delay,
+            max_delay,
+        }
+    }
+
+    fn record_finalization(&mut self, latency: Duration) {
+        let latency = latency.clamp(self.min_delay, self.max_delay);
+        self.finalization_latency = (self.finalization_latency * (LATENCY_AVERAGE_WINDOW - 1)
+            + latency)
+            / LATENCY_AVERAGE_WINDOW;
+    }
+
+    /// The delay before the next request after `tries` unsuccessful ones. The `jitter`, between 0
+    /// and 1, randomizes the upper half of the delay.
+    fn backoff(&self, tries: u32, jitter: f64) -> Duration {
+        let delay = self
+            .finalization_latency
+            .saturating_mul(1 << min(tries, 16))
+            .min(self.max_delay);
+        delay / 2 + (delay / 2).mul_f64(jitter)
+    }
+
+    fn escalation(tries: RequestTries) -> SchedulerActions {
+        match max(tries.block, tries.children) {
+            tries if tries >= CLEAR_QUEUE_TRIES => SchedulerActions::ClearQueue,
+            tries if tries >= STALE_BLOCK_TRIES => SchedulerActions::RequestStale,
+            _ => SchedulerActions::Request,
+        }
+    }
+}
+
+impl JustificationRequestScheduler for AdaptiveJustificationRequestScheduler {
+    fn schedule_action(&mut self, tries: RequestTries) -> SchedulerActions {
+        let now = Instant::now();
+        if now.saturating_duration_since(self.last_finalization_time) <= self.finalization_latency
+            || now.saturating_duration_since(self.last_request_time) <= self.delay
+        {
+            return SchedulerActions::Wait;
+        }
+        self.last_request_time = now;
+        self.delay = self.backoff(max(tries.block, tries.children), rand::thread_rng().gen());
+        Self::escalation(tries)
+    }
+
+    fn on_block_finalized(&mut self) {
+        let now = Instant::now();
+        self.record_finalization(now.saturating_duration_since(self.last_finalization_time));
+        self.last_finalization_time = now;
+        self.delay = Duration::ZERO;
+    }
+
+    fn on_request_sent(&mut self) {
+        self.last_request_time = Instant::now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{
+        AdaptiveJustificationRequestScheduler, JustificationRequestScheduler, RequestTries,
+        SchedulerActions,
+    };
+    use crate::{MillisecsPerBlock, SessionPeriod};
+
+    fn scheduler() -> AdaptiveJustificationRequestScheduler {
+        AdaptiveJustificationRequestScheduler::new(&SessionPeriod(100), &MillisecsPerBlock(1000))
+    }
+
+    fn tries(block: u32, children: u32) -> RequestTries {
+        RequestTries { block, children }
+    }
+
+    #[test]
+    fn waits_for_usual_finalization_latency() {
+        let mut scheduler = scheduler();
+        assert!(matches!(
+            scheduler.schedule_action(tries(0, 0)),
+            SchedulerActions::Wait
+        ));
+    }
+
+    #[test]
+    fn escalates_with_tries() {
+        use AdaptiveJustificationRequestScheduler as Scheduler;
+        assert!(matches!(
+            Scheduler::escalation(tries(0, 0)),
+            SchedulerActions::Request
+        ));
+        assert!(matches!(
+            Scheduler::escalation(tries(2, 1)),
+            SchedulerActions::Request
+        ));
+        assert!(matches!(
+            Scheduler::escalation(tries(1, 3)),
+            SchedulerActions::RequestStale
+        ));
+        assert!(matches!(
+            Scheduler::escalation(tries(6, 0)),
+            SchedulerActions::ClearQueue
+        ));
+    }
+
+    #[test]
+    fn backs_off_exponentially_up_to_limit() {
+        let scheduler = scheduler();
+        let base = Duration::from_secs(2);
+        assert_eq!(scheduler.backoff(0, 1.0), base);
+        assert_eq!(scheduler.backoff(0, 0.0), base / 2);
+        assert_eq!(scheduler.backoff(2, 1.0), 4 * base);
+        assert_eq!(scheduler.backoff(100, 1.0), Duration::from_secs(25));
+        let jittered = scheduler.backoff(1, 0.5);
+        assert!(jittered > base && jittered < 2 * base);
+    }
+
+    #[test]
+    fn learns_finalization_latency() {
+        let mut scheduler = scheduler();
+        for _ in 0..100 {
+            scheduler.record_finalization(Duration::from_millis(700));
+        }
+        let learned = scheduler.finalization_latency;
+        assert!(learned > Duration::from_millis(690) && learned < Duration::from_millis(710));
+
+        for _ in 0..100 {
+            scheduler.record_finalization(Duration::from_secs(3600));
+        }
+        assert!(scheduler.finalization_latency <= Duration::from_secs(25));
+    }
+}
@@ -2,13 +2,14 @@ extern crate core;
 
 use std::{fmt::Debug, path::PathBuf, sync::Arc};
 
-use codec::{Decode, Encode, Output};
-use derive_more::Display;
+use codec::{Decode, Encode};
 use futures::{
     channel::{mpsc, oneshot},
     Future,
 };
-use sc_client_api::{Backend, BlockchainEvents, Finalizer, LockImportRun, TransactionFor};
+use sc_client_api::{
+    AuxStore, Backend, BlockBackend, BlockchainEvents, Finalizer, LockImportRun, TransactionFor,
+};
 use sc_consensus::BlockImport;
 use sc_network::NetworkService;
 use sc_network_common::ExHashT;
@@ -20,14 +21,10 @@ use sp_runtime::traits::{BlakeTwo256, Block, Header};
 use tokio::time::Duration;
 
 use crate::{
-    abft::{CurrentNetworkData, LegacyNetworkData, CURRENT_VERSION, LEGACY_VERSION},
-    aggregation::{CurrentRmcNetworkData, LegacyRmcNetworkData},
-    network::data::split::Split,
     session::{
         first_block_of_session, last_block_of_session, session_id_from_block_num,
         SessionBoundaries, SessionId,
     },
-    VersionedTryFromError::{ExpectedNewGotOld, ExpectedOldGotNew},
 };
 
 mod abft;
@@ -42,23 +39,35 @@ pub mod metrics;
 mod network;
 mod nodes;
 mod party;
+pub mod rpc;
 mod session;
 mod session_map;
-// TODO: remove when module is used
-#[allow(dead_code)]
 mod sync;
 #[cfg(test)]
 pub mod testing;
+mod versions;
+mod warp_sync;
 
 pub use abft::{Keychain, NodeCount, NodeIndex, Recipient, SignatureSet, SpawnHandle};
 pub use dagestan_primitives::{AuthorityId, AuthorityPair, AuthoritySignature};
+pub use data_io::{NoPayload, Payload, PayloadProvider, PayloadSink, MAX_PAYLOAD_SIZE};
 pub use import::DagestanBlockImport;
 pub use justification::{DagestanJustification, JustificationNotification};
-pub use network::{Protocol, ProtocolNaming};
+pub use network::{ImportBlocks, Protocol, ProtocolNaming};
 pub use nodes::{run_nonvalidator_node, run_validator_node};
+pub use party::backup::{
+    inspect_backup, truncate_backup, AuxBackupStore, BackupInspection, BackupStore, BackupUnit,
+    FileBackupStore, InspectedUnit, InspectionError,
+};
 pub use session::SessionPeriod;
+pub use session_map::{ReadOnlySessionMap, SharedSessionMap};
+pub use versions::{ConfiguredPayload, VersionedNetworkData, VersionedTryFromError};
+pub use warp_sync::{
+    AuthorityDataStorageKeys, Error as WarpSyncError, FinalityProofProvider, WarpSyncFragment,
+    WarpSyncProof, WarpSyncTarget, MAX_WARP_SYNC_PROOF_SIZE,
+};
 
-use crate::compatibility::{Version, Versioned};
+use crate::compatibility::Version;
 pub use crate::metrics::Metrics;
 
 /// Constant defining how often components of dagestan-finality-gadget should report their state
@@ -94,104 +103,6 @@ pub struct MillisecsPerBlock(pub u64);
 #[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash, Ord, PartialOrd, Encode, Decode)]
 pub struct UnitCreationDelay(pub u64);
 
-pub type LegacySplitData<B> = Split<LegacyNetworkData<B>, LegacyRmcNetworkData<B>>;
-pub type CurrentSplitData<B> = Split<CurrentNetworkData<B>, CurrentRmcNetworkData<B>>;
-
-impl<B: Block> Versioned for LegacyNetworkData<B> {
-    const VERSION: Version = Version(LEGACY_VERSION);
-}
-
-impl<B: Block> Versioned for CurrentNetworkData<B> {
-    const VERSION: Version = Version(CURRENT_VERSION);
-}
-
-/// The main purpose of this data type is to enable a seamless transition between protocol versions at the Network level. It
-/// provides a generic implementation of the Decode and Encode traits (LE byte representation) by prepending byte
-/// representations for provided type parameters with their version (they need to implement the `Versioned` trait). If one
-/// provides data types that declares equal versions, the first data type parameter will have priority while decoding. Keep in
-/// mind that in such case, `decode` might fail even if the second data type would be able decode provided byte representation.
-#[derive(Clone)]
-pub enum VersionedEitherMessage<L, R> {
-    Left(L),
-    Right(R),
-}
-
-impl<L: Versioned + Decode, R: Versioned + Decode> Decode for VersionedEitherMessage<L, R> {
-    fn decode<I: codec::Input>(input: &mut I) -> Result<Self, codec::Error> {
-        let version = Version::decode(input)?;
-        if version == L::VERSION {
-            return Ok(VersionedEitherMessage::Left(L::decode(input)?));
-        }
-        if version == R::VERSION {
-            return Ok(VersionedEitherMessage::Right(R::decode(input)?));
-        }
-        Err("Invalid version while decoding VersionedEitherMessage".into())
-    }
-}
-
-impl<L: Versioned + Encode, R: Versioned + Encode> Encode for VersionedEitherMessage<L, R> {
-    fn encode_to<T: Output + ?Sized>(&self, dest: &mut T) {
-        match self {
-            VersionedEitherMessage::Left(left) => {
-                L::VERSION.encode_to(dest);
-                left.encode_to(dest);
-            }
-            VersionedEitherMessage::Right(right) => {
-                R::VERSION.encode_to(dest);
-                right.encode_to(dest);
-            }
-        }
-    }
-
-    fn size_hint(&self) -> usize {
-        match self {
-            VersionedEitherMessage::Left(left) => L::VERSION.size_hint() + left.size_hint(),
-            VersionedEitherMessage::Right(right) => R::VERSION.size_hint() + right.size_hint(),
-        }
-    }
-}
-
-pub type VersionedNetworkData<B> = VersionedEitherMessage<LegacySplitData<B>, CurrentSplitData<B>>;
-
-#[derive(Debug, Display, Clone)]
-pub enum VersionedTryFromError {
-    ExpectedNewGotOld,
-    ExpectedOldGotNew,
-}
-
-impl<B: Block> TryFrom<VersionedNetworkData<B>> for LegacySplitData<B> {
-    type Error = VersionedTryFromError;
-
-    fn try_from(value: VersionedNetworkData<B>) -> Result<Self, Self::Error> {
-        Ok(match value {
-            VersionedEitherMessage::Left(data) => data,
-            VersionedEitherMessage::Right(_) => return Err(ExpectedOldGotNew),
-        })
-    }
-}
-impl<B: Block> TryFrom<VersionedNetworkData<B>> for CurrentSplitData<B> {
-    type Error = VersionedTryFromError;
-
-    fn try_from(value: VersionedNetworkData<B>) -> Result<Self, Self::Error> {
-        Ok(match value {
-            VersionedEitherMessage::Left(_) => return Err(ExpectedNewGotOld),
-            VersionedEitherMessage::Right(data) => data,
-        })
-    }
-}
-
-impl<B: Block> From<LegacySplitData<B>> for VersionedNetworkData<B> {
-    fn from(data: LegacySplitData<B>) -> Self {
-        VersionedEitherMessage::Left(data)
-    }
-}
-
-impl<B: Block> From<CurrentSplitData<B>> for VersionedNetworkData<B> {
-    fn from(data: CurrentSplitData<B>) -> Self {
-        VersionedEitherMessage::Right(data)
-    }
-}
-
 pub trait ClientForDagestan<B, BE>:
     LockImportRun<B, BE>
     + Finalizer<B, BE>
@@ -200,6 +111,8 @@ pub trait ClientForDagestan<B, BE>:
     + HeaderBackend<B>
     + HeaderMetadata<B, Error = sp_blockchain::Error>
     + BlockchainEvents<B>
+    + BlockBackend<B>
+    + AuxStore
 where
     BE: Backend<B>,
     B: Block,
@@ -216,6 +129,8 @@ where
         + HeaderBackend<B>
         + HeaderMetadata<B, Error = sp_blockchain::Error>
         + BlockchainEvents<B>
+        + BlockBackend<B>
+        + AuxStore
         + BlockImport<B, Transaction = TransactionFor<BE, B>, Error = sp_consensus::Error>,
 {
 }
@@ -242,7 +157,7 @@ impl<H, N> From<(H, N)> for HashNum<H, N> {
 
 pub type BlockHashNum<B> = HashNum<<B as Block>::Hash, NumberFor<B>>;
 
-pub struct DagestanConfig<B: Block, H: ExHashT, C, SC, BB> {
+pub struct DagestanConfig<B: Block, H: ExHashT, C, SC, BB, BI, PP = NoPayload, PS = NoPayload> {
     pub network: Arc<NetworkService<B, H>>,
     pub client: Arc<C>,
     pub blockchain_backend: BB,
@@ -254,10 +169,24 @@ pub struct DagestanConfig<B: Block, H: ExHashT, C, SC, BB> {
     pub session_period: SessionPeriod,
     pub millisecs_per_block: MillisecsPerBlock,
     pub unit_creation_delay: UnitCreationDelay,
+    /// The directory to keep AlephBFT backups in. If not given, they are kept in the client database.
     pub backup_saving_path: Option<PathBuf>,
     pub external_addresses: Vec<String>,
     pub validator_port: u16,
     pub protocol_naming: ProtocolNaming,
+    pub rpc_link: rpc::RpcLink<B>,
+    /// The map of session authorities the gadget keeps up to date. Its read only view can be
+    /// shared with the block import, see `DagestanBlockImport::with_verification`.
+    pub session_map: SharedSessionMap,
+    /// Imports the blocks of pending proposals fetched directly from the members that proposed
+    /// them, see `ImportBlocks`.
+    pub block_importer: BI,
+    /// Provides the payloads the node proposes in sessions ordering payloads, every such session
+    /// uses a clone of it. Use `NoPayload` if the chain orders none.
+    pub payload_provider: PP,
+    /// Receives the payloads ordered in sessions ordering payloads, every such session uses a
+    /// clone of it.
+    pub payload_sink: PS,
 }
 
 pub trait BlockchainBackend<B: Block> {
@@ -8,7 +8,9 @@ use std::{
 use log::{trace, warn};
 use lru::LruCache;
 use parking_lot::Mutex;
-use prometheus_endpoint::{register, Gauge, PrometheusError, Registry, U64};
+use prometheus_endpoint::{
+    register, Counter, CounterVec, Gauge, GaugeVec, Opts, PrometheusError, Registry, U64,
+};
 use sc_service::Arc;
 
 // How many entries (block hash + timestamp) we keep in memory per one checkpoint type.
@@ -69,9 +71,102 @@ pub(crate) enum Checkpoint {
     Finalized,
 }
 
+/// What the data store holds back and what it does about it.
+#[derive(Clone)]
+pub(crate) struct DataStoreMetrics {
+    pending_proposals: GaugeVec<U64>,
+    pending_messages: Gauge<U64>,
+    cached_available_proposals: Gauge<U64>,
+    pruned_messages: CounterVec<U64>,
+    block_requests: Counter<U64>,
+    justification_requests: Counter<U64>,
+}
+
+impl DataStoreMetrics {
+    fn register(registry: &Registry) -> Result<Self, PrometheusError> {
+        Ok(DataStoreMetrics {
+            pending_proposals: register(
+                GaugeVec::new(
+                    Opts::new(
+                        "dagestan_data_store_pending_proposals",
+                        "Number of proposals waiting to become available, by status",
+                    ),
+                    &["status"],
+                )?,
+                registry,
+            )?,
+            pending_messages: register(
+                Gauge::new(
+                    "dagestan_data_store_pending_messages",
+                    "Number of messages waiting for their proposals to become available",
+                )?,
+                registry,
+            )?,
+            cached_available_proposals: register(
+                Gauge::new(
+                    "dagestan_data_store_cached_available_proposals",
+                    "Number of entries in the cache of available proposals",
+                )?,
+                registry,
+            )?,
+            pruned_messages: register(
+                CounterVec::new(
+                    Opts::new(
+                        "dagestan_data_store_pruned_messages_total",
+                        "Number of pending messages dropped because of the exceeded limit",
+                    ),
+                    &["limit"],
+                )?,
+                registry,
+            )?,
+            block_requests: register(
+                Counter::new(
+                    "dagestan_data_store_block_requests_total",
+                    "Number of requests for blocks missing for too long",
+                )?,
+                registry,
+            )?,
+            justification_requests: register(
+                Counter::new(
+                    "dagestan_data_store_justification_requests_total",
+                    "Number of requests for justifications missing for too long",
+                )?,
+                registry,
+            )?,
+        })
+    }
+
+    pub(crate) fn set_pending_proposals(&self, status: &str, count: usize) {
+        self.pending_proposals
+            .with_label_values(&[status])
+            .set(count as u64);
+    }
+
+    pub(crate) fn set_pending_messages(&self, count: usize) {
+        self.pending_messages.set(count as u64);
+    }
+
+    pub(crate) fn set_cached_available_proposals(&self, count: usize) {
+        self.cached_available_proposals.set(count as u64);
+    }
+
+    pub(crate) fn report_pruned_message(&self, limit: &str) {
+        self.pruned_messages.with_label_values(&[limit]).inc();
+    }
+
+    pub(crate) fn report_block_request(&self) {
+        self.block_requests.inc();
+    }
+
+    pub(crate) fn report_justification_request(&self) {
+        self.justification_requests.inc();
+    }
+}
+
 #[derive(Clone)]
 pub struct Metrics<H: Key> {
     inner: Arc<Mutex<Inner<H>>>,
+    data_store: DataStoreMetrics,
 }
 
 impl<H: Key> Metrics<H> {
@@ -108,7 +203,14 @@ impl<H: Key> Metrics<H> {
                 .collect(),
         }));
 
-        Ok(Self { inner })
+        Ok(Self {
+            inner,
+            data_store: DataStoreMetrics::register(registry)?,
+        })
+    }
+
+    pub(crate) fn data_store(&self) -> &DataStoreMetrics {
+        &self.data_store
     }
 
     pub(crate) fn report_block(
@@ -127,6 +229,8 @@ impl<H: Key> Metrics<H> {
 mod tests {
     use std::cmp::min;
 
+    use prometheus_endpoint::prometheus::proto::MetricType;
+
     use super::*;
 
     fn starts_for<H: Key>(m: &Metrics<H>, c: Checkpoint) -> usize {
@@ -156,6 +260,31 @@ mod tests {
         check_reporting_with_memory_excess(&m, Checkpoint::Imported);
     }
 
+    #[test]
+    fn data_store_metrics_are_registered() {
+        let registry = Registry::new();
+        let metrics = Metrics::<usize>::register(&registry).unwrap();
+        metrics
+            .data_store()
+            .set_pending_proposals("pending_top_block", 3);
+        metrics.data_store().set_pending_messages(2);
+        metrics.data_store().set_cached_available_proposals(1);
+        metrics.data_store().report_pruned_message("messages");
+        metrics.data_store().report_block_request();
+        metrics.data_store().report_justification_request();
+
+        let families: Vec<_> = registry
+            .gather()
+            .into_iter()
+            .filter(|family| family.get_name().starts_with("dagestan_data_store"))
+            .collect();
+        assert_eq!(families.len(), 6);
+        for family in families {
+            let is_counter = family.get_field_type() == MetricType::COUNTER;
+            assert_eq!(family.get_name().ends_with("_total"), is_counter);
+        }
+    }
+
     #[test]
     fn given_not_monotonic_clock_when_report_block_is_called_repeatedly_code_does_not_panic() {
         let metrics = Metrics::<usize>::register(&Registry::new()).unwrap();
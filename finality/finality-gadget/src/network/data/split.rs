@@ -15,7 +15,7 @@ use crate::{
         },
         Data,
     },
-    Recipient, Version, Versioned,
+    Recipient,
 };
 
 /// Used for routing data through split networks.
@@ -25,10 +25,6 @@ pub enum Split<LeftData: Data, RightData: Data> {
     Right(RightData),
 }
 
-impl<LeftData: Versioned + Data, RightData: Data> Versioned for Split<LeftData, RightData> {
-    const VERSION: Version = LeftData::VERSION;
-}
-
 trait Convert {
     type From;
     type To;
@@ -21,9 +21,86 @@ use crate::{
 };
 
 enum Command<D: Data, P: Clone + Debug + Eq + Hash + Send + 'static> {
-    Send(D, P, Protocol),
-    SendToRandom(D, HashSet<P>, Protocol),
-    Broadcast(D, Protocol),
+    Send(D, P),
+    SendToRandom(D, HashSet<P>),
+    Broadcast(D),
+}
+
+/// The peers connected using a single protocol, together with the channels for sending data to
+/// them.
+struct ProtocolPeers<D: Data, P: Clone + Debug + Eq + Hash + Send + 'static> {
+    connected: HashSet<P>,
+    senders: HashMap<P, TracingUnboundedSender<D>>,
+}
+
+impl<D: Data, P: Clone + Debug + Eq + Hash + Send + 'static> ProtocolPeers<D, P> {
+    fn new() -> Self {
+        ProtocolPeers {
+            connected: HashSet::new(),
+            senders: HashMap::new(),
+        }
+    }
+
+    fn add(&mut self, peer: P, sender: TracingUnboundedSender<D>) {
+        self.connected.insert(peer.clone());
+        self.senders.insert(peer, sender);
+    }
+
+    fn remove(&mut self, peer: &P) {
+        self.connected.remove(peer);
+        self.senders.remove(peer);
+    }
+
+    fn send_to_peer(&mut self, data: D, peer: &P) -> Result<(), SendError> {
+        match self.senders.get_mut(peer) {
+            Some(sender) => {
+                match sender.unbounded_send(data) {
+                    Err(e) => {
+                        // Receiver can also be dropped when thread cannot send to peer. In case receiver is dropped this entry will be removed by Event::NotificationStreamClosed
+                        // No need to remove the entry here
+                        if e.is_disconnected() {
+                            trace!(target: "dagestan-network", "Failed sending data to peer because peer_sender receiver is dropped: {:?}", peer);
+                        }
+                        Err(SendError::SendingFailed)
+                    }
+                    Ok(_) => Ok(()),
+                }
+            }
+            None => Err(SendError::MissingSender),
+        }
+    }
+
+    fn send(&mut self, data: D, peer_id: P) {
+        if let Err(e) = self.send_to_peer(data, &peer_id) {
+            trace!(target: "dagestan-network", "Failed to send to peer{:?}, {:?}", peer_id, e);
+        }
+    }
+
+    fn random_peer<'a>(&'a self, peer_ids: &'a HashSet<P>) -> Option<&'a P> {
+        peer_ids
+            .intersection(&self.connected)
+            .into_iter()
+            .choose(&mut thread_rng())
+            .or(self.connected.iter().choose(&mut thread_rng()))
+    }
+
+    fn send_to_random(&mut self, data: D, peer_ids: HashSet<P>) {
+        let peer_id = match self.random_peer(&peer_ids) {
+            Some(peer_id) => peer_id.clone(),
+            None => {
+                trace!(target: "dagestan-network", "Failed to send to random peer, no peers are available.");
+                return;
+            }
+        };
+        self.send(data, peer_id);
+    }
+
+    fn broadcast(&mut self, data: D) {
+        let peers = self.connected.clone();
+        for peer in peers {
+            self.send(data.clone(), peer);
+        }
+    }
 }
 
 /// A service managing all the direct interaction with the underlying network implementation. It
@@ -32,20 +109,20 @@ enum Command<D: Data, P: Clone + Debug + Eq + Hash + Send + 'static> {
 ///   1. Messages are forwarded to the user.
 ///   2. Various forms of (dis)connecting, keeping track of all currently connected nodes.
 /// 3. Outgoing messages, sending them out, using 1.2. to broadcast.
-pub struct Service<N: RawNetwork, D: Data> {
+/// The authentication and block sync protocols use separate data types, `AD` and `BSD`
+/// respectively.
+pub struct Service<N: RawNetwork, AD: Data, BSD: Data> {
     network: N,
-    messages_from_user: mpsc::UnboundedReceiver<Command<D, N::PeerId>>,
-    messages_for_authentication_user: mpsc::UnboundedSender<(D, N::PeerId)>,
-    messages_for_block_sync_user: mpsc::UnboundedSender<(D, N::PeerId)>,
-    authentication_connected_peers: HashSet<N::PeerId>,
-    authentication_peer_senders: HashMap<N::PeerId, TracingUnboundedSender<D>>,
-    block_sync_connected_peers: HashSet<N::PeerId>,
-    block_sync_peer_senders: HashMap<N::PeerId, TracingUnboundedSender<D>>,
+    messages_from_authentication_user: mpsc::UnboundedReceiver<Command<AD, N::PeerId>>,
+    messages_from_block_sync_user: mpsc::UnboundedReceiver<Command<BSD, N::PeerId>>,
+    messages_for_authentication_user: mpsc::UnboundedSender<(AD, N::PeerId)>,
+    messages_for_block_sync_user: mpsc::UnboundedSender<(BSD, N::PeerId)>,
+    authentication_peers: ProtocolPeers<AD, N::PeerId>,
+    block_sync_peers: ProtocolPeers<BSD, N::PeerId>,
     spawn_handle: SpawnTaskHandle,
 }
 
 struct ServiceInterface<D: Data, P: Clone + Debug + Eq + Hash + Send + 'static> {
-    protocol: Protocol,
     messages_from_service: mpsc::UnboundedReceiver<(D, P)>,
     messages_for_service: mpsc::UnboundedSender<Command<D, P>>,
 }
@@ -74,7 +151,7 @@ impl<D: Data, P: Clone + Debug + Eq + Hash + Send + 'static> Network<D> for Serv
 
     fn send_to(&mut self, data: D, peer_id: Self::PeerId) -> Result<(), Self::Error> {
         self.messages_for_service
-            .unbounded_send(Command::Send(data, peer_id, self.protocol))
+            .unbounded_send(Command::Send(data, peer_id))
             .map_err(|_| Error::ServiceStopped)
     }
 
@@ -84,13 +161,13 @@ impl<D: Data, P: Clone + Debug + Eq + Hash + Send + 'static> Network<D> for Serv
         peer_ids: HashSet<Self::PeerId>,
     ) -> Result<(), Self::Error> {
         self.messages_for_service
-            .unbounded_send(Command::SendToRandom(data, peer_ids, self.protocol))
+            .unbounded_send(Command::SendToRandom(data, peer_ids))
             .map_err(|_| Error::ServiceStopped)
     }
 
     fn broadcast(&mut self, data: D) -> Result<(), Self::Error> {
         self.messages_for_service
-            .unbounded_send(Command::Broadcast(data, self.protocol))
+            .unbounded_send(Command::Broadcast(data))
             .map_err(|_| Error::ServiceStopped)
     }
 
@@ -108,56 +185,44 @@ enum SendError {
     SendingFailed,
 }
 
-impl<N: RawNetwork, D: Data> Service<N, D> {
+impl<N: RawNetwork, AD: Data, BSD: Data> Service<N, AD, BSD> {
     pub fn new(
         network: N,
         spawn_handle: SpawnTaskHandle,
     ) -> (
-        Service<N, D>,
-        impl Network<D, Error = Error, PeerId = N::PeerId>,
-        impl Network<D, Error = Error, PeerId = N::PeerId>,
+        Service<N, AD, BSD>,
+        impl Network<AD, Error = Error, PeerId = N::PeerId>,
+        impl Network<BSD, Error = Error, PeerId = N::PeerId>,
     ) {
         let (messages_for_authentication_user, messages_from_authentication_service) =
             mpsc::unbounded();
         let (messages_for_block_sync_user, messages_from_block_sync_service) = mpsc::unbounded();
-        let (messages_for_service, messages_from_user) = mpsc::unbounded();
+        let (messages_for_authentication_service, messages_from_authentication_user) =
+            mpsc::unbounded();
+        let (messages_for_block_sync_service, messages_from_block_sync_user) = mpsc::unbounded();
         (
             Service {
                 network,
-                messages_from_user,
+                messages_from_authentication_user,
+                messages_from_block_sync_user,
                 messages_for_authentication_user,
                 messages_for_block_sync_user,
                 spawn_handle,
-                authentication_connected_peers: HashSet::new(),
-                authentication_peer_senders: HashMap::new(),
-                block_sync_connected_peers: HashSet::new(),
-                block_sync_peer_senders: HashMap::new(),
+                authentication_peers: ProtocolPeers::new(),
+                block_sync_peers: ProtocolPeers::new(),
             },
             ServiceInterface {
-                protocol: Protocol::Authentication,
                 messages_from_service: messages_from_authentication_service,
-                messages_for_service: messages_for_service.clone(),
+                messages_for_service: messages_for_authentication_service,
             },
             ServiceInterface {
-                protocol: Protocol::BlockSync,
                 messages_from_service: messages_from_block_sync_service,
-                messages_for_service,
+                messages_for_service: messages_for_block_sync_service,
             },
         )
     }
 
-    fn get_sender(
-        &mut self,
-        peer: &N::PeerId,
-        protocol: Protocol,
-    ) -> Option<&mut TracingUnboundedSender<D>> {
-        match protocol {
-            Protocol::Authentication => self.authentication_peer_senders.get_mut(peer),
-            Protocol::BlockSync => self.block_sync_peer_senders.get_mut(peer),
-        }
-    }
-
-    fn peer_sender(
+    fn peer_sender<D: Data>(
         &self,
         peer_id: N::PeerId,
         mut receiver: TracingUnboundedReceiver<D>,
@@ -191,134 +256,98 @@ impl<N: RawNetwork, D: Data> Service<N, D> {
         }
     }
 
-    fn send_to_peer(
-        &mut self,
-        data: D,
-        peer: N::PeerId,
-        protocol: Protocol,
-    ) -> Result<(), SendError> {
-        match self.get_sender(&peer, protocol) {
-            Some(sender) => {
-                match sender.unbounded_send(data) {
-                    Err(e) => {
-                        // Receiver can also be dropped when thread cannot send to peer. In case receiver is dropped this entry will be removed by Event::NotificationStreamClosed
-                        // No need to remove the entry here
-                        if e.is_disconnected() {
-                            trace!(target: "dagestan-network", "Failed sending data to peer because peer_sender receiver is dropped: {:?}", peer);
-                        }
-                        Err(SendError::SendingFailed)
-                    }
-                    Ok(_) => Ok(()),
-                }
-            }
-            None => Err(SendError::MissingSender),
-        }
+    fn send_authentication_data(&mut self, data: AD, peer_id: N::PeerId) {
+        self.authentication_peers.send(data, peer_id)
     }
 
-    fn send(&mut self, data: D, peer_id: N::PeerId, protocol: Protocol) {
-        if let Err(e) = self.send_to_peer(data, peer_id.clone(), protocol) {
-            trace!(target: "dagestan-network", "Failed to send to peer{:?}, {:?}", peer_id, e);
-        }
+    fn send_to_random_authentication(&mut self, data: AD, peer_ids: HashSet<N::PeerId>) {
+        self.authentication_peers.send_to_random(data, peer_ids)
     }
 
-    fn protocol_peers(&self, protocol: Protocol) -> &HashSet<N::PeerId> {
-        match protocol {
-            Protocol::Authentication => &self.authentication_connected_peers,
-            Protocol::BlockSync => &self.block_sync_connected_peers,
-        }
+    fn broadcast_authentication(&mut self, data: AD) {
+        self.authentication_peers.broadcast(data)
     }
 
-    fn random_peer<'a>(
-        &'a self,
-        peer_ids: &'a HashSet<N::PeerId>,
-        protocol: Protocol,
-    ) -> Option<&'a N::PeerId> {
-        peer_ids
-            .intersection(self.protocol_peers(protocol))
-            .into_iter()
-            .choose(&mut thread_rng())
-            .or(self
-                .protocol_peers(protocol)
-                .iter()
-                .choose(&mut thread_rng()))
+    fn send_block_sync_data(&mut self, data: BSD, peer_id: N::PeerId) {
+        self.block_sync_peers.send(data, peer_id)
     }
 
-    fn send_to_random(&mut self, data: D, peer_ids: HashSet<N::PeerId>, protocol: Protocol) {
-        let peer_id = match self.random_peer(&peer_ids, protocol) {
-            Some(peer_id) => peer_id.clone(),
-            None => {
-                trace!(target: "dagestan-network", "Failed to send to random peer, no peers are available.");
-                return;
-            }
-        };
-        self.send(data, peer_id, protocol);
+    fn send_to_random_block_sync(&mut self, data: BSD, peer_ids: HashSet<N::PeerId>) {
+        self.block_sync_peers.send_to_random(data, peer_ids)
     }
 
-    fn broadcast(&mut self, data: D, protocol: Protocol) {
-        let peers = self.protocol_peers(protocol).clone();
-        for peer in peers {
-            self.send(data.clone(), peer, protocol);
+    fn broadcast_block_sync(&mut self, data: BSD) {
+        self.block_sync_peers.broadcast(data)
+    }
+
+    fn handle_authentication_command(&mut self, command: Command<AD, N::PeerId>) {
+        use Command::*;
+        match command {
+            Broadcast(data) => self.broadcast_authentication(data),
+            SendToRandom(data, peer_ids) => self.send_to_random_authentication(data, peer_ids),
+            Send(data, peer_id) => self.send_authentication_data(data, peer_id),
         }
     }
 
-    fn handle_network_event(
-        &mut self,
-        event: Event<N::PeerId>,
-    ) -> Result<(), mpsc::TrySendError<(D, N::PeerId)>> {
+    fn handle_block_sync_command(&mut self, command: Command<BSD, N::PeerId>) {
+        use Command::*;
+        match command {
+            Broadcast(data) => self.broadcast_block_sync(data),
+            SendToRandom(data, peer_ids) => self.send_to_random_block_sync(data, peer_ids),
+            Send(data, peer_id) => self.send_block_sync_data(data, peer_id),
+        }
+    }
+
+    fn handle_network_event(&mut self, event: Event<N::PeerId>) -> Result<(), mpsc::SendError> {
         use Event::*;
         match event {
             StreamOpened(peer, protocol) => {
                 trace!(target: "dagestan-network", "StreamOpened event for peer {:?} and the protocol {:?}.", peer, protocol);
-                let rx = match &protocol {
+                match protocol {
                     Protocol::Authentication => {
                         let (tx, rx) = tracing_unbounded("mpsc_notification_stream_authentication");
-                        self.authentication_connected_peers.insert(peer.clone());
-                        self.authentication_peer_senders.insert(peer.clone(), tx);
-                        rx
+                        self.authentication_peers.add(peer.clone(), tx);
+                        self.spawn_handle.spawn(
+                            "dagestan/network/peer_sender",
+                            None,
+                            self.peer_sender(peer, rx, protocol),
+                        );
                     }
                     Protocol::BlockSync => {
                         let (tx, rx) = tracing_unbounded("mpsc_notification_stream_block_sync");
-                        self.block_sync_connected_peers.insert(peer.clone());
-                        self.block_sync_peer_senders.insert(peer.clone(), tx);
-                        rx
+                        self.block_sync_peers.add(peer.clone(), tx);
+                        self.spawn_handle.spawn(
+                            "dagestan/network/peer_sender",
+                            None,
+                            self.peer_sender(peer, rx, protocol),
+                        );
                     }
                 };
-                self.spawn_handle.spawn(
-                    "dagestan/network/peer_sender",
-                    None,
-                    self.peer_sender(peer, rx, protocol),
-                );
             }
             StreamClosed(peer, protocol) => {
                 trace!(target: "dagestan-network", "StreamClosed event for peer {:?} and protocol {:?}", peer, protocol);
                 match protocol {
-                    Protocol::Authentication => {
-                        self.authentication_connected_peers.remove(&peer);
-                        self.authentication_peer_senders.remove(&peer);
-                    }
-                    Protocol::BlockSync => {
-                        self.block_sync_connected_peers.remove(&peer);
-                        self.block_sync_peer_senders.remove(&peer);
-                    }
+                    Protocol::Authentication => self.authentication_peers.remove(&peer),
+                    Protocol::BlockSync => self.block_sync_peers.remove(&peer),
                 }
             }
             Messages(peer_id, messages) => {
                 for (protocol, data) in messages.into_iter() {
                     match protocol {
-                        Protocol::Authentication => match D::decode(&mut &data[..]) {
+                        Protocol::Authentication => match AD::decode(&mut &data[..]) {
                             Ok(data) => self
                                 .messages_for_authentication_user
-                                .unbounded_send((data, peer_id.clone()))?,
+                                .unbounded_send((data, peer_id.clone()))
+                                .map_err(|e| e.into_send_error())?,
                             Err(e) => {
                                 warn!(target: "dagestan-network", "Error decoding authentication protocol message: {}", e)
                             }
                         },
-                        // This is a bit of a placeholder for now, as we are not yet using this
-                        // protocol. In the future we will not be using the same D as above.
-                        Protocol::BlockSync => match D::decode(&mut &data[..]) {
+                        Protocol::BlockSync => match BSD::decode(&mut &data[..]) {
                             Ok(data) => self
                                 .messages_for_block_sync_user
-                                .unbounded_send((data, peer_id.clone()))?,
+                                .unbounded_send((data, peer_id.clone()))
+                                .map_err(|e| e.into_send_error())?,
                             Err(e) => {
                                 warn!(target: "dagestan-network", "Error decoding block sync protocol message: {}", e)
                             }
@@ -335,11 +364,11 @@ impl<N: RawNetwork, D: Data> Service<N, D> {
 
         status.push_str(&format!(
             "authentication connected peers - {:?}; ",
-            self.authentication_connected_peers.len()
+            self.authentication_peers.connected.len()
         ));
         status.push_str(&format!(
             "block sync connected peers - {:?}; ",
-            self.block_sync_connected_peers.len()
+            self.block_sync_peers.connected.len()
         ));
 
         info!(target: "dagestan-network", "{}", status);
@@ -349,7 +378,14 @@ impl<N: RawNetwork, D: Data> Service<N, D> {
         let mut events_from_network = self.network.event_stream();
 
         let mut status_ticker = time::interval(STATUS_REPORT_INTERVAL);
+        // The service keeps running as long as any of its users does.
+        let mut authentication_user_active = true;
+        let mut block_sync_user_active = true;
         loop {
+            if !authentication_user_active && !block_sync_user_active {
+                error!(target: "dagestan-network", "User message stream ended.");
+                return;
+            }
             tokio::select! {
                 maybe_event = events_from_network.next_event() => match maybe_event {
                     Some(event) => if let Err(e) = self.handle_network_event(event) {
@@ -361,13 +397,18 @@ impl<N: RawNetwork, D: Data> Service<N, D> {
                         return;
                     }
                 },
-                maybe_message = self.messages_from_user.next() => match maybe_message {
-                    Some(Command::Broadcast(message, protocol)) => self.broadcast(message, protocol),
-                    Some(Command::SendToRandom(message, peer_ids, protocol)) => self.send_to_random(message, peer_ids, protocol),
-                    Some(Command::Send(message, peer_id, protocol)) => self.send(message, peer_id, protocol),
+                maybe_message = self.messages_from_authentication_user.next(), if authentication_user_active => match maybe_message {
+                    Some(command) => self.handle_authentication_command(command),
                     None => {
-                        error!(target: "dagestan-network", "User message stream ended.");
-                        return;
+                        debug!(target: "dagestan-network", "Authentication user message stream ended.");
+                        authentication_user_active = false;
+                    }
+                },
+                maybe_message = self.messages_from_block_sync_user.next(), if block_sync_user_active => match maybe_message {
+                    Some(command) => self.handle_block_sync_command(command),
+                    None => {
+                        debug!(target: "dagestan-network", "Block sync user message stream ended.");
+                        block_sync_user_active = false;
                     }
                 },
                 _ = status_ticker.tick() => {
@@ -403,7 +444,7 @@ mod tests {
     pub struct TestData {
         pub network: MockRawNetwork,
         gossip_network: Box<dyn Network<MockData, Error = Error, PeerId = MockPublicKey>>,
-        pub service: Service<MockRawNetwork, MockData>,
+        pub service: Service<MockRawNetwork, MockData, MockData>,
         // `TaskManager` can't be dropped for `SpawnTaskHandle` to work
         _task_manager: TaskManager,
     }
@@ -479,7 +520,7 @@ mod tests {
         });
 
         let message = message(1);
-        test_data.service.broadcast(message.clone(), PROTOCOL);
+        test_data.service.broadcast_authentication(message.clone());
 
         let broadcasted_messages = HashSet::<_>::from_iter(
             test_data
@@ -526,7 +567,7 @@ mod tests {
             });
 
         let message = message(1);
-        test_data.service.broadcast(message.clone(), PROTOCOL);
+        test_data.service.broadcast_authentication(message.clone());
 
         let broadcasted_messages = HashSet::<_>::from_iter(
             test_data
@@ -569,9 +610,11 @@ mod tests {
             .handle_network_event(MockEvent::StreamOpened(peer_id.clone(), PROTOCOL))
             .expect("Should handle");
 
-        test_data.service.broadcast(message_1, PROTOCOL);
+        test_data.service.broadcast_authentication(message_1);
 
-        test_data.service.broadcast(message_2.clone(), PROTOCOL);
+        test_data
+            .service
+            .broadcast_authentication(message_2.clone());
 
         let expected = (message_2.encode(), peer_id, PROTOCOL);
 
@@ -608,9 +651,11 @@ mod tests {
             .handle_network_event(MockEvent::StreamOpened(peer_id.clone(), PROTOCOL))
             .expect("Should handle");
 
-        test_data.service.broadcast(message_1, PROTOCOL);
+        test_data.service.broadcast_authentication(message_1);
 
-        test_data.service.broadcast(message_2.clone(), PROTOCOL);
+        test_data
+            .service
+            .broadcast_authentication(message_2.clone());
 
         let expected = (message_2.encode(), peer_id, PROTOCOL);
 
@@ -665,7 +710,7 @@ mod tests {
 
         test_data
             .service
-            .send(message.clone(), peer_id.clone(), PROTOCOL);
+            .send_authentication_data(message.clone(), peer_id.clone());
 
         let expected = (message.encode(), peer_id, PROTOCOL);
 
@@ -690,7 +735,7 @@ mod tests {
 
         let message = message(1);
 
-        test_data.service.send(message, peer_id, PROTOCOL);
+        test_data.service.send_authentication_data(message, peer_id);
 
         test_data.cleanup().await
     }
@@ -708,11 +753,9 @@ mod tests {
             .handle_network_event(MockEvent::StreamOpened(peer_id.clone(), PROTOCOL))
             .expect("Should handle");
 
-        test_data.service.send_to_random(
-            message.clone(),
-            iter::once(peer_id.clone()).collect(),
-            PROTOCOL,
-        );
+        test_data
+            .service
+            .send_to_random_authentication(message.clone(), iter::once(peer_id.clone()).collect());
 
         let expected = (message.encode(), peer_id, PROTOCOL);
 
@@ -743,11 +786,9 @@ mod tests {
             .handle_network_event(MockEvent::StreamOpened(other_peer_id.clone(), PROTOCOL))
             .expect("Should handle");
 
-        test_data.service.send_to_random(
-            message.clone(),
-            iter::once(peer_id.clone()).collect(),
-            PROTOCOL,
-        );
+        test_data
+            .service
+            .send_to_random_authentication(message.clone(), iter::once(peer_id.clone()).collect());
 
         let expected = (message.encode(), other_peer_id, PROTOCOL);
 
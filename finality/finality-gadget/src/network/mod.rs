@@ -78,6 +78,14 @@ pub trait RequestBlocks<B: Block>: Clone + Send + Sync + 'static {
     fn is_major_syncing(&self) -> bool;
 }
 
+/// Abstraction for importing blocks fetched directly from other nodes.
+pub trait ImportBlocks<B: Block>: Clone + Send + Sync + 'static {
+    /// Import the given blocks, starting from the oldest one. They come from untrusted nodes, so
+    /// they have to be verified just like the blocks downloaded by sync, e.g. by passing them to
+    /// the import queue.
+    fn import_blocks(&self, blocks: Vec<B>);
+}
+
 /// A basic alias for properties we expect basic data to satisfy.
 pub trait Data: Clone + Codec + Send + Sync + 'static {}
 
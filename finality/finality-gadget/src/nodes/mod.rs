@@ -3,13 +3,16 @@ mod validator_node;
 
 use std::{future::Future, sync::Arc};
 
-use dagestan_primitives::{AuthorityId, SessionAuthorityData};
+use dagestan_primitives::{
+    AuthorityId, SessionAuthorityData, Version, BLS_AGGREGATION_FINALITY_VERSION,
+};
 use codec::Encode;
-use log::warn;
+use log::{trace, warn};
+use lru::LruCache;
 pub use nonvalidator_node::run_nonvalidator_node;
+use parking_lot::Mutex;
 use sc_client_api::Backend;
-use sc_network::NetworkService;
-use sc_network_common::ExHashT;
+use sp_core::hashing::blake2_256;
 use sp_runtime::{
     traits::{Block, Header, NumberFor},
     RuntimeAppPublic,
@@ -20,14 +23,16 @@ use crate::{
     crypto::AuthorityVerifier,
     finalization::DagestanFinalizer,
     justification::{
-        DagestanJustification, JustificationHandler, JustificationRequestSchedulerImpl, SessionInfo,
+        DagestanJustification, JustificationHandler, JustificationRequestScheduler, SessionInfo,
         SessionInfoProvider, Verifier,
     },
     last_block_of_session, mpsc,
     mpsc::UnboundedSender,
+    network::RequestBlocks,
+    rpc::RpcLink,
     session_id_from_block_num,
     session_map::ReadOnlySessionMap,
-    BlockchainBackend, JustificationNotification, Metrics, MillisecsPerBlock, SessionPeriod,
+    BlockchainBackend, JustificationNotification, Metrics, SessionId, SessionPeriod,
 };
 
 #[cfg(test)]
@@ -38,28 +43,82 @@ pub mod testing {
 /// Max amount of tries we can not update a finalized block number before we will clear requests queue
 const MAX_ATTEMPTS: u32 = 5;
 
-struct JustificationVerifier {
+/// How many verified justifications we remember, so that a justification received from many peers
+/// during catch-up only gets verified once.
+const VERIFIED_JUSTIFICATIONS_CACHE_SIZE: usize = 4096;
+
+/// Digests of block hashes together with their justifications that were already verified, per
+/// session. Shared by all the verifiers created by a single `SessionInfoProviderImpl`.
+#[derive(Clone)]
+pub struct VerifiedJustifications(Arc<Mutex<LruCache<(SessionId, [u8; 32]), ()>>>);
+
+impl VerifiedJustifications {
+    pub fn new() -> Self {
+        VerifiedJustifications(Arc::new(Mutex::new(LruCache::new(
+            VERIFIED_JUSTIFICATIONS_CACHE_SIZE,
+        ))))
+    }
+
+    fn contains(&self, key: &(SessionId, [u8; 32])) -> bool {
+        self.0.lock().get(key).is_some()
+    }
+
+    fn insert(&self, key: (SessionId, [u8; 32])) {
+        self.0.lock().put(key, ());
+    }
+}
+
+impl Default for VerifiedJustifications {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct JustificationVerifier {
     authority_verifier: AuthorityVerifier,
     emergency_signer: Option<AuthorityId>,
+    finality_version: Version,
+    verified: Option<(SessionId, VerifiedJustifications)>,
 }
 
-impl From<SessionAuthorityData> for JustificationVerifier {
-    fn from(authority_data: SessionAuthorityData) -> Self {
+impl JustificationVerifier {
+    /// A verifier of justifications of blocks of a session with the given authority data and
+    /// finality version.
+    pub fn new(authority_data: SessionAuthorityData, finality_version: Version) -> Self {
+        let authority_verifier = AuthorityVerifier::new(authority_data.authorities().to_vec());
+        let authority_verifier = match authority_data.bls_authorities() {
+            Some(bls_authorities) => {
+                authority_verifier.with_bls_authorities(bls_authorities.clone())
+            }
+            None => authority_verifier,
+        };
         JustificationVerifier {
-            authority_verifier: AuthorityVerifier::new(authority_data.authorities().to_vec()),
+            authority_verifier,
             emergency_signer: authority_data.emergency_finalizer().clone(),
+            finality_version,
+            verified: None,
         }
     }
-}
 
-impl<B: Block> Verifier<B> for JustificationVerifier {
-    fn verify(&self, justification: &DagestanJustification, hash: B::Hash) -> bool {
+    /// Remembers the justifications this verifier accepts in `verified`, and accepts the ones that
+    /// are already there without verifying them again. The session has to be the one whose
+    /// authority data this verifier was created from.
+    pub fn with_cache(mut self, session: SessionId, verified: VerifiedJustifications) -> Self {
+        self.verified = Some((session, verified));
+        self
+    }
+
+    fn verify_uncached<H: std::fmt::Debug>(
+        &self,
+        justification: &DagestanJustification,
+        hash: H,
+        encoded_hash: &[u8],
+    ) -> bool {
         use DagestanJustification::*;
-        let encoded_hash = hash.encode();
         match justification {
             CommitteeMultisignature(multisignature) => match self
                 .authority_verifier
-                .is_complete(&encoded_hash, multisignature)
+                .is_complete(encoded_hash, multisignature)
             {
                 true => true,
                 false => {
@@ -67,6 +126,20 @@ impl<B: Block> Verifier<B> for JustificationVerifier {
                     false
                 }
             },
+            AggregateSignature(_) if self.finality_version < BLS_AGGREGATION_FINALITY_VERSION => {
+                warn!(target: "dagestan-justification", "Aggregate signature for block hash #{:?} in a session with finality version {}, which does not aggregate signatures.", hash, self.finality_version);
+                false
+            }
+            AggregateSignature(aggregate) => match self
+                .authority_verifier
+                .is_complete_aggregate(encoded_hash, aggregate)
+            {
+                true => true,
+                false => {
+                    warn!(target: "dagestan-justification", "Bad aggregate signature for block hash #{:?} {:?}", hash, aggregate);
+                    false
+                }
+            },
             EmergencySignature(signature) => match &self.emergency_signer {
                 Some(emergency_signer) => match emergency_signer.verify(&encoded_hash, signature) {
                     true => true,
@@ -84,29 +157,60 @@ impl<B: Block> Verifier<B> for JustificationVerifier {
     }
 }
 
-struct JustificationParams<B: Block, H: ExHashT, C, BB> {
-    pub network: Arc<NetworkService<B, H>>,
+impl<B: Block> Verifier<B> for JustificationVerifier {
+    fn verify(&self, justification: &DagestanJustification, hash: B::Hash) -> bool {
+        let encoded_hash = hash.encode();
+        let (session, verified) = match &self.verified {
+            Some((session, verified)) => (session, verified),
+            None => return self.verify_uncached(justification, hash, &encoded_hash),
+        };
+        let key = (
+            *session,
+            blake2_256(&(&encoded_hash, justification).encode()),
+        );
+        if verified.contains(&key) {
+            trace!(target: "dagestan-justification", "Justification for block hash #{:?} already verified.", hash);
+            return true;
+        }
+        let result = self.verify_uncached(justification, hash, &encoded_hash);
+        if result {
+            verified.insert(key);
+        }
+        result
+    }
+}
+
+struct JustificationParams<B: Block, RB, C, BB, S> {
+    pub block_requester: RB,
     pub client: Arc<C>,
     pub blockchain_backend: BB,
     pub justification_rx: mpsc::UnboundedReceiver<JustificationNotification<B>>,
     pub metrics: Option<Metrics<<B::Header as Header>::Hash>>,
     pub session_period: SessionPeriod,
-    pub millisecs_per_block: MillisecsPerBlock,
+    pub justification_request_scheduler: S,
     pub session_map: ReadOnlySessionMap,
+    pub rpc_link: RpcLink<B>,
 }
 
-struct SessionInfoProviderImpl {
+#[derive(Clone)]
+pub struct SessionInfoProviderImpl {
     session_authorities: ReadOnlySessionMap,
     session_period: SessionPeriod,
+    verified_justifications: VerifiedJustifications,
 }
 
 impl SessionInfoProviderImpl {
-    fn new(session_authorities: ReadOnlySessionMap, session_period: SessionPeriod) -> Self {
+    pub fn new(session_authorities: ReadOnlySessionMap, session_period: SessionPeriod) -> Self {
         Self {
             session_authorities,
             session_period,
+            verified_justifications: VerifiedJustifications::new(),
         }
     }
+
+    pub fn session_authorities(&self) -> &ReadOnlySessionMap {
+        &self.session_authorities
+    }
 }
 
 #[async_trait::async_trait]
@@ -116,9 +220,12 @@ impl<B: Block> SessionInfoProvider<B, JustificationVerifier> for SessionInfoProv
         let last_block_height = last_block_of_session::<B>(current_session, self.session_period);
         let verifier = self
             .session_authorities
-            .get(current_session)
+            .get_with_finality_version(current_session)
             .await
-            .map(|authority_data| authority_data.into());
+            .map(|(authority_data, finality_version)| {
+                JustificationVerifier::new(authority_data, finality_version)
+                    .with_cache(current_session, self.verified_justifications.clone())
+            });
 
         SessionInfo {
             current_session,
@@ -128,45 +235,189 @@ impl<B: Block> SessionInfoProvider<B, JustificationVerifier> for SessionInfoProv
     }
 }
 
-fn setup_justification_handler<B, H, C, BB, BE>(
-    just_params: JustificationParams<B, H, C, BB>,
+fn setup_justification_handler<B, RB, C, BB, BE, S>(
+    just_params: JustificationParams<B, RB, C, BB, S>,
 ) -> (
     UnboundedSender<JustificationNotification<B>>,
     impl Future<Output = ()>,
 )
 where
     B: Block,
-    H: ExHashT,
+    RB: RequestBlocks<B>,
     C: crate::ClientForDagestan<B, BE> + Send + Sync + 'static,
     C::Api: dagestan_primitives::DagestanSessionApi<B>,
     BE: Backend<B> + 'static,
     BB: BlockchainBackend<B> + 'static + Send,
+    S: JustificationRequestScheduler + Send + 'static,
 {
     let JustificationParams {
-        network,
+        block_requester,
         client,
         blockchain_backend,
         justification_rx,
         metrics,
         session_period,
-        millisecs_per_block,
+        justification_request_scheduler,
         session_map,
+        rpc_link,
     } = just_params;
 
+    let session_info_provider = SessionInfoProviderImpl::new(session_map, session_period);
+    rpc_link.set_session_info(session_info_provider.clone());
+    let rpc_justification_rx = rpc_link
+        .take_emergency_justifications()
+        .expect("the justification handler is the only consumer of emergency justifications");
+
     let handler = JustificationHandler::new(
-        SessionInfoProviderImpl::new(session_map, session_period),
-        network,
+        session_info_provider,
+        block_requester,
         blockchain_backend,
         DagestanFinalizer::new(client),
-        JustificationRequestSchedulerImpl::new(&session_period, &millisecs_per_block, MAX_ATTEMPTS),
+        justification_request_scheduler,
         metrics,
         Default::default(),
+        rpc_link,
     );
 
     let (authority_justification_tx, authority_justification_rx) = mpsc::unbounded();
     (authority_justification_tx, async move {
         handler
-            .run(authority_justification_rx, justification_rx)
+            .run(
+                authority_justification_rx,
+                justification_rx,
+                rpc_justification_rx,
+            )
             .await;
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use codec::Encode;
+    use dagestan_primitives::{
+        AuthorityId, AuthorityPair, SessionAuthorityData, BLS_AGGREGATION_FINALITY_VERSION,
+        DEFAULT_FINALITY_VERSION, KEY_TYPE,
+    };
+    use sp_core::Pair;
+    use sp_keystore::{testing::KeyStore, CryptoStore};
+
+    use super::{JustificationVerifier, VerifiedJustifications};
+    use crate::{
+        abft::{NodeCount, NodeIndex, SignatureSet},
+        crypto::{AggregateSignature, BlsPen, Signature},
+        justification::{DagestanJustification, Verifier},
+        testing::mocks::{TBlock, THash},
+        SessionId,
+    };
+
+    fn authority_data(seeds: &[u8]) -> (Vec<AuthorityPair>, SessionAuthorityData) {
+        let pairs: Vec<_> = seeds
+            .iter()
+            .map(|seed| AuthorityPair::from_seed(&[*seed; 32]))
+            .collect();
+        let authority_data =
+            SessionAuthorityData::new(pairs.iter().map(|pair| pair.public()).collect(), None);
+        (pairs, authority_data)
+    }
+
+    fn justification(pairs: &[AuthorityPair], hash: THash) -> DagestanJustification {
+        let message = hash.encode();
+        let signatures = pairs.iter().enumerate().fold(
+            SignatureSet::with_size(NodeCount(pairs.len())),
+            |signatures, (index, pair)| {
+                signatures.add_signature(&Signature::from(pair.sign(&message)), NodeIndex(index))
+            },
+        );
+        DagestanJustification::CommitteeMultisignature(signatures)
+    }
+
+    fn verify(
+        hash: THash,
+        authority_data: SessionAuthorityData,
+        session: SessionId,
+        verified: &VerifiedJustifications,
+        justification: &DagestanJustification,
+    ) -> bool {
+        let verifier = JustificationVerifier::new(authority_data, DEFAULT_FINALITY_VERSION)
+            .with_cache(session, verified.clone());
+        Verifier::<TBlock>::verify(&verifier, justification, hash)
+    }
+
+    #[test]
+    fn remembers_verified_justifications() {
+        let (pairs, good_authorities) = authority_data(&[1, 2, 3]);
+        let (_, other_authorities) = authority_data(&[4, 5, 6]);
+        let hash = THash::repeat_byte(7);
+        let verified = VerifiedJustifications::new();
+        let justification = justification(&pairs, hash);
+
+        assert!(!verify(
+            hash,
+            other_authorities.clone(),
+            SessionId(0),
+            &verified,
+            &justification
+        ));
+        assert!(verify(
+            hash,
+            good_authorities,
+            SessionId(0),
+            &verified,
+            &justification
+        ));
+        // Verifiers of the same session no longer check the signatures.
+        assert!(verify(
+            hash,
+            other_authorities.clone(),
+            SessionId(0),
+            &verified,
+            &justification
+        ));
+        // Other sessions do not share the result.
+        assert!(!verify(
+            hash,
+            other_authorities,
+            SessionId(1),
+            &verified,
+            &justification
+        ));
+    }
+
+    #[tokio::test]
+    async fn accepts_aggregates_only_from_bls_aggregation_finality_version() {
+        let keystore = Arc::new(KeyStore::new());
+        let mut authorities = Vec::new();
+        let mut pens = Vec::new();
+        for _ in 0..3 {
+            let authority_id =
+                AuthorityId::from(keystore.ed25519_generate_new(KEY_TYPE, None).await.unwrap());
+            pens.push(BlsPen::generate(keystore.clone()).await.unwrap());
+            authorities.push(authority_id);
+        }
+        let authority_data = SessionAuthorityData::new(authorities, None)
+            .with_bls_authorities(pens.iter().map(|pen| pen.public()).collect());
+        let hash = THash::repeat_byte(7);
+        let message = hash.encode();
+        let aggregate = pens.iter().enumerate().skip(1).fold(
+            AggregateSignature::new(NodeCount(3), pens[0].sign(&message), NodeIndex(0)),
+            |aggregate, (index, pen)| {
+                aggregate.add_signature(&pen.sign(&message), NodeIndex(index))
+            },
+        );
+        let justification = DagestanJustification::AggregateSignature(aggregate);
+
+        let old_verifier = JustificationVerifier::new(
+            authority_data.clone(),
+            BLS_AGGREGATION_FINALITY_VERSION - 1,
+        );
+        assert!(!Verifier::<TBlock>::verify(
+            &old_verifier,
+            &justification,
+            hash
+        ));
+        let verifier = JustificationVerifier::new(authority_data, BLS_AGGREGATION_FINALITY_VERSION);
+        assert!(Verifier::<TBlock>::verify(&verifier, &justification, hash));
+    }
+}
@@ -5,13 +5,15 @@ use sp_consensus::SelectChain;
 use sp_runtime::traits::Block;
 
 use crate::{
+    justification::AdaptiveJustificationRequestScheduler,
     nodes::{setup_justification_handler, JustificationParams},
     session_map::{AuthorityProviderImpl, FinalityNotificatorImpl, SessionMapUpdater},
     DagestanConfig, BlockchainBackend,
 };
 
-pub async fn run_nonvalidator_node<B, H, C, BB, BE, SC>(dagestan_config: DagestanConfig<B, H, C, SC, BB>)
-where
+pub async fn run_nonvalidator_node<B, H, C, BB, BI, BE, SC, PP, PS>(
+    dagestan_config: DagestanConfig<B, H, C, SC, BB, BI, PP, PS>,
+) where
     B: Block,
     H: ExHashT,
     C: crate::ClientForDagestan<B, BE> + Send + Sync + 'static,
@@ -29,9 +31,12 @@ where
         millisecs_per_block,
         justification_rx,
         spawn_handle,
+        rpc_link,
+        session_map,
         ..
     } = dagestan_config;
     let map_updater = SessionMapUpdater::<_, _, B>::new(
+        session_map,
         AuthorityProviderImpl::new(client.clone()),
         FinalityNotificatorImpl::new(client.clone()),
     );
@@ -42,13 +47,19 @@ where
     });
     let (_, handler_task) = setup_justification_handler(JustificationParams {
         justification_rx,
-        network,
+        block_requester: network,
         client,
         blockchain_backend,
         metrics,
         session_period,
-        millisecs_per_block,
+        // Non-validators only learn about finality from requested justifications, so they keep
+        // escalating the requests when those fail.
+        justification_request_scheduler: AdaptiveJustificationRequestScheduler::new(
+            &session_period,
+            &millisecs_per_block,
+        ),
         session_map: session_authorities,
+        rpc_link,
     });
 
     debug!(target: "dagestan-party", "JustificationHandler has started.");
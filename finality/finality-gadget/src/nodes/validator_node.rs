@@ -1,29 +1,38 @@
 use std::{marker::PhantomData, sync::Arc};
 
 use bip39::{Language, Mnemonic, MnemonicType};
+use dagestan_primitives::BlockNumber;
 use futures::channel::oneshot;
 use log::{debug, error};
-use sc_client_api::Backend;
+use sc_client_api::{Backend, BlockchainEvents};
 use sc_network_common::ExHashT;
 use sp_consensus::SelectChain;
 use sp_keystore::CryptoStore;
-use sp_runtime::traits::Block;
+use sp_runtime::traits::{Block, Header};
 
 use crate::{
-    crypto::AuthorityPen,
+    crypto::{AuthorityPen, BlsPen},
+    data_io::{PayloadProvider, PayloadSink},
+    finalization::DagestanFinalizer,
+    justification::JustificationRequestSchedulerImpl,
     network::{
         clique::Service,
         session::{ConnectionManager, ConnectionManagerConfig},
         tcp::{new_tcp_network, KEY_TYPE},
-        GossipService, SubstrateNetwork,
+        GossipService, ImportBlocks, SubstrateNetwork,
     },
-    nodes::{setup_justification_handler, JustificationParams},
+    nodes::{setup_justification_handler, JustificationParams, MAX_ATTEMPTS},
     party::{
+        backup::{AuxBackupStore, BackupStore, FileBackupStore},
         impls::{ChainStateImpl, SessionInfoImpl},
         manager::NodeSessionManagerImpl,
         ConsensusParty, ConsensusPartyParams,
     },
     session_map::{AuthorityProviderImpl, FinalityNotificatorImpl, SessionMapUpdater},
+    sync::{
+        Service as BlockSyncService, SubstrateBlockRequester, SubstrateChainStatus,
+        SubstrateChainStatusNotifier, SubstrateJustificationVerifier,
+    },
     DagestanConfig, BlockchainBackend,
 };
 
@@ -37,15 +46,20 @@ pub async fn new_pen(mnemonic: &str, keystore: Arc<dyn CryptoStore>) -> Authorit
         .expect("we just generated this key so everything should work")
 }
 
-pub async fn run_validator_node<B, H, C, BB, BE, SC>(dagestan_config: DagestanConfig<B, H, C, SC, BB>)
-where
+pub async fn run_validator_node<B, H, C, BB, BI, BE, SC, PP, PS>(
+    dagestan_config: DagestanConfig<B, H, C, SC, BB, BI, PP, PS>,
+) where
     B: Block,
+    B::Header: Header<Number = BlockNumber>,
     H: ExHashT,
     C: crate::ClientForDagestan<B, BE> + Send + Sync + 'static,
     C::Api: dagestan_primitives::DagestanSessionApi<B>,
     BE: Backend<B> + 'static,
     BB: BlockchainBackend<B> + Send + 'static,
+    BI: ImportBlocks<B>,
     SC: SelectChain<B> + 'static,
+    PP: PayloadProvider + Clone,
+    PS: PayloadSink<Payload = PP::Payload> + Clone,
 {
     let DagestanConfig {
         network,
@@ -63,6 +77,11 @@ where
         external_addresses,
         validator_port,
         protocol_naming,
+        rpc_link,
+        session_map,
+        payload_provider,
+        payload_sink,
+        block_importer,
         ..
     } = dagestan_config;
 
@@ -74,6 +93,13 @@ where
         keystore.clone(),
     )
     .await;
+
+    // The BLS key has to be registered before any session can aggregate signatures with it, so we
+    // make sure it exists from the start. Its public key and proof of possession get logged.
+    if let Err(e) = BlsPen::load(None, keystore.clone()).await {
+        error!(target: "dagestan-party", "Failed to load the BLS key: {:?}.", e);
+    }
+
     let (dialer, listener, network_identity) = new_tcp_network(
         ("0.0.0.0", validator_port),
         external_addresses,
@@ -93,14 +119,14 @@ where
         validator_network_service.run(exit).await
     });
 
-    let (gossip_network_service, authentication_network, _block_sync_network) = GossipService::new(
+    let (gossip_network_service, authentication_network, block_sync_network) = GossipService::new(
         SubstrateNetwork::new(network.clone(), protocol_naming),
         spawn_handle.clone(),
     );
     let gossip_network_task = async move { gossip_network_service.run().await };
 
-    let block_requester = network.clone();
     let map_updater = SessionMapUpdater::<_, _, B>::new(
+        session_map,
         AuthorityProviderImpl::new(client.clone()),
         FinalityNotificatorImpl::new(client.clone()),
     );
@@ -110,16 +136,41 @@ where
         map_updater.run(session_period).await
     });
 
+    let (block_sync_service, justification_requester) = BlockSyncService::new(
+        block_sync_network,
+        SubstrateChainStatusNotifier::new(
+            client.finality_notification_stream(),
+            client.import_notification_stream(),
+        ),
+        SubstrateChainStatus::<B, _>::new(client.clone()),
+        SubstrateJustificationVerifier::<B>::new(session_authorities.clone(), session_period),
+        DagestanFinalizer::new(client.clone()),
+    );
+    let block_sync_task = async move {
+        if let Err(e) = block_sync_service.run().await {
+            error!(target: "dagestan-party", "Block sync service finished unexpectedly: {}", e);
+        }
+    };
+
+    // Dagestan justifications are requested through the block sync, everything else through
+    // substrate.
+    let block_requester = SubstrateBlockRequester::new(network.clone(), justification_requester);
+
     let (authority_justification_tx, handler_task) =
         setup_justification_handler(JustificationParams {
             justification_rx,
-            network,
+            block_requester: block_requester.clone(),
             client: client.clone(),
             blockchain_backend,
             metrics: metrics.clone(),
             session_period,
-            millisecs_per_block,
+            justification_request_scheduler: JustificationRequestSchedulerImpl::new(
+                &session_period,
+                &millisecs_per_block,
+                MAX_ATTEMPTS,
+            ),
             session_map: session_authorities.clone(),
+            rpc_link: rpc_link.clone(),
         });
 
     let (connection_manager_service, connection_manager) = ConnectionManager::new(
@@ -142,10 +193,18 @@ where
     spawn_handle.spawn("dagestan/gossip_network", None, gossip_network_task);
     debug!(target: "dagestan-party", "Gossip network has started.");
 
+    spawn_handle.spawn("dagestan/block_sync", None, block_sync_task);
+    debug!(target: "dagestan-party", "Block sync has started.");
+
+    let backup_store: Arc<dyn BackupStore> = match backup_saving_path {
+        Some(path) => Arc::new(FileBackupStore::new(path)),
+        None => Arc::new(AuxBackupStore::new(client.clone())),
+    };
+
     let party = ConsensusParty::new(ConsensusPartyParams {
         session_authorities,
         sync_state: block_requester.clone(),
-        backup_saving_path,
+        backup_store,
         chain_state: ChainStateImpl {
             client: client.clone(),
             _phantom: PhantomData,
@@ -157,10 +216,14 @@ where
             unit_creation_delay,
             authority_justification_tx,
             block_requester,
+            block_importer,
             metrics,
             spawn_handle.into(),
             connection_manager,
             keystore,
+            rpc_link,
+            payload_provider,
+            payload_sink,
         ),
         _phantom: PhantomData,
         session_info: SessionInfoImpl::new(session_period),
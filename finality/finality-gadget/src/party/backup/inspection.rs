@@ -0,0 +1,360 @@
+//! Inspection and repair of the AlephBFT backup of a session, for when a validator misbehaves
+//! after a crash.
+
+use std::{
+    collections::HashMap,
+    fmt::{Display, Error as FmtError, Formatter},
+    io,
+};
+
+use sp_core::H256;
+use sp_runtime::traits::Block;
+
+use super::{
+    decode_backup_part, encode_backup_part, get_session_backup_idxs, BackupLoadError, BackupStore,
+};
+use crate::{
+    compatibility::Version,
+    crypto::{AuthorityVerifier, Signature},
+    data_io::Payload,
+    versions::backup_unit_decoder,
+    AuthorityId, NodeIndex,
+};
+
+/// A unit saved in a backup, regardless of the version of AlephBFT that saved it.
+pub struct BackupUnit {
+    pub creator: NodeIndex,
+    pub round: u16,
+    pub session_id: u64,
+    /// The hash of the unit, which its creator signs.
+    pub hash: H256,
+    pub(crate) signature: Signature,
+}
+
+/// A unit found in a backup, together with the results of checking it.
+pub struct InspectedUnit {
+    pub unit: BackupUnit,
+    /// The part of the backup the unit was saved in.
+    pub part: usize,
+    /// Whether the signature of the unit is correct, if the authorities are known.
+    pub signature_valid: Option<bool>,
+    /// Whether the same unit was saved earlier in the backup.
+    pub duplicate: bool,
+    /// Whether a different unit with the same creator and round was saved earlier in the backup.
+    pub fork: bool,
+    encoded: Vec<u8>,
+}
+
+/// The contents of the backup of a session.
+pub struct BackupInspection {
+    /// All the decoded units, in the order they were saved in.
+    pub units: Vec<InspectedUnit>,
+    /// Parts ending with a torn or corrupted record, with its length in bytes. Loading the backup
+    /// stops at the first of these records, so the units saved after it are inconsistent.
+    pub corrupted_records: Vec<(usize, usize)>,
+    /// Parts containing data which could not be decoded as units, with its length in bytes.
+    pub undecodable_data: Vec<(usize, usize)>,
+    /// The number of units in the longest prefix of the backup, in which all the units are
+    /// correctly signed units of the session, and none of our own units is forked.
+    pub consistent_units: usize,
+    parts: Vec<usize>,
+}
+
+#[derive(Debug)]
+pub enum InspectionError {
+    UnknownConsensusVersion(u16),
+    Load(BackupLoadError),
+}
+
+impl Display for InspectionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        match self {
+            InspectionError::UnknownConsensusVersion(version) => {
+                write!(f, "unknown consensus version {}", version)
+            }
+            InspectionError::Load(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<BackupLoadError> for InspectionError {
+    fn from(err: BackupLoadError) -> Self {
+        InspectionError::Load(err)
+    }
+}
+
+impl From<io::Error> for InspectionError {
+    fn from(err: io::Error) -> Self {
+        InspectionError::Load(err.into())
+    }
+}
+
+/// Decodes all the units in the backup of the session, saved by the AlephBFT of the given
+/// consensus version run by a node configured with the payload `P`. Signatures are verified only
+/// if the authorities of the session are given, in the order of their node indices. Forks of units created by `own_index` make the backup
+/// inconsistent, or forks of any units if it is not given.
+pub fn inspect_backup<B: Block, P: Payload>(
+    store: &dyn BackupStore,
+    session_id: u32,
+    consensus_version: u16,
+    authorities: Option<Vec<AuthorityId>>,
+    own_index: Option<NodeIndex>,
+) -> Result<BackupInspection, InspectionError> {
+    let decode = backup_unit_decoder::<B, P>(Version(consensus_version))
+        .ok_or(InspectionError::UnknownConsensusVersion(consensus_version))?;
+    let verifier = authorities.map(AuthorityVerifier::new);
+    let parts = get_session_backup_idxs(store, session_id)?;
+
+    let mut inspection = BackupInspection {
+        units: Vec::new(),
+        corrupted_records: Vec::new(),
+        undecodable_data: Vec::new(),
+        consistent_units: 0,
+        parts: parts.clone(),
+    };
+    let mut saved_hashes: HashMap<(NodeIndex, u16), H256> = HashMap::new();
+    let mut consistent = true;
+    for part in parts {
+        let content = store.read(session_id, part)?;
+        let part_data = decode_backup_part(&content)?;
+        let corrupted = part_data.valid_length < content.len();
+        if corrupted {
+            inspection
+                .corrupted_records
+                .push((part, content.len() - part_data.valid_length));
+        }
+
+        let mut input = &part_data.data[..];
+        while !input.is_empty() {
+            let remaining = input;
+            let unit = match decode(&mut input) {
+                Ok(unit) => unit,
+                Err(_) => {
+                    inspection.undecodable_data.push((part, remaining.len()));
+                    consistent = false;
+                    break;
+                }
+            };
+            let encoded = remaining[..remaining.len() - input.len()].to_vec();
+
+            let signature_valid = verifier
+                .as_ref()
+                .map(|verifier| verifier.verify(unit.hash.as_ref(), &unit.signature, unit.creator));
+            let (duplicate, fork) = match saved_hashes.get(&(unit.creator, unit.round)) {
+                Some(hash) => (*hash == unit.hash, *hash != unit.hash),
+                None => {
+                    saved_hashes.insert((unit.creator, unit.round), unit.hash);
+                    (false, false)
+                }
+            };
+            let own_fork = fork && own_index.map_or(true, |index| index == unit.creator);
+            consistent = consistent
+                && signature_valid != Some(false)
+                && unit.session_id == session_id as u64
+                && !own_fork;
+            if consistent {
+                inspection.consistent_units += 1;
+            }
+
+            inspection.units.push(InspectedUnit {
+                unit,
+                part,
+                signature_valid,
+                duplicate,
+                fork,
+                encoded,
+            });
+        }
+        consistent = consistent && !corrupted;
+    }
+    Ok(inspection)
+}
+
+/// Truncates the backup of the session to its longest consistent prefix, as found by
+/// `inspect_backup`. The parts past the prefix are rewritten, so that the parts stay numbered
+/// sequentially, and the ones entirely past it are left empty.
+pub fn truncate_backup(
+    store: &dyn BackupStore,
+    session_id: u32,
+    inspection: &BackupInspection,
+) -> io::Result<()> {
+    let first_inconsistent_part = inspection
+        .units
+        .get(inspection.consistent_units)
+        .map(|unit| unit.part)
+        .into_iter()
+        .chain(inspection.undecodable_data.iter().map(|(part, _)| *part))
+        .min();
+    let first_inconsistent_part = match first_inconsistent_part {
+        Some(part) => part,
+        None => return Ok(()),
+    };
+
+    let consistent_units = &inspection.units[..inspection.consistent_units];
+    for part in inspection
+        .parts
+        .iter()
+        .filter(|part| **part >= first_inconsistent_part)
+    {
+        let content = encode_backup_part(
+            consistent_units
+                .iter()
+                .filter(|unit| unit.part == *part)
+                .map(|unit| &unit.encoded[..]),
+        );
+        store.write(session_id, *part, &content)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use substrate_test_runtime_client::runtime::Block;
+
+    use super::{inspect_backup, truncate_backup, InspectionError};
+    use crate::{
+        abft::{v0_19, v0_20},
+        network::mock::crypto_basics,
+        party::backup::{encode_backup_part, BackupStore, MemoryBackupStore},
+        NodeIndex,
+    };
+
+    fn write_part(store: &MemoryBackupStore, part: usize, units: &[Vec<u8>]) {
+        let content = encode_backup_part(units.iter().map(|unit| &unit[..]));
+        store.write(0, part, &content).unwrap();
+    }
+
+    #[test]
+    fn rejects_unknown_consensus_versions() {
+        let store = MemoryBackupStore::default();
+        assert!(matches!(
+            inspect_backup::<Block, ()>(&store, 0, 7, None, None),
+            Err(InspectionError::UnknownConsensusVersion(7))
+        ));
+    }
+
+    #[test]
+    fn truncates_undecodable_data() {
+        let store = MemoryBackupStore::default();
+        store
+            .write(0, 0, &encode_backup_part([&[0xff; 3][..]]))
+            .unwrap();
+        store.write(0, 1, &encode_backup_part([])).unwrap();
+
+        let inspection = inspect_backup::<Block, ()>(&store, 0, 1, None, None).unwrap();
+        assert!(inspection.units.is_empty());
+        assert_eq!(inspection.undecodable_data, vec![(0, 3)]);
+        assert_eq!(inspection.consistent_units, 0);
+
+        truncate_backup(&store, 0, &inspection).unwrap();
+        let inspection = inspect_backup::<Block, ()>(&store, 0, 1, None, None).unwrap();
+        assert!(inspection.undecodable_data.is_empty());
+        assert_eq!(store.parts(0).unwrap(), vec![0, 1]);
+    }
+
+    #[tokio::test]
+    async fn units_after_corrupted_record_are_inconsistent() {
+        let (pens, _) = crypto_basics(1).await;
+        let pen = &pens[0].1;
+        let store = MemoryBackupStore::default();
+        let mut content = encode_backup_part([
+            &v0_20::encode_backup_unit::<Block, ()>(pen, NodeIndex(0), 0, 0, 0).await[..],
+            &v0_20::encode_backup_unit::<Block, ()>(pen, NodeIndex(0), 1, 0, 0).await[..],
+        ]);
+        let last = content.len() - 1;
+        content[last] ^= 1;
+        store.write(0, 0, &content).unwrap();
+        write_part(
+            &store,
+            1,
+            &[v0_20::encode_backup_unit::<Block, ()>(pen, NodeIndex(0), 2, 0, 0).await],
+        );
+
+        let inspection = inspect_backup::<Block, ()>(&store, 0, 2, None, None).unwrap();
+        assert_eq!(inspection.corrupted_records.len(), 1);
+        assert_eq!(inspection.corrupted_records[0].0, 0);
+        assert_eq!(inspection.units.len(), 2);
+        assert_eq!(inspection.consistent_units, 1);
+
+        truncate_backup(&store, 0, &inspection).unwrap();
+        let inspection = inspect_backup::<Block, ()>(&store, 0, 2, None, None).unwrap();
+        assert_eq!(inspection.units.len(), 1);
+        assert_eq!(store.parts(0).unwrap(), vec![0, 1]);
+    }
+
+    #[tokio::test]
+    async fn verifies_unit_signatures() {
+        let (pens, _) = crypto_basics(2).await;
+        let authorities: Vec<_> = pens.iter().map(|(_, pen)| pen.authority_id()).collect();
+        let store = MemoryBackupStore::default();
+        write_part(
+            &store,
+            0,
+            &[
+                v0_20::encode_backup_unit::<Block, ()>(&pens[0].1, NodeIndex(0), 0, 0, 0).await,
+                // Signed by someone else than its creator.
+                v0_20::encode_backup_unit::<Block, ()>(&pens[0].1, NodeIndex(1), 0, 0, 0).await,
+                v0_20::encode_backup_unit::<Block, ()>(&pens[1].1, NodeIndex(1), 1, 0, 0).await,
+            ],
+        );
+
+        let inspection =
+            inspect_backup::<Block, ()>(&store, 0, 2, Some(authorities), None).unwrap();
+        let signatures: Vec<_> = inspection
+            .units
+            .iter()
+            .map(|unit| unit.signature_valid)
+            .collect();
+        assert_eq!(signatures, vec![Some(true), Some(false), Some(true)]);
+        assert_eq!(inspection.consistent_units, 1);
+
+        let inspection = inspect_backup::<Block, ()>(&store, 0, 2, None, None).unwrap();
+        assert!(inspection
+            .units
+            .iter()
+            .all(|unit| unit.signature_valid.is_none()));
+        assert_eq!(inspection.consistent_units, 3);
+    }
+
+    #[tokio::test]
+    async fn detects_forks_of_own_units() {
+        let (pens, _) = crypto_basics(2).await;
+        let (own, other) = (&pens[0].1, &pens[1].1);
+        let store = MemoryBackupStore::default();
+        write_part(
+            &store,
+            0,
+            &[
+                v0_19::encode_backup_unit::<Block, ()>(other, NodeIndex(1), 0, 0, 0).await,
+                v0_19::encode_backup_unit::<Block, ()>(own, NodeIndex(0), 0, 0, 0).await,
+            ],
+        );
+        write_part(
+            &store,
+            1,
+            &[
+                v0_19::encode_backup_unit::<Block, ()>(own, NodeIndex(0), 0, 0, 0).await,
+                v0_19::encode_backup_unit::<Block, ()>(other, NodeIndex(1), 0, 0, 1).await,
+                v0_19::encode_backup_unit::<Block, ()>(own, NodeIndex(0), 1, 0, 0).await,
+                v0_19::encode_backup_unit::<Block, ()>(own, NodeIndex(0), 1, 0, 1).await,
+            ],
+        );
+
+        let inspection =
+            inspect_backup::<Block, ()>(&store, 0, 1, None, Some(NodeIndex(0))).unwrap();
+        let duplicates: Vec<_> = inspection.units.iter().map(|unit| unit.duplicate).collect();
+        assert_eq!(duplicates, vec![false, false, true, false, false, false]);
+        let forks: Vec<_> = inspection.units.iter().map(|unit| unit.fork).collect();
+        assert_eq!(forks, vec![false, false, false, true, false, true]);
+        assert_eq!(inspection.consistent_units, 5);
+        let any_fork = inspect_backup::<Block, ()>(&store, 0, 1, None, None).unwrap();
+        assert_eq!(any_fork.consistent_units, 3);
+
+        truncate_backup(&store, 0, &inspection).unwrap();
+        let inspection =
+            inspect_backup::<Block, ()>(&store, 0, 1, None, Some(NodeIndex(0))).unwrap();
+        assert_eq!(inspection.units.len(), 5);
+        assert_eq!(inspection.consistent_units, 5);
+        assert_eq!(store.parts(0).unwrap(), vec![0, 1]);
+    }
+}
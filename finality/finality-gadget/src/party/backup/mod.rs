@@ -0,0 +1,408 @@
+use std::{
+    fmt, io,
+    io::{Cursor, Read, Write},
+    sync::Arc,
+};
+
+use log::{debug, info, warn};
+
+mod inspection;
+mod store;
+
+pub use inspection::{
+    inspect_backup, truncate_backup, BackupInspection, BackupUnit, InspectedUnit, InspectionError,
+};
+#[cfg(test)]
+pub use store::MemoryBackupStore;
+pub use store::{AuxBackupStore, BackupStore, FileBackupStore};
+
+/// Starts every backup part written in the record format. Parts not starting with it were written
+/// before the format was introduced and contain raw AlephBFT backup data.
+const BACKUP_MAGIC: &[u8; 8] = b"DGSTABFT";
+const BACKUP_FORMAT_VERSION: u16 = 1;
+const BACKUP_HEADER_LENGTH: usize = BACKUP_MAGIC.len() + 2;
+
+/// Every record is prefixed with the length of its payload and the CRC32 checksum of it.
+const RECORD_HEADER_LENGTH: usize = 8;
+
+#[derive(Debug)]
+pub enum BackupLoadError {
+    BackupIncomplete(Vec<usize>),
+    UnsupportedFormat(u16),
+    IOError(io::Error),
+}
+
+impl fmt::Display for BackupLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackupLoadError::BackupIncomplete(backups) => {
+                write!(
+                    f,
+                    "Backup is not complete. Got backup for runs numbered: {:?}",
+                    backups
+                )
+            }
+            BackupLoadError::UnsupportedFormat(version) => {
+                write!(f, "Backup is in an unsupported format version {}", version)
+            }
+            BackupLoadError::IOError(err) => {
+                write!(f, "Backup could not be loaded because of IO error: {}", err)
+            }
+        }
+    }
+}
+
+impl From<io::Error> for BackupLoadError {
+    fn from(err: io::Error) -> Self {
+        Self::IOError(err)
+    }
+}
+
+impl std::error::Error for BackupLoadError {}
+
+pub type Saver = Box<dyn Write + Send + Sync>;
+pub type Loader = Box<dyn Read + Send + Sync>;
+pub type ABFTBackup = (Saver, Loader);
+
+fn backup_header() -> Vec<u8> {
+    let mut header = BACKUP_MAGIC.to_vec();
+    header.extend_from_slice(&BACKUP_FORMAT_VERSION.to_le_bytes());
+    header
+}
+
+fn encode_record(payload: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(RECORD_HEADER_LENGTH + payload.len());
+    record.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    record.extend_from_slice(&crc32fast::hash(payload).to_le_bytes());
+    record.extend_from_slice(payload);
+    record
+}
+
+/// The records successfully read from a backup file.
+struct Records {
+    /// The concatenated payloads of the records.
+    data: Vec<u8>,
+    count: usize,
+    /// The length of the prefix of the file containing only valid records.
+    valid_length: usize,
+}
+
+/// Reads consecutive records, stopping at the first one that is incomplete or fails the checksum.
+fn decode_records(bytes: &[u8]) -> Records {
+    let mut records = Records {
+        data: Vec::new(),
+        count: 0,
+        valid_length: 0,
+    };
+    let mut rest = bytes;
+    while rest.len() >= RECORD_HEADER_LENGTH {
+        let (length, checksum) = rest[..RECORD_HEADER_LENGTH].split_at(4);
+        let length = u32::from_le_bytes(length.try_into().expect("has 4 bytes")) as usize;
+        let checksum = u32::from_le_bytes(checksum.try_into().expect("has 4 bytes"));
+        let payload = match rest[RECORD_HEADER_LENGTH..].get(..length) {
+            Some(payload) if crc32fast::hash(payload) == checksum => payload,
+            _ => break,
+        };
+        records.data.extend_from_slice(payload);
+        records.count += 1;
+        records.valid_length += RECORD_HEADER_LENGTH + length;
+        rest = &rest[RECORD_HEADER_LENGTH + length..];
+    }
+    records
+}
+
+/// Saves the data written to it as a checksummed record on every flush. AlephBFT flushes after
+/// saving every unit, so a crash can only tear the record of the unit being saved.
+struct RecordWriter {
+    store: Arc<dyn BackupStore>,
+    session_id: u32,
+    part: usize,
+    buffer: Vec<u8>,
+}
+
+impl RecordWriter {
+    fn new(store: Arc<dyn BackupStore>, session_id: u32, part: usize) -> Self {
+        RecordWriter {
+            store,
+            session_id,
+            part,
+            buffer: Vec::new(),
+        }
+    }
+}
+
+impl Write for RecordWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        self.store
+            .append(self.session_id, self.part, &encode_record(&self.buffer))?;
+        self.buffer.clear();
+        Ok(())
+    }
+}
+
+/// Find all the parts of the backup of the session and return their indexes, if all are present.
+fn get_session_backup_idxs(
+    store: &dyn BackupStore,
+    session_id: u32,
+) -> Result<Vec<usize>, BackupLoadError> {
+    let session_backups = store.parts(session_id)?;
+    if !session_backups.iter().cloned().eq(0..session_backups.len()) {
+        return Err(BackupLoadError::BackupIncomplete(session_backups));
+    }
+    Ok(session_backups)
+}
+
+/// The data saved in a part of the backup.
+struct PartData {
+    data: Vec<u8>,
+    /// The number of records the data was read from, `None` if the part is in the legacy format.
+    records: Option<usize>,
+    /// The length of the prefix of the part containing only valid records.
+    valid_length: usize,
+}
+
+/// Reads the data saved in a part of the backup, up to its first corrupted record.
+fn decode_backup_part(content: &[u8]) -> Result<PartData, BackupLoadError> {
+    match content.strip_prefix(&BACKUP_MAGIC[..]) {
+        Some(rest) if rest.len() >= 2 => {
+            let version = u16::from_le_bytes([rest[0], rest[1]]);
+            if version != BACKUP_FORMAT_VERSION {
+                return Err(BackupLoadError::UnsupportedFormat(version));
+            }
+            let records = decode_records(&rest[2..]);
+            Ok(PartData {
+                data: records.data,
+                records: Some(records.count),
+                valid_length: BACKUP_HEADER_LENGTH + records.valid_length,
+            })
+        }
+        _ => Ok(PartData {
+            data: content.to_vec(),
+            records: None,
+            valid_length: content.len(),
+        }),
+    }
+}
+
+/// Encodes a part of the backup containing the given records.
+fn encode_backup_part<'a>(payloads: impl IntoIterator<Item = &'a [u8]>) -> Vec<u8> {
+    let mut content = backup_header();
+    for payload in payloads {
+        content.extend(encode_record(payload));
+    }
+    content
+}
+
+/// Load a single part of the backup, truncating it to the last valid record if needed. Returns
+/// the loaded data and whether the part had to be truncated.
+fn load_backup_part(
+    store: &dyn BackupStore,
+    session_id: u32,
+    part: usize,
+) -> Result<(Vec<u8>, bool), BackupLoadError> {
+    let content = store.read(session_id, part)?;
+    let PartData {
+        data,
+        records,
+        valid_length,
+    } = decode_backup_part(&content)?;
+    let records = match records {
+        Some(records) => records,
+        None => {
+            debug!(target: "dagestan-party", "Loading part {} of backup for session {:?} written in the legacy format", part, session_id);
+            return Ok((data, false));
+        }
+    };
+    let corrupted = valid_length < content.len();
+    if corrupted {
+        warn!(target: "dagestan-party", "Part {} of backup for session {:?} has a corrupted record after {} valid ones, truncating it by {} bytes", part, session_id, records, content.len() - valid_length);
+        store.write(session_id, part, &content[..valid_length])?;
+    }
+    info!(target: "dagestan-party", "Recovered {} records from part {} of backup for session {:?}", records, part, session_id);
+    Ok((data, corrupted))
+}
+
+/// Load the backup of the session from all `session_idxs`, up to the first corrupted record. The
+/// parts after the one containing it are emptied, as the units in them might depend on the lost
+/// ones.
+fn load_backup(
+    store: &dyn BackupStore,
+    session_id: u32,
+    session_idxs: &[usize],
+) -> Result<Loader, BackupLoadError> {
+    let mut buffer = Vec::new();
+    let mut corrupted = false;
+    for part in session_idxs.iter() {
+        if corrupted {
+            warn!(target: "dagestan-party", "Emptying part {} of backup for session {:?}, as it follows a corrupted record", part, session_id);
+            store.write(session_id, *part, &backup_header())?;
+            continue;
+        }
+        let (data, part_corrupted) = load_backup_part(store, session_id, *part)?;
+        buffer.extend(data);
+        corrupted = part_corrupted;
+    }
+    Ok(Box::new(Cursor::new(buffer)))
+}
+
+/// Loads the existing backup of the session, and starts a new part of it to write to.
+///
+/// Returns the saver writing to the new part, and the concatenation of the contents of all
+/// existing parts.
+///
+/// Every part starts with a header with the format version, followed by records with checksums,
+/// one for every flush of the saver. Loading stops at the first corrupted record, the part
+/// containing it is then truncated to the records before it and the later parts are emptied.
+/// Parts without the header are loaded as they are.
+pub fn rotate(store: Arc<dyn BackupStore>, session_id: u32) -> Result<ABFTBackup, BackupLoadError> {
+    debug!(target: "dagestan-party", "Loading AlephBFT backup for session {:?}", session_id);
+
+    let session_backup_idxs = get_session_backup_idxs(store.as_ref(), session_id)?;
+
+    let backup_loader = load_backup(store.as_ref(), session_id, &session_backup_idxs)?;
+
+    let next_part = session_backup_idxs.last().map_or(0, |i| i + 1);
+    debug!(target: "dagestan-party", "Loaded backup for session {:?}. Creating new backup part {}", session_id, next_part);
+    store.write(session_id, next_part, &backup_header())?;
+    let backup_saver = Box::new(RecordWriter::new(store, session_id, next_part));
+
+    debug!(target: "dagestan-party", "Backup rotation done for session {:?}", session_id);
+    Ok((backup_saver, backup_loader))
+}
+
+/// Removes the backup of a session.
+///
+/// Any storage errors are logged and dropped.
+///
+/// This should be done after the end of the session.
+pub fn remove(store: &dyn BackupStore, session_id: u32) {
+    match store.remove(session_id) {
+        Ok(()) => {
+            debug!(target: "dagestan-party", "Removed backup for session {}", session_id);
+        }
+        Err(err) => {
+            if err.kind() != io::ErrorKind::NotFound {
+                warn!(target: "dagestan-party", "Error cleaning up backup for session {}: {}", session_id, err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        sync::Arc,
+    };
+
+    use super::{decode_records, encode_record, rotate, BackupStore, MemoryBackupStore};
+
+    #[test]
+    fn decodes_encoded_records() {
+        let mut bytes = encode_record(b"first unit");
+        bytes.extend(encode_record(b"second unit"));
+        let records = decode_records(&bytes);
+        assert_eq!(records.count, 2);
+        assert_eq!(records.valid_length, bytes.len());
+        assert_eq!(records.data, b"first unitsecond unit".to_vec());
+    }
+
+    #[test]
+    fn stops_at_torn_record() {
+        let mut bytes = encode_record(b"first unit");
+        let valid_length = bytes.len();
+        let second = encode_record(b"second unit");
+        bytes.extend_from_slice(&second[..second.len() - 1]);
+        let records = decode_records(&bytes);
+        assert_eq!(records.count, 1);
+        assert_eq!(records.valid_length, valid_length);
+        assert_eq!(records.data, b"first unit".to_vec());
+    }
+
+    #[test]
+    fn stops_at_record_with_bad_checksum() {
+        let mut bytes = encode_record(b"first unit");
+        let valid_length = bytes.len();
+        let mut second = encode_record(b"second unit");
+        let last = second.len() - 1;
+        second[last] ^= 1;
+        bytes.extend(second);
+        bytes.extend(encode_record(b"third unit"));
+        let records = decode_records(&bytes);
+        assert_eq!(records.count, 1);
+        assert_eq!(records.valid_length, valid_length);
+    }
+
+    #[test]
+    fn rotation_loads_saved_data_up_to_corrupted_record() {
+        let store = Arc::new(MemoryBackupStore::default());
+        let (mut saver, _) = rotate(store.clone(), 0).expect("backup should rotate");
+        saver.write_all(b"first unit").unwrap();
+        saver.flush().unwrap();
+        saver.write_all(b"second unit").unwrap();
+        saver.flush().unwrap();
+        let mut content = store.read(0, 0).unwrap();
+        let last = content.len() - 1;
+        content[last] ^= 1;
+        store.write(0, 0, &content).unwrap();
+
+        let (mut saver, mut loader) = rotate(store.clone(), 0).expect("backup should rotate");
+        let mut loaded = Vec::new();
+        loader.read_to_end(&mut loaded).unwrap();
+        assert_eq!(loaded, b"first unit".to_vec());
+        saver.write_all(b"third unit").unwrap();
+        saver.flush().unwrap();
+
+        let (_, mut loader) = rotate(store.clone(), 0).expect("backup should rotate");
+        let mut loaded = Vec::new();
+        loader.read_to_end(&mut loaded).unwrap();
+        assert_eq!(loaded, b"first unitthird unit".to_vec());
+        assert_eq!(store.parts(0).unwrap(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn rotation_drops_parts_after_corrupted_record() {
+        let store = Arc::new(MemoryBackupStore::default());
+        let (mut saver, _) = rotate(store.clone(), 0).expect("backup should rotate");
+        saver.write_all(b"first unit").unwrap();
+        saver.flush().unwrap();
+        saver.write_all(b"second unit").unwrap();
+        saver.flush().unwrap();
+        let (mut saver, _) = rotate(store.clone(), 0).expect("backup should rotate");
+        saver.write_all(b"third unit").unwrap();
+        saver.flush().unwrap();
+        let mut content = store.read(0, 0).unwrap();
+        let last = content.len() - 1;
+        content[last] ^= 1;
+        store.write(0, 0, &content).unwrap();
+
+        let (_, mut loader) = rotate(store.clone(), 0).expect("backup should rotate");
+        let mut loaded = Vec::new();
+        loader.read_to_end(&mut loaded).unwrap();
+        assert_eq!(loaded, b"first unit".to_vec());
+
+        let (_, mut loader) = rotate(store.clone(), 0).expect("backup should rotate");
+        let mut loaded = Vec::new();
+        loader.read_to_end(&mut loaded).unwrap();
+        assert_eq!(loaded, b"first unit".to_vec());
+        assert_eq!(store.parts(0).unwrap(), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn loads_legacy_backups() {
+        let store = Arc::new(MemoryBackupStore::default());
+        store.write(0, 0, b"raw units").unwrap();
+        let (_, mut loader) = rotate(store, 0).expect("backup should rotate");
+        let mut loaded = Vec::new();
+        loader.read_to_end(&mut loaded).unwrap();
+        assert_eq!(loaded, b"raw units".to_vec());
+    }
+}
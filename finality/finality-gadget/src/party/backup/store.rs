@@ -0,0 +1,504 @@
+use std::{
+    fs,
+    fs::{File, OpenOptions},
+    io,
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+#[cfg(test)]
+use std::collections::HashMap;
+
+#[cfg(test)]
+use parking_lot::Mutex;
+use sc_client_api::AuxStore;
+
+const BACKUP_FILE_EXTENSION: &str = ".abfts";
+const TEMPORARY_FILE_EXTENSION: &str = ".tmp";
+
+/// Storage for the AlephBFT backups of sessions. The backup of a session consists of parts
+/// numbered sequentially from 0, every one of them written by a single run of the session.
+pub trait BackupStore: Send + Sync {
+    /// Returns the indexes of the parts of the backup of the session, sorted.
+    fn parts(&self, session_id: u32) -> io::Result<Vec<usize>>;
+
+    /// Returns the content of a part.
+    fn read(&self, session_id: u32, part: usize) -> io::Result<Vec<u8>>;
+
+    /// Replaces the content of a part, creating it if needed. The part has either the old or the
+    /// new content, even if we crash in the meantime.
+    fn write(&self, session_id: u32, part: usize, content: &[u8]) -> io::Result<()>;
+
+    /// Appends data to an existing part. The data is persisted once this returns.
+    fn append(&self, session_id: u32, part: usize, data: &[u8]) -> io::Result<()>;
+
+    /// Removes the whole backup of the session.
+    fn remove(&self, session_id: u32) -> io::Result<()>;
+}
+
+/// Keeps the backups in files, in a directory per session.
+///
+/// Current directory structure (this is an implementation detail, not part of the public API):
+///   backup-stash/      - the main directory, backup_path/--backup-saving-path
+///   `-- 18723/         - subdirectory for the current session
+///       |-- 0.abfts    - files containing data
+///       |-- 1.abfts    - each restart after a crash will cause another one to be created
+///       |-- 2.abfts    - these numbers count up sequentially
+///       `-- 3.abfts
+pub struct FileBackupStore {
+    path: PathBuf,
+}
+
+impl FileBackupStore {
+    /// `path` is the path to the backup directory (i.e. the argument to `--backup-saving-path`).
+    pub fn new(path: PathBuf) -> Self {
+        FileBackupStore { path }
+    }
+
+    fn session_path(&self, session_id: u32) -> PathBuf {
+        self.path.join(session_id.to_string())
+    }
+
+    fn part_path(&self, session_id: u32, part: usize) -> PathBuf {
+        self.session_path(session_id)
+            .join(format!("{}{}", part, BACKUP_FILE_EXTENSION))
+    }
+
+    /// Creates the directory of the session, unless it exists, and persists its creation.
+    fn create_session_dir(&self, session_id: u32) -> io::Result<PathBuf> {
+        let session_path = self.session_path(session_id);
+        if !session_path.is_dir() {
+            fs::create_dir_all(&session_path)?;
+            sync_dir(&self.path)?;
+            if let Some(parent) = self.path.parent().filter(|parent| parent.is_dir()) {
+                sync_dir(parent)?;
+            }
+        }
+        Ok(session_path)
+    }
+}
+
+/// Persists the entries of the directory, i.e. the files created, renamed or removed in it.
+fn sync_dir(path: &Path) -> io::Result<()> {
+    File::open(path)?.sync_all()
+}
+
+/// Writes `content` to a temporary file and moves it to `path`, so that `path` either does not
+/// exist or has all of `content`, even if we crash in the meantime.
+fn write_atomically(path: &Path, content: &[u8]) -> io::Result<()> {
+    let mut temporary_path = path.as_os_str().to_owned();
+    temporary_path.push(TEMPORARY_FILE_EXTENSION);
+    let temporary_path = PathBuf::from(temporary_path);
+    let mut file = File::create(&temporary_path)?;
+    file.write_all(content)?;
+    file.sync_all()?;
+    fs::rename(temporary_path, path)?;
+    match path.parent() {
+        Some(parent) => sync_dir(parent),
+        None => Ok(()),
+    }
+}
+
+impl BackupStore for FileBackupStore {
+    fn parts(&self, session_id: u32) -> io::Result<Vec<usize>> {
+        let session_path = self.create_session_dir(session_id)?;
+        let mut parts: Vec<_> = fs::read_dir(session_path)?
+            .filter_map(|r| r.ok())
+            .filter_map(|x| x.file_name().into_string().ok())
+            .filter_map(|s| usize::from_str(s.strip_suffix(BACKUP_FILE_EXTENSION)?).ok())
+            .collect();
+        parts.sort_unstable();
+        Ok(parts)
+    }
+
+    fn read(&self, session_id: u32, part: usize) -> io::Result<Vec<u8>> {
+        fs::read(self.part_path(session_id, part))
+    }
+
+    fn write(&self, session_id: u32, part: usize, content: &[u8]) -> io::Result<()> {
+        self.create_session_dir(session_id)?;
+        write_atomically(&self.part_path(session_id, part), content)
+    }
+
+    fn append(&self, session_id: u32, part: usize, data: &[u8]) -> io::Result<()> {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(self.part_path(session_id, part))?;
+        file.write_all(data)?;
+        file.sync_data()
+    }
+
+    fn remove(&self, session_id: u32) -> io::Result<()> {
+        fs::remove_dir_all(self.session_path(session_id))
+    }
+}
+
+const AUX_BACKUP_PREFIX: &[u8] = b"dagestan-abft-backup";
+
+/// Keeps the backups in the auxiliary storage of the client database, so that they are available
+/// without any additional configuration.
+///
+/// Every append is stored under a separate key, so that saving a unit does not rewrite the whole
+/// backup. The number of chunks of every part and the number of parts of every session are stored
+/// as well, and updated atomically with the data.
+pub struct AuxBackupStore<C: AuxStore> {
+    client: Arc<C>,
+}
+
+impl<C: AuxStore> AuxBackupStore<C> {
+    pub fn new(client: Arc<C>) -> Self {
+        AuxBackupStore { client }
+    }
+
+    fn get_count(&self, key: &[u8]) -> io::Result<u32> {
+        Ok(self
+            .client
+            .get_aux(key)
+            .map_err(aux_error)?
+            .and_then(|count| Some(u32::from_le_bytes(count.try_into().ok()?)))
+            .unwrap_or(0))
+    }
+
+    /// Returns the keys of all the chunks of a part.
+    fn chunk_keys(&self, session_id: u32, part: usize) -> io::Result<Vec<Vec<u8>>> {
+        let part_key = part_key(session_id, part);
+        let chunks = self.get_count(&part_key)?;
+        Ok((0..chunks)
+            .map(|chunk| chunk_key(&part_key, chunk))
+            .collect())
+    }
+
+    fn insert(&self, insert: &[(Vec<u8>, Vec<u8>)], delete: &[Vec<u8>]) -> io::Result<()> {
+        let insert: Vec<_> = insert
+            .iter()
+            .map(|(key, value)| (&key[..], &value[..]))
+            .collect();
+        let delete: Vec<_> = delete.iter().map(|key| &key[..]).collect();
+        self.client
+            .insert_aux(insert.iter(), delete.iter())
+            .map_err(aux_error)
+    }
+}
+
+fn aux_error(error: sp_blockchain::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, error.to_string())
+}
+
+fn session_key(session_id: u32) -> Vec<u8> {
+    let mut key = AUX_BACKUP_PREFIX.to_vec();
+    key.extend_from_slice(&session_id.to_le_bytes());
+    key
+}
+
+fn part_key(session_id: u32, part: usize) -> Vec<u8> {
+    let mut key = session_key(session_id);
+    key.extend_from_slice(&(part as u32).to_le_bytes());
+    key
+}
+
+fn chunk_key(part_key: &[u8], chunk: u32) -> Vec<u8> {
+    let mut key = part_key.to_vec();
+    key.extend_from_slice(&chunk.to_le_bytes());
+    key
+}
+
+impl<C: AuxStore + Send + Sync> BackupStore for AuxBackupStore<C> {
+    fn parts(&self, session_id: u32) -> io::Result<Vec<usize>> {
+        let parts = self.get_count(&session_key(session_id))?;
+        Ok((0..parts as usize).collect())
+    }
+
+    fn read(&self, session_id: u32, part: usize) -> io::Result<Vec<u8>> {
+        let mut content = Vec::new();
+        for key in self.chunk_keys(session_id, part)? {
+            let chunk = self
+                .client
+                .get_aux(&key)
+                .map_err(aux_error)?
+                .ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::NotFound, "missing chunk of a backup part")
+                })?;
+            content.extend(chunk);
+        }
+        Ok(content)
+    }
+
+    fn write(&self, session_id: u32, part: usize, content: &[u8]) -> io::Result<()> {
+        let session_key = session_key(session_id);
+        let part_key = part_key(session_id, part);
+        let parts = self.get_count(&session_key)?.max(part as u32 + 1);
+        let mut delete = self.chunk_keys(session_id, part)?;
+        delete.retain(|key| *key != chunk_key(&part_key, 0));
+        self.insert(
+            &[
+                (session_key, parts.to_le_bytes().to_vec()),
+                (part_key.clone(), 1u32.to_le_bytes().to_vec()),
+                (chunk_key(&part_key, 0), content.to_vec()),
+            ],
+            &delete,
+        )
+    }
+
+    fn append(&self, session_id: u32, part: usize, data: &[u8]) -> io::Result<()> {
+        let part_key = part_key(session_id, part);
+        let chunks = self.get_count(&part_key)?;
+        self.insert(
+            &[
+                (chunk_key(&part_key, chunks), data.to_vec()),
+                (part_key, (chunks + 1).to_le_bytes().to_vec()),
+            ],
+            &[],
+        )
+    }
+
+    fn remove(&self, session_id: u32) -> io::Result<()> {
+        let session_key = session_key(session_id);
+        let mut delete = Vec::new();
+        for part in self.parts(session_id)? {
+            delete.extend(self.chunk_keys(session_id, part)?);
+            delete.push(part_key(session_id, part));
+        }
+        delete.push(session_key);
+        self.insert(&[], &delete)
+    }
+}
+
+/// Keeps the backups in memory, for tests.
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryBackupStore {
+    sessions: Mutex<HashMap<u32, Vec<Vec<u8>>>>,
+}
+
+#[cfg(test)]
+impl BackupStore for MemoryBackupStore {
+    fn parts(&self, session_id: u32) -> io::Result<Vec<usize>> {
+        let parts = self.sessions.lock().get(&session_id).map_or(0, Vec::len);
+        Ok((0..parts).collect())
+    }
+
+    fn read(&self, session_id: u32, part: usize) -> io::Result<Vec<u8>> {
+        self.sessions
+            .lock()
+            .get(&session_id)
+            .and_then(|parts| parts.get(part).cloned())
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+    }
+
+    fn write(&self, session_id: u32, part: usize, content: &[u8]) -> io::Result<()> {
+        let mut sessions = self.sessions.lock();
+        let parts = sessions.entry(session_id).or_default();
+        if parts.len() <= part {
+            parts.resize(part + 1, Vec::new());
+        }
+        parts[part] = content.to_vec();
+        Ok(())
+    }
+
+    fn append(&self, session_id: u32, part: usize, data: &[u8]) -> io::Result<()> {
+        self.sessions
+            .lock()
+            .get_mut(&session_id)
+            .and_then(|parts| parts.get_mut(part))
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?
+            .extend_from_slice(data);
+        Ok(())
+    }
+
+    fn remove(&self, session_id: u32) -> io::Result<()> {
+        self.sessions
+            .lock()
+            .remove(&session_id)
+            .map(|_| ())
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, env, fs, path::PathBuf, process, sync::Arc};
+
+    use parking_lot::Mutex;
+    use sc_client_api::AuxStore;
+
+    use super::{
+        part_key, session_key, AuxBackupStore, BackupStore, FileBackupStore,
+        TEMPORARY_FILE_EXTENSION,
+    };
+
+    /// A fresh directory for the backups of a single test.
+    fn backup_dir(test: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("dagestan-backup-{}-{}", process::id(), test));
+        let _ = fs::remove_dir_all(&path);
+        path
+    }
+
+    #[test]
+    fn file_store_reads_written_and_appended_parts() {
+        let path = backup_dir("file_store_reads_written_and_appended_parts");
+        let store = FileBackupStore::new(path.clone());
+
+        assert!(store.parts(7).unwrap().is_empty());
+        store.write(7, 0, b"unit").unwrap();
+        store.append(7, 0, b"s").unwrap();
+        store.write(7, 1, b"more").unwrap();
+
+        assert_eq!(store.parts(7).unwrap(), vec![0, 1]);
+        assert_eq!(store.read(7, 0).unwrap(), b"units");
+        assert_eq!(store.read(7, 1).unwrap(), b"more");
+        let leftovers = fs::read_dir(path.join("7"))
+            .unwrap()
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter(|name| name.ends_with(TEMPORARY_FILE_EXTENSION))
+            .count();
+        assert_eq!(leftovers, 0);
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn file_store_write_replaces_part() {
+        let path = backup_dir("file_store_write_replaces_part");
+        let store = FileBackupStore::new(path.clone());
+
+        store.write(3, 0, b"old").unwrap();
+        store.append(3, 0, b"er").unwrap();
+        store.write(3, 0, b"new").unwrap();
+
+        assert_eq!(store.parts(3).unwrap(), vec![0]);
+        assert_eq!(store.read(3, 0).unwrap(), b"new");
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn file_store_does_not_append_to_missing_part() {
+        let path = backup_dir("file_store_does_not_append_to_missing_part");
+        let store = FileBackupStore::new(path.clone());
+
+        store.write(3, 0, b"unit").unwrap();
+
+        assert!(store.append(3, 1, b"unit").is_err());
+        assert_eq!(store.parts(3).unwrap(), vec![0]);
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn file_store_removes_only_the_session() {
+        let path = backup_dir("file_store_removes_only_the_session");
+        let store = FileBackupStore::new(path.clone());
+
+        store.write(1, 0, b"first").unwrap();
+        store.write(2, 0, b"second").unwrap();
+        store.remove(1).unwrap();
+
+        assert!(!path.join("1").exists());
+        assert!(store.parts(1).unwrap().is_empty());
+        assert_eq!(store.read(2, 0).unwrap(), b"second");
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[derive(Default)]
+    struct MemoryAuxStore(Mutex<HashMap<Vec<u8>, Vec<u8>>>);
+
+    impl AuxStore for MemoryAuxStore {
+        fn insert_aux<
+            'a,
+            'b: 'a,
+            'c: 'a,
+            I: IntoIterator<Item = &'a (&'c [u8], &'c [u8])>,
+            D: IntoIterator<Item = &'a &'b [u8]>,
+        >(
+            &self,
+            insert: I,
+            delete: D,
+        ) -> sp_blockchain::Result<()> {
+            let mut aux = self.0.lock();
+            for (key, value) in insert {
+                aux.insert(key.to_vec(), value.to_vec());
+            }
+            for key in delete {
+                aux.remove(*key);
+            }
+            Ok(())
+        }
+
+        fn get_aux(&self, key: &[u8]) -> sp_blockchain::Result<Option<Vec<u8>>> {
+            Ok(self.0.lock().get(key).cloned())
+        }
+    }
+
+    fn aux_store() -> (AuxBackupStore<MemoryAuxStore>, Arc<MemoryAuxStore>) {
+        let aux = Arc::new(MemoryAuxStore::default());
+        (AuxBackupStore::new(aux.clone()), aux)
+    }
+
+    fn count(aux: &MemoryAuxStore, key: &[u8]) -> Option<u32> {
+        let count = aux.get_aux(key).unwrap()?;
+        Some(u32::from_le_bytes(count.try_into().unwrap()))
+    }
+
+    fn keys_of_session(aux: &MemoryAuxStore, session_id: u32) -> usize {
+        let session_key = session_key(session_id);
+        aux.0
+            .lock()
+            .keys()
+            .filter(|key| key.starts_with(&session_key))
+            .count()
+    }
+
+    #[test]
+    fn aux_store_counts_chunks_and_parts() {
+        let (store, aux) = aux_store();
+
+        store.write(5, 0, b"unit").unwrap();
+        store.append(5, 0, b"s").unwrap();
+        store.append(5, 0, b"!").unwrap();
+        assert_eq!(count(&aux, &part_key(5, 0)), Some(3));
+        assert_eq!(count(&aux, &session_key(5)), Some(1));
+
+        store.write(5, 1, b"more").unwrap();
+        assert_eq!(count(&aux, &part_key(5, 1)), Some(1));
+        assert_eq!(count(&aux, &session_key(5)), Some(2));
+
+        assert_eq!(store.parts(5).unwrap(), vec![0, 1]);
+        assert_eq!(store.read(5, 0).unwrap(), b"units!");
+        assert_eq!(store.read(5, 1).unwrap(), b"more");
+    }
+
+    #[test]
+    fn aux_store_write_replaces_all_chunks_of_part() {
+        let (store, aux) = aux_store();
+
+        store.write(5, 0, b"old").unwrap();
+        store.append(5, 0, b"er").unwrap();
+        store.append(5, 0, b"!").unwrap();
+        store.write(5, 0, b"new").unwrap();
+
+        assert_eq!(count(&aux, &part_key(5, 0)), Some(1));
+        assert_eq!(count(&aux, &session_key(5)), Some(1));
+        // The session count, the chunk count of the part and its single chunk.
+        assert_eq!(keys_of_session(&aux, 5), 3);
+        assert_eq!(store.read(5, 0).unwrap(), b"new");
+    }
+
+    #[test]
+    fn aux_store_removes_all_keys_of_session() {
+        let (store, aux) = aux_store();
+
+        store.write(1, 0, b"first").unwrap();
+        store.append(1, 0, b"!").unwrap();
+        store.write(1, 1, b"again").unwrap();
+        store.write(2, 0, b"second").unwrap();
+        store.remove(1).unwrap();
+
+        assert_eq!(keys_of_session(&aux, 1), 0);
+        assert!(store.parts(1).unwrap().is_empty());
+        assert_eq!(store.parts(2).unwrap(), vec![0]);
+        assert_eq!(store.read(2, 0).unwrap(), b"second");
+    }
+}
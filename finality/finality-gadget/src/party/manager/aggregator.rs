@@ -10,36 +10,29 @@ use sp_runtime::traits::{Block, Header};
 use tokio::time;
 
 use crate::{
-    abft::SignatureSet,
     aggregation::Aggregator,
-    crypto::Signature,
+    data_io::Payload,
     justification::{DagestanJustification, JustificationNotification},
     metrics::Checkpoint,
     network::data::Network,
-    party::{
-        manager::aggregator::AggregatorVersion::{Current, Legacy},
-        AuthoritySubtaskCommon, Task,
-    },
-    BlockHashNum, CurrentRmcNetworkData, Keychain, LegacyRmcNetworkData, Metrics,
-    SessionBoundaries, STATUS_REPORT_INTERVAL,
+    party::{AuthoritySubtaskCommon, Task},
+    rpc::{AggregatorStatus, RpcLink},
+    versions::ConsensusVersion,
+    BlockHashNum, Metrics, SessionBoundaries, STATUS_REPORT_INTERVAL,
 };
 
 /// IO channels used by the aggregator task.
 pub struct IO<B: Block> {
     pub blocks_from_interpreter: mpsc::UnboundedReceiver<BlockHashNum<B>>,
     pub justifications_for_chain: mpsc::UnboundedSender<JustificationNotification<B>>,
+    pub rpc_link: RpcLink<B>,
 }
 
-async fn process_new_block_data<B, CN, LN>(
-    aggregator: &mut Aggregator<'_, B, CN, LN>,
+async fn process_new_block_data<B: Block>(
+    aggregator: &mut Aggregator<'_, B>,
     block: BlockHashNum<B>,
     metrics: &Option<Metrics<<B::Header as Header>::Hash>>,
-) where
-    B: Block,
-    CN: Network<CurrentRmcNetworkData<B>>,
-    LN: Network<LegacyRmcNetworkData<B>>,
-    <B as Block>::Hash: AsRef<[u8]>,
-{
+) {
     trace!(target: "dagestan-party", "Received unit {:?} in aggregator.", block);
     if let Some(metrics) = &metrics {
         metrics.report_block(block.hash, std::time::Instant::now(), Checkpoint::Ordered);
@@ -50,7 +43,7 @@ async fn process_new_block_data<B, CN, LN>(
 
 fn process_hash<B, C>(
     hash: B::Hash,
-    multisignature: SignatureSet<Signature>,
+    justification: DagestanJustification,
     justifications_for_chain: &mpsc::UnboundedSender<JustificationNotification<B>>,
     client: &Arc<C>,
 ) -> Result<(), ()>
@@ -61,7 +54,7 @@ where
     let number = client.number(hash).unwrap().unwrap();
     // The unwrap might actually fail if data availability is not implemented correctly.
     let notification = JustificationNotification {
-        justification: DagestanJustification::CommitteeMultisignature(multisignature),
+        justification,
         hash,
         number,
     };
@@ -72,24 +65,23 @@ where
     Ok(())
 }
 
-async fn run_aggregator<B, C, CN, LN>(
-    mut aggregator: Aggregator<'_, B, CN, LN>,
+async fn run_aggregator<B, C>(
+    mut aggregator: Aggregator<'_, B>,
     io: IO<B>,
     client: Arc<C>,
     session_boundaries: &SessionBoundaries<B>,
+    session_id: u32,
     metrics: Option<Metrics<<B::Header as Header>::Hash>>,
     mut exit_rx: oneshot::Receiver<()>,
 ) -> Result<(), ()>
 where
     B: Block,
     C: HeaderBackend<B> + Send + Sync + 'static,
-    LN: Network<LegacyRmcNetworkData<B>>,
-    CN: Network<CurrentRmcNetworkData<B>>,
-    <B as Block>::Hash: AsRef<[u8]>,
 {
     let IO {
         blocks_from_interpreter,
         justifications_for_chain,
+        rpc_link,
     } = io;
 
     let blocks_from_interpreter = blocks_from_interpreter.take_while(|block| {
@@ -104,6 +96,12 @@ where
     pin_mut!(blocks_from_interpreter);
     let mut hash_of_last_block = None;
     let mut no_more_blocks = false;
+    let mut status = AggregatorStatus {
+        session: session_id,
+        started_hashes: 0,
+        multisigned_hashes: 0,
+        last_multisigned: None,
+    };
 
     let mut status_ticker = time::interval(STATUS_REPORT_INTERVAL);
 
@@ -113,7 +111,8 @@ where
             maybe_block = blocks_from_interpreter.next() => {
                 if let Some(block) = maybe_block {
                     hash_of_last_block = Some(block.hash);
-                    process_new_block_data::<B, CN, LN>(
+                    status.started_hashes += 1;
+                    process_new_block_data::<B>(
                         &mut aggregator,
                         block,
                         &metrics
@@ -124,8 +123,10 @@ where
                 }
             }
             multisigned_hash = aggregator.next_multisigned_hash() => {
-                if let Some((hash, multisignature)) = multisigned_hash {
-                    process_hash(hash, multisignature, &justifications_for_chain, &client)?;
+                if let Some((hash, justification)) = multisigned_hash {
+                    process_hash(hash, justification, &justifications_for_chain, &client)?;
+                    status.multisigned_hashes += 1;
+                    status.last_multisigned = Some(hash);
                     if Some(hash) == hash_of_last_block {
                         hash_of_last_block = None;
                     }
@@ -136,6 +137,7 @@ where
             }
             _ = status_ticker.tick() => {
                 aggregator.status_report();
+                rpc_link.report_aggregator_status(status.clone());
             },
             _ = &mut exit_rx => {
                 debug!(target: "dagestan-party", "Aggregator received exit signal. Terminating.");
@@ -151,26 +153,23 @@ where
     Ok(())
 }
 
-pub enum AggregatorVersion<CN, LN> {
-    Current(CN),
-    Legacy(LN),
-}
-
-/// Runs the justification signature aggregator within a single session.
-pub fn task<B, C, CN, LN>(
+/// Runs the justification signature aggregator of the given consensus version within a single
+/// session.
+pub fn task<B, C, V, P, N>(
     subtask_common: AuthoritySubtaskCommon,
     client: Arc<C>,
     io: IO<B>,
     session_boundaries: SessionBoundaries<B>,
     metrics: Option<Metrics<<B::Header as Header>::Hash>>,
-    multikeychain: Keychain,
-    version: AggregatorVersion<CN, LN>,
+    multikeychain: V::AggregatorKeychain,
+    rmc_network: N,
 ) -> Task
 where
     B: Block,
     C: HeaderBackend<B> + Send + Sync + 'static,
-    LN: Network<LegacyRmcNetworkData<B>> + 'static,
-    CN: Network<CurrentRmcNetworkData<B>> + 'static,
+    V: ConsensusVersion<B, P>,
+    P: Payload,
+    N: Network<V::RmcNetworkData> + 'static,
 {
     let AuthoritySubtaskCommon {
         spawn_handle,
@@ -179,20 +178,14 @@ where
     let (stop, exit) = oneshot::channel();
     let task = {
         async move {
-            let aggregator_io = match version {
-                Current(rmc_network) => {
-                    Aggregator::new_current(&multikeychain, rmc_network, metrics.clone())
-                }
-                Legacy(rmc_network) => {
-                    Aggregator::new_legacy(&multikeychain, rmc_network, metrics.clone())
-                }
-            };
+            let aggregator_io = V::aggregator(&multikeychain, rmc_network, metrics.clone());
             debug!(target: "dagestan-party", "Running the aggregator task for {:?}", session_id);
             let result = run_aggregator(
                 aggregator_io,
                 io,
                 client,
                 &session_boundaries,
+                session_id,
                 metrics,
                 exit,
             )
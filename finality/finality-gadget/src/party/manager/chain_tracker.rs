@@ -1,6 +1,6 @@
 use futures::channel::oneshot;
 use log::debug;
-use sc_client_api::HeaderBackend;
+use sc_client_api::{BlockchainEvents, HeaderBackend};
 use sp_consensus::SelectChain;
 use sp_runtime::traits::Block;
 
@@ -17,7 +17,7 @@ pub fn task<B, SC, C>(
 ) -> Task
 where
     B: Block,
-    C: HeaderBackend<B> + 'static,
+    C: HeaderBackend<B> + BlockchainEvents<B> + 'static,
     SC: SelectChain<B> + 'static,
 {
     let AuthoritySubtaskCommon {
@@ -3,27 +3,32 @@ use std::fmt::Debug;
 use codec::Codec;
 use futures::channel::oneshot;
 use log::debug;
-use sc_client_api::{BlockchainEvents, HeaderBackend};
+use sc_client_api::{BlockBackend, BlockchainEvents, HeaderBackend};
 use sp_runtime::traits::Block;
 
 use crate::{
     abft::SpawnHandleT,
-    data_io::{DagestanNetworkMessage, DataStore},
-    network::{data::component::Receiver, RequestBlocks},
+    data_io::{BlockFetchMessage, DagestanNetworkMessage, DataStore},
+    network::{
+        data::{component::Receiver, Network},
+        ImportBlocks, RequestBlocks,
+    },
     party::{AuthoritySubtaskCommon, Task},
 };
 
 /// Runs the data store within a single session.
-pub fn task<B, C, RB, R, Message>(
+pub fn task<B, C, RB, BI, R, Message, BN>(
     subtask_common: AuthoritySubtaskCommon,
-    mut data_store: DataStore<B, C, RB, Message, R>,
+    mut data_store: DataStore<B, C, RB, BI, Message, R, BN>,
 ) -> Task
 where
     B: Block,
-    C: HeaderBackend<B> + BlockchainEvents<B> + Send + Sync + 'static,
+    C: HeaderBackend<B> + BlockBackend<B> + BlockchainEvents<B> + Send + Sync + 'static,
     RB: RequestBlocks<B> + 'static,
+    BI: ImportBlocks<B>,
     Message: DagestanNetworkMessage<B> + Debug + Send + Sync + Codec + 'static,
     R: Receiver<Message> + 'static,
+    BN: Network<BlockFetchMessage<B>> + 'static,
 {
     let AuthoritySubtaskCommon {
         spawn_handle,
@@ -1,10 +1,20 @@
-use std::{collections::HashSet, marker::PhantomData, sync::Arc};
+use std::{
+    collections::HashSet,
+    fmt::{Display, Error as FmtError, Formatter},
+    marker::PhantomData,
+    sync::Arc,
+};
 
-use dagestan_primitives::{DagestanSessionApi, KEY_TYPE};
+use dagestan_primitives::{
+    BlsPublic, DagestanSessionApi, BLS_AGGREGATION_FINALITY_VERSION,
+    CONFIGURABLE_BRANCH_LEN_FINALITY_VERSION, DEFAULT_FINALITY_VERSION, KEY_TYPE,
+    MAX_DATA_BRANCH_LEN_LIMIT, PAYLOAD_FINALITY_VERSION,
+};
 use async_trait::async_trait;
 use futures::channel::oneshot;
 use log::{debug, info, trace, warn};
 use sc_client_api::Backend;
+use sp_api::{ApiError, ApiExt};
 use sp_consensus::SelectChain;
 use sp_keystore::CryptoStore;
 use sp_runtime::{
@@ -13,27 +23,29 @@ use sp_runtime::{
 };
 
 use crate::{
-    abft::{
-        current_create_dagestan_config, legacy_create_dagestan_config, run_current_member,
-        run_legacy_member, SpawnHandle, SpawnHandleT,
+    abft::{BlsKeychain, SpawnHandle, SpawnHandleT},
+    compatibility::Versioned,
+    crypto::{AuthorityPen, AuthorityVerifier, BlsPen},
+    data_io::{
+        ChainTracker, DataStore, NoPayload, OrderedDataInterpreter, Payload, PayloadProvider,
+        PayloadSink, DEFAULT_MAX_DATA_BRANCH_LEN,
     },
-    crypto::{AuthorityPen, AuthorityVerifier},
-    data_io::{ChainTracker, DataStore, OrderedDataInterpreter},
     mpsc,
     network::{
         data::{
-            component::{Network, NetworkMap, SimpleNetwork},
+            component::{Network, NetworkMap},
             split::split,
         },
-        session::{SessionManager, SessionSender},
-        RequestBlocks,
+        session::SessionManager,
+        ImportBlocks, RequestBlocks,
     },
-    party::{
-        backup::ABFTBackup, manager::aggregator::AggregatorVersion, traits::NodeSessionManager,
-    },
-    AuthorityId, CurrentRmcNetworkData, JustificationNotification, Keychain, LegacyRmcNetworkData,
-    Metrics, NodeIndex, SessionBoundaries, SessionId, SessionPeriod, UnitCreationDelay,
-    VersionedNetworkData,
+    party::{backup::ABFTBackup, traits::NodeSessionManager},
+    rpc::RpcLink,
+    session_map::runtime_next_session_authority_data,
+    versions::{ConsensusVersion, SessionNetworkData, V1, V2, V3, V4, V5},
+    AuthorityId, BlockHashNum, JustificationNotification, Keychain, Metrics, NodeIndex,
+    SessionBoundaries, SessionId, SessionPeriod, UnitCreationDelay, VersionedNetworkData,
+    VersionedTryFromError,
 };
 
 mod aggregator;
@@ -45,32 +57,44 @@ mod task;
 pub use authority::{SubtaskCommon, Subtasks, Task as AuthorityTask};
 pub use task::{Handle, Task};
 
-use crate::{
-    abft::{CURRENT_VERSION, LEGACY_VERSION},
-    data_io::DataProvider,
-};
+/// The first version of `DagestanSessionApi` providing the maximal data branch length.
+const MAX_DATA_BRANCH_LEN_API_VERSION: u32 = 3;
+
+/// Errors of the node session manager.
+pub enum Error<E> {
+    /// The session network failed.
+    Network(E),
+    /// The runtime does not provide the maximal data branch length of the session, even though
+    /// its finality version needs one.
+    MaxDataBranchLenUnavailable(SessionId),
+    /// Reading the parameters of the session from the runtime failed.
+    RuntimeApi(SessionId, ApiError),
+}
+
+impl<E: Display> Display for Error<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        use Error::*;
+        match self {
+            Network(e) => write!(f, "session network error: {}", e),
+            MaxDataBranchLenUnavailable(session) => write!(
+                f,
+                "the runtime does not provide the maximal data branch length of session {:?}",
+                session
+            ),
+            RuntimeApi(session, e) => write!(
+                f,
+                "failed to read the parameters of session {:?} from the runtime: {}",
+                session, e
+            ),
+        }
+    }
+}
 
-#[cfg(feature = "only_legacy")]
-const ONLY_LEGACY_ENV: &str = "ONLY_LEGACY_PROTOCOL";
-
-type LegacyNetworkType<B> = SimpleNetwork<
-    LegacyRmcNetworkData<B>,
-    mpsc::UnboundedReceiver<LegacyRmcNetworkData<B>>,
-    SessionSender<LegacyRmcNetworkData<B>>,
->;
-type CurrentNetworkType<B> = SimpleNetwork<
-    CurrentRmcNetworkData<B>,
-    mpsc::UnboundedReceiver<CurrentRmcNetworkData<B>>,
-    SessionSender<CurrentRmcNetworkData<B>>,
->;
-
-struct SubtasksParams<C, SC, B, N, BE>
+struct SubtasksParams<B, P, N>
 where
     B: BlockT,
-    C: crate::ClientForDagestan<B, BE> + Send + Sync + 'static,
-    BE: Backend<B> + 'static,
-    SC: SelectChain<B> + 'static,
-    N: Network<VersionedNetworkData<B>> + 'static,
+    P: Payload,
+    N: Network<VersionedNetworkData<B, P>> + 'static,
 {
     n_members: usize,
     node_id: NodeIndex,
@@ -78,24 +102,28 @@ where
     data_network: N,
     session_boundaries: SessionBoundaries<B>,
     subtask_common: SubtaskCommon,
-    data_provider: DataProvider<B>,
-    ordered_data_interpreter: OrderedDataInterpreter<B, C>,
+    blocks_for_aggregator: mpsc::UnboundedSender<BlockHashNum<B>>,
     aggregator_io: aggregator::IO<B>,
     multikeychain: Keychain,
     exit_rx: oneshot::Receiver<()>,
     backup: ABFTBackup,
-    chain_tracker: ChainTracker<B, SC, C>,
-    phantom: PhantomData<BE>,
+    phantom: PhantomData<P>,
 }
 
-pub struct NodeSessionManagerImpl<C, SC, B, RB, BE, SM>
+/// Runs the sessions of the node. Sessions ordering payloads take them from clones of
+/// `payload_provider` and pass the ordered ones to clones of `payload_sink`. Blocks fetched from
+/// other members are imported with clones of `block_importer`.
+pub struct NodeSessionManagerImpl<C, SC, B, RB, BI, BE, SM, PP = NoPayload, PS = NoPayload>
 where
     B: BlockT,
     C: crate::ClientForDagestan<B, BE> + Send + Sync + 'static,
     BE: Backend<B> + 'static,
     SC: SelectChain<B> + 'static,
     RB: RequestBlocks<B>,
-    SM: SessionManager<VersionedNetworkData<B>> + 'static,
+    BI: ImportBlocks<B>,
+    PP: PayloadProvider + Clone,
+    PS: PayloadSink<Payload = PP::Payload> + Clone,
+    SM: SessionManager<VersionedNetworkData<B, PP::Payload>> + 'static,
 {
     client: Arc<C>,
     select_chain: SC,
@@ -103,14 +131,18 @@ where
     unit_creation_delay: UnitCreationDelay,
     authority_justification_tx: mpsc::UnboundedSender<JustificationNotification<B>>,
     block_requester: RB,
+    block_importer: BI,
     metrics: Option<Metrics<<B::Header as Header>::Hash>>,
     spawn_handle: SpawnHandle,
     session_manager: SM,
     keystore: Arc<dyn CryptoStore>,
+    rpc_link: RpcLink<B>,
+    payload_provider: PP,
+    payload_sink: PS,
     _phantom: PhantomData<BE>,
 }
 
-impl<C, SC, B, RB, BE, SM> NodeSessionManagerImpl<C, SC, B, RB, BE, SM>
+impl<C, SC, B, RB, BI, BE, SM, PP, PS> NodeSessionManagerImpl<C, SC, B, RB, BI, BE, SM, PP, PS>
 where
     B: BlockT,
     C: crate::ClientForDagestan<B, BE> + Send + Sync + 'static,
@@ -118,7 +150,10 @@ where
     BE: Backend<B> + 'static,
     SC: SelectChain<B> + 'static,
     RB: RequestBlocks<B>,
-    SM: SessionManager<VersionedNetworkData<B>>,
+    BI: ImportBlocks<B>,
+    PP: PayloadProvider + Clone,
+    PS: PayloadSink<Payload = PP::Payload> + Clone,
+    SM: SessionManager<VersionedNetworkData<B, PP::Payload>>,
 {
     #[allow(clippy::too_many_arguments)]
     pub fn new(
@@ -128,10 +163,14 @@ where
         unit_creation_delay: UnitCreationDelay,
         authority_justification_tx: mpsc::UnboundedSender<JustificationNotification<B>>,
         block_requester: RB,
+        block_importer: BI,
         metrics: Option<Metrics<<B::Header as Header>::Hash>>,
         spawn_handle: SpawnHandle,
         session_manager: SM,
         keystore: Arc<dyn CryptoStore>,
+        rpc_link: RpcLink<B>,
+        payload_provider: PP,
+        payload_sink: PS,
     ) -> Self {
         Self {
             client,
@@ -140,18 +179,29 @@ where
             unit_creation_delay,
             authority_justification_tx,
             block_requester,
+            block_importer,
             metrics,
             spawn_handle,
             session_manager,
             keystore,
+            rpc_link,
+            payload_provider,
+            payload_sink,
             _phantom: PhantomData,
         }
     }
 
-    fn legacy_subtasks<N: Network<VersionedNetworkData<B>> + 'static>(
+    fn subtasks<V, N>(
         &self,
-        params: SubtasksParams<C, SC, B, N, BE>,
-    ) -> Subtasks {
+        params: SubtasksParams<B, PP::Payload, N>,
+        aggregator_multikeychain: V::AggregatorKeychain,
+    ) -> Subtasks
+    where
+        V: ConsensusVersion<B, PP::Payload>,
+        N: Network<VersionedNetworkData<B, PP::Payload>> + 'static,
+        SessionNetworkData<B, PP::Payload, V>: Into<VersionedNetworkData<B, PP::Payload>>
+            + TryFrom<VersionedNetworkData<B, PP::Payload>, Error = VersionedTryFromError>,
+    {
         let SubtasksParams {
             n_members,
             node_id,
@@ -159,111 +209,143 @@ where
             data_network,
             session_boundaries,
             subtask_common,
-            data_provider,
-            ordered_data_interpreter,
+            blocks_for_aggregator,
             aggregator_io,
             multikeychain,
             exit_rx,
             backup,
-            chain_tracker,
             ..
         } = params;
-        let consensus_config =
-            legacy_create_dagestan_config(n_members, node_id, session_id, self.unit_creation_delay);
-        let data_network = data_network.map();
+        let (payload_provider, payload_sink) =
+            V::payload_io(self.payload_provider.clone(), self.payload_sink.clone());
 
-        let (unfiltered_dagestan_network, rmc_network) =
-            split(data_network, "dagestan_network", "rmc_network");
-        let (data_store, dagestan_network) = DataStore::new(
-            session_boundaries.clone(),
+        let (chain_tracker, data_provider) = ChainTracker::new(
+            self.select_chain.clone(),
             self.client.clone(),
-            self.block_requester.clone(),
+            session_boundaries.clone(),
             Default::default(),
-            unfiltered_dagestan_network,
+            payload_provider,
+            self.metrics.clone(),
         );
-        Subtasks::new(
-            exit_rx,
-            run_legacy_member(
-                subtask_common.clone(),
-                multikeychain.clone(),
-                consensus_config,
-                dagestan_network.into(),
-                data_provider,
-                ordered_data_interpreter,
-                backup,
-            ),
-            aggregator::task(
-                subtask_common.clone(),
-                self.client.clone(),
-                aggregator_io,
-                session_boundaries,
-                self.metrics.clone(),
-                multikeychain,
-                AggregatorVersion::<CurrentNetworkType<B>, _>::Legacy(rmc_network),
-            ),
-            chain_tracker::task(subtask_common.clone(), chain_tracker),
-            data_store::task(subtask_common, data_store),
-        )
-    }
 
-    fn current_subtasks<N: Network<VersionedNetworkData<B>> + 'static>(
-        &self,
-        params: SubtasksParams<C, SC, B, N, BE>,
-    ) -> Subtasks {
-        let SubtasksParams {
-            n_members,
-            node_id,
-            session_id,
-            data_network,
-            session_boundaries,
-            subtask_common,
-            data_provider,
-            ordered_data_interpreter,
-            aggregator_io,
-            multikeychain,
-            exit_rx,
-            backup,
-            chain_tracker,
-            ..
-        } = params;
-        let consensus_config =
-            current_create_dagestan_config(n_members, node_id, session_id, self.unit_creation_delay);
-        let data_network = data_network.map();
+        let ordered_data_interpreter = OrderedDataInterpreter::<B, C, V::Payload>::new(
+            blocks_for_aggregator,
+            self.client.clone(),
+            session_boundaries.clone(),
+            payload_sink,
+        );
 
+        let consensus_config =
+            V::create_config(n_members, node_id, session_id, self.unit_creation_delay);
+        let data_network = NetworkMap::<
+            VersionedNetworkData<B, PP::Payload>,
+            SessionNetworkData<B, PP::Payload, V>,
+        >::map(data_network);
+
+        let (consensus_network, block_fetch_network) =
+            split(data_network, "consensus_network", "block_fetch_network");
         let (unfiltered_dagestan_network, rmc_network) =
-            split(data_network, "dagestan_network", "rmc_network");
+            split(consensus_network, "dagestan_network", "rmc_network");
         let (data_store, dagestan_network) = DataStore::new(
+            node_id,
             session_boundaries.clone(),
             self.client.clone(),
             self.block_requester.clone(),
+            self.block_importer.clone(),
             Default::default(),
             unfiltered_dagestan_network,
+            block_fetch_network,
+            self.metrics.clone(),
         );
         Subtasks::new(
             exit_rx,
-            run_current_member(
+            V::run_member(
                 subtask_common.clone(),
-                multikeychain.clone(),
+                multikeychain,
                 consensus_config,
-                dagestan_network.into(),
+                dagestan_network,
                 data_provider,
                 ordered_data_interpreter,
                 backup,
             ),
-            aggregator::task(
+            aggregator::task::<_, _, V, PP::Payload, _>(
                 subtask_common.clone(),
                 self.client.clone(),
                 aggregator_io,
                 session_boundaries,
                 self.metrics.clone(),
-                multikeychain,
-                AggregatorVersion::<_, LegacyNetworkType<B>>::Current(rmc_network),
+                aggregator_multikeychain,
+                rmc_network,
             ),
             chain_tracker::task(subtask_common.clone(), chain_tracker),
             data_store::task(subtask_common, data_store),
         )
     }
 
+    /// The BLS keys of the authorities of the session following the one containing `block`, if
+    /// every authority has one.
+    fn next_session_bls_authorities(
+        &self,
+        block: NumberFor<B>,
+        n_members: usize,
+    ) -> Option<Vec<BlsPublic>> {
+        let runtime_api = self.client.runtime_api();
+        runtime_next_session_authority_data(&*runtime_api, &BlockId::Number(block))
+            .ok()
+            .and_then(|result| result.ok())
+            .and_then(|authority_data| authority_data.bls_authorities().clone())
+            .filter(|bls_authorities| bls_authorities.len() == n_members)
+    }
+
+    /// The maximal length of proposal branches in the session `session_id`, which follows the
+    /// one containing `block`. Sessions with older finality versions use the default, the newer
+    /// ones cannot run without the runtime providing it.
+    fn next_session_max_data_branch_len(
+        &self,
+        session_id: SessionId,
+        block: NumberFor<B>,
+    ) -> Result<usize, Error<SM::Error>> {
+        let runtime_api = self.client.runtime_api();
+        let at = BlockId::Number(block);
+        match runtime_api.next_session_finality_version(&at) {
+            Ok(version) if version >= CONFIGURABLE_BRANCH_LEN_FINALITY_VERSION => {
+                match runtime_api.has_api_with::<dyn DagestanSessionApi<B>, _>(&at, |version| {
+                    version >= MAX_DATA_BRANCH_LEN_API_VERSION
+                }) {
+                    Ok(true) => (),
+                    Ok(false) => return Err(Error::MaxDataBranchLenUnavailable(session_id)),
+                    Err(e) => return Err(Error::RuntimeApi(session_id, e)),
+                }
+                runtime_api
+                    .next_session_max_data_branch_len(&at)
+                    .map(|max_branch_len| {
+                        max_branch_len.clamp(1, MAX_DATA_BRANCH_LEN_LIMIT) as usize
+                    })
+                    .map_err(|e| Error::RuntimeApi(session_id, e))
+            }
+            _ => Ok(DEFAULT_MAX_DATA_BRANCH_LEN),
+        }
+    }
+
+    async fn bls_multikeychain(
+        &self,
+        node_id: NodeIndex,
+        authority_verifier: AuthorityVerifier,
+        bls_authorities: Vec<BlsPublic>,
+    ) -> BlsKeychain {
+        let bls_pen = BlsPen::load(Some(bls_authorities[node_id.0]), self.keystore.clone())
+            .await
+            .expect("The keys should sign successfully");
+        if bls_authorities[node_id.0] != bls_pen.public() {
+            warn!(target: "dagestan-party", "Our BLS key {:?} differs from the registered one {:?}, our signatures will not be aggregated.", bls_pen.public(), bls_authorities[node_id.0]);
+        }
+        BlsKeychain::new(
+            node_id,
+            authority_verifier.with_bls_authorities(bls_authorities),
+            bls_pen,
+        )
+    }
+
     async fn spawn_subtasks(
         &self,
         session_id: SessionId,
@@ -271,7 +353,7 @@ where
         node_id: NodeIndex,
         exit_rx: oneshot::Receiver<()>,
         backup: ABFTBackup,
-    ) -> Subtasks {
+    ) -> Result<Subtasks, Error<SM::Error>> {
         debug!(target: "afa", "Authority task {:?}", session_id);
 
         let authority_verifier = AuthorityVerifier::new(authorities.to_vec());
@@ -283,21 +365,13 @@ where
             Keychain::new(node_id, authority_verifier.clone(), authority_pen.clone());
 
         let session_boundaries = SessionBoundaries::new(session_id, self.session_period);
-        let (blocks_for_aggregator, blocks_from_interpreter) = mpsc::unbounded();
-
-        let (chain_tracker, data_provider) = ChainTracker::new(
-            self.select_chain.clone(),
-            self.client.clone(),
-            session_boundaries.clone(),
-            Default::default(),
-            self.metrics.clone(),
-        );
-
-        let ordered_data_interpreter = OrderedDataInterpreter::<B, C>::new(
-            blocks_for_aggregator,
-            self.client.clone(),
-            session_boundaries.clone(),
+        let last_block_of_previous_session = session_boundaries
+            .first_block()
+            .saturating_sub(<NumberFor<B>>::one());
+        let session_boundaries = session_boundaries.with_max_branch_len(
+            self.next_session_max_data_branch_len(session_id, last_block_of_previous_session)?,
         );
+        let (blocks_for_aggregator, blocks_from_interpreter) = mpsc::unbounded();
 
         let subtask_common = SubtaskCommon {
             spawn_handle: self.spawn_handle.clone(),
@@ -306,20 +380,19 @@ where
         let aggregator_io = aggregator::IO {
             blocks_from_interpreter,
             justifications_for_chain: self.authority_justification_tx.clone(),
+            rpc_link: self.rpc_link.clone(),
         };
 
-        let data_network = match self
+        let data_network = self
             .session_manager
-            .start_validator_session(session_id, authority_verifier, node_id, authority_pen)
+            .start_validator_session(
+                session_id,
+                authority_verifier.clone(),
+                node_id,
+                authority_pen,
+            )
             .await
-        {
-            Ok(data_network) => data_network,
-            Err(e) => panic!("Failed to start validator session: {}", e),
-        };
-
-        let last_block_of_previous_session = session_boundaries
-            .first_block()
-            .saturating_sub(<NumberFor<B>>::one());
+            .map_err(Error::Network)?;
 
         let params = SubtasksParams {
             n_members: authorities.len(),
@@ -328,56 +401,81 @@ where
             data_network,
             session_boundaries,
             subtask_common,
-            data_provider,
-            ordered_data_interpreter,
+            blocks_for_aggregator,
             aggregator_io,
-            multikeychain,
+            multikeychain: multikeychain.clone(),
             exit_rx,
             backup,
-            chain_tracker,
             phantom: PhantomData,
         };
 
-        match self
+        // Bound separately, so that the runtime API does not live across awaits in the match.
+        let finality_version = self
             .client
             .runtime_api()
-            .next_session_finality_version(&BlockId::Number(last_block_of_previous_session))
-        {
-            #[cfg(feature = "only_legacy")]
-            _ if self.only_legacy() => {
-                info!(target: "dagestan-party", "Running session with legacy-only AlephBFT version.");
-                self.legacy_subtasks(params)
+            .next_session_finality_version(&BlockId::Number(last_block_of_previous_session));
+        let subtasks = match finality_version {
+            // Sessions aggregating BLS signatures need every authority to have a BLS key, otherwise
+            // they fall back to collecting ed25519 signatures. Payloads are ordered only by the
+            // consensus versions introduced with them.
+            Ok(version)
+                if (BLS_AGGREGATION_FINALITY_VERSION..=PAYLOAD_FINALITY_VERSION)
+                    .contains(&version) =>
+            {
+                let payloads = version >= PAYLOAD_FINALITY_VERSION;
+                match self
+                    .next_session_bls_authorities(last_block_of_previous_session, authorities.len())
+                {
+                    Some(bls_authorities) => {
+                        let bls_multikeychain = self
+                            .bls_multikeychain(node_id, authority_verifier, bls_authorities)
+                            .await;
+                        if payloads {
+                            info!(target: "dagestan-party", "Running session with finality version {}, using consensus version {:?} with BLS signature aggregation and payloads.", version, V5::VERSION);
+                            self.subtasks::<V5, _>(params, bls_multikeychain)
+                        } else {
+                            info!(target: "dagestan-party", "Running session with finality version {}, using consensus version {:?} with BLS signature aggregation.", version, V3::VERSION);
+                            self.subtasks::<V3, _>(params, bls_multikeychain)
+                        }
+                    }
+                    None if payloads => {
+                        warn!(target: "dagestan-party", "Running session with finality version {}, but not all authorities have BLS keys, using consensus version {:?} with payloads, without BLS signature aggregation.", version, V4::VERSION);
+                        self.subtasks::<V4, _>(params, multikeychain)
+                    }
+                    None => {
+                        warn!(target: "dagestan-party", "Running session with finality version {}, but not all authorities have BLS keys, using consensus version {:?} without BLS signature aggregation.", version, V2::VERSION);
+                        self.subtasks::<V2, _>(params, multikeychain)
+                    }
+                }
             }
-            // The `as`es here should be removed, but this would require a pallet migration and I
-            // am lazy.
-            Ok(version) if version == CURRENT_VERSION as u32 => {
-                info!(target: "dagestan-party", "Running session with AlephBFT version {}, which is current.", version);
-                self.current_subtasks(params)
+            // Finality versions between the default one and BLS aggregation only change how
+            // justifications are stored, so they all run the same consensus version.
+            Ok(version)
+                if (DEFAULT_FINALITY_VERSION + 1..BLS_AGGREGATION_FINALITY_VERSION)
+                    .contains(&version) =>
+            {
+                info!(target: "dagestan-party", "Running session with finality version {}, using consensus version {:?}.", version, V2::VERSION);
+                self.subtasks::<V2, _>(params, multikeychain)
             }
-            Ok(version) if version == LEGACY_VERSION as u32 => {
-                info!(target: "dagestan-party", "Running session with AlephBFT version {}, which is legacy.", version);
-                self.legacy_subtasks(params)
+            Ok(version) if version == DEFAULT_FINALITY_VERSION => {
+                info!(target: "dagestan-party", "Running session with finality version {}, using consensus version {:?}.", version, V1::VERSION);
+                self.subtasks::<V1, _>(params, multikeychain)
             }
             Ok(version) => {
-                panic!("Unsupported version {}. Supported versions: {} or {}. Potentially outdated node.", version, LEGACY_VERSION, CURRENT_VERSION)
+                panic!("Unsupported version {}. Supported versions: {} to {}. Potentially outdated node.", version, DEFAULT_FINALITY_VERSION, PAYLOAD_FINALITY_VERSION)
             }
             _ => {
-                // this might happen when there was no runtime upgrade yet. Fallback to legacy version
-                self.legacy_subtasks(params)
+                // this might happen when there was no runtime upgrade yet. Fallback to the default version
+                self.subtasks::<V1, _>(params, multikeychain)
             }
-        }
-    }
-
-    #[cfg(feature = "only_legacy")]
-    fn only_legacy(&self) -> bool {
-        std::env::var(ONLY_LEGACY_ENV)
-            .map(|legacy| !legacy.is_empty())
-            .unwrap_or(false)
+        };
+        Ok(subtasks)
     }
 }
 
 #[async_trait]
-impl<C, SC, B, RB, BE, SM> NodeSessionManager for NodeSessionManagerImpl<C, SC, B, RB, BE, SM>
+impl<C, SC, B, RB, BI, BE, SM, PP, PS> NodeSessionManager
+    for NodeSessionManagerImpl<C, SC, B, RB, BI, BE, SM, PP, PS>
 where
     B: BlockT,
     C: crate::ClientForDagestan<B, BE> + Send + Sync + 'static,
@@ -385,9 +483,12 @@ where
     BE: Backend<B> + 'static,
     SC: SelectChain<B> + 'static,
     RB: RequestBlocks<B>,
-    SM: SessionManager<VersionedNetworkData<B>>,
+    BI: ImportBlocks<B>,
+    PP: PayloadProvider + Clone,
+    PS: PayloadSink<Payload = PP::Payload> + Clone,
+    SM: SessionManager<VersionedNetworkData<B, PP::Payload>>,
 {
-    type Error = SM::Error;
+    type Error = Error<SM::Error>;
 
     async fn spawn_authority_task_for_session(
         &self,
@@ -395,13 +496,13 @@ where
         node_id: NodeIndex,
         backup: ABFTBackup,
         authorities: &[AuthorityId],
-    ) -> AuthorityTask {
+    ) -> Result<AuthorityTask, Self::Error> {
         let (exit, exit_rx) = futures::channel::oneshot::channel();
         let subtasks = self
             .spawn_subtasks(session, authorities, node_id, exit_rx, backup)
-            .await;
+            .await?;
 
-        AuthorityTask::new(
+        Ok(AuthorityTask::new(
             self.spawn_handle
                 .spawn_essential("dagestan/session_authority", async move {
                     if subtasks.wait_completion().await.is_err() {
@@ -410,7 +511,7 @@ where
                 }),
             node_id,
             exit,
-        )
+        ))
     }
 
     async fn early_start_validator_session(
@@ -424,12 +525,9 @@ where
             AuthorityPen::new(authorities[node_id.0].clone(), self.keystore.clone())
                 .await
                 .expect("The keys should sign successfully");
-        self.session_manager.early_start_validator_session(
-            session,
-            authority_verifier,
-            node_id,
-            authority_pen,
-        )
+        self.session_manager
+            .early_start_validator_session(session, authority_verifier, node_id, authority_pen)
+            .map_err(Error::Network)
     }
 
     fn start_nonvalidator_session(
@@ -441,10 +539,13 @@ where
 
         self.session_manager
             .start_nonvalidator_session(session, authority_verifier)
+            .map_err(Error::Network)
     }
 
     fn stop_session(&self, session: SessionId) -> Result<(), Self::Error> {
-        self.session_manager.stop_session(session)
+        self.session_manager
+            .stop_session(session)
+            .map_err(Error::Network)
     }
 
     async fn node_idx(&self, authorities: &[AuthorityId]) -> Option<NodeIndex> {
@@ -130,13 +130,13 @@ impl NodeSessionManager for Arc<MockNodeSessionManager> {
         node_id: NodeIndex,
         _backup: ABFTBackup,
         _authorities: &[AuthorityId],
-    ) -> AuthorityTask {
+    ) -> Result<AuthorityTask, Self::Error> {
         self.insert(self.validator_session_started.clone(), session);
 
         let (exit, _) = oneshot::channel();
         let handle = async { Ok(()) };
 
-        AuthorityTask::new(Box::pin(handle), node_id, exit)
+        Ok(AuthorityTask::new(Box::pin(handle), node_id, exit))
     }
 
     async fn early_start_validator_session(
@@ -1,4 +1,4 @@
-use std::{default::Default, marker::PhantomData, path::PathBuf, time::Duration};
+use std::{default::Default, marker::PhantomData, sync::Arc, time::Duration};
 
 use futures_timer::Delay;
 use log::{debug, error, info, trace, warn};
@@ -6,6 +6,7 @@ use tokio::{task::spawn_blocking, time::sleep};
 
 use crate::{
     party::{
+        backup::BackupStore,
         manager::{Handle, SubtaskCommon as AuthoritySubtaskCommon, Task},
         traits::{Block, ChainState, NodeSessionManager, SessionInfo, SyncState},
     },
@@ -25,7 +26,7 @@ pub(crate) struct ConsensusPartyParams<B: Block, ST, CS, NSM, SI> {
     pub session_authorities: ReadOnlySessionMap,
     pub chain_state: CS,
     pub sync_state: ST,
-    pub backup_saving_path: Option<PathBuf>,
+    pub backup_store: Arc<dyn BackupStore>,
     pub session_manager: NSM,
     pub session_info: SI,
     pub _phantom: PhantomData<B>,
@@ -42,7 +43,7 @@ where
     session_authorities: ReadOnlySessionMap,
     chain_state: CS,
     sync_state: ST,
-    backup_saving_path: Option<PathBuf>,
+    backup_store: Arc<dyn BackupStore>,
     session_manager: NSM,
     session_info: SI,
     _phantom: PhantomData<B>,
@@ -62,7 +63,7 @@ where
         let ConsensusPartyParams {
             session_authorities,
             sync_state,
-            backup_saving_path,
+            backup_store,
             chain_state,
             session_manager,
             session_info,
@@ -71,7 +72,7 @@ where
         Self {
             sync_state,
             session_authorities,
-            backup_saving_path,
+            backup_store,
             chain_state,
             session_manager,
             session_info,
@@ -82,8 +83,8 @@ where
     async fn run_session(&mut self, session_id: SessionId) {
         let last_block = self.session_info.last_block_of_session(session_id);
         if let Some(previous_session_id) = session_id.0.checked_sub(1) {
-            let backup_saving_path = self.backup_saving_path.clone();
-            spawn_blocking(move || backup::remove(backup_saving_path, previous_session_id));
+            let backup_store = self.backup_store.clone();
+            spawn_blocking(move || backup::remove(backup_store.as_ref(), previous_session_id));
         }
 
         // Early skip attempt -- this will trigger during catching up (initial sync).
@@ -125,19 +126,20 @@ where
         let mut maybe_authority_task = if let Some(node_id) =
             self.session_manager.node_idx(authorities).await
         {
-            match backup::rotate(self.backup_saving_path.clone(), session_id.0) {
+            match backup::rotate(self.backup_store.clone(), session_id.0) {
                 Ok(backup) => {
                     debug!(target: "dagestan-party", "Running session {:?} as authority id {:?}", session_id, node_id);
-                    Some(
-                        self.session_manager
-                            .spawn_authority_task_for_session(
-                                session_id,
-                                node_id,
-                                backup,
-                                authorities,
-                            )
-                            .await,
-                    )
+                    match self
+                        .session_manager
+                        .spawn_authority_task_for_session(session_id, node_id, backup, authorities)
+                        .await
+                    {
+                        Ok(authority_task) => Some(authority_task),
+                        Err(e) => {
+                            error!(target: "dagestan-party", "Failed to start validator session {:?}. Not running the session: {}", session_id, e);
+                            return;
+                        }
+                    }
                 }
                 Err(err) => {
                     error!(
@@ -268,12 +270,13 @@ mod tests {
         time::Duration,
     };
 
-    use dagestan_primitives::{AuthorityId, SessionAuthorityData};
+    use dagestan_primitives::{AuthorityId, SessionAuthorityData, DEFAULT_FINALITY_VERSION};
     use sp_runtime::testing::UintAuthorityId;
     use tokio::{task::JoinHandle, time::sleep};
 
     use crate::{
         party::{
+            backup::MemoryBackupStore,
             mocks::{
                 MockChainState, MockNodeSessionManager, MockSessionInfo, MockSyncState, SimpleBlock,
             },
@@ -424,7 +427,11 @@ mod tests {
             if let Some((session, authorities)) = session_authorities {
                 self.controller
                     .shared_session_map
-                    .update(session, SessionAuthorityData::new(authorities, None))
+                    .update(
+                        session,
+                        SessionAuthorityData::new(authorities, None),
+                        DEFAULT_FINALITY_VERSION,
+                    )
                     .await;
             }
 
@@ -486,7 +493,11 @@ mod tests {
             if let Some((session, authorities)) = session_authorities {
                 self.controller
                     .shared_session_map
-                    .update(session, SessionAuthorityData::new(authorities, None))
+                    .update(
+                        session,
+                        SessionAuthorityData::new(authorities, None),
+                        DEFAULT_FINALITY_VERSION,
+                    )
                     .await;
             }
 
@@ -540,7 +551,7 @@ mod tests {
             session_authorities: readonly_session_authorities,
             chain_state,
             sync_state,
-            backup_saving_path: None,
+            backup_store: Arc::new(MemoryBackupStore::default()),
             session_manager,
             session_info,
             _phantom: Default::default(),
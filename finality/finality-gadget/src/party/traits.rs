@@ -43,7 +43,7 @@ pub trait NodeSessionManager {
         node_id: NodeIndex,
         backup: ABFTBackup,
         authorities: &[AuthorityId],
-    ) -> AuthorityTask;
+    ) -> Result<AuthorityTask, Self::Error>;
 
     /// Prepare validator session.
     async fn early_start_validator_session(
@@ -0,0 +1,111 @@
+use std::sync::Arc;
+
+use futures::channel::mpsc;
+use parking_lot::Mutex;
+use sc_utils::notification::{NotificationSender, NotificationStream, TracingKeyStr};
+use sp_runtime::traits::{Block, NumberFor};
+
+use crate::{
+    nodes::SessionInfoProviderImpl,
+    rpc::{AggregatorStatus, RequesterStatus},
+    JustificationNotification,
+};
+
+#[derive(Clone)]
+pub struct JustificationsTracingKey;
+
+impl TracingKeyStr for JustificationsTracingKey {
+    const TRACING_KEY: &'static str = "mpsc_dagestan_justification_notification_stream";
+}
+
+/// Stream of justifications of blocks finalized by the justification handler.
+pub type JustificationStream<B> =
+    NotificationStream<JustificationNotification<B>, JustificationsTracingKey>;
+
+type JustificationSender<B> = NotificationSender<JustificationNotification<B>>;
+
+/// State shared between the running finality gadget and the RPC handlers, or the block import when
+/// it verifies justifications. The gadget fills it in, the others only read it.
+#[derive(Clone)]
+pub struct RpcLink<B: Block> {
+    justification_sender: JustificationSender<B>,
+    justification_stream: JustificationStream<B>,
+    emergency_justification_tx: mpsc::UnboundedSender<JustificationNotification<B>>,
+    emergency_justification_rx:
+        Arc<Mutex<Option<mpsc::UnboundedReceiver<JustificationNotification<B>>>>>,
+    session_info: Arc<Mutex<Option<SessionInfoProviderImpl>>>,
+    requester_status: Arc<Mutex<Option<RequesterStatus<B::Hash, NumberFor<B>>>>>,
+    aggregator_status: Arc<Mutex<Option<AggregatorStatus<B::Hash>>>>,
+}
+
+impl<B: Block> RpcLink<B> {
+    pub fn new() -> Self {
+        let (justification_sender, justification_stream) = JustificationStream::channel();
+        let (emergency_justification_tx, emergency_justification_rx) = mpsc::unbounded();
+        RpcLink {
+            justification_sender,
+            justification_stream,
+            emergency_justification_tx,
+            emergency_justification_rx: Arc::new(Mutex::new(Some(emergency_justification_rx))),
+            session_info: Arc::new(Mutex::new(None)),
+            requester_status: Arc::new(Mutex::new(None)),
+            aggregator_status: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn justification_stream(&self) -> JustificationStream<B> {
+        self.justification_stream.clone()
+    }
+
+    pub(crate) fn notify_justification(&self, notification: JustificationNotification<B>) {
+        // Sending can only fail if the closure returns an error.
+        let _ = self
+            .justification_sender
+            .notify(|| Ok::<_, ()>(notification));
+    }
+
+    /// Passes an already verified emergency justification to the justification handler.
+    pub(crate) fn submit_emergency_justification(
+        &self,
+        notification: JustificationNotification<B>,
+    ) -> Result<(), mpsc::TrySendError<JustificationNotification<B>>> {
+        self.emergency_justification_tx.unbounded_send(notification)
+    }
+
+    /// Returns the receiving end of emergency justifications, only the first call returns it.
+    pub(crate) fn take_emergency_justifications(
+        &self,
+    ) -> Option<mpsc::UnboundedReceiver<JustificationNotification<B>>> {
+        self.emergency_justification_rx.lock().take()
+    }
+
+    pub(crate) fn set_session_info(&self, session_info: SessionInfoProviderImpl) {
+        *self.session_info.lock() = Some(session_info);
+    }
+
+    pub(crate) fn session_info(&self) -> Option<SessionInfoProviderImpl> {
+        self.session_info.lock().clone()
+    }
+
+    pub(crate) fn report_requester_status(&self, status: RequesterStatus<B::Hash, NumberFor<B>>) {
+        *self.requester_status.lock() = Some(status);
+    }
+
+    pub(crate) fn requester_status(&self) -> Option<RequesterStatus<B::Hash, NumberFor<B>>> {
+        self.requester_status.lock().clone()
+    }
+
+    pub(crate) fn report_aggregator_status(&self, status: AggregatorStatus<B::Hash>) {
+        *self.aggregator_status.lock() = Some(status);
+    }
+
+    pub(crate) fn aggregator_status(&self) -> Option<AggregatorStatus<B::Hash>> {
+        self.aggregator_status.lock().clone()
+    }
+}
+
+impl<B: Block> Default for RpcLink<B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
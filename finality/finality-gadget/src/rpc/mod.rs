@@ -0,0 +1,651 @@
+//! JSON-RPC interface exposing the state of Dagestan finality of a running node.
+use std::{
+    fmt::{Display, Error as FmtError, Formatter},
+    sync::Arc,
+};
+
+use codec::Decode;
+use dagestan_primitives::{
+    AuthorityId, AuthoritySignature, DagestanSessionApi, SessionAuthorityData, Version,
+    DEFAULT_FINALITY_VERSION,
+};
+use futures::{FutureExt, StreamExt};
+use jsonrpsee::{
+    core::{async_trait, Error as JsonRpseeError, RpcResult},
+    proc_macros::rpc,
+    types::error::{CallError, ErrorObject},
+    PendingSubscription,
+};
+use log::warn;
+use sc_client_api::BlockBackend;
+use serde::{Deserialize, Serialize};
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::{Error as ClientError, HeaderBackend};
+use sp_core::{traits::SpawnNamed, Bytes};
+use sp_runtime::{
+    generic::BlockId,
+    traits::{Block, NumberFor},
+};
+
+use crate::{
+    justification::{
+        backwards_compatible_decode, find_dagestan_justification, versioned_encode, DecodeError,
+        SessionInfo, SessionInfoProvider, Verifier,
+    },
+    session_map::runtime_authority_data,
+    DagestanJustification, JustificationNotification, SessionId,
+};
+
+mod link;
+
+pub use link::{JustificationStream, JustificationsTracingKey, RpcLink};
+
+/// Base code for all Dagestan RPC errors.
+const BASE_ERROR: i32 = 6000;
+
+/// How many notifications may be queued for a single subscriber before we start warning.
+const SUBSCRIPTION_QUEUE_WARNING: usize = 100_000;
+
+/// What can go wrong when answering a Dagestan RPC call.
+#[derive(Debug)]
+pub enum Error {
+    Client(ClientError),
+    JustificationDecode(DecodeError),
+    NotRunning,
+    UnknownBlock,
+    SignatureDecode(codec::Error),
+    UnknownAuthorityData(SessionId),
+    BadEmergencySignature,
+    HandlerClosed,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        use Error::*;
+        match self {
+            Client(e) => write!(f, "substrate client error {}", e),
+            JustificationDecode(e) => write!(f, "could not decode stored justification: {}", e),
+            NotRunning => write!(f, "the finality gadget is not running yet"),
+            UnknownBlock => write!(f, "no block with the given hash and number"),
+            SignatureDecode(e) => write!(f, "could not decode signature: {}", e),
+            UnknownAuthorityData(session) => {
+                write!(
+                    f,
+                    "authority data of session {:?} is not known yet",
+                    session
+                )
+            }
+            BadEmergencySignature => write!(
+                f,
+                "the signature does not match the emergency finalizer of the block's session"
+            ),
+            HandlerClosed => write!(
+                f,
+                "the justification handler is not accepting justifications"
+            ),
+        }
+    }
+}
+
+impl From<ClientError> for Error {
+    fn from(value: ClientError) -> Self {
+        Error::Client(value)
+    }
+}
+
+impl From<Error> for JsonRpseeError {
+    fn from(error: Error) -> Self {
+        use Error::*;
+        let code = match error {
+            Client(_) => BASE_ERROR + 1,
+            JustificationDecode(_) => BASE_ERROR + 2,
+            NotRunning => BASE_ERROR + 3,
+            UnknownBlock => BASE_ERROR + 4,
+            SignatureDecode(_) => BASE_ERROR + 5,
+            UnknownAuthorityData(_) => BASE_ERROR + 6,
+            BadEmergencySignature => BASE_ERROR + 7,
+            HandlerClosed => BASE_ERROR + 8,
+        };
+        CallError::Custom(ErrorObject::owned(code, error.to_string(), None::<()>)).into()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum JustificationKind {
+    CommitteeMultisignature,
+    EmergencySignature,
+    AggregateSignature,
+}
+
+/// A justification of a finalized block, together with the authorities that signed it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JustificationInfo<Hash, Number> {
+    pub hash: Hash,
+    pub number: Number,
+    pub kind: JustificationKind,
+    /// Indices of the signers within the committee, empty for emergency signatures.
+    pub signer_indices: Vec<u32>,
+    /// The signers, if the authority data of the block's session is available.
+    pub signers: Vec<AuthorityId>,
+    /// The justification in the format in which it is stored in the chain.
+    pub encoded: Bytes,
+}
+
+impl<Hash, Number> JustificationInfo<Hash, Number> {
+    fn new(
+        hash: Hash,
+        number: Number,
+        justification: DagestanJustification,
+        authority_data: Option<SessionAuthorityData>,
+        finality_version: Version,
+    ) -> Self {
+        let committee_signers = |signer_indices: Vec<usize>| {
+            let signers = authority_data
+                .as_ref()
+                .map(|data| {
+                    signer_indices
+                        .iter()
+                        .filter_map(|index| data.authorities().get(*index).cloned())
+                        .collect()
+                })
+                .unwrap_or_default();
+            (
+                signer_indices
+                    .into_iter()
+                    .map(|index| index as u32)
+                    .collect(),
+                signers,
+            )
+        };
+        let (kind, (signer_indices, signers)) = match &justification {
+            DagestanJustification::CommitteeMultisignature(signatures) => (
+                JustificationKind::CommitteeMultisignature,
+                committee_signers(signatures.iter().map(|(index, _)| index.0).collect()),
+            ),
+            DagestanJustification::AggregateSignature(aggregate) => (
+                JustificationKind::AggregateSignature,
+                committee_signers(aggregate.signers().map(|index| index.0).collect()),
+            ),
+            DagestanJustification::EmergencySignature(_) => (
+                JustificationKind::EmergencySignature,
+                (
+                    Vec::new(),
+                    authority_data
+                        .as_ref()
+                        .and_then(|data| data.emergency_finalizer().clone())
+                        .into_iter()
+                        .collect(),
+                ),
+            ),
+        };
+        JustificationInfo {
+            hash,
+            number,
+            kind,
+            signer_indices,
+            signers,
+            encoded: versioned_encode(justification, finality_version).into(),
+        }
+    }
+}
+
+/// Authority data of a single session.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionAuthorities {
+    pub authorities: Vec<AuthorityId>,
+    pub emergency_finalizer: Option<AuthorityId>,
+}
+
+impl From<SessionAuthorityData> for SessionAuthorities {
+    fn from(authority_data: SessionAuthorityData) -> Self {
+        SessionAuthorities {
+            authorities: authority_data.authorities().clone(),
+            emergency_finalizer: authority_data.emergency_finalizer().clone(),
+        }
+    }
+}
+
+/// State of the justification requester, as reported in its status logs.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RequesterStatus<Hash, Number> {
+    pub finalized_number: Number,
+    pub requested_block: Option<(Hash, Number)>,
+    pub block_tries: u32,
+    pub children_of: Option<Hash>,
+    pub children_count: usize,
+    pub children_tries: u32,
+}
+
+/// State of the signature aggregator of the current session, as reported in its status logs.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AggregatorStatus<Hash> {
+    pub session: u32,
+    pub started_hashes: usize,
+    pub multisigned_hashes: usize,
+    pub last_multisigned: Option<Hash>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FinalityStatus<Hash, Number> {
+    pub requester: Option<RequesterStatus<Hash, Number>>,
+    pub aggregator: Option<AggregatorStatus<Hash>>,
+}
+
+#[rpc(server)]
+pub trait DagestanApi<Hash, Number> {
+    /// Returns the justification of the block with the given hash, if we have one.
+    #[method(name = "dagestan_getJustification")]
+    fn justification(&self, hash: Hash) -> RpcResult<Option<JustificationInfo<Hash, Number>>>;
+
+    /// Subscribes to justifications of blocks finalized by this node.
+    #[subscription(
+        name = "dagestan_subscribeJustifications" => "dagestan_justifications",
+        unsubscribe = "dagestan_unsubscribeJustifications",
+        item = JustificationInfo<Hash, Number>,
+    )]
+    fn subscribe_justifications(&self);
+
+    /// Returns the authority data of the given session, if it is still kept by the node.
+    #[method(name = "dagestan_sessionAuthorities")]
+    async fn session_authorities(&self, session: u32) -> RpcResult<Option<SessionAuthorities>>;
+
+    /// Returns the state of the justification requester and the signature aggregator.
+    #[method(name = "dagestan_status")]
+    fn status(&self) -> RpcResult<FinalityStatus<Hash, Number>>;
+
+    /// Finalizes the given block with a signature of the emergency finalizer of its session.
+    /// The signature is checked before it is passed to the justification handler.
+    #[method(name = "dagestan_emergencyFinalize")]
+    async fn emergency_finalize(
+        &self,
+        hash: Hash,
+        number: Number,
+        signature: Bytes,
+    ) -> RpcResult<()>;
+}
+
+/// Implements the Dagestan RPC on top of the client and the state shared by the finality gadget.
+pub struct Dagestan<B: Block, C> {
+    client: Arc<C>,
+    rpc_link: RpcLink<B>,
+    executor: Arc<dyn SpawnNamed>,
+}
+
+impl<B, C> Dagestan<B, C>
+where
+    B: Block,
+    C: HeaderBackend<B> + BlockBackend<B> + ProvideRuntimeApi<B> + Send + Sync + 'static,
+    C::Api: DagestanSessionApi<B>,
+{
+    pub fn new(client: Arc<C>, rpc_link: RpcLink<B>, executor: Arc<dyn SpawnNamed>) -> Self {
+        Dagestan {
+            client,
+            rpc_link,
+            executor,
+        }
+    }
+}
+
+fn justification_info<B, C>(
+    client: &C,
+    notification: JustificationNotification<B>,
+) -> JustificationInfo<B::Hash, NumberFor<B>>
+where
+    B: Block,
+    C: ProvideRuntimeApi<B>,
+    C::Api: DagestanSessionApi<B>,
+{
+    let JustificationNotification {
+        justification,
+        hash,
+        number,
+    } = notification;
+    let runtime_api = client.runtime_api();
+    let authority_data = runtime_authority_data(&*runtime_api, &BlockId::Hash(hash))
+        .map_err(|e| {
+            warn!(target: "dagestan-justification", "Could not get authority data at block {:?}: {}", hash, e);
+        })
+        .ok();
+    let finality_version = runtime_api
+        .finality_version(&BlockId::Hash(hash))
+        .unwrap_or(DEFAULT_FINALITY_VERSION);
+    JustificationInfo::new(
+        hash,
+        number,
+        justification,
+        authority_data,
+        finality_version,
+    )
+}
+
+/// Checks the emergency signature of the block against the emergency finalizer of its session and
+/// passes the resulting justification to the justification handler.
+async fn submit_emergency_justification<B: Block>(
+    rpc_link: &RpcLink<B>,
+    hash: B::Hash,
+    number: NumberFor<B>,
+    signature: Bytes,
+) -> Result<(), Error> {
+    let signature =
+        AuthoritySignature::decode(&mut &signature[..]).map_err(Error::SignatureDecode)?;
+    let justification = DagestanJustification::EmergencySignature(signature);
+
+    let session_info = rpc_link.session_info().ok_or(Error::NotRunning)?;
+    let SessionInfo {
+        current_session,
+        verifier,
+        ..
+    } = SessionInfoProvider::<B, _>::for_block_num(&session_info, number).await;
+    let verifier = verifier.ok_or(Error::UnknownAuthorityData(current_session))?;
+    if !Verifier::<B>::verify(&verifier, &justification, hash) {
+        return Err(Error::BadEmergencySignature);
+    }
+
+    rpc_link
+        .submit_emergency_justification(JustificationNotification {
+            justification,
+            hash,
+            number,
+        })
+        .map_err(|_| Error::HandlerClosed)
+}
+
+#[async_trait]
+impl<B, C> DagestanApiServer<B::Hash, NumberFor<B>> for Dagestan<B, C>
+where
+    B: Block,
+    C: HeaderBackend<B> + BlockBackend<B> + ProvideRuntimeApi<B> + Send + Sync + 'static,
+    C::Api: DagestanSessionApi<B>,
+{
+    fn justification(
+        &self,
+        hash: B::Hash,
+    ) -> RpcResult<Option<JustificationInfo<B::Hash, NumberFor<B>>>> {
+        let number = match self.client.number(hash).map_err(Error::from)? {
+            Some(number) => number,
+            None => return Ok(None),
+        };
+        let justification = match self
+            .client
+            .justifications(&BlockId::Hash(hash))
+            .map_err(Error::from)?
+            .as_ref()
+            .and_then(find_dagestan_justification)
+        {
+            Some((_, justification)) => justification,
+            None => return Ok(None),
+        };
+        let justification =
+            backwards_compatible_decode(justification).map_err(Error::JustificationDecode)?;
+        Ok(Some(justification_info(
+            self.client.as_ref(),
+            JustificationNotification {
+                justification,
+                hash,
+                number,
+            },
+        )))
+    }
+
+    fn subscribe_justifications(&self, pending: PendingSubscription) {
+        let client = self.client.clone();
+        let stream = self
+            .rpc_link
+            .justification_stream()
+            .subscribe(SUBSCRIPTION_QUEUE_WARNING)
+            .map(move |notification| justification_info(client.as_ref(), notification));
+
+        let fut = async move {
+            if let Some(mut sink) = pending.accept() {
+                sink.pipe_from_stream(stream).await;
+            }
+        };
+
+        self.executor
+            .spawn("dagestan-rpc-subscription", Some("rpc"), fut.boxed());
+    }
+
+    async fn session_authorities(&self, session: u32) -> RpcResult<Option<SessionAuthorities>> {
+        let session_info = self.rpc_link.session_info().ok_or(Error::NotRunning)?;
+        Ok(session_info
+            .session_authorities()
+            .get(SessionId(session))
+            .await
+            .map(SessionAuthorities::from))
+    }
+
+    fn status(&self) -> RpcResult<FinalityStatus<B::Hash, NumberFor<B>>> {
+        Ok(FinalityStatus {
+            requester: self.rpc_link.requester_status(),
+            aggregator: self.rpc_link.aggregator_status(),
+        })
+    }
+
+    async fn emergency_finalize(
+        &self,
+        hash: B::Hash,
+        number: NumberFor<B>,
+        signature: Bytes,
+    ) -> RpcResult<()> {
+        if self.client.number(hash).map_err(Error::from)? != Some(number) {
+            return Err(Error::UnknownBlock.into());
+        }
+        Ok(submit_emergency_justification(&self.rpc_link, hash, number, signature).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use codec::Encode;
+    use dagestan_primitives::{AuthorityPair, SessionAuthorityData, DEFAULT_FINALITY_VERSION};
+    use futures::{channel::mpsc::UnboundedReceiver, StreamExt};
+    use sp_core::{Bytes, Pair};
+
+    use super::{
+        submit_emergency_justification, Error, JustificationInfo, JustificationKind, RpcLink,
+    };
+    use crate::{
+        abft::{NodeCount, NodeIndex, SignatureSet},
+        crypto::Signature,
+        justification::versioned_encode,
+        nodes::SessionInfoProviderImpl,
+        session_map::SharedSessionMap,
+        testing::mocks::{TBlock, THash},
+        DagestanJustification, JustificationNotification, SessionId, SessionPeriod,
+    };
+
+    type EmergencyJustifications = UnboundedReceiver<JustificationNotification<TBlock>>;
+
+    fn authority_data(emergency_finalizer: bool) -> (Vec<AuthorityPair>, SessionAuthorityData) {
+        let pairs: Vec<_> = (0..4u8)
+            .map(|seed| AuthorityPair::from_seed(&[seed; 32]))
+            .collect();
+        let authorities = pairs.iter().map(|pair| pair.public()).collect();
+        let emergency_finalizer = match emergency_finalizer {
+            true => Some(pairs[3].public()),
+            false => None,
+        };
+        (
+            pairs,
+            SessionAuthorityData::new(authorities, emergency_finalizer),
+        )
+    }
+
+    fn multisignature(pairs: &[AuthorityPair], signers: &[usize]) -> DagestanJustification {
+        let signatures = signers.iter().fold(
+            SignatureSet::with_size(NodeCount(pairs.len())),
+            |signatures, index| {
+                signatures.add_signature(
+                    &Signature::from(pairs[*index].sign(b"block")),
+                    NodeIndex(*index),
+                )
+            },
+        );
+        DagestanJustification::CommitteeMultisignature(signatures)
+    }
+
+    #[test]
+    fn lists_committee_signers() {
+        let (pairs, data) = authority_data(false);
+        let justification = multisignature(&pairs, &[0, 2, 3]);
+
+        let info = JustificationInfo::new(1u64, 7u64, justification.clone(), Some(data), 1);
+
+        assert_eq!(info.kind, JustificationKind::CommitteeMultisignature);
+        assert_eq!(info.signer_indices, vec![0, 2, 3]);
+        assert_eq!(
+            info.signers,
+            vec![pairs[0].public(), pairs[2].public(), pairs[3].public()]
+        );
+        assert_eq!(info.encoded.0, versioned_encode(justification, 1));
+    }
+
+    #[test]
+    fn lists_emergency_signer() {
+        let (pairs, data) = authority_data(true);
+        let justification = DagestanJustification::EmergencySignature(pairs[3].sign(b"block"));
+
+        let info = JustificationInfo::new(1u64, 7u64, justification, Some(data), 1);
+
+        assert_eq!(info.kind, JustificationKind::EmergencySignature);
+        assert!(info.signer_indices.is_empty());
+        assert_eq!(info.signers, vec![pairs[3].public()]);
+    }
+
+    #[test]
+    fn lists_only_indices_without_authority_data() {
+        let (pairs, _) = authority_data(false);
+        let justification = multisignature(&pairs, &[1, 2, 3]);
+
+        let info = JustificationInfo::new(1u64, 7u64, justification, None, 1);
+
+        assert_eq!(info.signer_indices, vec![1, 2, 3]);
+        assert!(info.signers.is_empty());
+    }
+
+    async fn running_link(
+        data: SessionAuthorityData,
+    ) -> (RpcLink<TBlock>, EmergencyJustifications) {
+        let mut session_map = SharedSessionMap::new();
+        session_map
+            .update(SessionId(0), data, DEFAULT_FINALITY_VERSION)
+            .await;
+        let link = RpcLink::new();
+        link.set_session_info(SessionInfoProviderImpl::new(
+            session_map.read_only(),
+            SessionPeriod(10),
+        ));
+        let emergency_justifications = link
+            .take_emergency_justifications()
+            .expect("taken for the first time");
+        (link, emergency_justifications)
+    }
+
+    fn emergency_signature(pair: &AuthorityPair, hash: THash) -> Bytes {
+        pair.sign(&hash.encode()).encode().into()
+    }
+
+    #[tokio::test]
+    async fn submits_valid_emergency_justification() {
+        let (pairs, data) = authority_data(true);
+        let (link, mut emergency_justifications) = running_link(data).await;
+        let hash = THash::repeat_byte(7);
+
+        submit_emergency_justification(&link, hash, 7, emergency_signature(&pairs[3], hash))
+            .await
+            .expect("the signature is correct");
+
+        let notification = emergency_justifications
+            .try_next()
+            .expect("the link is alive")
+            .expect("the justification was submitted");
+        assert_eq!(notification.hash, hash);
+        assert_eq!(notification.number, 7);
+        assert_eq!(
+            notification.justification,
+            DagestanJustification::EmergencySignature(pairs[3].sign(&hash.encode()))
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_emergency_justification_with_bad_signature() {
+        let (pairs, data) = authority_data(true);
+        let (link, mut emergency_justifications) = running_link(data).await;
+        let hash = THash::repeat_byte(7);
+
+        let result =
+            submit_emergency_justification(&link, hash, 7, emergency_signature(&pairs[0], hash))
+                .await;
+
+        assert!(matches!(result, Err(Error::BadEmergencySignature)));
+        assert!(emergency_justifications.try_next().is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_emergency_justification_without_emergency_finalizer() {
+        let (pairs, data) = authority_data(false);
+        let (link, mut emergency_justifications) = running_link(data).await;
+        let hash = THash::repeat_byte(7);
+
+        let result =
+            submit_emergency_justification(&link, hash, 7, emergency_signature(&pairs[3], hash))
+                .await;
+
+        assert!(matches!(result, Err(Error::BadEmergencySignature)));
+        assert!(emergency_justifications.try_next().is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_undecodable_emergency_signature() {
+        let (_, data) = authority_data(true);
+        let (link, mut emergency_justifications) = running_link(data).await;
+
+        let result =
+            submit_emergency_justification(&link, THash::repeat_byte(7), 7, vec![1, 2, 3].into())
+                .await;
+
+        assert!(matches!(result, Err(Error::SignatureDecode(_))));
+        assert!(emergency_justifications.try_next().is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_emergency_justification_of_unknown_session() {
+        let (pairs, data) = authority_data(true);
+        let (link, mut emergency_justifications) = running_link(data).await;
+        let hash = THash::repeat_byte(7);
+
+        let result =
+            submit_emergency_justification(&link, hash, 17, emergency_signature(&pairs[3], hash))
+                .await;
+
+        assert!(matches!(
+            result,
+            Err(Error::UnknownAuthorityData(SessionId(1)))
+        ));
+        assert!(emergency_justifications.try_next().is_err());
+    }
+
+    #[tokio::test]
+    async fn forwards_justifications_to_subscribers() {
+        let (pairs, _) = authority_data(false);
+        let link = RpcLink::<TBlock>::new();
+        let mut subscription = link.justification_stream().subscribe(10);
+        let notification = JustificationNotification {
+            justification: multisignature(&pairs, &[0, 1, 2]),
+            hash: Default::default(),
+            number: 3,
+        };
+
+        link.notify_justification(notification.clone());
+
+        let received = subscription.next().await.expect("the link is alive");
+        assert_eq!(received.number, notification.number);
+        assert_eq!(received.justification, notification.justification);
+    }
+}
@@ -1,12 +1,15 @@
 use codec::{Decode, Encode};
 use sp_runtime::{traits::Block, SaturatedConversion};
 
-use crate::NumberFor;
+use crate::{data_io::DEFAULT_MAX_DATA_BRANCH_LEN, NumberFor};
 
+/// The blocks of a session, together with how far above the last finalized block proposals made
+/// in the session may reach.
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub struct SessionBoundaries<B: Block> {
     first_block: NumberFor<B>,
     last_block: NumberFor<B>,
+    max_branch_len: usize,
 }
 
 impl<B: Block> SessionBoundaries<B> {
@@ -14,9 +17,17 @@ impl<B: Block> SessionBoundaries<B> {
         SessionBoundaries {
             first_block: first_block_of_session::<B>(session_id, period),
             last_block: last_block_of_session::<B>(session_id, period),
+            max_branch_len: DEFAULT_MAX_DATA_BRANCH_LEN,
         }
     }
 
+    /// Sets the maximal length of proposal branches, all the nodes of the session have to use the
+    /// same value.
+    pub fn with_max_branch_len(mut self, max_branch_len: usize) -> Self {
+        self.max_branch_len = max_branch_len;
+        self
+    }
+
     pub fn first_block(&self) -> NumberFor<B> {
         self.first_block
     }
@@ -24,6 +35,10 @@ impl<B: Block> SessionBoundaries<B> {
     pub fn last_block(&self) -> NumberFor<B> {
         self.last_block
     }
+
+    pub fn max_branch_len(&self) -> usize {
+        self.max_branch_len
+    }
 }
 
 pub fn first_block_of_session<B: Block>(
@@ -1,10 +1,14 @@
 use std::{collections::HashMap, marker::PhantomData, sync::Arc};
 
-use dagestan_primitives::{DagestanSessionApi, SessionAuthorityData};
+use dagestan_primitives::{
+    ApiError as DagestanApiError, DagestanSessionApi, SessionAuthorityData, Version,
+    DEFAULT_FINALITY_VERSION,
+};
 use futures::StreamExt;
 use log::{debug, error, trace};
 use sc_client_api::{Backend, FinalityNotification};
 use sc_utils::mpsc::TracingUnboundedReceiver;
+use sp_api::{ApiError, ApiExt};
 use sp_runtime::{
     generic::BlockId,
     traits::{Block, Header, NumberFor},
@@ -20,14 +24,57 @@ use crate::{
 };
 
 const PRUNING_THRESHOLD: u32 = 10;
-type SessionMap = HashMap<SessionId, SessionAuthorityData>;
+/// Authority data of sessions, together with their finality versions.
+type SessionMap = HashMap<SessionId, (SessionAuthorityData, Version)>;
 type SessionSubscribers = HashMap<SessionId, Vec<OneShotSender<SessionAuthorityData>>>;
 
+/// Reads the authority data of the session containing `at`, whichever version of
+/// `DagestanSessionApi` the runtime implements.
+#[allow(deprecated)]
+pub fn runtime_authority_data<B, A>(
+    runtime_api: &A,
+    at: &BlockId<B>,
+) -> Result<SessionAuthorityData, ApiError>
+where
+    B: Block,
+    A: ApiExt<B> + DagestanSessionApi<B>,
+{
+    match runtime_api.api_version::<dyn DagestanSessionApi<B>>(at)? {
+        Some(version) if version >= 2 => runtime_api.authority_data(at),
+        _ => runtime_api
+            .authority_data_before_version_2(at)
+            .map(SessionAuthorityData::from),
+    }
+}
+
+/// Reads the authority data of the session following the one containing `at`, whichever version
+/// of `DagestanSessionApi` the runtime implements.
+#[allow(deprecated)]
+pub fn runtime_next_session_authority_data<B, A>(
+    runtime_api: &A,
+    at: &BlockId<B>,
+) -> Result<Result<SessionAuthorityData, DagestanApiError>, ApiError>
+where
+    B: Block,
+    A: ApiExt<B> + DagestanSessionApi<B>,
+{
+    match runtime_api.api_version::<dyn DagestanSessionApi<B>>(at)? {
+        Some(version) if version >= 2 => runtime_api.next_session_authority_data(at),
+        _ => runtime_api
+            .next_session_authority_data_before_version_2(at)
+            .map(|result| result.map(SessionAuthorityData::from)),
+    }
+}
+
 pub trait AuthorityProvider<B> {
     /// returns authority data for block
     fn authority_data(&self, block: B) -> Option<SessionAuthorityData>;
     /// returns next session authority data where current session is for block
     fn next_authority_data(&self, block: B) -> Option<SessionAuthorityData>;
+    /// returns finality version of the session containing block
+    fn finality_version(&self, block: B) -> Version;
+    /// returns next session finality version where current session is for block
+    fn next_finality_version(&self, block: B) -> Version;
 }
 
 /// Default implementation of authority provider trait.
@@ -65,11 +112,7 @@ where
     BE: Backend<B> + 'static,
 {
     fn authority_data(&self, num: NumberFor<B>) -> Option<SessionAuthorityData> {
-        match self
-            .client
-            .runtime_api()
-            .authority_data(&BlockId::Number(num))
-        {
+        match runtime_authority_data(&*self.client.runtime_api(), &BlockId::Number(num)) {
             Ok(data) => Some(data),
             Err(_) => self
                 .client
@@ -81,11 +124,11 @@ where
     }
 
     fn next_authority_data(&self, num: NumberFor<B>) -> Option<SessionAuthorityData> {
-        match self
-            .client
-            .runtime_api()
-            .next_session_authority_data(&BlockId::Number(num))
-            .map(|r| r.ok())
+        match runtime_next_session_authority_data(
+            &*self.client.runtime_api(),
+            &BlockId::Number(num),
+        )
+        .map(|r| r.ok())
         {
             Ok(maybe_data) => maybe_data,
             Err(_) => self
@@ -100,6 +143,20 @@ where
                 .flatten(),
         }
     }
+
+    fn finality_version(&self, num: NumberFor<B>) -> Version {
+        self.client
+            .runtime_api()
+            .finality_version(&BlockId::Number(num))
+            .unwrap_or(DEFAULT_FINALITY_VERSION)
+    }
+
+    fn next_finality_version(&self, num: NumberFor<B>) -> Version {
+        self.client
+            .runtime_api()
+            .next_session_finality_version(&BlockId::Number(num))
+            .unwrap_or(DEFAULT_FINALITY_VERSION)
+    }
 }
 
 pub trait FinalityNotificator<B, N> {
@@ -171,6 +228,7 @@ impl SharedSessionMap {
         &mut self,
         id: SessionId,
         authority_data: SessionAuthorityData,
+        finality_version: Version,
     ) -> Option<SessionAuthorityData> {
         let mut guard = self.0.write().await;
 
@@ -183,7 +241,10 @@ impl SharedSessionMap {
             }
         }
 
-        guard.0.insert(id, authority_data)
+        guard
+            .0
+            .insert(id, (authority_data, finality_version))
+            .map(|(authority_data, _)| authority_data)
     }
 
     async fn prune_below(&mut self, id: SessionId) {
@@ -200,11 +261,43 @@ impl SharedSessionMap {
     }
 }
 
+impl Default for SharedSessionMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ReadOnlySessionMap {
     pub async fn get(&self, id: SessionId) -> Option<SessionAuthorityData> {
+        self.get_with_finality_version(id)
+            .await
+            .map(|(authority_data, _)| authority_data)
+    }
+
+    /// Like `get`, but for synchronous contexts. Returns `None` also when the map is being updated
+    /// at the moment.
+    pub fn try_get(&self, id: SessionId) -> Option<SessionAuthorityData> {
+        self.try_get_with_finality_version(id)
+            .map(|(authority_data, _)| authority_data)
+    }
+
+    /// Returns the authority data of the session together with its finality version.
+    pub async fn get_with_finality_version(
+        &self,
+        id: SessionId,
+    ) -> Option<(SessionAuthorityData, Version)> {
         self.inner.read().await.0.get(&id).cloned()
     }
 
+    /// Like `get_with_finality_version`, but for synchronous contexts. Returns `None` also when
+    /// the map is being updated at the moment.
+    pub fn try_get_with_finality_version(
+        &self,
+        id: SessionId,
+    ) -> Option<(SessionAuthorityData, Version)> {
+        self.inner.try_read().ok()?.0.get(&id).cloned()
+    }
+
     /// returns an end of the oneshot channel that fires a message if either authority data is already
     /// known for the session with id = `id` or when the data is inserted for this session.
     pub async fn subscribe_to_insertion(
@@ -215,7 +308,7 @@ impl ReadOnlySessionMap {
 
         let mut guard = self.inner.write().await;
 
-        if let Some(authority_data) = guard.0.get(&id) {
+        if let Some((authority_data, _)) = guard.0.get(&id) {
             // if the value is already present notify immediately
             sender
                 .send(authority_data.clone())
@@ -232,18 +325,24 @@ fn get_authority_data_for_session<AP, B>(
     authority_provider: &AP,
     session_id: SessionId,
     first_block: NumberFor<B>,
-) -> SessionAuthorityData
+) -> (SessionAuthorityData, Version)
 where
     B: Block,
     AP: AuthorityProvider<NumberFor<B>>,
 {
     if session_id == SessionId(0) {
-        authority_provider
-            .authority_data(<NumberFor<B>>::saturated_from(0u32))
-            .expect("Authorities for the session 0 must be available from the beginning")
+        let genesis = <NumberFor<B>>::saturated_from(0u32);
+        let authority_data = authority_provider
+            .authority_data(genesis)
+            .expect("Authorities for the session 0 must be available from the beginning");
+        (authority_data, authority_provider.finality_version(genesis))
     } else {
-        authority_provider.next_authority_data(first_block).unwrap_or_else(||
+        let authority_data = authority_provider.next_authority_data(first_block).unwrap_or_else(||
             panic!("Authorities for next session {:?} must be available at first block #{:?} of current session", session_id.0, first_block)
+        );
+        (
+            authority_data,
+            authority_provider.next_finality_version(first_block),
         )
     }
 }
@@ -267,9 +366,10 @@ where
     FN: FinalityNotificator<FinalityNotification<B>, NumberFor<B>>,
     B: Block,
 {
-    pub fn new(authority_provider: AP, finality_notificator: FN) -> Self {
+    /// An updater keeping `session_map` up to date.
+    pub fn new(session_map: SharedSessionMap, authority_provider: AP, finality_notificator: FN) -> Self {
         Self {
-            session_map: SharedSessionMap::new(),
+            session_map,
             authority_provider,
             finality_notificator,
             _phantom: PhantomData,
@@ -285,22 +385,18 @@ where
     async fn handle_first_block_of_session(&mut self, num: NumberFor<B>, session_id: SessionId) {
         debug!(target: "dagestan-session-updater", "Handling first block #{:?} of session {:?}", num, session_id.0);
         let next_session = SessionId(session_id.0 + 1);
-        let authority_provider = &self.authority_provider;
+        let (authority_data, finality_version) =
+            get_authority_data_for_session::<_, B>(&self.authority_provider, next_session, num);
         self.session_map
-            .update(
-                next_session,
-                get_authority_data_for_session::<_, B>(authority_provider, next_session, num),
-            )
+            .update(next_session, authority_data, finality_version)
             .await;
 
         // if this is the first session we also need to include starting authority data into the map
         if session_id.0 == 0 {
-            let authority_provider = &self.authority_provider;
+            let (authority_data, finality_version) =
+                get_authority_data_for_session::<_, B>(&self.authority_provider, session_id, num);
             self.session_map
-                .update(
-                    session_id,
-                    get_authority_data_for_session::<_, B>(authority_provider, session_id, num),
-                )
+                .update(session_id, authority_data, finality_version)
                 .await;
         }
 
@@ -379,6 +475,7 @@ mod tests {
     struct MockProvider {
         pub session_map: HashMap<NumberFor<TBlock>, SessionAuthorityData>,
         pub next_session_map: HashMap<NumberFor<TBlock>, SessionAuthorityData>,
+        pub next_finality_versions: HashMap<NumberFor<TBlock>, Version>,
         pub asked_for: Arc<Mutex<Vec<NumberFor<TBlock>>>>,
     }
 
@@ -392,6 +489,7 @@ mod tests {
             Self {
                 session_map: HashMap::new(),
                 next_session_map: HashMap::new(),
+                next_finality_versions: HashMap::new(),
                 asked_for: Arc::new(Mutex::new(Vec::new())),
             }
         }
@@ -418,6 +516,17 @@ mod tests {
             asked.push(b);
             self.next_session_map.get(&b).cloned()
         }
+
+        fn finality_version(&self, _: NumberFor<TBlock>) -> Version {
+            DEFAULT_FINALITY_VERSION
+        }
+
+        fn next_finality_version(&self, b: NumberFor<TBlock>) -> Version {
+            self.next_finality_versions
+                .get(&b)
+                .cloned()
+                .unwrap_or(DEFAULT_FINALITY_VERSION)
+        }
     }
 
     impl FinalityNotificator<FinalityNotification<TBlock>, NumberFor<TBlock>> for MockNotificator {
@@ -476,7 +585,7 @@ mod tests {
             .next_session_map
             .insert(2, authority_data(12, 16));
 
-        let updater = SessionMapUpdater::new(mock_provider, mock_notificator);
+        let updater = SessionMapUpdater::new(SharedSessionMap::new(), mock_provider, mock_notificator);
         let session_map = updater.readonly_session_map();
 
         let blocks = n_new_blocks(&mut client, 2);
@@ -541,7 +650,7 @@ mod tests {
 
         mock_notificator.last_finalized = 2;
 
-        let updater = SessionMapUpdater::new(mock_provider, mock_notificator);
+        let updater = SessionMapUpdater::new(SharedSessionMap::new(), mock_provider, mock_notificator);
         let session_map = updater.readonly_session_map();
 
         let _handle = tokio::spawn(updater.run(SessionPeriod(1)));
@@ -567,6 +676,41 @@ mod tests {
         );
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn keeps_finality_versions_of_sessions() {
+        let (_sender, receiver) = tracing_unbounded("test");
+        let mut mock_provider = MockProvider::new();
+        let mut mock_notificator = MockNotificator::new(receiver);
+
+        mock_provider.session_map.insert(0, authority_data(0, 4));
+        mock_provider
+            .next_session_map
+            .insert(0, authority_data(4, 8));
+        mock_provider
+            .next_session_map
+            .insert(1, authority_data(8, 12));
+        mock_provider.next_finality_versions.insert(1, 5);
+
+        mock_notificator.last_finalized = 1;
+
+        let updater = SessionMapUpdater::new(SharedSessionMap::new(), mock_provider, mock_notificator);
+        let session_map = updater.readonly_session_map();
+
+        let _handle = tokio::spawn(updater.run(SessionPeriod(1)));
+
+        // wait a bit
+        Delay::new(Duration::from_millis(50)).await;
+
+        assert_eq!(
+            session_map.get_with_finality_version(SessionId(1)).await,
+            Some((authority_data(4, 8), DEFAULT_FINALITY_VERSION))
+        );
+        assert_eq!(
+            session_map.get_with_finality_version(SessionId(2)).await,
+            Some((authority_data(8, 12), 5))
+        );
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn prunes_old_sessions() {
         let (_sender, receiver) = tracing_unbounded("test");
@@ -584,7 +728,7 @@ mod tests {
         mock_notificator.last_finalized = 20;
 
         let asked = mock_provider.asked_for.clone();
-        let updater = SessionMapUpdater::new(mock_provider, mock_notificator);
+        let updater = SessionMapUpdater::new(SharedSessionMap::new(), mock_provider, mock_notificator);
         let session_map = updater.readonly_session_map();
 
         let _handle = tokio::spawn(updater.run(SessionPeriod(1)));
@@ -620,7 +764,9 @@ mod tests {
         let readonly = shared.read_only();
         let session = SessionId(0);
 
-        shared.update(session, authority_data(0, 2)).await;
+        shared
+            .update(session, authority_data(0, 2), DEFAULT_FINALITY_VERSION)
+            .await;
 
         let mut receiver = readonly.subscribe_to_insertion(session).await;
 
@@ -637,7 +783,9 @@ mod tests {
 
         // does not yet have any value
         assert_eq!(Err(TryRecvError::Empty), receiver.try_recv());
-        shared.update(session, authority_data(0, 2)).await;
+        shared
+            .update(session, authority_data(0, 2), DEFAULT_FINALITY_VERSION)
+            .await;
         assert_eq!(Ok(authority_data(0, 2)), receiver.await);
     }
 }
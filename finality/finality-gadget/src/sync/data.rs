@@ -0,0 +1,87 @@
+use codec::{Decode, Encode};
+
+use crate::sync::{BlockIdFor, Header, Justification, UnverifiedJustification};
+
+/// The state of a node, as much as is needed for deciding what can be sent to it.
+#[derive(Clone, Debug, Encode, Decode)]
+pub struct State<J: Justification> {
+    top_justification: J::Unverified,
+}
+
+impl<J: Justification> State<J> {
+    pub fn new(top_justification: J::Unverified) -> Self {
+        State { top_justification }
+    }
+
+    /// The justification of the top finalized block of the node.
+    pub fn top_justification(self) -> J::Unverified {
+        self.top_justification
+    }
+
+    /// The identifier of the top finalized block of the node.
+    pub fn top_id(&self) -> BlockIdFor<J> {
+        self.top_justification.header().id()
+    }
+}
+
+/// A request for the justification of a specific block.
+#[derive(Clone, Debug, Encode, Decode)]
+pub struct Request<J: Justification> {
+    target_id: BlockIdFor<J>,
+}
+
+impl<J: Justification> Request<J> {
+    pub fn new(target_id: BlockIdFor<J>) -> Self {
+        Request { target_id }
+    }
+
+    /// The block we want the justification for.
+    pub fn target_id(self) -> BlockIdFor<J> {
+        self.target_id
+    }
+}
+
+/// A request for the headers of the chain between the top finalized block of the requester and a
+/// specific block.
+#[derive(Clone, Debug, Encode, Decode)]
+pub struct HeaderRequest<J: Justification> {
+    target_id: BlockIdFor<J>,
+    top_finalized_id: BlockIdFor<J>,
+}
+
+impl<J: Justification> HeaderRequest<J> {
+    pub fn new(target_id: BlockIdFor<J>, top_finalized_id: BlockIdFor<J>) -> Self {
+        HeaderRequest {
+            target_id,
+            top_finalized_id,
+        }
+    }
+
+    /// The highest block we want the header of.
+    pub fn target_id(&self) -> &BlockIdFor<J> {
+        &self.target_id
+    }
+
+    /// The top finalized block of the requester, no headers at or below it are needed.
+    pub fn top_finalized_id(&self) -> &BlockIdFor<J> {
+        &self.top_finalized_id
+    }
+}
+
+/// Data exchanged by the block sync over the network.
+#[derive(Clone, Debug, Encode, Decode)]
+pub enum NetworkData<J: Justification> {
+    /// A periodic state broadcast, so that neighbouring nodes can send us what we are missing.
+    StateBroadcast(State<J>),
+    /// A justification sent in response to a state broadcast of a node that is behind us.
+    StateBroadcastResponse(J::Unverified),
+    /// An explicit request for the justification of a block.
+    Request(Request<J>),
+    /// A justification finalizing the requested block.
+    RequestResponse(J::Unverified),
+    /// A request for the headers leading to a block, sent to a peer that should have them.
+    HeaderRequest(HeaderRequest<J>),
+    /// Headers sent in response to a header request, starting with the requested block and going
+    /// down, each being the parent of the previous one.
+    HeaderResponse(Vec<J::Header>),
+}
@@ -0,0 +1,621 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::{Display, Error as FmtError, Formatter},
+    hash::Hash,
+};
+
+use crate::sync::{BlockIdFor, BlockIdentifier, Header, Justification};
+
+/// How far above the top finalized block we keep track of blocks.
+const MAX_HEIGHT_ABOVE_TOP_FINALIZED: u32 = 4096;
+/// How many blocks we keep track of at most.
+const MAX_VERTICES: usize = 16384;
+
+/// How much we care about acquiring the justification of a block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Importance {
+    /// We only know about the block from its descendants or from peers announcing it.
+    Auxiliary,
+    /// We imported the block, so we will want to finalize it.
+    Imported,
+    /// The justification of the block was explicitly requested.
+    Required,
+}
+
+struct Vertex<J: Justification, I: Clone + Eq + Hash> {
+    header_known: bool,
+    parent: Option<BlockIdFor<J>>,
+    justification: Option<J>,
+    importance: Importance,
+    holders: HashSet<I>,
+    children: HashSet<BlockIdFor<J>>,
+}
+
+impl<J: Justification, I: Clone + Eq + Hash> Vertex<J, I> {
+    fn new() -> Self {
+        Vertex {
+            header_known: false,
+            parent: None,
+            justification: None,
+            importance: Importance::Auxiliary,
+            holders: HashSet::new(),
+            children: HashSet::new(),
+        }
+    }
+
+    fn add_holder(&mut self, holder: Option<I>) {
+        if let Some(holder) = holder {
+            self.holders.insert(holder);
+        }
+    }
+
+    fn raise_importance(&mut self, importance: Importance) {
+        self.importance = self.importance.max(importance);
+    }
+
+    /// Where the block goes in the request order, if its justification should be requested.
+    fn request_key(&self, number: u32) -> Option<RequestKey> {
+        match self.justification.is_none() && self.importance > Importance::Auxiliary {
+            true => Some((Reverse(self.importance), number)),
+            false => None,
+        }
+    }
+}
+
+/// More important and then lower blocks go first.
+type RequestKey = (Reverse<Importance>, u32);
+
+/// What can go wrong when updating the forest.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The block is not above the top finalized block.
+    TooOld,
+    /// The block is too far above the top finalized block.
+    TooNew,
+    /// We already keep track of the maximal number of blocks.
+    Full,
+    /// The block descends from a block that is not finalized, but is not above the top finalized
+    /// block either.
+    HopelessFork,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        use Error::*;
+        match self {
+            TooOld => write!(f, "block is not above the top finalized block"),
+            TooNew => write!(
+                f,
+                "block is more than {} blocks above the top finalized block",
+                MAX_HEIGHT_ABOVE_TOP_FINALIZED
+            ),
+            Full => write!(f, "already tracking {} blocks", MAX_VERTICES),
+            HopelessFork => write!(f, "block is on a fork that can never be finalized"),
+        }
+    }
+}
+
+/// The blocks above the top finalized block that we know of, together with what we know about
+/// them and who else knows about them. Until the top finalized block is set no block is considered
+/// too old or too new. The number of blocks is limited, as peers can make us learn about blocks.
+pub struct Forest<J: Justification, I: Clone + Eq + Hash> {
+    vertices: HashMap<BlockIdFor<J>, Vertex<J, I>>,
+    requests: BTreeMap<RequestKey, HashSet<BlockIdFor<J>>>,
+    top_finalized: Option<BlockIdFor<J>>,
+    max_height: u32,
+    max_vertices: usize,
+}
+
+impl<J: Justification, I: Clone + Eq + Hash> Forest<J, I> {
+    pub fn new() -> Self {
+        Self::with_limits(MAX_HEIGHT_ABOVE_TOP_FINALIZED, MAX_VERTICES)
+    }
+
+    fn with_limits(max_height: u32, max_vertices: usize) -> Self {
+        Forest {
+            vertices: HashMap::new(),
+            requests: BTreeMap::new(),
+            top_finalized: None,
+            max_height,
+            max_vertices,
+        }
+    }
+
+    fn is_too_old(&self, id: &BlockIdFor<J>) -> bool {
+        match &self.top_finalized {
+            Some(top_finalized) => id.number() <= top_finalized.number(),
+            None => false,
+        }
+    }
+
+    fn is_too_new(&self, id: &BlockIdFor<J>) -> bool {
+        match &self.top_finalized {
+            Some(top_finalized) => {
+                id.number() > top_finalized.number().saturating_add(self.max_height)
+            }
+            None => false,
+        }
+    }
+
+    fn remove_request(&mut self, id: &BlockIdFor<J>, key: RequestKey) {
+        if let Some(ids) = self.requests.get_mut(&key) {
+            ids.remove(id);
+            if ids.is_empty() {
+                self.requests.remove(&key);
+            }
+        }
+    }
+
+    /// Forgets the block, without its descendants.
+    fn remove_vertex(&mut self, id: &BlockIdFor<J>) -> Option<Vertex<J, I>> {
+        let vertex = self.vertices.remove(id)?;
+        if let Some(key) = vertex.request_key(id.number()) {
+            self.remove_request(id, key);
+        }
+        Some(vertex)
+    }
+
+    /// Forgets the block together with all its descendants.
+    fn remove_subtree(&mut self, id: BlockIdFor<J>) {
+        let mut to_remove = vec![id];
+        while let Some(id) = to_remove.pop() {
+            if let Some(vertex) = self.remove_vertex(&id) {
+                to_remove.extend(vertex.children);
+            }
+        }
+    }
+
+    fn vertex(&mut self, id: &BlockIdFor<J>) -> Result<&mut Vertex<J, I>, Error> {
+        if self.is_too_old(id) {
+            return Err(Error::TooOld);
+        }
+        if self.is_too_new(id) {
+            return Err(Error::TooNew);
+        }
+        if !self.vertices.contains_key(id) && self.vertices.len() >= self.max_vertices {
+            return Err(Error::Full);
+        }
+        Ok(self.vertices.entry(id.clone()).or_insert_with(Vertex::new))
+    }
+
+    /// Modifies the vertex of the block, keeping the request order up to date.
+    fn modify_vertex<R>(
+        &mut self,
+        id: &BlockIdFor<J>,
+        modify: impl FnOnce(&mut Vertex<J, I>) -> R,
+    ) -> Result<R, Error> {
+        let vertex = self.vertex(id)?;
+        let old_key = vertex.request_key(id.number());
+        let result = modify(vertex);
+        let new_key = vertex.request_key(id.number());
+        if old_key != new_key {
+            if let Some(key) = old_key {
+                self.remove_request(id, key);
+            }
+            if let Some(key) = new_key {
+                self.requests.entry(key).or_default().insert(id.clone());
+            }
+        }
+        Ok(result)
+    }
+
+    /// Notes that `holder` knows about the block, if we know about it as well. Peers cannot make
+    /// us track blocks this way. Returns whether the holder was noted.
+    pub fn add_holder(&mut self, id: &BlockIdFor<J>, holder: I) -> bool {
+        match self.vertices.get_mut(id) {
+            Some(vertex) => {
+                vertex.add_holder(Some(holder));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Marks the justification of the block as required. Returns whether it was not required
+    /// before.
+    pub fn set_required(&mut self, id: &BlockIdFor<J>) -> Result<bool, Error> {
+        self.modify_vertex(id, |vertex| {
+            let newly_required = vertex.importance != Importance::Required;
+            vertex.raise_importance(Importance::Required);
+            newly_required
+        })
+    }
+
+    /// Notes the header of a block, connecting it to its parent, and that `holder`, if any, has
+    /// the block. A header of a block that cannot be finalized anymore is rejected.
+    pub fn update_header(
+        &mut self,
+        header: &J::Header,
+        holder: Option<I>,
+        imported: bool,
+    ) -> Result<(), Error> {
+        let id = header.id();
+        let header_known = self.modify_vertex(&id, |vertex| {
+            vertex.add_holder(holder);
+            if imported {
+                vertex.raise_importance(Importance::Imported);
+            }
+            vertex.header_known
+        })?;
+        if header_known {
+            return Ok(());
+        }
+        let parent_id = match (header.parent_id(), &self.top_finalized) {
+            (Some(parent_id), Some(top_finalized)) if self.is_too_old(&parent_id) => {
+                if &parent_id != top_finalized {
+                    self.remove_subtree(id);
+                    return Err(Error::HopelessFork);
+                }
+                None
+            }
+            (parent_id, _) => parent_id,
+        };
+        if let Some(parent_id) = &parent_id {
+            self.vertex(parent_id)?.children.insert(id.clone());
+        }
+        let vertex = self.vertex(&id)?;
+        vertex.header_known = true;
+        vertex.parent = parent_id;
+        Ok(())
+    }
+
+    /// Notes the justification of a block, together with its header, and that `holder`, if any,
+    /// has it.
+    pub fn update_justification(
+        &mut self,
+        justification: J,
+        holder: Option<I>,
+    ) -> Result<(), Error> {
+        let id = justification.header().id();
+        self.update_header(justification.header(), holder, false)?;
+        self.modify_vertex(&id, |vertex| vertex.justification = Some(justification))
+    }
+
+    /// The justification of the block, if we know it.
+    pub fn justification(&self, id: &BlockIdFor<J>) -> Option<&J> {
+        self.vertices.get(id)?.justification.as_ref()
+    }
+
+    /// Whether we know about the block, but not about its header.
+    pub fn is_missing_header(&self, id: &BlockIdFor<J>) -> bool {
+        self.vertices
+            .get(id)
+            .map_or(false, |vertex| !vertex.header_known)
+    }
+
+    /// The highest block, out of the block itself and its ancestors, whose header we are missing,
+    /// or `None` if the known headers connect the block to the top finalized one. Descendants of
+    /// the top finalized block lose their parent vertex when it gets finalized, so a missing
+    /// parent vertex also means the chain is connected.
+    pub fn missing_ancestor(&self, id: &BlockIdFor<J>) -> Option<BlockIdFor<J>> {
+        let mut current = id;
+        loop {
+            let vertex = self.vertices.get(current)?;
+            if !vertex.header_known {
+                return Some(current.clone());
+            }
+            current = vertex.parent.as_ref()?;
+        }
+    }
+
+    /// The peers to request the justification of the block from, or `None` if we already have the
+    /// justification or the block cannot be finalized anymore.
+    pub fn request_peers(&self, id: &BlockIdFor<J>) -> Option<HashSet<I>> {
+        let vertex = self.vertices.get(id)?;
+        match vertex.justification {
+            Some(_) => None,
+            None => Some(vertex.holders.clone()),
+        }
+    }
+
+    /// The block whose justification we should request next, together with the peers that should
+    /// have it. Required blocks go before imported ones and lower blocks before higher ones.
+    pub fn next_request(&self) -> Option<(BlockIdFor<J>, HashSet<I>)> {
+        let id = self.requests.values().next()?.iter().next()?;
+        let vertex = self.vertices.get(id)?;
+        Some((id.clone(), vertex.holders.clone()))
+    }
+
+    /// Sets the new top finalized block and forgets all the blocks that are not above it, together
+    /// with all the blocks descending from the forgotten ones, as they cannot be finalized anymore.
+    pub fn set_top_finalized(&mut self, id: BlockIdFor<J>) {
+        if self.is_too_old(&id) {
+            return;
+        }
+        // The new top finalized block goes first, so that its descendants are not forgotten together
+        // with its ancestors.
+        self.remove_vertex(&id);
+        let number = id.number();
+        let too_old: Vec<_> = self
+            .vertices
+            .keys()
+            .filter(|vertex_id| vertex_id.number() <= number)
+            .cloned()
+            .collect();
+        for vertex_id in too_old {
+            self.remove_subtree(vertex_id);
+        }
+        self.top_finalized = Some(id);
+    }
+
+    /// The number of blocks we know about.
+    pub fn len(&self) -> usize {
+        self.vertices.len()
+    }
+}
+
+impl<J: Justification, I: Clone + Eq + Hash> Default for Forest<J, I> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::{Error, Forest};
+    use crate::sync::{
+        mock::{MockHeader, MockIdentifier, MockJustification, MockPeerId},
+        Header,
+    };
+
+    type MockForest = Forest<MockJustification, MockPeerId>;
+
+    fn ids(headers: &[MockHeader]) -> Vec<MockIdentifier> {
+        headers.iter().map(|header| header.id()).collect()
+    }
+
+    #[test]
+    fn imported_blocks_are_requested_lowest_first() {
+        let mut forest = MockForest::new();
+        let genesis = MockHeader::genesis();
+        forest.set_top_finalized(genesis.id());
+        let branch = genesis.branch(5, 0);
+        for header in branch.iter().rev() {
+            forest
+                .update_header(header, Some(7), true)
+                .expect("block is above the top finalized");
+        }
+
+        assert_eq!(
+            forest.next_request(),
+            Some((branch[0].id(), HashSet::from([7])))
+        );
+    }
+
+    #[test]
+    fn required_blocks_go_before_imported_ones() {
+        let mut forest = MockForest::new();
+        let genesis = MockHeader::genesis();
+        forest.set_top_finalized(genesis.id());
+        let branch = genesis.branch(5, 0);
+        for header in &branch {
+            forest
+                .update_header(header, None, true)
+                .expect("block is above the top finalized");
+        }
+        assert_eq!(forest.set_required(&branch[3].id()), Ok(true));
+        assert_eq!(forest.set_required(&branch[3].id()), Ok(false));
+        assert!(forest.add_holder(&branch[3].id(), 1));
+        assert!(forest.add_holder(&branch[3].id(), 2));
+
+        assert_eq!(
+            forest.next_request(),
+            Some((branch[3].id(), HashSet::from([1, 2])))
+        );
+    }
+
+    #[test]
+    fn auxiliary_and_justified_blocks_are_not_requested() {
+        let mut forest = MockForest::new();
+        let genesis = MockHeader::genesis();
+        forest.set_top_finalized(genesis.id());
+        let branch = genesis.branch(3, 0);
+        forest
+            .update_header(&branch[1], Some(1), false)
+            .expect("block is above the top finalized");
+        forest
+            .update_header(&branch[2], None, true)
+            .expect("block is above the top finalized");
+        forest
+            .update_justification(MockJustification::for_header(branch[2].clone()), Some(1))
+            .expect("block is above the top finalized");
+
+        assert_eq!(forest.next_request(), None);
+        assert_eq!(forest.request_peers(&branch[2].id()), None);
+        assert_eq!(
+            forest.justification(&branch[2].id()),
+            Some(&MockJustification::for_header(branch[2].clone()))
+        );
+        assert_eq!(
+            forest.request_peers(&branch[1].id()),
+            Some(HashSet::from([1]))
+        );
+    }
+
+    #[test]
+    fn rejects_old_blocks_and_hopeless_forks() {
+        let mut forest = MockForest::new();
+        let genesis = MockHeader::genesis();
+        let branch = genesis.branch(4, 0);
+        let fork = branch[0].branch(3, 1);
+        forest.set_top_finalized(branch[1].id());
+
+        assert_eq!(
+            forest.update_header(&branch[1], None, true),
+            Err(Error::TooOld)
+        );
+        assert_eq!(forest.set_required(&branch[0].id()), Err(Error::TooOld));
+        assert_eq!(
+            forest.update_header(&fork[0], None, true),
+            Err(Error::HopelessFork)
+        );
+        assert_eq!(forest.update_header(&branch[2], None, true), Ok(()));
+        assert_eq!(forest.len(), 1);
+    }
+
+    #[test]
+    fn finalization_prunes_hopeless_forks() {
+        let mut forest = MockForest::new();
+        let genesis = MockHeader::genesis();
+        forest.set_top_finalized(genesis.id());
+        let branch = genesis.branch(6, 0);
+        let fork = branch[1].branch(4, 1);
+        let late_fork = branch[3].branch(2, 2);
+        for header in branch.iter().chain(&fork).chain(&late_fork) {
+            forest
+                .update_header(header, None, true)
+                .expect("block is above the top finalized");
+        }
+        assert_eq!(forest.len(), 12);
+
+        forest.set_top_finalized(branch[2].id());
+
+        let mut remaining: Vec<_> = ids(&branch[3..]);
+        remaining.extend(ids(&late_fork));
+        assert_eq!(forest.len(), remaining.len());
+        for id in remaining {
+            assert!(forest.request_peers(&id).is_some());
+        }
+        for id in ids(&fork).into_iter().chain(ids(&branch[..3])) {
+            assert_eq!(forest.request_peers(&id), None);
+        }
+        assert_eq!(
+            forest.next_request().map(|(id, _)| id),
+            Some(branch[3].id())
+        );
+    }
+
+    #[test]
+    fn finalization_keeps_blocks_of_unknown_ancestry() {
+        let mut forest = MockForest::new();
+        let genesis = MockHeader::genesis();
+        forest.set_top_finalized(genesis.id());
+        let branch = genesis.branch(8, 0);
+        forest
+            .update_header(&branch[6], Some(3), true)
+            .expect("block is above the top finalized");
+        forest
+            .update_header(&branch[7], Some(3), true)
+            .expect("block is above the top finalized");
+
+        forest.set_top_finalized(branch[3].id());
+
+        assert_eq!(forest.len(), 3);
+        assert_eq!(
+            forest.next_request().map(|(id, _)| id),
+            Some(branch[6].id())
+        );
+        assert_eq!(forest.update_header(&branch[5], None, false), Ok(()));
+        assert_eq!(forest.update_header(&branch[4], None, false), Ok(()));
+        assert_eq!(forest.len(), 4);
+    }
+
+    #[test]
+    fn finds_missing_ancestors() {
+        let mut forest = MockForest::new();
+        let genesis = MockHeader::genesis();
+        forest.set_top_finalized(genesis.id());
+        let branch = genesis.branch(6, 0);
+        forest
+            .update_justification(MockJustification::for_header(branch[5].clone()), Some(1))
+            .expect("block is above the top finalized");
+
+        assert_eq!(
+            forest.missing_ancestor(&branch[5].id()),
+            Some(branch[4].id())
+        );
+        assert!(forest.is_missing_header(&branch[4].id()));
+        assert!(!forest.is_missing_header(&branch[5].id()));
+
+        for header in branch[2..5].iter().rev() {
+            forest
+                .update_header(header, Some(1), false)
+                .expect("block is above the top finalized");
+        }
+        assert_eq!(
+            forest.missing_ancestor(&branch[5].id()),
+            Some(branch[1].id())
+        );
+
+        forest.set_top_finalized(branch[1].id());
+        assert_eq!(forest.missing_ancestor(&branch[5].id()), None);
+        assert_eq!(forest.missing_ancestor(&branch[0].id()), None);
+    }
+
+    #[test]
+    fn notes_holders_only_of_known_blocks() {
+        let mut forest = MockForest::new();
+        let genesis = MockHeader::genesis();
+        forest.set_top_finalized(genesis.id());
+        let branch = genesis.branch(2, 0);
+        forest
+            .update_header(&branch[0], None, true)
+            .expect("block is above the top finalized");
+
+        assert!(forest.add_holder(&branch[0].id(), 1));
+        assert!(!forest.add_holder(&branch[1].id(), 1));
+        assert_eq!(forest.len(), 1);
+        assert_eq!(
+            forest.request_peers(&branch[0].id()),
+            Some(HashSet::from([1]))
+        );
+    }
+
+    #[test]
+    fn rejects_blocks_too_far_above_top_finalized() {
+        let mut forest = MockForest::with_limits(3, 100);
+        let genesis = MockHeader::genesis();
+        forest.set_top_finalized(genesis.id());
+        let branch = genesis.branch(5, 0);
+
+        assert_eq!(forest.update_header(&branch[2], None, true), Ok(()));
+        assert_eq!(
+            forest.update_header(&branch[3], None, true),
+            Err(Error::TooNew)
+        );
+        assert_eq!(forest.set_required(&branch[4].id()), Err(Error::TooNew));
+
+        forest.set_top_finalized(branch[0].id());
+        assert_eq!(forest.update_header(&branch[3], None, true), Ok(()));
+    }
+
+    #[test]
+    fn survives_flood_of_blocks() {
+        let mut forest = MockForest::with_limits(100, 50);
+        let genesis = MockHeader::genesis();
+        forest.set_top_finalized(genesis.id());
+        let branch = genesis.branch(5, 0);
+        forest
+            .update_header(&branch[0], None, true)
+            .expect("block is above the top finalized");
+        forest
+            .set_required(&branch[0].id())
+            .expect("block is known");
+
+        let mut full = 0;
+        for fork in 1..100 {
+            for header in genesis.branch(3, fork) {
+                match forest.update_header(&header, Some(fork as MockPeerId), true) {
+                    Ok(()) => (),
+                    Err(Error::Full) => full += 1,
+                    Err(e) => panic!("unexpected error {}", e),
+                }
+            }
+        }
+
+        assert!(full > 0);
+        assert_eq!(forest.len(), 50);
+        assert_eq!(
+            forest.next_request().map(|(id, _)| id),
+            Some(branch[0].id())
+        );
+
+        forest.set_top_finalized(branch[0].id());
+        assert_eq!(forest.len(), 0);
+        assert_eq!(forest.next_request(), None);
+        assert_eq!(forest.update_header(&branch[1], None, true), Ok(()));
+    }
+}
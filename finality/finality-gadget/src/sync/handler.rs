@@ -0,0 +1,544 @@
+use std::{
+    collections::HashSet,
+    fmt::{Display, Error as FmtError, Formatter},
+    hash::Hash,
+};
+
+use codec::Encode;
+
+use crate::sync::{
+    data::{HeaderRequest, Request, State},
+    forest::{Error as ForestError, Forest},
+    BlockIdFor, BlockIdentifier, BlockStatus, ChainStatus, Finalizer, Header, Justification,
+    UnverifiedJustification, Verifier,
+};
+
+/// The largest number of headers sent in, or accepted from, a single header response.
+const MAX_HEADERS_IN_RESPONSE: usize = 128;
+/// The largest total encoded size of the headers sent in a single header response.
+const MAX_HEADER_RESPONSE_SIZE: usize = 256 * 1024;
+
+/// Handles the data of the block sync, deciding what to finalize and what to send back.
+pub struct Handler<I, J, CS, V, F>
+where
+    I: Clone + Eq + Hash,
+    J: Justification,
+    CS: ChainStatus<J>,
+    V: Verifier<J>,
+    F: Finalizer<J>,
+{
+    chain_status: CS,
+    verifier: V,
+    finalizer: F,
+    forest: Forest<J, I>,
+}
+
+/// What can go wrong when handling a piece of data.
+pub enum Error<J, CS, V, F>
+where
+    J: Justification,
+    CS: ChainStatus<J>,
+    V: Verifier<J>,
+    F: Finalizer<J>,
+{
+    Verifier(V::Error),
+    ChainStatus(CS::Error),
+    Finalizer(F::Error),
+    Forest(ForestError),
+    TooManyHeaders(usize),
+    BrokenHeaderChain,
+    UnexpectedHeaders,
+}
+
+impl<J, CS, V, F> Display for Error<J, CS, V, F>
+where
+    J: Justification,
+    CS: ChainStatus<J>,
+    V: Verifier<J>,
+    F: Finalizer<J>,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        use Error::*;
+        match self {
+            Verifier(e) => write!(f, "verifier error: {}", e),
+            ChainStatus(e) => write!(f, "chain status error: {}", e),
+            Finalizer(e) => write!(f, "finalizer error: {}", e),
+            Forest(e) => write!(f, "forest error: {}", e),
+            TooManyHeaders(amount) => write!(
+                f,
+                "received {} headers, at most {} are allowed",
+                amount, MAX_HEADERS_IN_RESPONSE
+            ),
+            BrokenHeaderChain => write!(f, "received headers that are not a chain"),
+            UnexpectedHeaders => write!(f, "received headers we did not ask for"),
+        }
+    }
+}
+
+impl<I, J, CS, V, F> Handler<I, J, CS, V, F>
+where
+    I: Clone + Eq + Hash,
+    J: Justification,
+    CS: ChainStatus<J>,
+    V: Verifier<J>,
+    F: Finalizer<J>,
+{
+    pub fn new(chain_status: CS, verifier: V, finalizer: F) -> Self {
+        Handler {
+            chain_status,
+            verifier,
+            finalizer,
+            forest: Forest::new(),
+        }
+    }
+
+    fn top_finalized(&mut self) -> Result<J, Error<J, CS, V, F>> {
+        let top_finalized = self
+            .chain_status
+            .top_finalized()
+            .map_err(Error::ChainStatus)?;
+        self.forest.set_top_finalized(top_finalized.header().id());
+        Ok(top_finalized)
+    }
+
+    /// Finalizes the block of the justification. Blocks are also finalized outside of the block
+    /// sync, so if finalization fails because the block got finalized in the meantime, this is
+    /// not an error, just nothing to do. Returns whether the block got finalized.
+    fn finalize(&mut self, justification: J) -> Result<bool, Error<J, CS, V, F>> {
+        let number = justification.header().id().number();
+        match self.finalizer.finalize(justification) {
+            Ok(()) => Ok(true),
+            Err(e) => match number <= self.top_finalized()?.header().id().number() {
+                true => Ok(false),
+                false => Err(Error::Finalizer(e)),
+            },
+        }
+    }
+
+    /// Our current state, to be broadcast to the peers.
+    pub fn state(&mut self) -> Result<State<J>, Error<J, CS, V, F>> {
+        Ok(State::new(self.top_finalized()?.into_unverified()))
+    }
+
+    /// Verifies the justification and finalizes its block, if it is above our top finalized one
+    /// and we have already imported it. Otherwise the justification is kept until we import the
+    /// block. Returns whether the block got finalized.
+    pub fn handle_justification(
+        &mut self,
+        justification: J::Unverified,
+        peer: Option<I>,
+    ) -> Result<bool, Error<J, CS, V, F>> {
+        let justification = self
+            .verifier
+            .verify(justification)
+            .map_err(Error::Verifier)?;
+        let id = justification.header().id();
+        if id.number() <= self.top_finalized()?.header().id().number() {
+            return Ok(false);
+        }
+        match self
+            .chain_status
+            .status_of(id)
+            .map_err(Error::ChainStatus)?
+        {
+            BlockStatus::Present(_) => self.finalize(justification),
+            BlockStatus::Unknown => match self.forest.update_justification(justification, peer) {
+                // We are too far behind to make use of the justification for now.
+                Ok(()) | Err(ForestError::TooNew) => Ok(false),
+                Err(e) => Err(Error::Forest(e)),
+            },
+            BlockStatus::Justified(_) => Ok(false),
+        }
+    }
+
+    /// Handles the state of a peer. Returns our top justification if the peer is behind us.
+    pub fn handle_state(
+        &mut self,
+        state: State<J>,
+        peer: I,
+    ) -> Result<Option<J::Unverified>, Error<J, CS, V, F>> {
+        let remote_justification = state.top_justification();
+        let remote_number = remote_justification.header().id().number();
+        let top_finalized = self.top_finalized()?;
+        let local_number = top_finalized.header().id().number();
+        if remote_number < local_number {
+            return Ok(Some(top_finalized.into_unverified()));
+        }
+        if remote_number > local_number {
+            self.handle_justification(remote_justification, Some(peer))?;
+        }
+        Ok(None)
+    }
+
+    /// Handles a request for the justification of a block. Returns the justification of the block
+    /// itself if we have it, otherwise our top justification if it finalizes the block as well.
+    pub fn handle_request(
+        &mut self,
+        request: Request<J>,
+        peer: I,
+    ) -> Result<Option<J::Unverified>, Error<J, CS, V, F>> {
+        let target_id = request.target_id();
+        if let BlockStatus::Justified(justification) = self
+            .chain_status
+            .status_of(target_id.clone())
+            .map_err(Error::ChainStatus)?
+        {
+            return Ok(Some(justification.into_unverified()));
+        }
+        let top_finalized = self.top_finalized()?;
+        if top_finalized.header().id().number() >= target_id.number() {
+            return Ok(Some(top_finalized.into_unverified()));
+        }
+        // The peer has the block, so it will have its justification sooner or later. We only note
+        // that for blocks we already know about, so that peers cannot flood us with made up ones.
+        self.forest.add_holder(&target_id, peer);
+        Ok(None)
+    }
+
+    /// Handles a freshly imported block, finalizing it if we already have its justification.
+    /// Returns whether the block got finalized.
+    pub fn handle_block_imported(&mut self, id: BlockIdFor<J>) -> Result<bool, Error<J, CS, V, F>> {
+        let header = match self
+            .chain_status
+            .status_of(id.clone())
+            .map_err(Error::ChainStatus)?
+        {
+            BlockStatus::Present(header) => header,
+            BlockStatus::Justified(_) | BlockStatus::Unknown => return Ok(false),
+        };
+        match self.forest.update_header(&header, None, true) {
+            Ok(()) => (),
+            // Blocks that cannot be finalized anymore are not interesting.
+            Err(ForestError::TooOld) | Err(ForestError::HopelessFork) => return Ok(false),
+            // Neither are blocks too far ahead, we will get back to them after finalizing more.
+            Err(ForestError::TooNew) => return Ok(false),
+            Err(e) => return Err(Error::Forest(e)),
+        }
+        match self.forest.justification(&id).cloned() {
+            Some(justification) => self.finalize(justification),
+            None => Ok(false),
+        }
+    }
+
+    /// A request for the headers we are missing between the block and our top finalized one, if
+    /// any. Ancestors we already imported are added to the forest on the way, so only the headers
+    /// we cannot get locally are requested.
+    pub fn header_request(
+        &mut self,
+        id: &BlockIdFor<J>,
+    ) -> Result<Option<HeaderRequest<J>>, Error<J, CS, V, F>> {
+        let top_finalized_id = self.top_finalized()?.header().id();
+        while let Some(missing_id) = self.forest.missing_ancestor(id) {
+            let header = match self
+                .chain_status
+                .status_of(missing_id.clone())
+                .map_err(Error::ChainStatus)?
+            {
+                BlockStatus::Present(header) => header,
+                BlockStatus::Justified(justification) => justification.header().clone(),
+                BlockStatus::Unknown => {
+                    return Ok(Some(HeaderRequest::new(missing_id, top_finalized_id)))
+                }
+            };
+            self.forest
+                .update_header(&header, None, true)
+                .map_err(Error::Forest)?;
+        }
+        Ok(None)
+    }
+
+    /// Handles a request for headers, returning the ones we have, starting from the target and
+    /// going down towards the top finalized block of the requester. The response is limited both
+    /// in the number of headers and in their total size.
+    pub fn handle_header_request(
+        &mut self,
+        request: HeaderRequest<J>,
+    ) -> Result<Vec<J::Header>, Error<J, CS, V, F>> {
+        let lowest_number = request.top_finalized_id().number();
+        let mut headers = Vec::new();
+        let mut size = 0;
+        let mut next_id = Some(request.target_id().clone());
+        while let Some(id) = next_id {
+            if id.number() <= lowest_number || headers.len() >= MAX_HEADERS_IN_RESPONSE {
+                break;
+            }
+            let header = match self
+                .chain_status
+                .status_of(id)
+                .map_err(Error::ChainStatus)?
+            {
+                BlockStatus::Present(header) => header,
+                BlockStatus::Justified(justification) => justification.header().clone(),
+                BlockStatus::Unknown => break,
+            };
+            size += header.encoded_size();
+            if size > MAX_HEADER_RESPONSE_SIZE {
+                break;
+            }
+            next_id = header.parent_id();
+            headers.push(header);
+        }
+        Ok(headers)
+    }
+
+    /// Handles headers received in response to our request. They have to form a chain starting
+    /// with a block whose header we are missing. Returns a request for the headers still missing
+    /// below them, which should be sent to the same peer.
+    pub fn handle_headers(
+        &mut self,
+        headers: Vec<J::Header>,
+        peer: I,
+    ) -> Result<Option<HeaderRequest<J>>, Error<J, CS, V, F>> {
+        if headers.len() > MAX_HEADERS_IN_RESPONSE {
+            return Err(Error::TooManyHeaders(headers.len()));
+        }
+        if headers
+            .iter()
+            .zip(headers.iter().skip(1))
+            .any(|(header, parent)| header.parent_id() != Some(parent.id()))
+        {
+            return Err(Error::BrokenHeaderChain);
+        }
+        let highest_id = match headers.first() {
+            Some(highest) => highest.id(),
+            None => return Ok(None),
+        };
+        // Updates the forest, so that it knows what is too old by now.
+        self.top_finalized()?;
+        if !self.forest.is_missing_header(&highest_id) {
+            return Err(Error::UnexpectedHeaders);
+        }
+        for header in headers.iter().rev() {
+            match self.forest.update_header(header, Some(peer.clone()), false) {
+                // Our top finalized block might have moved since we sent the request.
+                Ok(()) | Err(ForestError::TooOld) => (),
+                Err(e) => return Err(Error::Forest(e)),
+            }
+        }
+        self.header_request(&highest_id)
+    }
+
+    /// Forgets all the blocks that cannot be finalized anymore.
+    pub fn handle_block_finalized(&mut self, id: BlockIdFor<J>) {
+        self.forest.set_top_finalized(id);
+    }
+
+    /// Marks the justification of the block as required. Returns whether it was not required
+    /// before, so it has to be requested now.
+    pub fn request_justification(&mut self, id: BlockIdFor<J>) -> bool {
+        self.forest.set_required(&id).unwrap_or(false)
+    }
+
+    /// The peers to request the justification of the block from, or `None` if it should not be
+    /// requested anymore.
+    pub fn request_peers(&self, id: &BlockIdFor<J>) -> Option<HashSet<I>> {
+        self.forest.request_peers(id)
+    }
+
+    /// The block whose justification we should request next, and the peers to request it from.
+    pub fn next_request(&self) -> Option<(BlockIdFor<J>, HashSet<I>)> {
+        self.forest.next_request()
+    }
+
+    /// How many blocks above the top finalized one we keep track of.
+    pub fn tracked_blocks(&self) -> usize {
+        self.forest.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::{Error, Handler, MAX_HEADERS_IN_RESPONSE};
+    use crate::sync::{
+        data::{HeaderRequest, Request},
+        mock::{Backend, MockHeader, MockIdentifier, MockJustification, MockPeerId, MockVerifier},
+        ChainStatus, Finalizer, Header, Justification,
+    };
+
+    type MockHandler = Handler<MockPeerId, MockJustification, Backend, MockVerifier, Backend>;
+
+    fn setup() -> (MockHandler, Backend) {
+        let (backend, _notifier) = Backend::setup();
+        let handler = Handler::new(backend.clone(), MockVerifier, backend.clone());
+        (handler, backend)
+    }
+
+    fn top_finalized_id(backend: &Backend) -> MockIdentifier {
+        backend
+            .top_finalized()
+            .expect("genesis is finalized")
+            .header()
+            .id()
+    }
+
+    #[test]
+    fn finalizes_imported_block() {
+        let (mut handler, backend) = setup();
+        let branch = MockHeader::genesis().branch(3, 0);
+        backend.import_branch(&branch);
+
+        assert!(matches!(
+            handler.handle_justification(MockJustification::for_header(branch[2].clone()), Some(1)),
+            Ok(true)
+        ));
+        assert_eq!(top_finalized_id(&backend), branch[2].id());
+    }
+
+    #[test]
+    fn finalizes_block_once_imported() {
+        let (mut handler, backend) = setup();
+        let branch = MockHeader::genesis().branch(3, 0);
+        backend.import_branch(&branch[..2]);
+
+        assert!(matches!(
+            handler.handle_justification(MockJustification::for_header(branch[2].clone()), Some(1)),
+            Ok(false)
+        ));
+        assert_eq!(top_finalized_id(&backend), MockHeader::genesis().id());
+
+        backend.import(branch[2].clone());
+        assert!(matches!(
+            handler.handle_block_imported(branch[2].id()),
+            Ok(true)
+        ));
+        assert_eq!(top_finalized_id(&backend), branch[2].id());
+    }
+
+    #[test]
+    fn ignores_blocks_finalized_in_the_meantime() {
+        let (mut handler, backend) = setup();
+        let branch = MockHeader::genesis().branch(4, 0);
+        backend.import_branch(&branch[..2]);
+
+        assert!(matches!(
+            handler.handle_justification(MockJustification::for_header(branch[2].clone()), Some(1)),
+            Ok(false)
+        ));
+        backend.import_branch(&branch[2..]);
+        // Finalization outside of the block sync overtakes it.
+        assert_eq!(
+            backend.finalize(MockJustification::for_header(branch[3].clone())),
+            Ok(())
+        );
+        assert!(matches!(
+            handler.handle_block_imported(branch[2].id()),
+            Ok(false)
+        ));
+        assert_eq!(top_finalized_id(&backend), branch[3].id());
+    }
+
+    #[test]
+    fn does_not_track_blocks_requested_by_peers() {
+        let (mut handler, backend) = setup();
+        let genesis = MockHeader::genesis();
+        let branch = genesis.branch(2, 0);
+        backend.import(branch[0].clone());
+        assert!(matches!(
+            handler.handle_block_imported(branch[0].id()),
+            Ok(false)
+        ));
+
+        for fork in 1..1000 {
+            for header in genesis.branch(3, fork) {
+                assert!(matches!(
+                    handler.handle_request(Request::new(header.id()), fork as MockPeerId),
+                    Ok(None)
+                ));
+            }
+        }
+        assert!(matches!(
+            handler.handle_request(Request::new(branch[0].id()), 1),
+            Ok(None)
+        ));
+
+        assert_eq!(handler.tracked_blocks(), 1);
+        assert_eq!(
+            handler.request_peers(&branch[0].id()),
+            Some(HashSet::from([1]))
+        );
+    }
+
+    #[test]
+    fn fetches_header_chain_from_peer() {
+        let (mut handler, backend) = setup();
+        let (mut remote_handler, remote_backend) = setup();
+        let genesis = MockHeader::genesis();
+        let branch = genesis.branch(MAX_HEADERS_IN_RESPONSE + 10, 0);
+        backend.import_branch(&branch[..5]);
+        remote_backend.import_branch(&branch);
+        let top = branch.last().expect("branch is not empty").clone();
+
+        assert!(matches!(
+            handler.handle_justification(MockJustification::for_header(top.clone()), Some(1)),
+            Ok(false)
+        ));
+        let mut request = match handler.header_request(&top.id()) {
+            Ok(Some(request)) => request,
+            _ => panic!("headers should be missing"),
+        };
+        assert_eq!(request.target_id(), &branch[branch.len() - 2].id());
+
+        let mut responses = 0;
+        loop {
+            let headers = match remote_handler.handle_header_request(request) {
+                Ok(headers) => headers,
+                Err(e) => panic!("remote failed to answer: {}", e),
+            };
+            assert!(headers.len() <= MAX_HEADERS_IN_RESPONSE);
+            responses += 1;
+            match handler.handle_headers(headers, 1) {
+                Ok(Some(next_request)) => request = next_request,
+                Ok(None) => break,
+                Err(e) => panic!("valid headers rejected: {}", e),
+            }
+        }
+        assert_eq!(responses, 2);
+    }
+
+    #[test]
+    fn rejects_invalid_headers() {
+        let (mut handler, _backend) = setup();
+        let genesis = MockHeader::genesis();
+        let branch = genesis.branch(6, 0);
+        assert!(matches!(
+            handler.handle_justification(MockJustification::for_header(branch[5].clone()), Some(1)),
+            Ok(false)
+        ));
+
+        let unrelated = genesis.branch(3, 1).into_iter().rev().collect();
+        assert!(matches!(
+            handler.handle_headers(unrelated, 1),
+            Err(Error::UnexpectedHeaders)
+        ));
+        let broken = vec![branch[4].clone(), branch[2].clone()];
+        assert!(matches!(
+            handler.handle_headers(broken, 1),
+            Err(Error::BrokenHeaderChain)
+        ));
+        let too_many = vec![branch[4].clone(); MAX_HEADERS_IN_RESPONSE + 1];
+        assert!(matches!(
+            handler.handle_headers(too_many, 1),
+            Err(Error::TooManyHeaders(_))
+        ));
+        let valid = branch[..5].iter().rev().cloned().collect();
+        assert!(matches!(handler.handle_headers(valid, 1), Ok(None)));
+    }
+
+    #[test]
+    fn answers_header_requests_down_to_requester_top() {
+        let (mut handler, backend) = setup();
+        let branch = MockHeader::genesis().branch(6, 0);
+        backend.import_branch(&branch);
+
+        let headers = match handler
+            .handle_header_request(HeaderRequest::new(branch[4].id(), branch[1].id()))
+        {
+            Ok(headers) => headers,
+            Err(e) => panic!("failed to answer: {}", e),
+        };
+        let expected: Vec<_> = branch[2..5].iter().rev().cloned().collect();
+        assert_eq!(headers, expected);
+    }
+}
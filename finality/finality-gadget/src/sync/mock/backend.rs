@@ -0,0 +1,315 @@
+use std::{
+    collections::HashMap,
+    fmt::{Display, Error as FmtError, Formatter},
+    sync::Arc,
+};
+
+use futures::{
+    channel::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    StreamExt,
+};
+use parking_lot::Mutex;
+
+use crate::sync::{
+    mock::{MockHeader, MockIdentifier, MockJustification},
+    BlockIdentifier, BlockStatus, ChainStatus, ChainStatusNotification, ChainStatusNotifier,
+    Finalizer, Header, Justification, Requester,
+};
+
+struct MockBlock {
+    header: MockHeader,
+    justification: Option<MockJustification>,
+}
+
+struct BackendStorage {
+    blocks: HashMap<MockIdentifier, MockBlock>,
+    // Headers waiting for the import of their parent, keyed by the parent.
+    delayed: HashMap<MockIdentifier, Vec<MockHeader>>,
+    top_finalized: MockIdentifier,
+    best_block: MockIdentifier,
+    requested: Vec<MockIdentifier>,
+}
+
+/// An in-memory chain, all the clones share the same blocks.
+#[derive(Clone)]
+pub struct Backend {
+    inner: Arc<Mutex<BackendStorage>>,
+    notification_sender: UnboundedSender<ChainStatusNotification<MockIdentifier>>,
+}
+
+/// Notifies about the changes of a backend.
+pub struct MockNotifier {
+    receiver: UnboundedReceiver<ChainStatusNotification<MockIdentifier>>,
+}
+
+/// What can go wrong when using the backend.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    StreamClosed,
+    UnknownBlock(MockIdentifier),
+    AlreadyFinalized(MockIdentifier),
+    NotDescendantOfTopFinalized(MockIdentifier),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        use Error::*;
+        match self {
+            StreamClosed => write!(f, "notification stream closed"),
+            UnknownBlock(id) => write!(f, "block {:?} is not imported", id),
+            AlreadyFinalized(id) => write!(f, "block {:?} is not above the top finalized", id),
+            NotDescendantOfTopFinalized(id) => {
+                write!(f, "block {:?} does not descend from the top finalized", id)
+            }
+        }
+    }
+}
+
+impl Backend {
+    /// A chain containing only the justified genesis, together with a notifier about its changes.
+    pub fn setup() -> (Self, MockNotifier) {
+        let genesis = MockHeader::genesis();
+        let id = genesis.id();
+        let blocks = HashMap::from([(
+            id.clone(),
+            MockBlock {
+                justification: Some(MockJustification::for_header(genesis.clone())),
+                header: genesis,
+            },
+        )]);
+        let (notification_sender, receiver) = mpsc::unbounded();
+        (
+            Backend {
+                inner: Arc::new(Mutex::new(BackendStorage {
+                    blocks,
+                    delayed: HashMap::new(),
+                    top_finalized: id.clone(),
+                    best_block: id,
+                    requested: Vec::new(),
+                })),
+                notification_sender,
+            },
+            MockNotifier { receiver },
+        )
+    }
+
+    fn notify(&self, notification: ChainStatusNotification<MockIdentifier>) {
+        // Nobody listening is fine, not all tests care about notifications.
+        let _ = self.notification_sender.unbounded_send(notification);
+    }
+
+    /// Imports the block if its parent is imported, otherwise delays the import until the parent
+    /// gets imported. Importing a block also imports all the delayed blocks waiting for it.
+    pub fn import(&self, header: MockHeader) {
+        let mut storage = self.inner.lock();
+        let mut to_import = vec![header];
+        while let Some(header) = to_import.pop() {
+            let id = header.id();
+            let parent_id = match header.parent_id() {
+                Some(parent_id) => parent_id,
+                None => continue,
+            };
+            if storage.blocks.contains_key(&id) {
+                continue;
+            }
+            if !storage.blocks.contains_key(&parent_id) {
+                storage.delayed.entry(parent_id).or_default().push(header);
+                continue;
+            }
+            storage.blocks.insert(
+                id.clone(),
+                MockBlock {
+                    header,
+                    justification: None,
+                },
+            );
+            if id.number() > storage.best_block.number() {
+                storage.best_block = id.clone();
+            }
+            to_import.extend(storage.delayed.remove(&id).unwrap_or_default());
+            self.notify(ChainStatusNotification::BlockImported(id));
+        }
+    }
+
+    /// Imports all the blocks, in order.
+    pub fn import_branch(&self, headers: &[MockHeader]) {
+        for header in headers {
+            self.import(header.clone());
+        }
+    }
+
+    /// The blocks whose justifications were requested so far, in order.
+    pub fn requested_justifications(&self) -> Vec<MockIdentifier> {
+        self.inner.lock().requested.clone()
+    }
+}
+
+#[async_trait::async_trait]
+impl ChainStatusNotifier<MockIdentifier> for MockNotifier {
+    type Error = Error;
+
+    async fn next(&mut self) -> Result<ChainStatusNotification<MockIdentifier>, Self::Error> {
+        self.receiver.next().await.ok_or(Error::StreamClosed)
+    }
+}
+
+impl ChainStatus<MockJustification> for Backend {
+    type Error = Error;
+
+    fn status_of(&self, id: MockIdentifier) -> Result<BlockStatus<MockJustification>, Self::Error> {
+        let storage = self.inner.lock();
+        Ok(match storage.blocks.get(&id) {
+            Some(MockBlock {
+                justification: Some(justification),
+                ..
+            }) => BlockStatus::Justified(justification.clone()),
+            Some(MockBlock { header, .. }) => BlockStatus::Present(header.clone()),
+            None => BlockStatus::Unknown,
+        })
+    }
+
+    fn best_block(&self) -> Result<MockHeader, Self::Error> {
+        let storage = self.inner.lock();
+        let id = &storage.best_block;
+        storage
+            .blocks
+            .get(id)
+            .map(|block| block.header.clone())
+            .ok_or_else(|| Error::UnknownBlock(id.clone()))
+    }
+
+    fn top_finalized(&self) -> Result<MockJustification, Self::Error> {
+        let storage = self.inner.lock();
+        let id = &storage.top_finalized;
+        storage
+            .blocks
+            .get(id)
+            .and_then(|block| block.justification.clone())
+            .ok_or_else(|| Error::UnknownBlock(id.clone()))
+    }
+}
+
+impl Finalizer<MockJustification> for Backend {
+    type Error = Error;
+
+    fn finalize(&self, justification: MockJustification) -> Result<(), Self::Error> {
+        let mut storage = self.inner.lock();
+        let id = justification.header().id();
+        if id.number() <= storage.top_finalized.number() {
+            return Err(Error::AlreadyFinalized(id));
+        }
+        if !storage.blocks.contains_key(&id) {
+            return Err(Error::UnknownBlock(id));
+        }
+        // Imported blocks always have imported parents, so the walk cannot get stuck.
+        let mut ancestor = id.clone();
+        while ancestor.number() > storage.top_finalized.number() {
+            ancestor = storage.blocks[&ancestor]
+                .header
+                .parent_id()
+                .expect("only the genesis has no parent");
+        }
+        if ancestor != storage.top_finalized {
+            return Err(Error::NotDescendantOfTopFinalized(id));
+        }
+        if let Some(block) = storage.blocks.get_mut(&id) {
+            block.justification = Some(justification);
+        }
+        storage.top_finalized = id.clone();
+        self.notify(ChainStatusNotification::BlockFinalized(id));
+        Ok(())
+    }
+}
+
+impl Requester<MockIdentifier> for Backend {
+    fn request_justification(&self, id: MockIdentifier) {
+        self.inner.lock().requested.push(id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Backend, Error};
+    use crate::sync::{
+        mock::{MockHeader, MockJustification},
+        BlockStatus, ChainStatus, ChainStatusNotification, ChainStatusNotifier, Finalizer, Header,
+        Requester,
+    };
+
+    #[tokio::test]
+    async fn delayed_imports_happen_with_their_parent() {
+        let (backend, mut notifier) = Backend::setup();
+        let branch = MockHeader::genesis().branch(3, 0);
+        backend.import(branch[2].clone());
+        backend.import(branch[1].clone());
+        assert!(matches!(
+            backend.status_of(branch[2].id()),
+            Ok(BlockStatus::Unknown)
+        ));
+
+        backend.import(branch[0].clone());
+
+        for header in &branch {
+            match notifier.next().await {
+                Ok(ChainStatusNotification::BlockImported(id)) => assert_eq!(id, header.id()),
+                _ => panic!("expected the import of {:?}", header.id()),
+            }
+        }
+        assert_eq!(
+            backend.best_block().map(|header| header.id()),
+            Ok(branch[2].id())
+        );
+    }
+
+    #[tokio::test]
+    async fn finalizes_descendants_of_top_finalized() {
+        let (backend, mut notifier) = Backend::setup();
+        let genesis = MockHeader::genesis();
+        let branch = genesis.branch(4, 0);
+        let fork = genesis.branch(4, 1);
+        backend.import_branch(&branch);
+        backend.import_branch(&fork);
+        for _ in 0..8 {
+            assert!(notifier.next().await.is_ok());
+        }
+
+        assert_eq!(
+            backend.finalize(MockJustification::for_header(branch[2].clone())),
+            Ok(())
+        );
+        assert!(matches!(
+            notifier.next().await,
+            Ok(ChainStatusNotification::BlockFinalized(id)) if id == branch[2].id()
+        ));
+        assert_eq!(
+            backend.top_finalized(),
+            Ok(MockJustification::for_header(branch[2].clone()))
+        );
+        assert_eq!(
+            backend.finalize(MockJustification::for_header(fork[3].clone())),
+            Err(Error::NotDescendantOfTopFinalized(fork[3].id()))
+        );
+        assert_eq!(
+            backend.finalize(MockJustification::for_header(branch[1].clone())),
+            Err(Error::AlreadyFinalized(branch[1].id()))
+        );
+        let unknown = branch[3].child(0);
+        assert_eq!(
+            backend.finalize(MockJustification::for_header(unknown.clone())),
+            Err(Error::UnknownBlock(unknown.id()))
+        );
+    }
+
+    #[test]
+    fn records_justification_requests() {
+        let (backend, _notifier) = Backend::setup();
+        let branch = MockHeader::genesis().branch(2, 0);
+        backend.request_justification(branch[1].id());
+        backend.clone().request_justification(branch[0].id());
+
+        assert_eq!(
+            backend.requested_justifications(),
+            vec![branch[1].id(), branch[0].id()]
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    convert::Infallible,
+    hash::{Hash, Hasher},
+};
+
+use codec::{Decode, Encode};
+
+use crate::sync::{BlockIdentifier, Header, Justification, UnverifiedJustification, Verifier};
+
+mod backend;
+
+pub use backend::{Backend, MockNotifier};
+
+pub type MockPeerId = u32;
+
+#[derive(Clone, Hash, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct MockIdentifier {
+    number: u32,
+    hash: u64,
+}
+
+impl MockIdentifier {
+    fn new(number: u32, hash: u64) -> Self {
+        MockIdentifier { number, hash }
+    }
+}
+
+impl BlockIdentifier for MockIdentifier {
+    fn number(&self) -> u32 {
+        self.number
+    }
+}
+
+#[derive(Clone, Hash, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct MockHeader {
+    id: MockIdentifier,
+    parent: Option<MockIdentifier>,
+}
+
+impl MockHeader {
+    /// A header without a parent, e.g. the genesis.
+    pub fn genesis() -> Self {
+        MockHeader {
+            id: MockIdentifier::new(0, 0),
+            parent: None,
+        }
+    }
+
+    /// A child of this header, different children are distinguished by `fork`.
+    pub fn child(&self, fork: u64) -> Self {
+        let mut hasher = DefaultHasher::new();
+        (self.id.hash, fork).hash(&mut hasher);
+        MockHeader {
+            id: MockIdentifier::new(self.id.number + 1, hasher.finish()),
+            parent: Some(self.id.clone()),
+        }
+    }
+
+    /// A chain of `length` descendants of this header, each the child of the previous one.
+    pub fn branch(&self, length: usize, fork: u64) -> Vec<Self> {
+        let mut result: Vec<Self> = Vec::with_capacity(length);
+        for _ in 0..length {
+            let next = result.last().unwrap_or(self).child(fork);
+            result.push(next);
+        }
+        result
+    }
+}
+
+impl Header for MockHeader {
+    type Identifier = MockIdentifier;
+
+    fn id(&self) -> Self::Identifier {
+        self.id.clone()
+    }
+
+    fn parent_id(&self) -> Option<Self::Identifier> {
+        self.parent.clone()
+    }
+}
+
+#[derive(Clone, Hash, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct MockJustification {
+    header: MockHeader,
+}
+
+impl MockJustification {
+    pub fn for_header(header: MockHeader) -> Self {
+        MockJustification { header }
+    }
+}
+
+impl UnverifiedJustification for MockJustification {
+    type Header = MockHeader;
+
+    fn header(&self) -> &Self::Header {
+        &self.header
+    }
+}
+
+impl Justification for MockJustification {
+    type Header = MockHeader;
+    type Unverified = Self;
+
+    fn header(&self) -> &Self::Header {
+        &self.header
+    }
+
+    fn into_unverified(self) -> Self::Unverified {
+        self
+    }
+}
+
+/// Accepts every justification.
+pub struct MockVerifier;
+
+impl Verifier<MockJustification> for MockVerifier {
+    type Error = Infallible;
+
+    fn verify(&self, justification: MockJustification) -> Result<MockJustification, Self::Error> {
+        Ok(justification)
+    }
+}
@@ -3,14 +3,28 @@ use std::{
     hash::Hash,
 };
 
+use codec::Codec;
+
+mod data;
+mod forest;
+mod handler;
+#[cfg(test)]
+mod mock;
+mod service;
 mod substrate;
 mod task_queue;
 mod ticker;
 
+pub use service::Service;
+pub use substrate::{
+    BlockRequester as SubstrateBlockRequester, SubstrateChainStatus, SubstrateChainStatusNotifier,
+    SubstrateJustificationVerifier,
+};
+
 const LOG_TARGET: &str = "dagestan-block-sync";
 
 /// The identifier of a block, the least amount of knowledge we can have about a block.
-pub trait BlockIdentifier: Clone + Hash + Debug + Eq {
+pub trait BlockIdentifier: Clone + Hash + Debug + Eq + Codec + Send + Sync + 'static {
     /// The block number, useful when reasoning about hopeless forks.
     fn number(&self) -> u32;
 }
@@ -22,7 +36,7 @@ pub trait Requester<BI: BlockIdentifier> {
 }
 
 /// The header of a block, containing information about the parent relation.
-pub trait Header: Clone {
+pub trait Header: Clone + Codec + Debug + Send + Sync + 'static {
     type Identifier: BlockIdentifier;
 
     /// The identifier of this block.
@@ -32,10 +46,18 @@ pub trait Header: Clone {
     fn parent_id(&self) -> Option<Self::Identifier>;
 }
 
+/// The unverified justification of a block, including a header, as received from the network.
+pub trait UnverifiedJustification: Clone + Codec + Debug + Send + Sync + 'static {
+    type Header: Header;
+
+    /// The header of the block.
+    fn header(&self) -> &Self::Header;
+}
+
 /// The verified justification of a block, including a header.
-pub trait Justification: Clone {
+pub trait Justification: Clone + Debug + Send + Sync + 'static {
     type Header: Header;
-    type Unverified;
+    type Unverified: UnverifiedJustification<Header = Self::Header>;
 
     /// The header of the block.
     fn header(&self) -> &Self::Header;
@@ -44,6 +66,9 @@ pub trait Justification: Clone {
     fn into_unverified(self) -> Self::Unverified;
 }
 
+/// The identifier of the block a justification is for.
+pub type BlockIdFor<J> = <<J as Justification>::Header as Header>::Identifier;
+
 /// A verifier of justifications.
 pub trait Verifier<J: Justification> {
     type Error: Display;
@@ -0,0 +1,327 @@
+use std::{
+    collections::HashSet,
+    fmt::{Display, Error as FmtError, Formatter},
+    time::Duration,
+};
+
+use futures::{channel::mpsc, StreamExt};
+use log::{debug, trace, warn};
+
+use crate::{
+    network::GossipNetwork,
+    sync::{
+        data::{HeaderRequest, NetworkData, Request},
+        handler::Handler,
+        task_queue::TaskQueue,
+        ticker::Ticker,
+        BlockIdFor, BlockIdentifier, ChainStatus, ChainStatusNotification, ChainStatusNotifier,
+        Finalizer, Header, Justification, Requester, UnverifiedJustification, Verifier, LOG_TARGET,
+    },
+};
+
+const BROADCAST_COOLDOWN: Duration = Duration::from_millis(200);
+const BROADCAST_PERIOD: Duration = Duration::from_secs(1);
+const REQUEST_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// What can go wrong when running the block sync.
+#[derive(Debug)]
+pub enum Error<NE: Display, CE: Display> {
+    Network(NE),
+    ChainEvents(CE),
+    RequestChannel,
+}
+
+impl<NE: Display, CE: Display> Display for Error<NE, CE> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        use Error::*;
+        match self {
+            Network(e) => write!(f, "network unexpectedly done: {}", e),
+            ChainEvents(e) => write!(f, "chain events unexpectedly done: {}", e),
+            RequestChannel => write!(f, "request channel unexpectedly closed"),
+        }
+    }
+}
+
+impl<BI: BlockIdentifier> Requester<BI> for mpsc::UnboundedSender<BI> {
+    fn request_justification(&self, id: BI) {
+        if let Err(e) = self.unbounded_send(id) {
+            warn!(
+                target: LOG_TARGET,
+                "Failed to send a justification request to the block sync: {}", e
+            );
+        }
+    }
+}
+
+/// The block sync service. It broadcasts our top finalized justification to the peers, answers
+/// their requests and finalizes blocks using the justifications they send us.
+///
+/// It does not own finalization. The `JustificationHandler` keeps finalizing blocks using the
+/// justifications produced by AlephBFT, submitted through the RPC, or requested by it, and both
+/// use the same finalizer. Whichever of them finalizes a block first wins, the other one then
+/// quietly skips it.
+pub struct Service<J, N, CE, CS, V, F>
+where
+    J: Justification,
+    N: GossipNetwork<NetworkData<J>>,
+    CE: ChainStatusNotifier<BlockIdFor<J>>,
+    CS: ChainStatus<J>,
+    V: Verifier<J>,
+    F: Finalizer<J>,
+{
+    network: N,
+    handler: Handler<N::PeerId, J, CS, V, F>,
+    tasks: TaskQueue<BlockIdFor<J>>,
+    broadcast_ticker: Ticker,
+    chain_events: CE,
+    requests_from_user: mpsc::UnboundedReceiver<BlockIdFor<J>>,
+}
+
+impl<J, N, CE, CS, V, F> Service<J, N, CE, CS, V, F>
+where
+    J: Justification,
+    N: GossipNetwork<NetworkData<J>>,
+    CE: ChainStatusNotifier<BlockIdFor<J>>,
+    CS: ChainStatus<J>,
+    V: Verifier<J>,
+    F: Finalizer<J>,
+{
+    /// Create a new service using the provided network for communication. Also returns an
+    /// interface for requesting justifications of specific blocks.
+    pub fn new(
+        network: N,
+        chain_events: CE,
+        chain_status: CS,
+        verifier: V,
+        finalizer: F,
+    ) -> (
+        Self,
+        impl Requester<BlockIdFor<J>> + Clone + Send + Sync + 'static,
+    ) {
+        let (requests_for_sync, requests_from_user) = mpsc::unbounded();
+        (
+            Service {
+                network,
+                handler: Handler::new(chain_status, verifier, finalizer),
+                tasks: TaskQueue::new(),
+                broadcast_ticker: Ticker::new(BROADCAST_PERIOD, BROADCAST_COOLDOWN),
+                chain_events,
+                requests_from_user,
+            },
+            requests_for_sync,
+        )
+    }
+
+    fn broadcast(&mut self) {
+        let state = match self.handler.state() {
+            Ok(state) => state,
+            Err(e) => {
+                warn!(target: LOG_TARGET, "Failed to construct own state: {}.", e);
+                return;
+            }
+        };
+        trace!(
+            target: LOG_TARGET,
+            "Broadcasting state, tracking {} blocks.",
+            self.handler.tracked_blocks()
+        );
+        if let Err(e) = self.network.broadcast(NetworkData::StateBroadcast(state)) {
+            warn!(target: LOG_TARGET, "Error sending broadcast: {}.", e);
+        }
+    }
+
+    /// Broadcasts our state and asks for the most needed justification.
+    fn handle_tick(&mut self) {
+        self.broadcast();
+        if let Some((id, peers)) = self.handler.next_request() {
+            self.send_request(id, peers);
+        }
+    }
+
+    fn send_to(&mut self, data: NetworkData<J>, peer: N::PeerId) {
+        if let Err(e) = self.network.send_to(data, peer) {
+            warn!(target: LOG_TARGET, "Error sending response: {}.", e);
+        }
+    }
+
+    fn send_header_request(&mut self, request: HeaderRequest<J>, peer: N::PeerId) {
+        trace!(
+            target: LOG_TARGET,
+            "Requesting headers down from {:?} from {:?}.",
+            request.target_id(),
+            peer
+        );
+        self.send_to(NetworkData::HeaderRequest(request), peer);
+    }
+
+    /// Asks the peer for the headers connecting the block to our top finalized one, if we are
+    /// missing any of them.
+    fn request_headers(&mut self, id: BlockIdFor<J>, peer: N::PeerId) {
+        match self.handler.header_request(&id) {
+            Ok(Some(request)) => self.send_header_request(request, peer),
+            Ok(None) => (),
+            Err(e) => warn!(
+                target: LOG_TARGET,
+                "Error preparing a header request for {:?}: {}.", id, e
+            ),
+        }
+    }
+
+    fn handle_justification(&mut self, justification: J::Unverified, peer: N::PeerId) {
+        let id = justification.header().id();
+        match self
+            .handler
+            .handle_justification(justification, Some(peer.clone()))
+        {
+            Ok(true) => trace!(
+                target: LOG_TARGET,
+                "Finalized a block using a justification from {:?}.",
+                peer
+            ),
+            // The block might be unknown, the peer should know how to get to it.
+            Ok(false) => self.request_headers(id, peer),
+            Err(e) => warn!(
+                target: LOG_TARGET,
+                "Error handling justification from {:?}: {}.", peer, e
+            ),
+        }
+    }
+
+    fn handle_network_data(&mut self, data: NetworkData<J>, peer: N::PeerId) {
+        trace!(target: LOG_TARGET, "Handling network data from {:?}.", peer);
+        match data {
+            NetworkData::StateBroadcast(state) => {
+                let remote_id = state.top_id();
+                match self.handler.handle_state(state, peer.clone()) {
+                    Ok(Some(justification)) => {
+                        self.send_to(NetworkData::StateBroadcastResponse(justification), peer)
+                    }
+                    Ok(None) => self.request_headers(remote_id, peer),
+                    Err(e) => warn!(
+                        target: LOG_TARGET,
+                        "Error handling state from {:?}: {}.", peer, e
+                    ),
+                }
+            }
+            NetworkData::StateBroadcastResponse(justification)
+            | NetworkData::RequestResponse(justification) => {
+                self.handle_justification(justification, peer)
+            }
+            NetworkData::Request(request) => {
+                match self.handler.handle_request(request, peer.clone()) {
+                    Ok(Some(justification)) => {
+                        self.send_to(NetworkData::RequestResponse(justification), peer)
+                    }
+                    Ok(None) => (),
+                    Err(e) => warn!(
+                        target: LOG_TARGET,
+                        "Error handling request from {:?}: {}.", peer, e
+                    ),
+                }
+            }
+            NetworkData::HeaderRequest(request) => {
+                match self.handler.handle_header_request(request) {
+                    Ok(headers) if headers.is_empty() => (),
+                    Ok(headers) => self.send_to(NetworkData::HeaderResponse(headers), peer),
+                    Err(e) => warn!(
+                        target: LOG_TARGET,
+                        "Error handling header request from {:?}: {}.", peer, e
+                    ),
+                }
+            }
+            NetworkData::HeaderResponse(headers) => {
+                match self.handler.handle_headers(headers, peer.clone()) {
+                    Ok(Some(request)) => self.send_header_request(request, peer),
+                    Ok(None) => (),
+                    Err(e) => warn!(
+                        target: LOG_TARGET,
+                        "Error handling headers from {:?}: {}.", peer, e
+                    ),
+                }
+            }
+        }
+    }
+
+    /// Asks a random one of the peers for the justification of the block. It does not schedule
+    /// asking again, the callers decide whether the request should be retried.
+    fn send_request(&mut self, id: BlockIdFor<J>, peers: HashSet<N::PeerId>) {
+        trace!(
+            target: LOG_TARGET,
+            "Requesting justification for {:?} from {:?}.",
+            id,
+            peers
+        );
+        if let Err(e) = self
+            .network
+            .send_to_random(NetworkData::Request(Request::new(id)), peers)
+        {
+            warn!(target: LOG_TARGET, "Error sending request: {}.", e);
+        }
+    }
+
+    /// Asks one of the peers that should have it for the justification of the block, and
+    /// schedules asking again in case nobody answers.
+    fn handle_task(&mut self, id: BlockIdFor<J>) {
+        match self.handler.request_peers(&id) {
+            Some(peers) => {
+                self.send_request(id.clone(), peers);
+                self.tasks.schedule_in(id, REQUEST_RETRY_DELAY);
+            }
+            None => trace!(
+                target: LOG_TARGET,
+                "Dropping request for {:?}, its justification is no longer needed.",
+                id
+            ),
+        }
+    }
+
+    fn handle_chain_event(&mut self, event: ChainStatusNotification<BlockIdFor<J>>) {
+        use ChainStatusNotification::*;
+        match event {
+            BlockImported(id) => match self.handler.handle_block_imported(id.clone()) {
+                Ok(true) => trace!(
+                    target: LOG_TARGET,
+                    "Finalized the imported block {:?} using a kept justification.",
+                    id
+                ),
+                Ok(false) => (),
+                Err(e) => warn!(
+                    target: LOG_TARGET,
+                    "Error handling the import of {:?}: {}.", id, e
+                ),
+            },
+            BlockFinalized(id) => {
+                trace!(target: LOG_TARGET, "Block {:?} finalized.", id);
+                self.handler.handle_block_finalized(id);
+                if self.broadcast_ticker.try_tick() {
+                    self.broadcast();
+                }
+            }
+        }
+    }
+
+    /// Stay synchronized.
+    pub async fn run(mut self) -> Result<(), Error<N::Error, CE::Error>> {
+        loop {
+            tokio::select! {
+                maybe_data = self.network.next() => {
+                    let (data, peer) = maybe_data.map_err(Error::Network)?;
+                    self.handle_network_data(data, peer);
+                },
+                Some(id) = self.tasks.pop() => self.handle_task(id),
+                _ = self.broadcast_ticker.wait_and_tick() => self.handle_tick(),
+                maybe_event = self.chain_events.next() => {
+                    let event = maybe_event.map_err(Error::ChainEvents)?;
+                    self.handle_chain_event(event);
+                },
+                maybe_id = self.requests_from_user.next() => {
+                    let id = maybe_id.ok_or(Error::RequestChannel)?;
+                    if self.handler.request_justification(id.clone()) {
+                        debug!(target: LOG_TARGET, "Received a justification request for {:?}.", id);
+                        self.handle_task(id);
+                    }
+                },
+            }
+        }
+    }
+}
@@ -1,18 +1,20 @@
 use std::{
     fmt::{Display, Error as FmtError, Formatter},
     marker::PhantomData,
+    sync::Arc,
 };
 
-use dagestan_primitives::{BlockNumber, DAGESTAN_ENGINE_ID};
+use dagestan_primitives::BlockNumber;
 use log::warn;
-use sp_blockchain::{Backend, Error as ClientError};
+use sc_client_api::{BlockBackend, HeaderBackend};
+use sp_blockchain::Error as ClientError;
 use sp_runtime::{
     generic::BlockId as SubstrateBlockId,
     traits::{Block as BlockT, Header as SubstrateHeader},
 };
 
 use crate::{
-    justification::backwards_compatible_decode,
+    justification::{backwards_compatible_decode, find_dagestan_justification},
     sync::{substrate::Justification, BlockStatus, ChainStatus, Header, LOG_TARGET},
     DagestanJustification,
 };
@@ -57,22 +59,29 @@ impl<B: BlockT> From<ClientError> for Error<B> {
 }
 
 /// Substrate implementation of ChainStatus trait
-pub struct SubstrateChainStatus<B, BE>
+pub struct SubstrateChainStatus<B, C>
 where
-    BE: Backend<B>,
+    C: HeaderBackend<B> + BlockBackend<B>,
     B: BlockT,
     B::Header: SubstrateHeader<Number = BlockNumber>,
 {
-    client: BE,
+    client: Arc<C>,
     _phantom: PhantomData<B>,
 }
 
-impl<B, BE> SubstrateChainStatus<B, BE>
+impl<B, C> SubstrateChainStatus<B, C>
 where
-    BE: Backend<B>,
+    C: HeaderBackend<B> + BlockBackend<B>,
     B: BlockT,
     B::Header: SubstrateHeader<Number = BlockNumber>,
 {
+    pub fn new(client: Arc<C>) -> Self {
+        SubstrateChainStatus {
+            client,
+            _phantom: PhantomData,
+        }
+    }
+
     fn header(&self, hash: B::Hash) -> Result<Option<B::Header>, ClientError> {
         let id = SubstrateBlockId::<B>::Hash(hash);
         self.client.header(id)
@@ -82,10 +91,11 @@ where
         let id = SubstrateBlockId::<B>::Hash(hash);
         let justification = match self
             .client
-            .justifications(id)?
-            .and_then(|j| j.into_justification(DAGESTAN_ENGINE_ID))
+            .justifications(&id)?
+            .as_ref()
+            .and_then(find_dagestan_justification)
         {
-            Some(justification) => justification,
+            Some((_, justification)) => justification,
             None => return Ok(None),
         };
 
@@ -111,9 +121,9 @@ where
     }
 }
 
-impl<B, BE> ChainStatus<Justification<B::Header>> for SubstrateChainStatus<B, BE>
+impl<B, C> ChainStatus<Justification<B::Header>> for SubstrateChainStatus<B, C>
 where
-    BE: Backend<B>,
+    C: HeaderBackend<B> + BlockBackend<B>,
     B: BlockT,
     B::Header: SubstrateHeader<Number = BlockNumber>,
 {
@@ -1,11 +1,11 @@
-use dagestan_primitives::{BlockNumber, DAGESTAN_ENGINE_ID};
+use dagestan_primitives::{BlockNumber, DagestanSessionApi};
 use sc_client_api::{Backend, Finalizer as SubstrateFinalizer, HeaderBackend, LockImportRun};
+use sp_api::ProvideRuntimeApi;
 use sp_blockchain::Error as ClientError;
 use sp_runtime::traits::{Block as BlockT, Header as SubstrateHeader};
 
 use crate::{
     finalization::{DagestanFinalizer, BlockFinalizer},
-    justification::versioned_encode,
     sync::{substrate::Justification, Finalizer},
 };
 
@@ -14,7 +14,8 @@ where
     B: BlockT,
     B::Header: SubstrateHeader<Number = BlockNumber>,
     BE: Backend<B>,
-    C: HeaderBackend<B> + LockImportRun<B, BE> + SubstrateFinalizer<B, BE>,
+    C: HeaderBackend<B> + LockImportRun<B, BE> + SubstrateFinalizer<B, BE> + ProvideRuntimeApi<B>,
+    C::Api: DagestanSessionApi<B>,
 {
     type Error = ClientError;
 
@@ -22,10 +23,7 @@ where
         self.finalize_block(
             justification.header.hash(),
             *justification.header.number(),
-            Some((
-                DAGESTAN_ENGINE_ID,
-                versioned_encode(justification.raw_justification),
-            )),
+            Some(justification.raw_justification),
         )
     }
 }
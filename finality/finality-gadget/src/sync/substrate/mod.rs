@@ -1,18 +1,29 @@
 use std::hash::{Hash, Hasher};
 
+use codec::{Decode, Encode};
 use dagestan_primitives::BlockNumber;
 use sp_runtime::traits::{CheckedSub, Header as SubstrateHeader, One};
 
 use crate::{
-    sync::{BlockIdentifier, Header, Justification as JustificationT},
+    sync::{
+        BlockIdentifier, Header, Justification as JustificationT,
+        UnverifiedJustification as UnverifiedJustificationT,
+    },
     DagestanJustification,
 };
 
 mod chain_status;
 mod finalizer;
+mod requester;
 mod status_notifier;
+mod verification;
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+pub use chain_status::SubstrateChainStatus;
+pub use requester::BlockRequester;
+pub use status_notifier::SubstrateChainStatusNotifier;
+pub use verification::SubstrateJustificationVerifier;
+
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
 pub struct BlockId<H: SubstrateHeader<Number = BlockNumber>> {
     hash: H::Hash,
     number: H::Number,
@@ -54,7 +65,7 @@ impl<H: SubstrateHeader<Number = BlockNumber>> Header for H {
 }
 
 /// A justification, including the related header.
-#[derive(Clone)]
+#[derive(Clone, Debug, Encode, Decode)]
 pub struct Justification<H: SubstrateHeader<Number = BlockNumber>> {
     header: H,
     raw_justification: DagestanJustification,
@@ -72,3 +83,11 @@ impl<H: SubstrateHeader<Number = BlockNumber>> JustificationT for Justification<
         self
     }
 }
+
+impl<H: SubstrateHeader<Number = BlockNumber>> UnverifiedJustificationT for Justification<H> {
+    type Header = H;
+
+    fn header(&self) -> &Self::Header {
+        &self.header
+    }
+}
@@ -0,0 +1,83 @@
+use std::marker::PhantomData;
+
+use dagestan_primitives::BlockNumber;
+use sp_runtime::traits::{Block as BlockT, Header as SubstrateHeader, NumberFor};
+
+use crate::{
+    network::RequestBlocks,
+    sync::{substrate::BlockId, Requester},
+};
+
+/// Requests justifications through the block sync, leaving everything else to substrate.
+pub struct BlockRequester<B, RB, R>
+where
+    B: BlockT,
+    B::Header: SubstrateHeader<Number = BlockNumber>,
+    RB: RequestBlocks<B>,
+    R: Requester<BlockId<B::Header>> + Clone + Send + Sync + 'static,
+{
+    substrate_requester: RB,
+    sync_requester: R,
+    _phantom: PhantomData<B>,
+}
+
+impl<B, RB, R> BlockRequester<B, RB, R>
+where
+    B: BlockT,
+    B::Header: SubstrateHeader<Number = BlockNumber>,
+    RB: RequestBlocks<B>,
+    R: Requester<BlockId<B::Header>> + Clone + Send + Sync + 'static,
+{
+    pub fn new(substrate_requester: RB, sync_requester: R) -> Self {
+        BlockRequester {
+            substrate_requester,
+            sync_requester,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<B, RB, R> Clone for BlockRequester<B, RB, R>
+where
+    B: BlockT,
+    B::Header: SubstrateHeader<Number = BlockNumber>,
+    RB: RequestBlocks<B>,
+    R: Requester<BlockId<B::Header>> + Clone + Send + Sync + 'static,
+{
+    fn clone(&self) -> Self {
+        BlockRequester {
+            substrate_requester: self.substrate_requester.clone(),
+            sync_requester: self.sync_requester.clone(),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<B, RB, R> RequestBlocks<B> for BlockRequester<B, RB, R>
+where
+    B: BlockT,
+    B::Header: SubstrateHeader<Number = BlockNumber>,
+    RB: RequestBlocks<B>,
+    R: Requester<BlockId<B::Header>> + Clone + Send + Sync + 'static,
+{
+    fn request_justification(&self, hash: &B::Hash, number: NumberFor<B>) {
+        self.sync_requester.request_justification(BlockId {
+            hash: *hash,
+            number,
+        })
+    }
+
+    fn request_stale_block(&self, hash: B::Hash, number: NumberFor<B>) {
+        self.substrate_requester.request_stale_block(hash, number)
+    }
+
+    /// Only clears the requests made through substrate, the block sync drops its requests by itself
+    /// once the blocks get finalized.
+    fn clear_justification_requests(&self) {
+        self.substrate_requester.clear_justification_requests()
+    }
+
+    fn is_major_syncing(&self) -> bool {
+        self.substrate_requester.is_major_syncing()
+    }
+}
@@ -0,0 +1,388 @@
+use std::{
+    fmt::{Display, Error as FmtError, Formatter},
+    marker::PhantomData,
+};
+
+use codec::Encode;
+use dagestan_primitives::{
+    BlockNumber, SessionAuthorityData, Version, BLS_AGGREGATION_FINALITY_VERSION,
+};
+use sp_runtime::{
+    traits::{Block as BlockT, Header as SubstrateHeader},
+    RuntimeAppPublic,
+};
+
+use crate::{
+    crypto::AuthorityVerifier,
+    session_id_from_block_num,
+    session_map::ReadOnlySessionMap,
+    sync::{substrate::Justification, Verifier},
+    DagestanJustification, SessionId, SessionPeriod,
+};
+
+/// What can go wrong when verifying a justification.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    UnknownSession(SessionId),
+    BadMultisignature(BlockNumber),
+    BadAggregateSignature(BlockNumber),
+    UnsupportedAggregateSignature(BlockNumber, Version),
+    BadEmergencySignature(BlockNumber),
+    MissingEmergencyKey(SessionId),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        use Error::*;
+        match self {
+            UnknownSession(session) => {
+                write!(f, "no authority data for session {:?}", session)
+            }
+            BadMultisignature(number) => {
+                write!(f, "bad multisignature for block #{}", number)
+            }
+            BadAggregateSignature(number) => {
+                write!(f, "bad aggregate signature for block #{}", number)
+            }
+            UnsupportedAggregateSignature(number, version) => {
+                write!(
+                    f,
+                    "aggregate signature for block #{} in a session with finality version {}",
+                    number, version
+                )
+            }
+            BadEmergencySignature(number) => {
+                write!(f, "bad emergency signature for block #{}", number)
+            }
+            MissingEmergencyKey(session) => {
+                write!(
+                    f,
+                    "emergency signature in session {:?}, which has no emergency finalizer",
+                    session
+                )
+            }
+        }
+    }
+}
+
+/// Verifies justifications against the authorities of the session of the justified block, as
+/// known to the session map.
+pub struct SubstrateJustificationVerifier<B>
+where
+    B: BlockT,
+    B::Header: SubstrateHeader<Number = BlockNumber>,
+{
+    session_map: ReadOnlySessionMap,
+    session_period: SessionPeriod,
+    _phantom: PhantomData<B>,
+}
+
+impl<B> SubstrateJustificationVerifier<B>
+where
+    B: BlockT,
+    B::Header: SubstrateHeader<Number = BlockNumber>,
+{
+    pub fn new(session_map: ReadOnlySessionMap, session_period: SessionPeriod) -> Self {
+        SubstrateJustificationVerifier {
+            session_map,
+            session_period,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+fn verify_with(
+    authority_data: SessionAuthorityData,
+    finality_version: Version,
+    session: SessionId,
+    number: BlockNumber,
+    justification: &DagestanJustification,
+    encoded_hash: &[u8],
+) -> Result<(), Error> {
+    use DagestanJustification::*;
+    let authority_verifier = AuthorityVerifier::new(authority_data.authorities().to_vec());
+    match justification {
+        CommitteeMultisignature(multisignature) => {
+            match authority_verifier.is_complete(encoded_hash, multisignature) {
+                true => Ok(()),
+                false => Err(Error::BadMultisignature(number)),
+            }
+        }
+        AggregateSignature(_) if finality_version < BLS_AGGREGATION_FINALITY_VERSION => Err(
+            Error::UnsupportedAggregateSignature(number, finality_version),
+        ),
+        AggregateSignature(aggregate) => {
+            let authority_verifier = match authority_data.bls_authorities() {
+                Some(bls_authorities) => {
+                    authority_verifier.with_bls_authorities(bls_authorities.clone())
+                }
+                None => authority_verifier,
+            };
+            match authority_verifier.is_complete_aggregate(encoded_hash, aggregate) {
+                true => Ok(()),
+                false => Err(Error::BadAggregateSignature(number)),
+            }
+        }
+        EmergencySignature(signature) => match authority_data.emergency_finalizer() {
+            Some(emergency_finalizer) => match emergency_finalizer.verify(&encoded_hash, signature)
+            {
+                true => Ok(()),
+                false => Err(Error::BadEmergencySignature(number)),
+            },
+            None => Err(Error::MissingEmergencyKey(session)),
+        },
+    }
+}
+
+impl<B> Verifier<Justification<B::Header>> for SubstrateJustificationVerifier<B>
+where
+    B: BlockT,
+    B::Header: SubstrateHeader<Number = BlockNumber>,
+{
+    type Error = Error;
+
+    fn verify(
+        &self,
+        justification: Justification<B::Header>,
+    ) -> Result<Justification<B::Header>, Self::Error> {
+        let number = *justification.header.number();
+        let session = session_id_from_block_num::<B>(number, self.session_period);
+        let (authority_data, finality_version) = self
+            .session_map
+            .try_get_with_finality_version(session)
+            .ok_or(Error::UnknownSession(session))?;
+        verify_with(
+            authority_data,
+            finality_version,
+            session,
+            number,
+            &justification.raw_justification,
+            &justification.header.hash().encode(),
+        )?;
+        Ok(justification)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use codec::Encode;
+    use dagestan_primitives::{
+        AuthorityId, AuthorityPair, BlockNumber, SessionAuthorityData, Version,
+        BLS_AGGREGATION_FINALITY_VERSION, DEFAULT_FINALITY_VERSION, KEY_TYPE,
+    };
+    use sp_core::Pair;
+    use sp_keystore::{testing::KeyStore, CryptoStore};
+    use sp_runtime::{
+        generic::{Block, Header},
+        traits::{BlakeTwo256, Header as SubstrateHeader},
+        OpaqueExtrinsic,
+    };
+
+    use super::{Error, SubstrateJustificationVerifier};
+    use crate::{
+        abft::{NodeCount, NodeIndex, SignatureSet},
+        crypto::{AggregateSignature, BlsPen, Signature},
+        session_map::SharedSessionMap,
+        sync::{substrate::Justification, Verifier},
+        DagestanJustification, SessionId, SessionPeriod,
+    };
+
+    type TestHeader = Header<BlockNumber, BlakeTwo256>;
+    type TestBlock = Block<TestHeader, OpaqueExtrinsic>;
+
+    const SESSION_PERIOD: SessionPeriod = SessionPeriod(10);
+
+    fn header(number: BlockNumber) -> TestHeader {
+        TestHeader::new(
+            number,
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+        )
+    }
+
+    fn pairs(seeds: &[u8]) -> Vec<AuthorityPair> {
+        seeds
+            .iter()
+            .map(|seed| AuthorityPair::from_seed(&[*seed; 32]))
+            .collect()
+    }
+
+    fn authority_data(
+        pairs: &[AuthorityPair],
+        emergency: Option<&AuthorityPair>,
+    ) -> SessionAuthorityData {
+        SessionAuthorityData::new(
+            pairs.iter().map(|pair| pair.public()).collect(),
+            emergency.map(|pair| pair.public()),
+        )
+    }
+
+    fn multisignature(pairs: &[AuthorityPair], header: TestHeader) -> Justification<TestHeader> {
+        let message = header.hash().encode();
+        let signatures = pairs.iter().enumerate().fold(
+            SignatureSet::with_size(NodeCount(pairs.len())),
+            |signatures, (index, pair)| {
+                signatures.add_signature(&Signature::from(pair.sign(&message)), NodeIndex(index))
+            },
+        );
+        Justification {
+            header,
+            raw_justification: DagestanJustification::CommitteeMultisignature(signatures),
+        }
+    }
+
+    fn emergency_signature(pair: &AuthorityPair, header: TestHeader) -> Justification<TestHeader> {
+        let signature = pair.sign(&header.hash().encode());
+        Justification {
+            header,
+            raw_justification: DagestanJustification::EmergencySignature(signature),
+        }
+    }
+
+    async fn versioned_verifier(
+        sessions: Vec<(SessionId, SessionAuthorityData, Version)>,
+    ) -> SubstrateJustificationVerifier<TestBlock> {
+        let mut session_map = SharedSessionMap::new();
+        for (session, authority_data, finality_version) in sessions {
+            session_map
+                .update(session, authority_data, finality_version)
+                .await;
+        }
+        SubstrateJustificationVerifier::new(session_map.read_only(), SESSION_PERIOD)
+    }
+
+    async fn verifier(
+        sessions: Vec<(SessionId, SessionAuthorityData)>,
+    ) -> SubstrateJustificationVerifier<TestBlock> {
+        versioned_verifier(
+            sessions
+                .into_iter()
+                .map(|(session, authority_data)| {
+                    (session, authority_data, DEFAULT_FINALITY_VERSION)
+                })
+                .collect(),
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn accepts_multisignature_of_session_authorities() {
+        let authorities = pairs(&[1, 2, 3, 4]);
+        let verifier = verifier(vec![(SessionId(1), authority_data(&authorities, None))]).await;
+
+        assert!(verifier
+            .verify(multisignature(&authorities, header(15)))
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn rejects_multisignature_of_other_authorities() {
+        let authorities = pairs(&[1, 2, 3, 4]);
+        let verifier = verifier(vec![
+            (SessionId(0), authority_data(&pairs(&[5, 6, 7, 8]), None)),
+            (SessionId(1), authority_data(&authorities, None)),
+        ])
+        .await;
+
+        assert_eq!(
+            verifier
+                .verify(multisignature(&authorities, header(5)))
+                .err(),
+            Some(Error::BadMultisignature(5))
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_justification_from_unknown_session() {
+        let authorities = pairs(&[1, 2, 3, 4]);
+        let verifier = verifier(vec![(SessionId(1), authority_data(&authorities, None))]).await;
+
+        assert_eq!(
+            verifier
+                .verify(multisignature(&authorities, header(25)))
+                .err(),
+            Some(Error::UnknownSession(SessionId(2)))
+        );
+    }
+
+    #[tokio::test]
+    async fn checks_emergency_signatures() {
+        let authorities = pairs(&[1, 2, 3, 4]);
+        let emergency = AuthorityPair::from_seed(&[9; 32]);
+        let verifier = verifier(vec![
+            (SessionId(0), authority_data(&authorities, None)),
+            (SessionId(1), authority_data(&authorities, Some(&emergency))),
+        ])
+        .await;
+
+        assert!(verifier
+            .verify(emergency_signature(&emergency, header(15)))
+            .is_ok());
+        assert_eq!(
+            verifier
+                .verify(emergency_signature(&authorities[0], header(15)))
+                .err(),
+            Some(Error::BadEmergencySignature(15))
+        );
+        assert_eq!(
+            verifier
+                .verify(emergency_signature(&emergency, header(5)))
+                .err(),
+            Some(Error::MissingEmergencyKey(SessionId(0)))
+        );
+    }
+
+    #[tokio::test]
+    async fn accepts_aggregates_only_from_bls_aggregation_finality_version() {
+        let keystore = Arc::new(KeyStore::new());
+        let mut authorities = Vec::new();
+        let mut pens = Vec::new();
+        for _ in 0..3 {
+            let authority_id =
+                AuthorityId::from(keystore.ed25519_generate_new(KEY_TYPE, None).await.unwrap());
+            pens.push(BlsPen::generate(keystore.clone()).await.unwrap());
+            authorities.push(authority_id);
+        }
+        let authority_data = SessionAuthorityData::new(authorities, None)
+            .with_bls_authorities(pens.iter().map(|pen| pen.public()).collect());
+        let verifier = versioned_verifier(vec![
+            (
+                SessionId(0),
+                authority_data.clone(),
+                BLS_AGGREGATION_FINALITY_VERSION - 1,
+            ),
+            (
+                SessionId(1),
+                authority_data,
+                BLS_AGGREGATION_FINALITY_VERSION,
+            ),
+        ])
+        .await;
+        let aggregate = |header: TestHeader| {
+            let message = header.hash().encode();
+            let aggregate = pens.iter().enumerate().skip(1).fold(
+                AggregateSignature::new(NodeCount(3), pens[0].sign(&message), NodeIndex(0)),
+                |aggregate, (index, pen)| {
+                    aggregate.add_signature(&pen.sign(&message), NodeIndex(index))
+                },
+            );
+            Justification {
+                header,
+                raw_justification: DagestanJustification::AggregateSignature(aggregate),
+            }
+        };
+
+        assert!(verifier.verify(aggregate(header(15))).is_ok());
+        assert_eq!(
+            verifier.verify(aggregate(header(5))).err(),
+            Some(Error::UnsupportedAggregateSignature(
+                5,
+                BLS_AGGREGATION_FINALITY_VERSION - 1
+            ))
+        );
+    }
+}
@@ -7,6 +7,7 @@ use futures::{
     },
     StreamExt,
 };
+use prometheus_endpoint::Registry;
 use sp_api::NumberFor;
 use sp_core::hash::H256;
 use sp_runtime::traits::Block as BlockT;
@@ -17,19 +18,30 @@ use substrate_test_runtime_client::{
 use tokio::time::timeout;
 
 use crate::{
-    data_io::{DagestanData, DagestanNetworkMessage, DataStore, DataStoreConfig, MAX_DATA_BRANCH_LEN},
+    data_io::{
+        BlockFetchMessage, DagestanData, DagestanNetworkMessage, DataStore, DataStoreConfig,
+        DEFAULT_MAX_DATA_BRANCH_LEN,
+    },
     network::{
-        data::{component::Network as ComponentNetwork, Network as DataNetwork},
-        Data, RequestBlocks,
+        data::{
+            component::{Network as ComponentNetwork, SimpleNetwork},
+            Network as DataNetwork,
+        },
+        Data, ImportBlocks, RequestBlocks,
     },
     session::{SessionBoundaries, SessionId, SessionPeriod},
     testing::{
         client_chain_builder::ClientChainBuilder,
         mocks::{dagestan_data_from_blocks, dagestan_data_from_headers},
     },
-    BlockHashNum, Recipient,
+    BlockHashNum, Metrics, NodeIndex, Recipient,
 };
 
+// The index of the node running the tested data store.
+const NODE_ID: NodeIndex = NodeIndex(0);
+// The creator of the units carrying the data of all the test messages.
+const CREATOR: NodeIndex = NodeIndex(1);
+
 #[derive(Clone)]
 struct TestBlockRequester<B: BlockT> {
     blocks: UnboundedSender<BlockHashNum<B>>,
@@ -75,12 +87,29 @@ impl<B: BlockT> RequestBlocks<B> for TestBlockRequester<B> {
     }
 }
 
+#[derive(Clone)]
+struct TestBlockImporter<B: BlockT> {
+    blocks: UnboundedSender<Vec<B>>,
+}
+
+impl<B: BlockT> ImportBlocks<B> for TestBlockImporter<B> {
+    fn import_blocks(&self, blocks: Vec<B>) {
+        self.blocks.unbounded_send(blocks).unwrap();
+    }
+}
+
 type TestData = Vec<DagestanData<Block>>;
 
 impl DagestanNetworkMessage<Block> for TestData {
+    type Payload = ();
+
     fn included_data(&self) -> Vec<DagestanData<Block>> {
         self.clone()
     }
+
+    fn data_with_creators(&self) -> Vec<(DagestanData<Block>, NodeIndex)> {
+        self.iter().map(|data| (data.clone(), CREATOR)).collect()
+    }
 }
 
 struct TestComponentNetwork<S, R> {
@@ -103,6 +132,10 @@ struct TestHandler {
     justification_requests_rx: UnboundedReceiver<BlockHashNum<Block>>,
     network_tx: UnboundedSender<TestData>,
     network: Box<dyn DataNetwork<TestData>>,
+    block_fetch_tx: UnboundedSender<BlockFetchMessage<Block>>,
+    block_fetch_rx: UnboundedReceiver<(BlockFetchMessage<Block>, Recipient)>,
+    imported_blocks_rx: UnboundedReceiver<Vec<Block>>,
+    registry: Registry,
 }
 
 impl TestHandler {
@@ -111,6 +144,20 @@ impl TestHandler {
         self.chain_builder.finalize_block(hash);
     }
 
+    /// The value of the metric, summed over all its labels.
+    fn metric_value(&self, name: &str) -> u64 {
+        self.registry
+            .gather()
+            .iter()
+            .filter(|family| family.get_name() == name)
+            .flat_map(|family| family.get_metric())
+            .map(|metric| match metric.has_gauge() {
+                true => metric.get_gauge().get_value() as u64,
+                false => metric.get_counter().get_value() as u64,
+            })
+            .sum()
+    }
+
     fn genesis_hash(&mut self) -> H256 {
         self.chain_builder.genesis_hash()
     }
@@ -160,6 +207,21 @@ impl TestHandler {
         self.justification_requests_rx.next().await.unwrap()
     }
 
+    /// Sends a block fetch message to Data Store
+    fn send_block_fetch_message(&self, message: BlockFetchMessage<Block>) {
+        self.block_fetch_tx.unbounded_send(message).unwrap()
+    }
+
+    /// Receive next block fetch message from Data Store, together with its recipient
+    async fn next_block_fetch_message(&mut self) -> (BlockFetchMessage<Block>, Recipient) {
+        self.block_fetch_rx.next().await.unwrap()
+    }
+
+    /// Receive next blocks passed by Data Store to the block importer
+    async fn next_imported_blocks(&mut self) -> Vec<Block> {
+        self.imported_blocks_rx.next().await.unwrap()
+    }
+
     async fn assert_no_message_out(&mut self, err_message: &'static str) {
         let res = timeout(TIMEOUT_FAIL, self.network.next()).await;
         assert!(res.is_err(), "{} (message out: {:?})", err_message, res);
@@ -179,12 +241,19 @@ fn prepare_data_store(
     let client = Arc::new(TestClientBuilder::new().build());
 
     let (block_requester, block_requests_rx, justification_requests_rx) = TestBlockRequester::new();
+    let (imported_blocks_tx, imported_blocks_rx) = mpsc::unbounded();
+    let block_importer = TestBlockImporter {
+        blocks: imported_blocks_tx,
+    };
     let (sender_tx, _sender_rx) = mpsc::unbounded();
     let (network_tx, network_rx) = mpsc::unbounded();
     let test_network = TestComponentNetwork {
         sender: sender_tx,
         receiver: network_rx,
     };
+    let (block_fetch_sender_tx, block_fetch_rx) = mpsc::unbounded();
+    let (block_fetch_tx, block_fetch_receiver_rx) = mpsc::unbounded();
+    let block_fetch_network = SimpleNetwork::new(block_fetch_receiver_rx, block_fetch_sender_tx);
     let data_store_config = DataStoreConfig {
         max_triggers_pending: 80_000,
         max_proposals_pending: 80_000,
@@ -192,7 +261,12 @@ fn prepare_data_store(
         available_proposals_cache_capacity: 8000,
         periodic_maintenance_interval: Duration::from_millis(20),
         request_block_after: Duration::from_millis(30),
+        metrics_report_interval: Duration::from_millis(20),
+        max_block_fetch_responses: 2,
+        block_fetch_response_period: Duration::from_secs(60),
     };
+    let registry = Registry::new();
+    let metrics = Metrics::register(&registry).expect("the registry is empty");
 
     let session_boundaries = if let Some(session_boundaries) = session_boundaries {
         session_boundaries
@@ -200,11 +274,15 @@ fn prepare_data_store(
         SessionBoundaries::new(SessionId(0), SessionPeriod(900))
     };
     let (mut data_store, network) = DataStore::new(
+        NODE_ID,
         session_boundaries,
         client.clone(),
         block_requester,
+        block_importer,
         data_store_config,
         test_network,
+        block_fetch_network,
+        Some(metrics),
     );
 
     let chain_builder = ClientChainBuilder::new(client, Arc::new(TestClientBuilder::new().build()));
@@ -221,6 +299,10 @@ fn prepare_data_store(
             justification_requests_rx,
             network_tx,
             network: Box::new(network),
+            block_fetch_tx,
+            block_fetch_rx,
+            imported_blocks_rx,
+            registry,
         },
     )
 }
@@ -256,10 +338,10 @@ where
 async fn correct_messages_go_through() {
     run_test(|mut test_handler| async move {
         let blocks = test_handler
-            .initialize_single_branch_and_import(MAX_DATA_BRANCH_LEN * 10)
+            .initialize_single_branch_and_import(DEFAULT_MAX_DATA_BRANCH_LEN * 10)
             .await;
 
-        for i in 1..=MAX_DATA_BRANCH_LEN {
+        for i in 1..=DEFAULT_MAX_DATA_BRANCH_LEN {
             let blocks_branch = blocks[0..i].to_vec();
             let test_data: TestData = vec![dagestan_data_from_blocks(blocks_branch)];
             test_handler.send_data(test_data.clone());
@@ -277,12 +359,12 @@ async fn correct_messages_go_through() {
 async fn too_long_branch_message_does_not_go_through() {
     run_test(|mut test_handler| async move {
         let blocks = test_handler
-            .initialize_single_branch_and_import(MAX_DATA_BRANCH_LEN * 10)
+            .initialize_single_branch_and_import(DEFAULT_MAX_DATA_BRANCH_LEN * 10)
             .await;
 
-        test_handler.finalize_block(&blocks[MAX_DATA_BRANCH_LEN + 2].hash());
+        test_handler.finalize_block(&blocks[DEFAULT_MAX_DATA_BRANCH_LEN + 2].hash());
 
-        let blocks_branch = blocks[0..(MAX_DATA_BRANCH_LEN + 1)].to_vec();
+        let blocks_branch = blocks[0..(DEFAULT_MAX_DATA_BRANCH_LEN + 1)].to_vec();
         let test_data: TestData = vec![dagestan_data_from_blocks(blocks_branch)];
         test_handler.send_data(test_data.clone());
         test_handler
@@ -301,15 +383,15 @@ async fn branch_not_within_session_boundaries_does_not_go_through() {
     let (task_handle, exit, mut test_handler) = prepare_data_store(Some(session_boundaries));
     let data_store_handle = tokio::spawn(task_handle);
     let blocks = test_handler
-        .initialize_single_branch_and_import(MAX_DATA_BRANCH_LEN * 10)
+        .initialize_single_branch_and_import(DEFAULT_MAX_DATA_BRANCH_LEN * 10)
         .await;
 
     for boundary_point in &[session_start, session_end] {
-        for l in 0..MAX_DATA_BRANCH_LEN {
-            for r in 0..MAX_DATA_BRANCH_LEN {
+        for l in 0..DEFAULT_MAX_DATA_BRANCH_LEN {
+            for r in 0..DEFAULT_MAX_DATA_BRANCH_LEN {
                 let left_end = boundary_point - l;
                 let right_end = boundary_point + r;
-                if right_end - left_end < MAX_DATA_BRANCH_LEN
+                if right_end - left_end < DEFAULT_MAX_DATA_BRANCH_LEN
                     && !(session_start <= left_end && right_end <= session_end)
                 {
                     // blocks start from block num 1, as genesis is block 0, we need to shift the indexing
@@ -324,18 +406,18 @@ async fn branch_not_within_session_boundaries_does_not_go_through() {
         .assert_no_message_out("Data Store let through a message not within session_boundaries")
         .await;
 
-    test_handler.finalize_block(&blocks[session_end + MAX_DATA_BRANCH_LEN].hash());
+    test_handler.finalize_block(&blocks[session_end + DEFAULT_MAX_DATA_BRANCH_LEN].hash());
 
     test_handler
         .assert_no_message_out("Data Store let through a message not within session_boundaries")
         .await;
 
     for boundary_point in &[session_start, session_end] {
-        for l in 0..MAX_DATA_BRANCH_LEN {
-            for r in 0..MAX_DATA_BRANCH_LEN {
+        for l in 0..DEFAULT_MAX_DATA_BRANCH_LEN {
+            for r in 0..DEFAULT_MAX_DATA_BRANCH_LEN {
                 let left_end = boundary_point - l;
                 let right_end = boundary_point + r;
-                if right_end - left_end < MAX_DATA_BRANCH_LEN
+                if right_end - left_end < DEFAULT_MAX_DATA_BRANCH_LEN
                     && session_start <= left_end
                     && right_end <= session_end
                 {
@@ -358,7 +440,7 @@ async fn branch_not_within_session_boundaries_does_not_go_through() {
 async fn branch_with_not_finalized_ancestor_correctly_handled() {
     run_test(|mut test_handler| async move {
         let blocks = test_handler
-            .initialize_single_branch_and_import(MAX_DATA_BRANCH_LEN * 10)
+            .initialize_single_branch_and_import(DEFAULT_MAX_DATA_BRANCH_LEN * 10)
             .await;
 
         let blocks_branch = blocks[1..2].to_vec();
@@ -380,7 +462,7 @@ async fn branch_with_not_finalized_ancestor_correctly_handled() {
 }
 
 fn send_proposals_of_each_len(blocks: Vec<Block>, test_handler: &mut TestHandler) {
-    for i in 1..=MAX_DATA_BRANCH_LEN {
+    for i in 1..=DEFAULT_MAX_DATA_BRANCH_LEN {
         let blocks_branch = blocks[0..i].to_vec();
         let test_data: TestData = vec![dagestan_data_from_blocks(blocks_branch)];
         test_handler.send_data(test_data.clone());
@@ -391,7 +473,7 @@ fn send_proposals_of_each_len(blocks: Vec<Block>, test_handler: &mut TestHandler
 async fn correct_messages_go_through_with_late_import() {
     run_test(|mut test_handler| async move {
         let blocks = test_handler
-            .initialize_single_branch(MAX_DATA_BRANCH_LEN * 10)
+            .initialize_single_branch(DEFAULT_MAX_DATA_BRANCH_LEN * 10)
             .await;
 
         send_proposals_of_each_len(blocks.clone(), &mut test_handler);
@@ -402,7 +484,7 @@ async fn correct_messages_go_through_with_late_import() {
 
         test_handler.import_branch(blocks).await;
 
-        for _ in 1..=MAX_DATA_BRANCH_LEN {
+        for _ in 1..=DEFAULT_MAX_DATA_BRANCH_LEN {
             test_handler
                 .assert_message_out("Did not receive message from Data Store")
                 .await;
@@ -414,9 +496,9 @@ async fn correct_messages_go_through_with_late_import() {
 #[tokio::test]
 async fn message_with_multiple_data_gets_through_when_it_should() {
     run_test(|mut test_handler| async move {
-        let max_height = MAX_DATA_BRANCH_LEN + 12;
+        let max_height = DEFAULT_MAX_DATA_BRANCH_LEN + 12;
         let blocks = test_handler
-            .initialize_single_branch_and_import(max_height + 10 * MAX_DATA_BRANCH_LEN)
+            .initialize_single_branch_and_import(max_height + 10 * DEFAULT_MAX_DATA_BRANCH_LEN)
             .await;
         let mut test_data = vec![];
         for i in 1..=max_height {
@@ -449,7 +531,7 @@ async fn message_with_multiple_data_gets_through_when_it_should() {
 async fn sends_block_request_on_missing_block() {
     run_test(|mut test_handler| async move {
         let blocks = test_handler
-            .initialize_single_branch(MAX_DATA_BRANCH_LEN * 10)
+            .initialize_single_branch(DEFAULT_MAX_DATA_BRANCH_LEN * 10)
             .await;
         let blocks_branch = blocks[0..1].to_vec();
         let test_data: TestData = vec![dagestan_data_from_blocks(blocks_branch)];
@@ -473,11 +555,239 @@ async fn sends_block_request_on_missing_block() {
     .await;
 }
 
+#[tokio::test]
+async fn fetches_missing_blocks_from_creator() {
+    run_test(|mut test_handler| async move {
+        let blocks = test_handler
+            .initialize_single_branch(DEFAULT_MAX_DATA_BRANCH_LEN * 10)
+            .await;
+        let blocks_branch = blocks[0..3].to_vec();
+        test_handler.send_data(vec![dagestan_data_from_blocks(blocks_branch.clone())]);
+
+        let (request, recipient) = timeout(TIMEOUT_SUCC, test_handler.next_block_fetch_message())
+            .await
+            .expect("Did not receive block fetch request from Data Store");
+        assert_eq!(recipient, Recipient::Node(CREATOR));
+        match request {
+            BlockFetchMessage::Request {
+                requester,
+                hash,
+                branch_len,
+            } => {
+                assert_eq!(requester, NODE_ID);
+                assert_eq!(hash, blocks[2].hash());
+                assert_eq!(branch_len, 3);
+            }
+            message => panic!("Expected a block fetch request, got {:?}", message),
+        }
+
+        test_handler.send_block_fetch_message(BlockFetchMessage::Response(blocks_branch.clone()));
+        let imported_blocks = timeout(TIMEOUT_SUCC, test_handler.next_imported_blocks())
+            .await
+            .expect("Data Store did not import the fetched blocks");
+        assert_eq!(imported_blocks, blocks_branch);
+
+        test_handler.import_branch(imported_blocks).await;
+        test_handler
+            .assert_message_out("Did not receive message from Data Store")
+            .await;
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn falls_back_to_block_request_when_fetch_fails() {
+    run_test(|mut test_handler| async move {
+        let blocks = test_handler
+            .initialize_single_branch(DEFAULT_MAX_DATA_BRANCH_LEN * 10)
+            .await;
+        test_handler.send_data(vec![dagestan_data_from_blocks(blocks[0..1].to_vec())]);
+
+        timeout(TIMEOUT_SUCC, test_handler.next_block_fetch_message())
+            .await
+            .expect("Did not receive block fetch request from Data Store");
+        let requested_block = timeout(TIMEOUT_SUCC, test_handler.next_block_request())
+            .await
+            .expect("Did not receive block request from Data Store");
+        assert_eq!(requested_block.hash, blocks[0].hash());
+
+        // The creator is asked only once.
+        let res = timeout(TIMEOUT_FAIL, test_handler.next_block_fetch_message()).await;
+        assert!(res.is_err(), "Data Store fetched the blocks again");
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn responds_to_block_fetch_requests() {
+    run_test(|mut test_handler| async move {
+        let blocks = test_handler.initialize_single_branch_and_import(5).await;
+        let requester = NodeIndex(2);
+        test_handler.send_block_fetch_message(BlockFetchMessage::Request {
+            requester,
+            hash: blocks[4].hash(),
+            branch_len: 3,
+        });
+
+        let (response, recipient) = timeout(TIMEOUT_SUCC, test_handler.next_block_fetch_message())
+            .await
+            .expect("Did not receive block fetch response from Data Store");
+        assert_eq!(recipient, Recipient::Node(requester));
+        match response {
+            BlockFetchMessage::Response(fetched_blocks) => {
+                let fetched_hashes: Vec<_> = fetched_blocks.iter().map(|b| b.hash()).collect();
+                let expected_hashes: Vec<_> = blocks[2..5].iter().map(|b| b.hash()).collect();
+                assert_eq!(fetched_hashes, expected_hashes);
+            }
+            message => panic!("Expected a block fetch response, got {:?}", message),
+        }
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn ignores_block_fetch_requests_naming_itself() {
+    run_test(|mut test_handler| async move {
+        let blocks = test_handler.initialize_single_branch_and_import(5).await;
+        test_handler.send_block_fetch_message(BlockFetchMessage::Request {
+            requester: NODE_ID,
+            hash: blocks[4].hash(),
+            branch_len: 3,
+        });
+
+        let res = timeout(TIMEOUT_FAIL, test_handler.next_block_fetch_message()).await;
+        assert!(
+            res.is_err(),
+            "Data Store responded to a block fetch request naming itself"
+        );
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn limits_block_fetch_responses_per_requester() {
+    run_test(|mut test_handler| async move {
+        let blocks = test_handler.initialize_single_branch_and_import(5).await;
+        let request = |requester| BlockFetchMessage::Request {
+            requester,
+            hash: blocks[4].hash(),
+            branch_len: 3,
+        };
+        for _ in 0..3 {
+            test_handler.send_block_fetch_message(request(NodeIndex(2)));
+        }
+        for _ in 0..2 {
+            let (_, recipient) = timeout(TIMEOUT_SUCC, test_handler.next_block_fetch_message())
+                .await
+                .expect("Did not receive block fetch response from Data Store");
+            assert_eq!(recipient, Recipient::Node(NodeIndex(2)));
+        }
+        let res = timeout(TIMEOUT_FAIL, test_handler.next_block_fetch_message()).await;
+        assert!(
+            res.is_err(),
+            "Data Store responded to more requests than the limit"
+        );
+
+        test_handler.send_block_fetch_message(request(NodeIndex(3)));
+        let (_, recipient) = timeout(TIMEOUT_SUCC, test_handler.next_block_fetch_message())
+            .await
+            .expect("Did not receive block fetch response from Data Store");
+        assert_eq!(recipient, Recipient::Node(NodeIndex(3)));
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn does_not_import_unrequested_blocks() {
+    run_test(|mut test_handler| async move {
+        let blocks = test_handler
+            .initialize_single_branch(DEFAULT_MAX_DATA_BRANCH_LEN * 10)
+            .await;
+        test_handler.send_block_fetch_message(BlockFetchMessage::Response(blocks[0..2].to_vec()));
+
+        let res = timeout(TIMEOUT_FAIL, test_handler.next_imported_blocks()).await;
+        assert!(
+            res.is_err(),
+            "Data Store imported blocks it did not request"
+        );
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn reports_pending_data_and_requests_to_metrics() {
+    run_test(|mut test_handler| async move {
+        let blocks = test_handler
+            .initialize_single_branch(DEFAULT_MAX_DATA_BRANCH_LEN * 10)
+            .await;
+        let test_data: TestData = vec![dagestan_data_from_blocks(blocks[0..1].to_vec())];
+        test_handler.send_data(test_data);
+
+        timeout(TIMEOUT_SUCC, test_handler.next_block_request())
+            .await
+            .expect("Did not receive block request from Data Store");
+        assert!(test_handler.metric_value("dagestan_data_store_block_requests_total") >= 1);
+        assert_eq!(
+            test_handler.metric_value("dagestan_data_store_pending_messages"),
+            1
+        );
+        assert_eq!(
+            test_handler.metric_value("dagestan_data_store_pending_proposals"),
+            1
+        );
+
+        test_handler.import_branch(blocks).await;
+        test_handler
+            .assert_message_out("Did not receive message from Data Store")
+            .await;
+        // Wait for the next report.
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(
+            test_handler.metric_value("dagestan_data_store_pending_messages"),
+            0
+        );
+        assert_eq!(
+            test_handler.metric_value("dagestan_data_store_pending_proposals"),
+            0
+        );
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn resets_gauges_on_exit() {
+    let (task_handle, exit, mut test_handler) = prepare_data_store(None);
+    let data_store_handle = tokio::spawn(task_handle);
+
+    let blocks = test_handler
+        .initialize_single_branch(DEFAULT_MAX_DATA_BRANCH_LEN * 10)
+        .await;
+    let test_data: TestData = vec![dagestan_data_from_blocks(blocks[0..1].to_vec())];
+    test_handler.send_data(test_data);
+    timeout(TIMEOUT_SUCC, test_handler.next_block_request())
+        .await
+        .expect("Did not receive block request from Data Store");
+    assert_eq!(
+        test_handler.metric_value("dagestan_data_store_pending_messages"),
+        1
+    );
+
+    exit.send(()).unwrap();
+    data_store_handle.await.unwrap();
+    for gauge in [
+        "dagestan_data_store_pending_proposals",
+        "dagestan_data_store_pending_messages",
+        "dagestan_data_store_cached_available_proposals",
+    ] {
+        assert_eq!(test_handler.metric_value(gauge), 0);
+    }
+}
+
 #[tokio::test]
 async fn sends_justification_request_when_not_finalized() {
     run_test(|mut test_handler| async move {
         let blocks = test_handler
-            .initialize_single_branch(MAX_DATA_BRANCH_LEN * 10)
+            .initialize_single_branch(DEFAULT_MAX_DATA_BRANCH_LEN * 10)
             .await;
         test_handler.import_branch(blocks.clone()).await;
 
@@ -509,7 +819,7 @@ async fn sends_justification_request_when_not_finalized() {
 async fn does_not_send_requests_when_no_block_missing() {
     run_test(|mut test_handler| async move {
         let blocks = test_handler
-            .initialize_single_branch_and_import(MAX_DATA_BRANCH_LEN * 10)
+            .initialize_single_branch_and_import(DEFAULT_MAX_DATA_BRANCH_LEN * 10)
             .await;
 
         send_proposals_of_each_len(blocks, &mut test_handler);
@@ -528,10 +838,10 @@ async fn does_not_send_requests_when_no_block_missing() {
 async fn message_with_genesis_block_does_not_get_through() {
     run_test(|mut test_handler| async move {
         let _ = test_handler
-            .initialize_single_branch_and_import(MAX_DATA_BRANCH_LEN * 10)
+            .initialize_single_branch_and_import(DEFAULT_MAX_DATA_BRANCH_LEN * 10)
             .await;
 
-        for i in 1..MAX_DATA_BRANCH_LEN {
+        for i in 1..DEFAULT_MAX_DATA_BRANCH_LEN {
             let test_data: TestData = vec![dagestan_data_from_headers(
                 (0..i)
                     .into_iter()
@@ -552,12 +862,12 @@ async fn message_with_genesis_block_does_not_get_through() {
 async fn unimported_hopeless_forks_go_through() {
     run_test(|mut test_handler| async move {
         let blocks = test_handler
-            .initialize_single_branch(MAX_DATA_BRANCH_LEN * 10)
+            .initialize_single_branch(DEFAULT_MAX_DATA_BRANCH_LEN * 10)
             .await;
 
-        let forking_block = &blocks[MAX_DATA_BRANCH_LEN + 2];
+        let forking_block = &blocks[DEFAULT_MAX_DATA_BRANCH_LEN + 2];
         let fork = test_handler
-            .build_branch_above(&forking_block.hash(), MAX_DATA_BRANCH_LEN * 10)
+            .build_branch_above(&forking_block.hash(), DEFAULT_MAX_DATA_BRANCH_LEN * 10)
             .await;
 
         send_proposals_of_each_len(fork.clone(), &mut test_handler);
@@ -568,7 +878,7 @@ async fn unimported_hopeless_forks_go_through() {
 
         test_handler.import_branch(blocks.clone()).await;
 
-        test_handler.finalize_block(&blocks[MAX_DATA_BRANCH_LEN + 2].hash());
+        test_handler.finalize_block(&blocks[DEFAULT_MAX_DATA_BRANCH_LEN + 2].hash());
 
         test_handler
         .assert_no_message_out(
@@ -576,9 +886,9 @@ async fn unimported_hopeless_forks_go_through() {
         )
         .await;
 
-        test_handler.finalize_block(&blocks[MAX_DATA_BRANCH_LEN + 3].hash());
+        test_handler.finalize_block(&blocks[DEFAULT_MAX_DATA_BRANCH_LEN + 3].hash());
 
-        for _ in 1..=MAX_DATA_BRANCH_LEN {
+        for _ in 1..=DEFAULT_MAX_DATA_BRANCH_LEN {
             test_handler
                 .assert_message_out("Did not receive message from Data Store")
                 .await;
@@ -591,12 +901,12 @@ async fn unimported_hopeless_forks_go_through() {
 async fn imported_hopeless_forks_go_through() {
     run_test(|mut test_handler| async move {
         let blocks = test_handler
-            .initialize_single_branch(10 * MAX_DATA_BRANCH_LEN)
+            .initialize_single_branch(10 * DEFAULT_MAX_DATA_BRANCH_LEN)
             .await;
 
-        let forking_block = &blocks[MAX_DATA_BRANCH_LEN + 2];
+        let forking_block = &blocks[DEFAULT_MAX_DATA_BRANCH_LEN + 2];
         let fork = test_handler
-            .build_branch_above(&forking_block.hash(), MAX_DATA_BRANCH_LEN * 10)
+            .build_branch_above(&forking_block.hash(), DEFAULT_MAX_DATA_BRANCH_LEN * 10)
             .await;
 
         test_handler.import_branch(blocks.clone()).await;
@@ -610,7 +920,7 @@ async fn imported_hopeless_forks_go_through() {
             )
             .await;
 
-        test_handler.finalize_block(&blocks[MAX_DATA_BRANCH_LEN + 1].hash());
+        test_handler.finalize_block(&blocks[DEFAULT_MAX_DATA_BRANCH_LEN + 1].hash());
 
         test_handler
             .assert_no_message_out(
@@ -618,9 +928,9 @@ async fn imported_hopeless_forks_go_through() {
             )
             .await;
 
-        test_handler.finalize_block(&blocks[MAX_DATA_BRANCH_LEN * 2 + 1].hash());
+        test_handler.finalize_block(&blocks[DEFAULT_MAX_DATA_BRANCH_LEN * 2 + 1].hash());
 
-        for _ in 1..=MAX_DATA_BRANCH_LEN {
+        for _ in 1..=DEFAULT_MAX_DATA_BRANCH_LEN {
             test_handler
                 .assert_message_out("Did not receive message from Data Store")
                 .await;
@@ -633,12 +943,12 @@ async fn imported_hopeless_forks_go_through() {
 async fn hopeless_fork_at_the_boundary_goes_through() {
     run_test(|mut test_handler| async move {
         let blocks = test_handler
-            .initialize_single_branch(10 * MAX_DATA_BRANCH_LEN)
+            .initialize_single_branch(10 * DEFAULT_MAX_DATA_BRANCH_LEN)
             .await;
-        let fork_num = MAX_DATA_BRANCH_LEN + 2;
+        let fork_num = DEFAULT_MAX_DATA_BRANCH_LEN + 2;
         let forking_block = &blocks[fork_num];
         let fork = test_handler
-            .build_branch_above(&forking_block.hash(), MAX_DATA_BRANCH_LEN * 10)
+            .build_branch_above(&forking_block.hash(), DEFAULT_MAX_DATA_BRANCH_LEN * 10)
             .await;
 
         test_handler.import_branch(blocks.clone()).await;
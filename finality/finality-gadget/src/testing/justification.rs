@@ -11,6 +11,7 @@ use AcceptancePolicy::*;
 
 use crate::{
     justification::{DagestanJustification, JustificationHandler, JustificationHandlerConfig},
+    rpc::RpcLink,
     testing::mocks::{
         create_block, AcceptancePolicy, Backend, JustificationRequestSchedulerImpl,
         MockedBlockFinalizer, MockedBlockRequester, SessionInfoProviderImpl, TBlock,
@@ -52,14 +53,18 @@ fn create_justification_notification_for(block: TBlock) -> JustificationNotifica
 
 fn run_justification_handler(
     justification_handler: TJustHandler,
-) -> (JoinHandle<()>, Sender, Sender) {
+) -> (JoinHandle<()>, Sender, Sender, Sender) {
     let (auth_just_tx, auth_just_rx) = unbounded();
     let (imp_just_tx, imp_just_rx) = unbounded();
+    let (rpc_just_tx, rpc_just_rx) = unbounded();
 
-    let handle =
-        tokio::spawn(async move { justification_handler.run(auth_just_rx, imp_just_rx).await });
+    let handle = tokio::spawn(async move {
+        justification_handler
+            .run(auth_just_rx, imp_just_rx, rpc_just_rx)
+            .await
+    });
 
-    (handle, auth_just_tx, imp_just_tx)
+    (handle, auth_just_tx, imp_just_tx, rpc_just_tx)
 }
 
 fn prepare_env(
@@ -82,6 +87,7 @@ fn prepare_env(
         justification_request_scheduler.clone(),
         None,
         config,
+        RpcLink::new(),
     );
 
     (
@@ -96,7 +102,7 @@ fn prepare_env(
 #[tokio::test(flavor = "multi_thread")]
 async fn panics_and_stops_when_authority_channel_is_closed() {
     let justification_handler = prepare_env(1u64, AlwaysReject, AlwaysReject).0;
-    let (handle, auth_just_tx, _) = run_justification_handler(justification_handler);
+    let (handle, auth_just_tx, _, _) = run_justification_handler(justification_handler);
     auth_just_tx.close_channel();
 
     let handle = async move { handle.await.unwrap_err() };
@@ -109,7 +115,7 @@ async fn panics_and_stops_when_authority_channel_is_closed() {
 #[tokio::test(flavor = "multi_thread")]
 async fn panics_and_stops_when_import_channel_is_closed() {
     let justification_handler = prepare_env(1u64, AlwaysReject, AlwaysReject).0;
-    let (handle, _, imp_just_tx) = run_justification_handler(justification_handler);
+    let (handle, _, imp_just_tx, _) = run_justification_handler(justification_handler);
     imp_just_tx.close_channel();
 
     let handle = async move { handle.await.unwrap_err() };
@@ -133,7 +139,8 @@ where
 {
     let (justification_handler, backend, requester, finalizer, justification_request_scheduler) =
         env;
-    let (handle_run, auth_just_tx, imp_just_tx) = run_justification_handler(justification_handler);
+    let (handle_run, auth_just_tx, imp_just_tx, rpc_just_tx) =
+        run_justification_handler(justification_handler);
     scenario(
         auth_just_tx.clone(),
         imp_just_tx.clone(),
@@ -145,6 +152,7 @@ where
     .await;
     auth_just_tx.close_channel();
     imp_just_tx.close_channel();
+    rpc_just_tx.close_channel();
     let _ = timeout(Duration::from_millis(10), handle_run).await;
 }
 
@@ -196,6 +204,21 @@ async fn leads_to_finalization_when_appropriate_justification_comes() {
     .await;
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn leads_to_finalization_when_justification_comes_from_rpc() {
+    let (justification_handler, backend, _, finalizer, justification_request_scheduler) =
+        prepare_env(FINALIZED_HEIGHT, AlwaysAccept, AlwaysReject);
+    let (handle, _auth_just_tx, _imp_just_tx, rpc_just_tx) =
+        run_justification_handler(justification_handler);
+
+    let block = backend.next_block_to_finalize();
+    let message = create_justification_notification_for(block.clone());
+    rpc_just_tx.unbounded_send(message).unwrap();
+    expect_finalized(&finalizer, &justification_request_scheduler, block).await;
+
+    handle.abort();
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn waits_for_verifier_before_finalizing() {
     let verification_policy = FromSequence(RefCell::new(VecDeque::from(vec![false, false, true])));
@@ -265,7 +288,7 @@ async fn ignores_notifications_from_future_session() {
 }
 
 #[tokio::test(flavor = "multi_thread")]
-async fn does_not_buffer_notifications_from_future_session() {
+async fn does_not_finalize_notifications_from_future_session_early() {
     run_test(
         prepare_env((SESSION_PERIOD.0 - 2) as u64, AlwaysAccept, AlwaysReject),
         |_, imp_just_tx, backend, _, finalizer, justification_request_scheduler| async move {
@@ -1,12 +1,13 @@
 use sp_blockchain::Error;
-use sp_runtime::{traits::Block, Justification};
+use sp_runtime::traits::Block;
 
 use crate::{
     finalization::BlockFinalizer,
+    justification::DagestanJustification,
     testing::mocks::{single_action_mock::SingleActionMock, TBlock, THash, TNumber},
 };
 
-type CallArgs = (THash, TNumber, Option<Justification>);
+type CallArgs = (THash, TNumber, Option<DagestanJustification>);
 
 #[derive(Clone)]
 pub(crate) struct MockedBlockFinalizer {
@@ -38,7 +39,7 @@ impl BlockFinalizer<TBlock> for MockedBlockFinalizer {
         &self,
         hash: THash,
         block_number: TNumber,
-        justification: Option<Justification>,
+        justification: Option<DagestanJustification>,
     ) -> Result<(), Error> {
         self.mock.invoke_with((hash, block_number, justification));
         Ok(())
@@ -4,7 +4,9 @@ use std::{
 };
 
 use crate::{
-    justification::{JustificationHandlerConfig, JustificationRequestScheduler, SchedulerActions},
+    justification::{
+        JustificationHandlerConfig, JustificationRequestScheduler, RequestTries, SchedulerActions,
+    },
     testing::mocks::{single_action_mock::SingleActionMock, AcceptancePolicy},
 };
 
@@ -38,7 +40,7 @@ impl JustificationRequestSchedulerImpl {
 }
 
 impl JustificationRequestScheduler for JustificationRequestSchedulerImpl {
-    fn schedule_action(&mut self) -> SchedulerActions {
+    fn schedule_action(&mut self, _tries: RequestTries) -> SchedulerActions {
         if self.acceptance_policy.lock().unwrap().accepts() {
             SchedulerActions::Request
         } else {
@@ -17,5 +17,6 @@ pub fn dagestan_data_from_blocks(blocks: Vec<Block>) -> DagestanData<Block> {
 pub fn dagestan_data_from_headers(headers: Vec<Header>) -> DagestanData<Block> {
     DagestanData {
         head_proposal: unvalidated_proposal_from_headers(headers),
+        payload: (),
     }
 }
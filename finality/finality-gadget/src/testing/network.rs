@@ -96,7 +96,7 @@ async fn prepare_one_session_test_data() -> TestData {
     let validator_network = MockCliqueNetwork::new();
 
     let (gossip_service, gossip_network, _) =
-        GossipService::new(network.clone(), task_manager.spawn_handle());
+        GossipService::<_, _, MockData>::new(network.clone(), task_manager.spawn_handle());
 
     let (connection_manager_service, session_manager) = ConnectionManager::new(
         authorities[0].address(),
@@ -0,0 +1,528 @@
+//! The registry of the versions of the consensus run in sessions. Every entry bundles a version of
+//! AlephBFT with a version of the aggregator and gives the data they exchange a version number,
+//! under which it is sent over the network. Which entry runs a session is decided by the finality
+//! version of the session, see `NodeSessionManagerImpl::spawn_subtasks`. Only the versions marked
+//! `payload: configured` order the payloads configured for the node, the others order no payloads,
+//! so payloads are never sent as network data of a version that predates them.
+
+use std::fmt::{Debug, Display, Error as FmtError, Formatter};
+
+use codec::{Decode, Encode, Output};
+use sp_blockchain::HeaderBackend;
+use sp_runtime::traits::Block;
+
+use crate::{
+    abft::{self, NetworkWrapper},
+    aggregation::{self, Aggregator},
+    compatibility::{Version, Versioned},
+    data_io::{
+        BlockFetchMessage, DagestanNetworkMessage, DataProvider, NoPayload, OrderedDataInterpreter,
+        Payload, PayloadProvider, PayloadSink,
+    },
+    network::{
+        data::{split::Split, Network},
+        Data,
+    },
+    party::{
+        backup::{ABFTBackup, BackupUnit},
+        manager::{SubtaskCommon, Task},
+    },
+    Keychain, Metrics, NodeIndex, SessionId, UnitCreationDelay,
+};
+
+/// A version of the consensus, consisting of AlephBFT ordering the data and the aggregator
+/// multisigning the finalized blocks. `P` is the payload configured for the node.
+pub trait ConsensusVersion<B: Block, P: Payload>: Versioned + 'static {
+    /// The payload ordered by AlephBFT, either the configured one or none.
+    type Payload: Payload;
+    /// The data exchanged by AlephBFT.
+    type NetworkData: DagestanNetworkMessage<B, Payload = Self::Payload> + Data + Debug;
+    /// The data exchanged by the aggregator.
+    type RmcNetworkData: Data;
+    type Config;
+    /// The keychain the aggregator multisigns block hashes with.
+    type AggregatorKeychain: Send + Sync + 'static;
+
+    fn create_config(
+        n_members: usize,
+        node_id: NodeIndex,
+        session_id: SessionId,
+        unit_creation_delay: UnitCreationDelay,
+    ) -> Self::Config;
+
+    /// The payload provider and sink of a session, made of the ones configured for the node.
+    /// Versions ordering no payloads ignore them.
+    #[allow(clippy::type_complexity)]
+    fn payload_io<PP, PS>(
+        payload_provider: PP,
+        payload_sink: PS,
+    ) -> (
+        Box<dyn PayloadProvider<Payload = Self::Payload>>,
+        Box<dyn PayloadSink<Payload = Self::Payload>>,
+    )
+    where
+        PP: PayloadProvider<Payload = P>,
+        PS: PayloadSink<Payload = P>;
+
+    fn run_member<C, N>(
+        subtask_common: SubtaskCommon,
+        multikeychain: Keychain,
+        config: Self::Config,
+        network: N,
+        data_provider: DataProvider<B, Self::Payload>,
+        ordered_data_interpreter: OrderedDataInterpreter<B, C, Self::Payload>,
+        backup: ABFTBackup,
+    ) -> Task
+    where
+        C: HeaderBackend<B> + Send + 'static,
+        N: Network<Self::NetworkData> + 'static;
+
+    fn aggregator<N>(
+        multikeychain: &Self::AggregatorKeychain,
+        rmc_network: N,
+        metrics: Option<Metrics<B::Hash>>,
+    ) -> Aggregator<'_, B>
+    where
+        N: Network<Self::RmcNetworkData> + 'static;
+
+    /// Decodes a single unit saved in the backup by the AlephBFT of this version.
+    fn decode_backup_unit(input: &mut &[u8]) -> Result<BackupUnit, codec::Error>;
+}
+
+/// The version under which block fetch messages are sent, the same in sessions of every consensus
+/// version. It is far from the consensus versions, so that they never collide.
+const BLOCK_FETCH_VERSION: Version = Version(u16::MAX);
+
+/// The data exchanged in a session run with the consensus version `V`, i.e. the data of AlephBFT,
+/// of the aggregator and the block fetch messages.
+pub type SessionNetworkData<B, P, V> = Split<
+    Split<
+        <V as ConsensusVersion<B, P>>::NetworkData,
+        <V as ConsensusVersion<B, P>>::RmcNetworkData,
+    >,
+    BlockFetchMessage<B>,
+>;
+
+/// Decodes a single unit saved in a backup, see `ConsensusVersion::decode_backup_unit`.
+pub type BackupUnitDecoder = fn(&mut &[u8]) -> Result<BackupUnit, codec::Error>;
+
+/// The payload ordered by the versions carrying the payload `P` configured for the node. It is
+/// encoded just like `P`, wrapping it only keeps the network data of these versions a different
+/// type than the one of the versions ordering no payloads.
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq, Decode, Encode)]
+pub struct ConfiguredPayload<P>(pub P);
+
+struct ConfiguredPayloadProvider<PP>(PP);
+
+impl<PP: PayloadProvider> PayloadProvider for ConfiguredPayloadProvider<PP> {
+    type Payload = ConfiguredPayload<PP::Payload>;
+
+    fn next_payload(&mut self) -> Self::Payload {
+        ConfiguredPayload(self.0.next_payload())
+    }
+}
+
+struct ConfiguredPayloadSink<PS>(PS);
+
+impl<PS: PayloadSink> PayloadSink for ConfiguredPayloadSink<PS> {
+    type Payload = ConfiguredPayload<PS::Payload>;
+
+    fn payload_ordered(&mut self, payload: Self::Payload) {
+        self.0.payload_ordered(payload.0)
+    }
+}
+
+/// The payload ordered by an entry of `consensus_versions`, given the one configured for the node.
+macro_rules! payload_type {
+    (none, $payload:ty) => {
+        ()
+    };
+    (configured, $payload:ty) => {
+        ConfiguredPayload<$payload>
+    };
+}
+
+/// The payload provider and sink of an entry of `consensus_versions`, see
+/// `ConsensusVersion::payload_io`.
+macro_rules! payload_io {
+    (none, $payload_provider:expr, $payload_sink:expr) => {{
+        let _ = ($payload_provider, $payload_sink);
+        (Box::new(NoPayload), Box::new(NoPayload))
+    }};
+    (configured, $payload_provider:expr, $payload_sink:expr) => {
+        (
+            Box::new(ConfiguredPayloadProvider($payload_provider)),
+            Box::new(ConfiguredPayloadSink($payload_sink)),
+        )
+    };
+}
+
+/// Generates an entry implementing `ConsensusVersion` for every given version, and
+/// `VersionedNetworkData` containing the data of all of them, as well as the block fetch
+/// messages.
+macro_rules! consensus_versions {
+    ($(
+        $(#[$doc:meta])*
+        $variant:ident = $version:literal {
+            abft: $abft:ident,
+            aggregator: $aggregator:ident,
+            payload: $payload:ident
+        }
+    ),* $(,)?) => {
+        $(
+            $(#[$doc])*
+            pub struct $variant;
+
+            impl Versioned for $variant {
+                const VERSION: Version = Version($version);
+            }
+
+            impl<B: Block, P: Payload> ConsensusVersion<B, P> for $variant {
+                type Payload = payload_type!($payload, P);
+                type NetworkData = abft::$abft::NetworkData<B, Self::Payload>;
+                type RmcNetworkData = aggregation::$aggregator::RmcNetworkData<B>;
+                type Config = abft::$abft::Config;
+                type AggregatorKeychain = aggregation::$aggregator::Keychain;
+
+                fn create_config(
+                    n_members: usize,
+                    node_id: NodeIndex,
+                    session_id: SessionId,
+                    unit_creation_delay: UnitCreationDelay,
+                ) -> Self::Config {
+                    abft::$abft::create_dagestan_config(
+                        n_members,
+                        node_id,
+                        session_id,
+                        unit_creation_delay,
+                    )
+                }
+
+                fn payload_io<PP, PS>(
+                    payload_provider: PP,
+                    payload_sink: PS,
+                ) -> (
+                    Box<dyn PayloadProvider<Payload = Self::Payload>>,
+                    Box<dyn PayloadSink<Payload = Self::Payload>>,
+                )
+                where
+                    PP: PayloadProvider<Payload = P>,
+                    PS: PayloadSink<Payload = P>,
+                {
+                    payload_io!($payload, payload_provider, payload_sink)
+                }
+
+                fn run_member<C, N>(
+                    subtask_common: SubtaskCommon,
+                    multikeychain: Keychain,
+                    config: Self::Config,
+                    network: N,
+                    data_provider: DataProvider<B, Self::Payload>,
+                    ordered_data_interpreter: OrderedDataInterpreter<B, C, Self::Payload>,
+                    backup: ABFTBackup,
+                ) -> Task
+                where
+                    C: HeaderBackend<B> + Send + 'static,
+                    N: Network<Self::NetworkData> + 'static,
+                {
+                    abft::$abft::run_member(
+                        subtask_common,
+                        multikeychain,
+                        config,
+                        NetworkWrapper::from(network),
+                        data_provider,
+                        ordered_data_interpreter,
+                        backup,
+                    )
+                }
+
+                fn aggregator<N>(
+                    multikeychain: &Self::AggregatorKeychain,
+                    rmc_network: N,
+                    metrics: Option<Metrics<B::Hash>>,
+                ) -> Aggregator<'_, B>
+                where
+                    N: Network<Self::RmcNetworkData> + 'static,
+                {
+                    aggregation::$aggregator::new(multikeychain, rmc_network, metrics)
+                }
+
+                fn decode_backup_unit(input: &mut &[u8]) -> Result<BackupUnit, codec::Error> {
+                    abft::$abft::decode_backup_unit::<B, Self::Payload>(input)
+                }
+            }
+
+            impl<B: Block, P: Payload> TryFrom<VersionedNetworkData<B, P>>
+                for Split<
+                    Split<
+                        abft::$abft::NetworkData<B, payload_type!($payload, P)>,
+                        aggregation::$aggregator::RmcNetworkData<B>,
+                    >,
+                    BlockFetchMessage<B>,
+                >
+            {
+                type Error = VersionedTryFromError;
+
+                fn try_from(value: VersionedNetworkData<B, P>) -> Result<Self, Self::Error> {
+                    match value {
+                        VersionedNetworkData::$variant(data) => Ok(Split::Left(data)),
+                        VersionedNetworkData::BlockFetch(message) => Ok(Split::Right(message)),
+                        value => Err(VersionedTryFromError {
+                            expected: $variant::VERSION,
+                            got: value.version(),
+                        }),
+                    }
+                }
+            }
+
+            impl<B: Block, P: Payload>
+                From<
+                    Split<
+                        Split<
+                            abft::$abft::NetworkData<B, payload_type!($payload, P)>,
+                            aggregation::$aggregator::RmcNetworkData<B>,
+                        >,
+                        BlockFetchMessage<B>,
+                    >,
+                > for VersionedNetworkData<B, P>
+            {
+                fn from(
+                    data: Split<
+                        Split<
+                            abft::$abft::NetworkData<B, payload_type!($payload, P)>,
+                            aggregation::$aggregator::RmcNetworkData<B>,
+                        >,
+                        BlockFetchMessage<B>,
+                    >,
+                ) -> Self {
+                    match data {
+                        Split::Left(data) => VersionedNetworkData::$variant(data),
+                        Split::Right(message) => VersionedNetworkData::BlockFetch(message),
+                    }
+                }
+            }
+        )*
+
+        /// The main purpose of this data type is to enable a seamless transition between protocol
+        /// versions at the Network level. It provides an implementation of the Decode and Encode
+        /// traits (LE byte representation) by prepending byte representations of the data of every
+        /// protocol version with that version. `P` is the payload configured for the node.
+        ///
+        /// Block fetch messages do not depend on the protocol version, so they have a version of
+        /// their own. Nodes predating them fail to decode and drop them.
+        #[derive(Clone)]
+        pub enum VersionedNetworkData<B: Block, P: Payload = ()> {
+            $(
+                $variant(
+                    Split<
+                        abft::$abft::NetworkData<B, payload_type!($payload, P)>,
+                        aggregation::$aggregator::RmcNetworkData<B>,
+                    >,
+                ),
+            )*
+            BlockFetch(BlockFetchMessage<B>),
+        }
+
+        impl<B: Block, P: Payload> VersionedNetworkData<B, P> {
+            fn version(&self) -> Version {
+                match self {
+                    $(VersionedNetworkData::$variant(_) => $variant::VERSION,)*
+                    VersionedNetworkData::BlockFetch(_) => BLOCK_FETCH_VERSION,
+                }
+            }
+        }
+
+        /// Returns the decoder of units backed up by the AlephBFT of the given consensus version, run
+        /// by a node configured with the payload `P`.
+        pub fn backup_unit_decoder<B: Block, P: Payload>(
+            version: Version,
+        ) -> Option<BackupUnitDecoder> {
+            $(
+                if version == $variant::VERSION {
+                    return Some(<$variant as ConsensusVersion<B, P>>::decode_backup_unit);
+                }
+            )*
+            None
+        }
+
+        impl<B: Block, P: Payload> Decode for VersionedNetworkData<B, P> {
+            fn decode<I: codec::Input>(input: &mut I) -> Result<Self, codec::Error> {
+                let version = Version::decode(input)?;
+                $(
+                    if version == $variant::VERSION {
+                        return Ok(VersionedNetworkData::$variant(Decode::decode(input)?));
+                    }
+                )*
+                if version == BLOCK_FETCH_VERSION {
+                    return Ok(VersionedNetworkData::BlockFetch(Decode::decode(input)?));
+                }
+                Err("Invalid version while decoding VersionedNetworkData".into())
+            }
+        }
+
+        impl<B: Block, P: Payload> Encode for VersionedNetworkData<B, P> {
+            fn encode_to<T: Output + ?Sized>(&self, dest: &mut T) {
+                self.version().encode_to(dest);
+                match self {
+                    $(VersionedNetworkData::$variant(data) => data.encode_to(dest),)*
+                    VersionedNetworkData::BlockFetch(message) => message.encode_to(dest),
+                }
+            }
+
+            fn size_hint(&self) -> usize {
+                self.version().size_hint()
+                    + match self {
+                        $(VersionedNetworkData::$variant(data) => data.size_hint(),)*
+                        VersionedNetworkData::BlockFetch(message) => message.size_hint(),
+                    }
+            }
+        }
+    };
+}
+
+consensus_versions! {
+    /// AlephBFT 0.19, with the aggregator collecting ed25519 signatures. Runs sessions with the
+    /// default finality version.
+    V1 = 1 { abft: v0_19, aggregator: v0_19, payload: none },
+    /// AlephBFT 0.20, with the aggregator collecting ed25519 signatures. Runs sessions with later
+    /// finality versions, unless they aggregate BLS signatures or order payloads.
+    V2 = 2 { abft: v0_20, aggregator: v0_20, payload: none },
+    /// AlephBFT 0.20, with the aggregator aggregating BLS signatures. Runs sessions with finality
+    /// versions supporting BLS aggregation, as long as all the authorities have BLS keys, unless
+    /// they order payloads.
+    V3 = 3 { abft: v0_20, aggregator: v0_20_bls, payload: none },
+    /// AlephBFT 0.20 ordering payloads, with the aggregator collecting ed25519 signatures. Runs
+    /// sessions with finality versions supporting payloads, unless all the authorities have BLS
+    /// keys.
+    V4 = 4 { abft: v0_20, aggregator: v0_20, payload: configured },
+    /// AlephBFT 0.20 ordering payloads, with the aggregator aggregating BLS signatures. Runs
+    /// sessions with finality versions supporting payloads, as long as all the authorities have
+    /// BLS keys.
+    V5 = 5 { abft: v0_20, aggregator: v0_20_bls, payload: configured },
+}
+
+/// Returned when converting `VersionedNetworkData` into the data of a version it does not contain.
+#[derive(Debug, Clone)]
+pub struct VersionedTryFromError {
+    expected: Version,
+    got: Version,
+}
+
+impl Display for VersionedTryFromError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        write!(
+            f,
+            "expected network data of version {:?}, got {:?}",
+            self.expected, self.got
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use codec::{Decode, Encode};
+    use sp_core::H256;
+    use substrate_test_runtime_client::runtime::Block;
+
+    use super::{
+        ConfiguredPayload, ConsensusVersion, SessionNetworkData, VersionedNetworkData, V2, V4,
+    };
+    use crate::{
+        compatibility::Version,
+        data_io::{BlockFetchMessage, PayloadProvider, PayloadSink},
+        network::data::split::Split,
+        NodeIndex,
+    };
+
+    #[derive(Clone)]
+    struct FixedPayload(Vec<u8>);
+
+    impl PayloadProvider for FixedPayload {
+        type Payload = Vec<u8>;
+
+        fn next_payload(&mut self) -> Vec<u8> {
+            self.0.clone()
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct RecordedPayloads(Arc<Mutex<Vec<Vec<u8>>>>);
+
+    impl PayloadSink for RecordedPayloads {
+        type Payload = Vec<u8>;
+
+        fn payload_ordered(&mut self, payload: Vec<u8>) {
+            self.0.lock().unwrap().push(payload);
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        let encoded = Version(7).encode();
+        assert!(VersionedNetworkData::<Block>::decode(&mut &encoded[..]).is_err());
+    }
+
+    #[test]
+    fn block_fetch_messages_reach_sessions_of_any_version() {
+        let hash = H256::repeat_byte(7);
+        let data = VersionedNetworkData::<Block>::BlockFetch(BlockFetchMessage::Request {
+            requester: NodeIndex(3),
+            hash,
+            branch_len: 5,
+        });
+        let decoded = VersionedNetworkData::<Block>::decode(&mut &data.encode()[..])
+            .expect("block fetch messages should decode");
+
+        match SessionNetworkData::<Block, (), V2>::try_from(decoded)
+            .expect("block fetch messages should convert")
+        {
+            Split::Right(BlockFetchMessage::Request {
+                requester,
+                hash: requested_hash,
+                branch_len,
+            }) => {
+                assert_eq!(requester, NodeIndex(3));
+                assert_eq!(requested_hash, hash);
+                assert_eq!(branch_len, 5);
+            }
+            _ => panic!("expected a block fetch request"),
+        }
+    }
+
+    #[test]
+    fn versions_predating_payloads_order_none() {
+        let recorded = RecordedPayloads::default();
+        let (mut provider, mut sink) = <V2 as ConsensusVersion<Block, Vec<u8>>>::payload_io(
+            FixedPayload(vec![1, 2, 3]),
+            recorded.clone(),
+        );
+
+        assert!(provider.next_payload().encode().is_empty());
+        sink.payload_ordered(());
+        assert!(recorded.0.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn payload_versions_order_configured_payloads() {
+        let recorded = RecordedPayloads::default();
+        let (mut provider, mut sink) = <V4 as ConsensusVersion<Block, Vec<u8>>>::payload_io(
+            FixedPayload(vec![1, 2, 3]),
+            recorded.clone(),
+        );
+
+        assert_eq!(provider.next_payload(), ConfiguredPayload(vec![1, 2, 3]));
+        sink.payload_ordered(ConfiguredPayload(vec![4]));
+        assert_eq!(*recorded.0.lock().unwrap(), vec![vec![4]]);
+    }
+
+    #[test]
+    fn configured_payloads_are_encoded_as_the_payload() {
+        let payload = vec![1u8, 2, 3];
+        assert_eq!(
+            ConfiguredPayload(payload.clone()).encode(),
+            payload.encode()
+        );
+    }
+}
@@ -0,0 +1,163 @@
+//! Warp-sync finality proofs.
+//!
+//! A warp-sync proof is a chain of justifications of the last blocks of consecutive sessions.
+//! Every such block carries, in its state, the authority data of the next session, so each
+//! fragment of the proof contains a storage proof of that data, along with the finality version of
+//! the next session. Starting from a known committee,
+//! a node can verify all the committee handovers and jump to a recent finalized block without
+//! downloading any of the intermediate blocks.
+use std::fmt::{Display, Error as FmtError, Formatter};
+
+use sp_blockchain::Error as ClientError;
+use sp_core::hashing::twox_128;
+use sp_runtime::traits::{Block, NumberFor};
+
+use crate::justification::DecodeError;
+
+mod proof;
+mod provider;
+
+pub use proof::{WarpSyncFragment, WarpSyncProof, WarpSyncTarget, MAX_WARP_SYNC_PROOF_SIZE};
+pub use provider::FinalityProofProvider;
+
+const NEXT_AUTHORITIES: &[u8] = b"NextAuthorities";
+const QUEUED_EMERGENCY_FINALIZER: &[u8] = b"QueuedEmergencyFinalizer";
+const NEXT_BLS_AUTHORITIES: &[u8] = b"NextBlsAuthorities";
+const FINALITY_VERSION: &[u8] = b"FinalityVersion";
+const FINALITY_SCHEDULED_VERSION_CHANGE: &[u8] = b"FinalityScheduledVersionChange";
+
+/// Storage keys of the runtime companion pallet items that hold the authority data and the
+/// finality version of the next session.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuthorityDataStorageKeys {
+    next_authorities: Vec<u8>,
+    queued_emergency_finalizer: Vec<u8>,
+    next_bls_authorities: Vec<u8>,
+    finality_version: Vec<u8>,
+    finality_scheduled_version_change: Vec<u8>,
+}
+
+impl AuthorityDataStorageKeys {
+    /// Keys for the companion pallet registered in the runtime under `pallet_name`.
+    pub fn new(pallet_name: &str) -> Self {
+        let storage_value_key = |item: &[u8]| {
+            let mut key = twox_128(pallet_name.as_bytes()).to_vec();
+            key.extend_from_slice(&twox_128(item));
+            key
+        };
+        AuthorityDataStorageKeys {
+            next_authorities: storage_value_key(NEXT_AUTHORITIES),
+            queued_emergency_finalizer: storage_value_key(QUEUED_EMERGENCY_FINALIZER),
+            next_bls_authorities: storage_value_key(NEXT_BLS_AUTHORITIES),
+            finality_version: storage_value_key(FINALITY_VERSION),
+            finality_scheduled_version_change: storage_value_key(FINALITY_SCHEDULED_VERSION_CHANGE),
+        }
+    }
+
+    pub fn next_authorities(&self) -> &[u8] {
+        &self.next_authorities
+    }
+
+    pub fn queued_emergency_finalizer(&self) -> &[u8] {
+        &self.queued_emergency_finalizer
+    }
+
+    pub fn next_bls_authorities(&self) -> &[u8] {
+        &self.next_bls_authorities
+    }
+
+    pub fn finality_version(&self) -> &[u8] {
+        &self.finality_version
+    }
+
+    pub fn finality_scheduled_version_change(&self) -> &[u8] {
+        &self.finality_scheduled_version_change
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        [
+            self.next_authorities(),
+            self.queued_emergency_finalizer(),
+            self.next_bls_authorities(),
+            self.finality_version(),
+            self.finality_scheduled_version_change(),
+        ]
+        .into_iter()
+    }
+}
+
+/// What can go wrong when generating or verifying a warp-sync proof.
+#[derive(Debug)]
+pub enum Error<B: Block> {
+    Client(ClientError),
+    MissingBlock(NumberFor<B>),
+    MissingHeader(B::Hash),
+    NotFinalized(B::Hash),
+    MissingJustification(B::Hash),
+    JustificationDecode(B::Hash, DecodeError),
+    MissingNextAuthorityData(B::Hash),
+    ProofDecode(codec::Error),
+    EmptyProof,
+    UnexpectedBlock {
+        expected: NumberFor<B>,
+        got: NumberFor<B>,
+    },
+    BadJustification(B::Hash),
+    BadStorageProof(B::Hash, String),
+    AuthorityDataMismatch(B::Hash),
+    FinalityVersionMismatch(B::Hash),
+}
+
+impl<B: Block> Display for Error<B> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        use Error::*;
+        match self {
+            Client(e) => write!(f, "substrate client error {}", e),
+            MissingBlock(number) => write!(f, "no finalized block with number {:?}", number),
+            MissingHeader(hash) => write!(f, "no header for block with hash {:?}", hash),
+            NotFinalized(hash) => write!(f, "block {:?} is not finalized", hash),
+            MissingJustification(hash) => {
+                write!(f, "no justification for session end block {:?}", hash)
+            }
+            JustificationDecode(hash, e) => write!(
+                f,
+                "could not decode stored justification for block {:?}: {}",
+                hash, e
+            ),
+            MissingNextAuthorityData(hash) => write!(
+                f,
+                "next session authority data unavailable at block {:?}",
+                hash
+            ),
+            ProofDecode(e) => write!(f, "could not decode warp sync proof: {}", e),
+            EmptyProof => write!(f, "warp sync proof contains no fragments"),
+            UnexpectedBlock { expected, got } => write!(
+                f,
+                "expected a fragment for block #{:?}, got one for block #{:?}",
+                expected, got
+            ),
+            BadJustification(hash) => write!(f, "bad justification for block {:?}", hash),
+            BadStorageProof(hash, e) => write!(
+                f,
+                "bad storage proof of authority data at block {:?}: {}",
+                hash, e
+            ),
+            AuthorityDataMismatch(hash) => write!(
+                f,
+                "authority data at block {:?} does not match its storage proof",
+                hash
+            ),
+            FinalityVersionMismatch(hash) => write!(
+                f,
+                "next session finality version at block {:?} does not match its storage proof",
+                hash
+            ),
+        }
+    }
+}
+
+impl<B: Block> From<ClientError> for Error<B> {
+    fn from(value: ClientError) -> Self {
+        Error::Client(value)
+    }
+}
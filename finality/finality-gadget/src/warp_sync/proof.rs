@@ -0,0 +1,536 @@
+use codec::{Decode, Encode};
+use dagestan_primitives::{
+    AuthorityId, BlsPublic, SessionAuthorityData, Version, VersionChange, DEFAULT_FINALITY_VERSION,
+};
+use sp_runtime::traits::{Block, Header, NumberFor};
+use sp_state_machine::read_proof_check;
+use sp_trie::StorageProof;
+
+use crate::{
+    justification::{DagestanJustification, Verifier},
+    last_block_of_session,
+    nodes::JustificationVerifier,
+    warp_sync::{AuthorityDataStorageKeys, Error},
+    SessionId, SessionPeriod,
+};
+
+/// Maximal size of an encoded warp-sync proof, so that it fits into a single network response.
+pub const MAX_WARP_SYNC_PROOF_SIZE: usize = 8 * 1024 * 1024;
+
+/// A single committee handover: the justified last block of a session, together with the
+/// authority data and finality version of the next session and a proof of them against the block's
+/// state root.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct WarpSyncFragment<B: Block> {
+    pub header: B::Header,
+    pub justification: DagestanJustification,
+    pub next_authority_data: SessionAuthorityData,
+    pub next_finality_version: Version,
+    pub authority_data_proof: StorageProof,
+}
+
+impl<B: Block> WarpSyncFragment<B> {
+    /// Checks the authority data and finality version of `next_session` against the storage proof.
+    fn verify_next_authority_data(
+        &self,
+        next_session: SessionId,
+        storage_keys: &AuthorityDataStorageKeys,
+    ) -> Result<(), Error<B>> {
+        let hash = self.header.hash();
+        let bad_proof = |e: String| Error::BadStorageProof(hash, e);
+        let values = read_proof_check::<<B::Header as Header>::Hashing, _>(
+            *self.header.state_root(),
+            self.authority_data_proof.clone(),
+            storage_keys.iter(),
+        )
+        .map_err(|e| bad_proof(e.to_string()))?;
+
+        let authorities = match values
+            .get(storage_keys.next_authorities())
+            .cloned()
+            .flatten()
+        {
+            Some(encoded) => Vec::<AuthorityId>::decode(&mut encoded.as_slice())
+                .map_err(|e| bad_proof(e.to_string()))?,
+            None => Vec::new(),
+        };
+        let emergency_finalizer = match values
+            .get(storage_keys.queued_emergency_finalizer())
+            .cloned()
+            .flatten()
+        {
+            Some(encoded) => Some(
+                AuthorityId::decode(&mut encoded.as_slice())
+                    .map_err(|e| bad_proof(e.to_string()))?,
+            ),
+            None => None,
+        };
+        let proven_authority_data = SessionAuthorityData::new(authorities, emergency_finalizer);
+        let proven_authority_data = match values
+            .get(storage_keys.next_bls_authorities())
+            .cloned()
+            .flatten()
+        {
+            Some(encoded) => proven_authority_data.with_bls_authorities(
+                Vec::<BlsPublic>::decode(&mut encoded.as_slice())
+                    .map_err(|e| bad_proof(e.to_string()))?,
+            ),
+            None => proven_authority_data,
+        };
+
+        if proven_authority_data != self.next_authority_data {
+            return Err(Error::AuthorityDataMismatch(hash));
+        }
+
+        let scheduled_version_change = match values
+            .get(storage_keys.finality_scheduled_version_change())
+            .cloned()
+            .flatten()
+        {
+            Some(encoded) => Some(
+                VersionChange::decode(&mut encoded.as_slice())
+                    .map_err(|e| bad_proof(e.to_string()))?,
+            ),
+            None => None,
+        };
+        let proven_finality_version = match scheduled_version_change {
+            Some(version_change) if version_change.session == next_session.0 => {
+                version_change.version_incoming
+            }
+            _ => match values
+                .get(storage_keys.finality_version())
+                .cloned()
+                .flatten()
+            {
+                Some(encoded) => Version::decode(&mut encoded.as_slice())
+                    .map_err(|e| bad_proof(e.to_string()))?,
+                None => DEFAULT_FINALITY_VERSION,
+            },
+        };
+        match proven_finality_version == self.next_finality_version {
+            true => Ok(()),
+            false => Err(Error::FinalityVersionMismatch(hash)),
+        }
+    }
+}
+
+/// The result of a successful warp-sync proof verification.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WarpSyncTarget<B: Block> {
+    /// The header of the last block covered by the proof.
+    pub header: B::Header,
+    /// The session following the last block.
+    pub session: SessionId,
+    /// The authority data of that session.
+    pub authority_data: SessionAuthorityData,
+    /// The finality version of that session.
+    pub finality_version: Version,
+    /// Whether the proof reached the last session end finalized by the prover.
+    pub is_finished: bool,
+}
+
+/// A chain of committee handovers for consecutive sessions.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct WarpSyncProof<B: Block> {
+    pub fragments: Vec<WarpSyncFragment<B>>,
+    pub is_finished: bool,
+}
+
+impl<B: Block> WarpSyncProof<B> {
+    /// Verifies the proof assuming `authority_data` and `finality_version` belong to `session`,
+    /// which should be the session of the first fragment.
+    pub fn verify(
+        &self,
+        mut session: SessionId,
+        mut authority_data: SessionAuthorityData,
+        mut finality_version: Version,
+        session_period: SessionPeriod,
+        storage_keys: &AuthorityDataStorageKeys,
+    ) -> Result<WarpSyncTarget<B>, Error<B>> {
+        let mut last_header = None;
+        for fragment in &self.fragments {
+            let expected = last_block_of_session::<B>(session, session_period);
+            let got = *fragment.header.number();
+            if got != expected {
+                return Err(Error::UnexpectedBlock { expected, got });
+            }
+
+            let hash = fragment.header.hash();
+            let verifier = JustificationVerifier::new(authority_data, finality_version);
+            if !Verifier::<B>::verify(&verifier, &fragment.justification, hash) {
+                return Err(Error::BadJustification(hash));
+            }
+            let next_session = SessionId(session.0 + 1);
+            fragment.verify_next_authority_data(next_session, storage_keys)?;
+
+            authority_data = fragment.next_authority_data.clone();
+            finality_version = fragment.next_finality_version;
+            session = next_session;
+            last_header = Some(fragment.header.clone());
+        }
+
+        Ok(WarpSyncTarget {
+            header: last_header.ok_or(Error::EmptyProof)?,
+            session,
+            authority_data,
+            finality_version,
+            is_finished: self.is_finished,
+        })
+    }
+
+    /// The number of the last block covered by the proof.
+    pub fn last_block(&self) -> Option<NumberFor<B>> {
+        self.fragments
+            .last()
+            .map(|fragment| *fragment.header.number())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use codec::Encode;
+    use dagestan_primitives::{
+        AuthorityId, AuthorityPair, BlsPublic, SessionAuthorityData, Version, VersionChange,
+        BLS_AGGREGATION_FINALITY_VERSION, DEFAULT_FINALITY_VERSION,
+    };
+    use sp_core::Pair;
+    use sp_runtime::{
+        traits::{BlakeTwo256, Header as HeaderT},
+        StateVersion,
+    };
+    use sp_state_machine::{prove_read, InMemoryBackend};
+
+    use super::{WarpSyncFragment, WarpSyncProof};
+    use crate::{
+        abft::{NodeCount, NodeIndex, SignatureSet},
+        crypto::Signature,
+        justification::DagestanJustification,
+        testing::mocks::{TBlock, THeader},
+        warp_sync::{AuthorityDataStorageKeys, Error},
+        SessionId, SessionPeriod,
+    };
+
+    const SESSION_PERIOD: SessionPeriod = SessionPeriod(10);
+
+    fn storage_keys() -> AuthorityDataStorageKeys {
+        AuthorityDataStorageKeys::new("Dagestan")
+    }
+
+    fn committee(seeds: &[u8]) -> Vec<AuthorityPair> {
+        seeds
+            .iter()
+            .map(|seed| AuthorityPair::from_seed(&[*seed; 32]))
+            .collect()
+    }
+
+    fn authority_data(pairs: &[AuthorityPair]) -> SessionAuthorityData {
+        SessionAuthorityData::new(pairs.iter().map(|pair| pair.public()).collect(), None)
+    }
+
+    fn sign(pairs: &[AuthorityPair], header: &THeader) -> DagestanJustification {
+        let message = header.hash().encode();
+        let signatures = pairs.iter().enumerate().fold(
+            SignatureSet::with_size(NodeCount(pairs.len())),
+            |signatures, (index, pair)| {
+                signatures.add_signature(&Signature::from(pair.sign(&message)), NodeIndex(index))
+            },
+        );
+        DagestanJustification::CommitteeMultisignature(signatures)
+    }
+
+    fn versioned_fragment(
+        number: u64,
+        current: &[AuthorityPair],
+        next_authority_data: SessionAuthorityData,
+        finality_version: Version,
+        scheduled_version_change: Option<VersionChange>,
+        next_finality_version: Version,
+    ) -> WarpSyncFragment<TBlock> {
+        let keys = storage_keys();
+        let mut storage = vec![
+            (
+                keys.next_authorities().to_vec(),
+                Some(next_authority_data.authorities().encode()),
+            ),
+            (
+                keys.finality_version().to_vec(),
+                Some(finality_version.encode()),
+            ),
+        ];
+        if let Some(version_change) = scheduled_version_change {
+            storage.push((
+                keys.finality_scheduled_version_change().to_vec(),
+                Some(version_change.encode()),
+            ));
+        }
+        let backend =
+            InMemoryBackend::<BlakeTwo256>::from((vec![(None, storage)], StateVersion::V1));
+        let state_root = *backend.root();
+        let authority_data_proof =
+            prove_read(backend, keys.iter()).expect("the keys should be provable");
+        let header = THeader::new(
+            number,
+            Default::default(),
+            state_root,
+            Default::default(),
+            Default::default(),
+        );
+        WarpSyncFragment {
+            justification: sign(current, &header),
+            header,
+            next_authority_data,
+            next_finality_version,
+            authority_data_proof,
+        }
+    }
+
+    fn fragment(
+        number: u64,
+        current: &[AuthorityPair],
+        next_authority_data: SessionAuthorityData,
+    ) -> WarpSyncFragment<TBlock> {
+        versioned_fragment(
+            number,
+            current,
+            next_authority_data,
+            DEFAULT_FINALITY_VERSION,
+            None,
+            DEFAULT_FINALITY_VERSION,
+        )
+    }
+
+    fn proof_over_sessions(committees: &[Vec<AuthorityPair>]) -> WarpSyncProof<TBlock> {
+        let fragments = committees
+            .windows(2)
+            .enumerate()
+            .map(|(session, window)| {
+                fragment(
+                    (session as u64 + 1) * SESSION_PERIOD.0 as u64 - 1,
+                    &window[0],
+                    authority_data(&window[1]),
+                )
+            })
+            .collect();
+        WarpSyncProof {
+            fragments,
+            is_finished: true,
+        }
+    }
+
+    #[test]
+    fn verifies_chain_of_handovers() {
+        let committees = vec![
+            committee(&[1, 2, 3]),
+            committee(&[4, 5, 6]),
+            committee(&[7, 8]),
+        ];
+        let proof = proof_over_sessions(&committees);
+
+        let target = proof
+            .verify(
+                SessionId(0),
+                authority_data(&committees[0]),
+                DEFAULT_FINALITY_VERSION,
+                SESSION_PERIOD,
+                &storage_keys(),
+            )
+            .expect("the proof should be correct");
+
+        assert_eq!(target.session, SessionId(2));
+        assert_eq!(target.authority_data, authority_data(&committees[2]));
+        assert_eq!(target.finality_version, DEFAULT_FINALITY_VERSION);
+        assert_eq!(*target.header.number(), 19);
+        assert!(target.is_finished);
+    }
+
+    #[test]
+    fn rejects_justification_from_wrong_committee() {
+        let committees = vec![
+            committee(&[1, 2, 3]),
+            committee(&[4, 5, 6]),
+            committee(&[7, 8]),
+        ];
+        let mut proof = proof_over_sessions(&committees);
+        let header = proof.fragments[1].header.clone();
+        proof.fragments[1].justification = sign(&committees[2], &header);
+
+        assert!(matches!(
+            proof.verify(
+                SessionId(0),
+                authority_data(&committees[0]),
+                DEFAULT_FINALITY_VERSION,
+                SESSION_PERIOD,
+                &storage_keys(),
+            ),
+            Err(Error::BadJustification(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_authority_data_not_matching_storage_proof() {
+        let committees = vec![
+            committee(&[1, 2, 3]),
+            committee(&[4, 5, 6]),
+            committee(&[7, 8]),
+        ];
+        let mut proof = proof_over_sessions(&committees);
+        proof.fragments[0].next_authority_data = authority_data(&committees[2]);
+
+        assert!(matches!(
+            proof.verify(
+                SessionId(0),
+                authority_data(&committees[0]),
+                DEFAULT_FINALITY_VERSION,
+                SESSION_PERIOD,
+                &storage_keys(),
+            ),
+            Err(Error::AuthorityDataMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_unproven_bls_keys() {
+        let committees = vec![
+            committee(&[1, 2, 3]),
+            committee(&[4, 5, 6]),
+            committee(&[7, 8]),
+        ];
+        let mut proof = proof_over_sessions(&committees);
+        proof.fragments[0].next_authority_data =
+            authority_data(&committees[1]).with_bls_authorities(vec![BlsPublic([1; 48]); 3]);
+
+        assert!(matches!(
+            proof.verify(
+                SessionId(0),
+                authority_data(&committees[0]),
+                DEFAULT_FINALITY_VERSION,
+                SESSION_PERIOD,
+                &storage_keys(),
+            ),
+            Err(Error::AuthorityDataMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_skipped_session() {
+        let committees = vec![
+            committee(&[1, 2, 3]),
+            committee(&[4, 5, 6]),
+            committee(&[7, 8]),
+        ];
+        let mut proof = proof_over_sessions(&committees);
+        proof.fragments.remove(0);
+
+        assert!(matches!(
+            proof.verify(
+                SessionId(0),
+                authority_data(&committees[1]),
+                DEFAULT_FINALITY_VERSION,
+                SESSION_PERIOD,
+                &storage_keys(),
+            ),
+            Err(Error::UnexpectedBlock {
+                expected: 9,
+                got: 19
+            })
+        ));
+    }
+
+    #[test]
+    fn rejects_empty_proof() {
+        let proof = WarpSyncProof::<TBlock> {
+            fragments: Vec::new(),
+            is_finished: true,
+        };
+
+        assert!(matches!(
+            proof.verify(
+                SessionId(0),
+                SessionAuthorityData::new(Vec::<AuthorityId>::new(), None),
+                DEFAULT_FINALITY_VERSION,
+                SESSION_PERIOD,
+                &storage_keys(),
+            ),
+            Err(Error::EmptyProof)
+        ));
+        assert!(proof.last_block().is_none());
+    }
+
+    #[test]
+    fn follows_scheduled_finality_version_changes() {
+        let committees = vec![
+            committee(&[1, 2, 3]),
+            committee(&[4, 5, 6]),
+            committee(&[7, 8]),
+        ];
+        let version_change = VersionChange {
+            version_incoming: BLS_AGGREGATION_FINALITY_VERSION,
+            session: 1,
+        };
+        let proof = WarpSyncProof {
+            fragments: vec![
+                versioned_fragment(
+                    9,
+                    &committees[0],
+                    authority_data(&committees[1]),
+                    DEFAULT_FINALITY_VERSION,
+                    Some(version_change),
+                    BLS_AGGREGATION_FINALITY_VERSION,
+                ),
+                versioned_fragment(
+                    19,
+                    &committees[1],
+                    authority_data(&committees[2]),
+                    BLS_AGGREGATION_FINALITY_VERSION,
+                    None,
+                    BLS_AGGREGATION_FINALITY_VERSION,
+                ),
+            ],
+            is_finished: true,
+        };
+
+        let target = proof
+            .verify(
+                SessionId(0),
+                authority_data(&committees[0]),
+                DEFAULT_FINALITY_VERSION,
+                SESSION_PERIOD,
+                &storage_keys(),
+            )
+            .expect("the proof should be correct");
+
+        assert_eq!(target.finality_version, BLS_AGGREGATION_FINALITY_VERSION);
+    }
+
+    #[test]
+    fn rejects_finality_version_not_matching_storage_proof() {
+        let committees = vec![committee(&[1, 2, 3]), committee(&[4, 5, 6])];
+        // The change is scheduled for a later session, so the next one keeps the current version.
+        let version_change = VersionChange {
+            version_incoming: BLS_AGGREGATION_FINALITY_VERSION,
+            session: 2,
+        };
+        let proof = WarpSyncProof {
+            fragments: vec![versioned_fragment(
+                9,
+                &committees[0],
+                authority_data(&committees[1]),
+                DEFAULT_FINALITY_VERSION,
+                Some(version_change),
+                BLS_AGGREGATION_FINALITY_VERSION,
+            )],
+            is_finished: true,
+        };
+
+        assert!(matches!(
+            proof.verify(
+                SessionId(0),
+                authority_data(&committees[0]),
+                DEFAULT_FINALITY_VERSION,
+                SESSION_PERIOD,
+                &storage_keys(),
+            ),
+            Err(Error::FinalityVersionMismatch(_))
+        ));
+    }
+}
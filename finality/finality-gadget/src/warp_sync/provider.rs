@@ -0,0 +1,154 @@
+use std::{marker::PhantomData, sync::Arc};
+
+use codec::{Decode, Encode};
+use dagestan_primitives::{DagestanSessionApi, SessionAuthorityData, Version};
+use log::debug;
+use sc_client_api::{BlockBackend, ProofProvider};
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::HeaderBackend;
+use sp_runtime::{
+    generic::BlockId,
+    traits::{Block, NumberFor},
+};
+
+use crate::{
+    justification::{backwards_compatible_decode, find_dagestan_justification},
+    last_block_of_session, session_id_from_block_num,
+    session_map::runtime_next_session_authority_data,
+    warp_sync::{
+        AuthorityDataStorageKeys, Error, WarpSyncFragment, WarpSyncProof, WarpSyncTarget,
+        MAX_WARP_SYNC_PROOF_SIZE,
+    },
+    SessionId, SessionPeriod,
+};
+
+/// Generates and verifies warp-sync proofs using the local client.
+pub struct FinalityProofProvider<B, C>
+where
+    B: Block,
+    C: HeaderBackend<B> + BlockBackend<B> + ProofProvider<B> + ProvideRuntimeApi<B>,
+    C::Api: DagestanSessionApi<B>,
+{
+    client: Arc<C>,
+    session_period: SessionPeriod,
+    storage_keys: AuthorityDataStorageKeys,
+    _phantom: PhantomData<B>,
+}
+
+impl<B, C> FinalityProofProvider<B, C>
+where
+    B: Block,
+    C: HeaderBackend<B> + BlockBackend<B> + ProofProvider<B> + ProvideRuntimeApi<B>,
+    C::Api: DagestanSessionApi<B>,
+{
+    pub fn new(
+        client: Arc<C>,
+        session_period: SessionPeriod,
+        storage_keys: AuthorityDataStorageKeys,
+    ) -> Self {
+        FinalityProofProvider {
+            client,
+            session_period,
+            storage_keys,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Returns an encoded `WarpSyncProof` starting at the end of the session containing the
+    /// finalized block `begin`. The proof ends at the last finalized session end, unless it would
+    /// exceed `MAX_WARP_SYNC_PROOF_SIZE`, in which case it is marked as not finished.
+    pub fn prove_finality(&self, begin: B::Hash) -> Result<Vec<u8>, Error<B>> {
+        self.generate_proof(begin).map(|proof| proof.encode())
+    }
+
+    /// Verifies an encoded `WarpSyncProof`, assuming `authority_data` and `finality_version`
+    /// belong to `session`.
+    pub fn verify_proof(
+        &self,
+        encoded_proof: &[u8],
+        session: SessionId,
+        authority_data: SessionAuthorityData,
+        finality_version: Version,
+    ) -> Result<WarpSyncTarget<B>, Error<B>> {
+        let proof =
+            WarpSyncProof::<B>::decode(&mut &encoded_proof[..]).map_err(Error::ProofDecode)?;
+        proof.verify(
+            session,
+            authority_data,
+            finality_version,
+            self.session_period,
+            &self.storage_keys,
+        )
+    }
+
+    fn generate_proof(&self, begin: B::Hash) -> Result<WarpSyncProof<B>, Error<B>> {
+        let begin_number = self
+            .client
+            .number(begin)?
+            .ok_or(Error::MissingHeader(begin))?;
+        let finalized_number = self.client.info().finalized_number;
+        if begin_number > finalized_number || self.client.hash(begin_number)? != Some(begin) {
+            return Err(Error::NotFinalized(begin));
+        }
+
+        let mut session = session_id_from_block_num::<B>(begin_number, self.session_period);
+        let mut fragments = Vec::new();
+        let mut proof_size = 0;
+        let mut is_finished = true;
+        loop {
+            let last_block = last_block_of_session::<B>(session, self.session_period);
+            if last_block > finalized_number {
+                break;
+            }
+            let fragment = self.fragment(last_block)?;
+            let fragment_size = fragment.encoded_size();
+            if proof_size + fragment_size > MAX_WARP_SYNC_PROOF_SIZE {
+                debug!(target: "dagestan-finality", "Warp sync proof size limit reached at block #{:?}.", last_block);
+                is_finished = false;
+                break;
+            }
+            proof_size += fragment_size;
+            fragments.push(fragment);
+            session = SessionId(session.0 + 1);
+        }
+
+        Ok(WarpSyncProof {
+            fragments,
+            is_finished,
+        })
+    }
+
+    fn fragment(&self, number: NumberFor<B>) -> Result<WarpSyncFragment<B>, Error<B>> {
+        let hash = self
+            .client
+            .hash(number)?
+            .ok_or(Error::MissingBlock(number))?;
+        let id = BlockId::Hash(hash);
+        let header = self.client.header(id)?.ok_or(Error::MissingHeader(hash))?;
+        let (_, justification) = self
+            .client
+            .justifications(&id)?
+            .as_ref()
+            .and_then(find_dagestan_justification)
+            .ok_or(Error::MissingJustification(hash))?;
+        let justification = backwards_compatible_decode(justification)
+            .map_err(|e| Error::JustificationDecode(hash, e))?;
+        let runtime_api = self.client.runtime_api();
+        let next_authority_data = runtime_next_session_authority_data(&*runtime_api, &id)
+            .ok()
+            .and_then(|result| result.ok())
+            .ok_or(Error::MissingNextAuthorityData(hash))?;
+        let next_finality_version = runtime_api
+            .next_session_finality_version(&id)
+            .map_err(|_| Error::MissingNextAuthorityData(hash))?;
+        let authority_data_proof = self.client.read_proof(&id, &mut self.storage_keys.iter())?;
+
+        Ok(WarpSyncFragment {
+            header,
+            justification,
+            next_authority_data,
+            next_finality_version,
+            authority_data_proof,
+        })
+    }
+}
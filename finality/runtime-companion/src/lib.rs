@@ -13,6 +13,13 @@
 //! It is always possible to reschedule a version change. In order to cancel a scheduled version
 //! change rather than reschedule it, a new version change should be scheduled with
 //! `version_incoming` set to the current value of `FinalityVersion`.
+//!
+//! Authorities register their BLS keys with `register_bls_key`, proving the possession of the
+//! secret BLS key and signing the BLS key with their authority key, together with the number of
+//! keys they registered so far, kept in `BlsKeyNonces`. The BLS keys of a session's
+//! authorities are only known, as `BlsAuthorities` and `NextBlsAuthorities`, if every one of them
+//! has registered one. `authority_data` and `next_session_authority_data` include them in the
+//! authority data the runtime should return from `DagestanSessionApi`.
 
 #![cfg_attr(not(feature = "std"), no_std)]
 
@@ -31,19 +38,24 @@ use frame_support::{
     traits::{OneSessionHandler, StorageVersion},
 };
 pub use pallet::*;
-use dagestan_primitives::{SessionIndex, Version, VersionChange};
+use dagestan_primitives::{
+    bls_crypto, bls_registration_message, AuthorityId, BlsPublic, BlsSignature,
+    SessionAuthorityData, SessionIndex, Version, VersionChange, DEFAULT_FINALITY_VERSION,
+    DEFAULT_MAX_DATA_BRANCH_LEN, MAX_DATA_BRANCH_LEN_LIMIT,
+};
 use sp_std::prelude::*;
 
 /// The current storage version.
-const STORAGE_VERSION: StorageVersion = StorageVersion::new(2);
+const STORAGE_VERSION: StorageVersion = StorageVersion::new(3);
 
-const DEFAULT_FINALITY_VERSION: Version = 1;
+/// The reference time of verifying a proof of possession of a BLS key, which takes two pairings.
+const BLS_PROOF_VERIFICATION_TIME: u64 = 2_000_000_000;
 
 #[frame_support::pallet]
 pub mod pallet {
     use frame_support::{pallet_prelude::*, sp_runtime::RuntimeAppPublic};
     use frame_system::{
-        ensure_root,
+        ensure_root, ensure_signed,
         pallet_prelude::{BlockNumberFor, OriginFor},
     };
     use pallet_session::SessionManager;
@@ -66,6 +78,8 @@ pub mod pallet {
         ChangeEmergencyFinalizer(T::AuthorityId),
         ScheduleFinalityVersionChange(VersionChange),
         FinalityVersionChange(VersionChange),
+        ChangeMaxDataBranchLen(u32),
+        RegisterBlsKey(T::AuthorityId, BlsPublic),
     }
 
     #[pallet::pallet]
@@ -80,17 +94,22 @@ pub mod pallet {
             T::DbWeight::get().reads(1)
                 + match on_chain {
                     _ if on_chain == STORAGE_VERSION => Weight::zero(),
+                    _ if on_chain == StorageVersion::new(2) => {
+                        migrations::v2_to_v3::Migration::<T, Self>::migrate()
+                    }
                     _ if on_chain == StorageVersion::new(1) => {
                         migrations::v1_to_v2::Migration::<T, Self>::migrate()
+                            + migrations::v2_to_v3::Migration::<T, Self>::migrate()
                     }
                     _ if on_chain == StorageVersion::new(0) => {
                         migrations::v0_to_v1::Migration::<T, Self>::migrate()
                             + migrations::v1_to_v2::Migration::<T, Self>::migrate()
+                            + migrations::v2_to_v3::Migration::<T, Self>::migrate()
                     }
                     _ => {
                         log::warn!(
                             target: "dagestan_finality_runtime_companion",
-                            "On chain storage version of pallet dagestan is {:?} but it should not be bigger than 2",
+                            "On chain storage version of pallet dagestan is {:?} but it should not be bigger than 3",
                             on_chain
                         );
                         Weight::zero()
@@ -105,10 +124,21 @@ pub mod pallet {
         DEFAULT_FINALITY_VERSION
     }
 
+    /// Default maximal length of proposal branches. Relevant for sessions before it is first set.
+    #[pallet::type_value]
+    pub(crate) fn DefaultMaxDataBranchLen<T: Config>() -> u32 {
+        DEFAULT_MAX_DATA_BRANCH_LEN
+    }
+
     #[pallet::storage]
     #[pallet::getter(fn authorities)]
     pub(super) type Authorities<T: Config> = StorageValue<_, Vec<T::AuthorityId>, ValueQuery>;
 
+    /// Authorities of the next session, as queued by `pallet_session`.
+    #[pallet::storage]
+    #[pallet::getter(fn next_authorities)]
+    pub(super) type NextAuthorities<T: Config> = StorageValue<_, Vec<T::AuthorityId>, ValueQuery>;
+
     #[pallet::storage]
     #[pallet::getter(fn emergency_finalizer)]
     pub(super) type EmergencyFinalizer<T: Config> = StorageValue<_, T::AuthorityId, OptionQuery>;
@@ -133,6 +163,43 @@ pub mod pallet {
     pub(super) type FinalityScheduledVersionChange<T: Config> =
         StorageValue<_, VersionChange, OptionQuery>;
 
+    /// How many blocks above the last finalized block AlephBFT proposals of the current session may
+    /// reach. Only used by the finality gadget in sessions with a finality version of at least
+    /// `CONFIGURABLE_BRANCH_LEN_FINALITY_VERSION`.
+    #[pallet::storage]
+    #[pallet::getter(fn max_data_branch_len)]
+    pub(super) type MaxDataBranchLen<T: Config> =
+        StorageValue<_, u32, ValueQuery, DefaultMaxDataBranchLen<T>>;
+
+    #[pallet::storage]
+    #[pallet::getter(fn queued_max_data_branch_len)]
+    pub(super) type QueuedMaxDataBranchLen<T: Config> = StorageValue<_, u32, OptionQuery>;
+
+    #[pallet::storage]
+    type NextMaxDataBranchLen<T: Config> = StorageValue<_, u32, OptionQuery>;
+
+    /// BLS keys registered by authorities.
+    #[pallet::storage]
+    #[pallet::getter(fn bls_key)]
+    pub(super) type BlsKeys<T: Config> =
+        StorageMap<_, Twox64Concat, T::AuthorityId, BlsPublic, OptionQuery>;
+
+    /// The number of BLS keys registered by authorities, which the next registration has to sign.
+    #[pallet::storage]
+    #[pallet::getter(fn bls_key_nonce)]
+    pub(super) type BlsKeyNonces<T: Config> =
+        StorageMap<_, Twox64Concat, T::AuthorityId, u32, ValueQuery>;
+
+    /// BLS keys of the current authorities, in the same order, if all of them have one.
+    #[pallet::storage]
+    #[pallet::getter(fn bls_authorities)]
+    pub(super) type BlsAuthorities<T: Config> = StorageValue<_, Vec<BlsPublic>, OptionQuery>;
+
+    /// BLS keys of the authorities of the next session, in the same order, if all of them have one.
+    #[pallet::storage]
+    #[pallet::getter(fn next_bls_authorities)]
+    pub(super) type NextBlsAuthorities<T: Config> = StorageValue<_, Vec<BlsPublic>, OptionQuery>;
+
     impl<T: Config> Pallet<T> {
         pub(crate) fn initialize_authorities(authorities: &[T::AuthorityId]) {
             if !authorities.is_empty() {
@@ -148,6 +215,27 @@ pub mod pallet {
             <Authorities<T>>::put(authorities);
         }
 
+        pub(crate) fn update_next_authorities(next_authorities: &[T::AuthorityId]) {
+            <NextAuthorities<T>>::put(next_authorities);
+        }
+
+        fn registered_bls_keys(authorities: &[T::AuthorityId]) -> Option<Vec<BlsPublic>> {
+            authorities.iter().map(<BlsKeys<T>>::get).collect()
+        }
+
+        pub(crate) fn initialize_bls_authorities(authorities: &[T::AuthorityId]) {
+            let bls_authorities = Self::registered_bls_keys(authorities);
+            <BlsAuthorities<T>>::set(bls_authorities.clone());
+            <NextBlsAuthorities<T>>::set(bls_authorities);
+        }
+
+        /// The BLS keys of the next session were fixed when it was queued, so they become the
+        /// current ones, even if some authority registered a new key since.
+        pub(crate) fn update_bls_authorities(next_authorities: &[T::AuthorityId]) {
+            <BlsAuthorities<T>>::set(<NextBlsAuthorities<T>>::get());
+            <NextBlsAuthorities<T>>::set(Self::registered_bls_keys(next_authorities));
+        }
+
         pub(crate) fn update_emergency_finalizer() {
             if let Some(emergency_finalizer) = <QueuedEmergencyFinalizer<T>>::get() {
                 <EmergencyFinalizer<T>>::put(emergency_finalizer)
@@ -162,6 +250,26 @@ pub mod pallet {
             <NextEmergencyFinalizer<T>>::put(emergency_finalizer);
         }
 
+        pub(crate) fn update_max_data_branch_len() {
+            if let Some(max_data_branch_len) = <QueuedMaxDataBranchLen<T>>::get() {
+                <MaxDataBranchLen<T>>::put(max_data_branch_len)
+            }
+
+            if let Some(max_data_branch_len) = <NextMaxDataBranchLen<T>>::get() {
+                <QueuedMaxDataBranchLen<T>>::put(max_data_branch_len)
+            }
+        }
+
+        pub(crate) fn set_next_max_data_branch_len(max_data_branch_len: u32) {
+            <NextMaxDataBranchLen<T>>::put(max_data_branch_len);
+        }
+
+        /// The maximal length of proposal branches in the next session. All the nodes read it
+        /// at the last block of the current session, so they agree on it.
+        pub fn next_session_max_data_branch_len() -> u32 {
+            Self::queued_max_data_branch_len().unwrap_or_else(Self::max_data_branch_len)
+        }
+
         pub(crate) fn current_session() -> u32 {
             T::SessionInfoProvider::current_session()
         }
@@ -206,6 +314,30 @@ pub mod pallet {
         }
     }
 
+    impl<T: Config<AuthorityId = AuthorityId>> Pallet<T> {
+        /// The authority data of the current session, as returned by `DagestanSessionApi`.
+        pub fn authority_data() -> SessionAuthorityData {
+            let authority_data =
+                SessionAuthorityData::new(Self::authorities(), Self::emergency_finalizer());
+            match Self::bls_authorities() {
+                Some(bls_authorities) => authority_data.with_bls_authorities(bls_authorities),
+                None => authority_data,
+            }
+        }
+
+        /// The authority data of the next session, as returned by `DagestanSessionApi`.
+        pub fn next_session_authority_data() -> SessionAuthorityData {
+            let authority_data = SessionAuthorityData::new(
+                Self::next_authorities(),
+                Self::queued_emergency_finalizer(),
+            );
+            match Self::next_bls_authorities() {
+                Some(bls_authorities) => authority_data.with_bls_authorities(bls_authorities),
+                None => authority_data,
+            }
+        }
+    }
+
     #[pallet::call]
     impl<T: Config> Pallet<T> {
         /// Sets the emergency finalization key. If called in session `N` the key can be used to
@@ -221,6 +353,25 @@ pub mod pallet {
             Ok(())
         }
 
+        /// Sets how many blocks above the last finalized block AlephBFT proposals may reach. If
+        /// called in session `N` the length is used from session `N+2` onwards, until it gets
+        /// overridden.
+        #[pallet::weight((T::BlockWeights::get().max_block, DispatchClass::Operational))]
+        pub fn set_max_data_branch_len(
+            origin: OriginFor<T>,
+            max_data_branch_len: u32,
+        ) -> DispatchResult {
+            ensure_root(origin)?;
+            if max_data_branch_len == 0 || max_data_branch_len > MAX_DATA_BRANCH_LEN_LIMIT {
+                return Err(DispatchError::Other(
+                    "The maximal data branch length has to be positive and within the limit!",
+                ));
+            }
+            Self::set_next_max_data_branch_len(max_data_branch_len);
+            Self::deposit_event(Event::ChangeMaxDataBranchLen(max_data_branch_len));
+            Ok(())
+        }
+
         /// Schedules a finality version change for a future session. If such a scheduled future
         /// version is already set, it is replaced with the provided one.
         /// Any rescheduling of a future version change needs to occur at least 2 sessions in
@@ -247,6 +398,42 @@ pub mod pallet {
             Self::deposit_event(Event::ScheduleFinalityVersionChange(version_change));
             Ok(())
         }
+
+        /// Registers the BLS key of an authority. `proof_of_possession` is a signature of the BLS
+        /// key by itself, and `authority_signature` a signature of
+        /// `bls_registration_message(authority, bls_key_nonce(authority), bls_key)` by the
+        /// authority. If called in session `N` the key is used from session `N+2` onwards, until it
+        /// gets overridden.
+        #[pallet::weight(
+            T::DbWeight::get()
+                .reads_writes(1, 2)
+                .saturating_add(Weight::from_ref_time(BLS_PROOF_VERIFICATION_TIME))
+        )]
+        pub fn register_bls_key(
+            origin: OriginFor<T>,
+            authority: T::AuthorityId,
+            bls_key: BlsPublic,
+            proof_of_possession: BlsSignature,
+            authority_signature: <T::AuthorityId as RuntimeAppPublic>::Signature,
+        ) -> DispatchResult {
+            ensure_signed(origin)?;
+            let nonce = <BlsKeyNonces<T>>::get(&authority);
+            let message = bls_registration_message(&authority, nonce, &bls_key);
+            if !authority.verify(&message, &authority_signature) {
+                return Err(DispatchError::Other(
+                    "The BLS key has to be signed by the authority!",
+                ));
+            }
+            if !bls_crypto::verify_proof_of_possession(&bls_key.0, &proof_of_possession.0) {
+                return Err(DispatchError::Other(
+                    "The proof of possession of the BLS key is incorrect!",
+                ));
+            }
+            <BlsKeys<T>>::insert(&authority, bls_key);
+            <BlsKeyNonces<T>>::insert(&authority, nonce.saturating_add(1));
+            Self::deposit_event(Event::RegisterBlsKey(authority, bls_key));
+            Ok(())
+        }
     }
 
     impl<T: Config> BoundToRuntimeAppPublic for Pallet<T> {
@@ -263,18 +450,25 @@ pub mod pallet {
         {
             let (_, authorities): (Vec<_>, Vec<_>) = validators.unzip();
             Self::initialize_authorities(authorities.as_slice());
+            // `pallet_session` queues the genesis validators for the next session as well.
+            Self::update_next_authorities(authorities.as_slice());
+            Self::initialize_bls_authorities(authorities.as_slice());
         }
 
-        fn on_new_session<'a, I: 'a>(changed: bool, validators: I, _queued_validators: I)
+        fn on_new_session<'a, I: 'a>(changed: bool, validators: I, queued_validators: I)
         where
             I: Iterator<Item = (&'a T::AccountId, T::AuthorityId)>,
             T::AccountId: 'a,
         {
             Self::update_emergency_finalizer();
+            Self::update_max_data_branch_len();
             if changed {
                 let (_, authorities): (Vec<_>, Vec<_>) = validators.unzip();
                 Self::update_authorities(authorities.as_slice());
             }
+            let (_, next_authorities): (Vec<_>, Vec<_>) = queued_validators.unzip();
+            Self::update_next_authorities(next_authorities.as_slice());
+            Self::update_bls_authorities(next_authorities.as_slice());
         }
 
         fn on_disabled(_validator_index: u32) {}
@@ -0,0 +1,60 @@
+use frame_support::{
+    log, storage_alias,
+    traits::{Get, OnRuntimeUpgrade, PalletInfoAccess, StorageVersion},
+    weights::Weight,
+};
+use sp_std::vec::Vec;
+#[cfg(feature = "try-runtime")]
+use {frame_support::ensure, dagestan_support::ensure_storage_version};
+
+use crate::{traits::SessionInfoProvider, Config};
+
+#[storage_alias]
+type NextAuthorities<T> = StorageValue<RuntimeCompanion, Vec<<T as Config>::AuthorityId>>;
+
+/// Seeds `NextAuthorities` with the authorities `pallet_session` has queued for the next session.
+/// Before, the storage was only filled in at the next session change, so the authorities of the
+/// next session were unknown until then.
+pub struct Migration<T, P>(sp_std::marker::PhantomData<(T, P)>);
+
+impl<T: Config, P: PalletInfoAccess> OnRuntimeUpgrade for Migration<T, P> {
+    fn on_runtime_upgrade() -> Weight {
+        let mut writes = 0;
+        let mut reads = 1;
+        log::info!(target: "dagestan_finality_runtime_companion", "Running migration from STORAGE_VERSION 2 to 3");
+
+        if NextAuthorities::<T>::exists() {
+            log::info!(target: "dagestan_finality_runtime_companion", "Storage item NextAuthorities already exists!");
+        } else {
+            let next_authorities = T::SessionInfoProvider::queued_authorities();
+            reads += 1;
+            log::info!(target: "dagestan_finality_runtime_companion", "Seeding NextAuthorities with {} queued authorities", next_authorities.len());
+            NextAuthorities::<T>::put(next_authorities);
+            writes += 1;
+        }
+
+        // store new version
+        StorageVersion::new(3).put::<P>();
+        writes += 1;
+
+        T::DbWeight::get().reads(reads) + T::DbWeight::get().writes(writes)
+    }
+
+    #[cfg(feature = "try-runtime")]
+    fn pre_upgrade() -> Result<Vec<u8>, &'static str> {
+        ensure_storage_version::<P>(2)?;
+        Ok(Vec::new())
+    }
+
+    #[cfg(feature = "try-runtime")]
+    fn post_upgrade(_state: Vec<u8>) -> Result<(), &'static str> {
+        ensure_storage_version::<P>(3)?;
+
+        ensure!(
+            NextAuthorities::<T>::exists(),
+            "`NextAuthorities` should be seeded"
+        );
+
+        Ok(())
+    }
+}
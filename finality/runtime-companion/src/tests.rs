@@ -1,7 +1,11 @@
 #![cfg(test)]
 
 use frame_support::{storage_alias, traits::OneSessionHandler};
-use dagestan_primitives::VersionChange;
+use dagestan_primitives::{
+    bls_registration_message, AuthorityPair, AuthoritySignature, BlsPublic, BlsSignature,
+    VersionChange, BLS_POP_DST, DEFAULT_MAX_DATA_BRANCH_LEN,
+};
+use sp_core::Pair;
 
 use crate::mock::*;
 
@@ -45,6 +49,21 @@ mod migration_tests {
             let _weight = migrations::v1_to_v2::Migration::<Test, RuntimeCompanion>::migrate();
         })
     }
+
+    #[test]
+    fn migration_from_v2_to_v3_seeds_next_authorities() {
+        new_test_ext(&[(1u64, 1u64), (2u64, 2u64)]).execute_with(|| {
+            StorageVersion::new(2).put::<Pallet<Test>>();
+            crate::NextAuthorities::<Test>::kill();
+
+            let _weight = migrations::v2_to_v3::Migration::<Test, RuntimeCompanion>::migrate();
+
+            assert_eq!(
+                RuntimeCompanion::next_authorities(),
+                to_authorities(&[1, 2])
+            );
+        })
+    }
 }
 
 #[test]
@@ -106,6 +125,22 @@ fn test_session_rotation() {
     })
 }
 
+#[test]
+fn test_next_authorities() {
+    new_test_ext(&[(1u64, 1u64), (2u64, 2u64)]).execute_with(|| {
+        assert_eq!(RuntimeCompanion::next_authorities(), to_authorities(&[1, 2]));
+
+        initialize_session();
+        run_session(1);
+
+        let new_validators = new_session_validators(&[1u64, 2u64]);
+        let queued_validators = new_session_validators(&[3u64, 4u64]);
+        RuntimeCompanion::on_new_session(false, new_validators, queued_validators);
+        assert_eq!(RuntimeCompanion::authorities(), to_authorities(&[1, 2]));
+        assert_eq!(RuntimeCompanion::next_authorities(), to_authorities(&[3, 4]));
+    })
+}
+
 #[test]
 fn test_emergency_signer() {
     new_test_ext(&[(1u64, 1u64), (2u64, 2u64)]).execute_with(|| {
@@ -132,6 +167,39 @@ fn test_emergency_signer() {
     })
 }
 
+#[test]
+fn test_max_data_branch_len() {
+    new_test_ext(&[(1u64, 1u64), (2u64, 2u64)]).execute_with(|| {
+        initialize_session();
+
+        run_session(1);
+
+        RuntimeCompanion::set_next_max_data_branch_len(12);
+
+        assert_eq!(
+            RuntimeCompanion::max_data_branch_len(),
+            DEFAULT_MAX_DATA_BRANCH_LEN
+        );
+        assert_eq!(
+            RuntimeCompanion::next_session_max_data_branch_len(),
+            DEFAULT_MAX_DATA_BRANCH_LEN
+        );
+
+        run_session(2);
+
+        assert_eq!(
+            RuntimeCompanion::max_data_branch_len(),
+            DEFAULT_MAX_DATA_BRANCH_LEN
+        );
+        assert_eq!(RuntimeCompanion::next_session_max_data_branch_len(), 12);
+
+        run_session(3);
+
+        assert_eq!(RuntimeCompanion::max_data_branch_len(), 12);
+        assert_eq!(RuntimeCompanion::next_session_max_data_branch_len(), 12);
+    })
+}
+
 #[test]
 fn test_finality_version_scheduling() {
     new_test_ext(&[(1u64, 1u64), (2u64, 2u64)]).execute_with(|| {
@@ -168,3 +236,189 @@ fn test_finality_version_scheduling() {
         assert!(scheduling_result.is_err());
     })
 }
+
+fn bls_key_with_proof(seed: u8) -> (BlsPublic, BlsSignature) {
+    let secret_key = blst::min_pk::SecretKey::key_gen(&[seed; 32], &[]).unwrap();
+    let bls_key = BlsPublic(secret_key.sk_to_pk().compress());
+    let proof_of_possession =
+        BlsSignature(secret_key.sign(&bls_key.0, BLS_POP_DST, &[]).compress());
+    (bls_key, proof_of_possession)
+}
+
+fn bls_registration(seed: u8) -> (AuthorityPair, BlsPublic, BlsSignature, AuthoritySignature) {
+    let (bls_key, proof_of_possession) = bls_key_with_proof(seed);
+    let pair = AuthorityPair::from_seed(&[seed; 32]);
+    let authority_signature = pair.sign(&bls_registration_message(&pair.public(), 0, &bls_key));
+    (pair, bls_key, proof_of_possession, authority_signature)
+}
+
+#[test]
+fn test_register_bls_key() {
+    new_test_ext(&[(1u64, 1u64), (2u64, 2u64)]).execute_with(|| {
+        let (pair, bls_key, proof_of_possession, authority_signature) = bls_registration(1);
+
+        assert!(RuntimeCompanion::register_bls_key(
+            RuntimeOrigin::signed(1),
+            pair.public(),
+            bls_key,
+            proof_of_possession,
+            authority_signature,
+        )
+        .is_ok());
+
+        assert_eq!(RuntimeCompanion::bls_key(pair.public()), Some(bls_key));
+        assert_eq!(RuntimeCompanion::bls_key_nonce(pair.public()), 1);
+    })
+}
+
+#[test]
+fn fails_to_replay_old_bls_key_registrations() {
+    new_test_ext(&[(1u64, 1u64), (2u64, 2u64)]).execute_with(|| {
+        let (pair, old_bls_key, old_proof_of_possession, old_authority_signature) =
+            bls_registration(1);
+        assert!(RuntimeCompanion::register_bls_key(
+            RuntimeOrigin::signed(1),
+            pair.public(),
+            old_bls_key,
+            old_proof_of_possession,
+            old_authority_signature.clone(),
+        )
+        .is_ok());
+
+        let (new_bls_key, new_proof_of_possession) = bls_key_with_proof(2);
+        assert!(RuntimeCompanion::register_bls_key(
+            RuntimeOrigin::signed(1),
+            pair.public(),
+            new_bls_key,
+            new_proof_of_possession,
+            pair.sign(&bls_registration_message(&pair.public(), 1, &new_bls_key)),
+        )
+        .is_ok());
+
+        assert!(RuntimeCompanion::register_bls_key(
+            RuntimeOrigin::signed(2),
+            pair.public(),
+            old_bls_key,
+            old_proof_of_possession,
+            old_authority_signature,
+        )
+        .is_err());
+
+        assert_eq!(RuntimeCompanion::bls_key(pair.public()), Some(new_bls_key));
+        assert_eq!(RuntimeCompanion::bls_key_nonce(pair.public()), 2);
+    })
+}
+
+#[test]
+fn fails_to_register_bls_key_signed_for_another_authority() {
+    new_test_ext(&[(1u64, 1u64), (2u64, 2u64)]).execute_with(|| {
+        let (pair, bls_key, proof_of_possession, _) = bls_registration(1);
+        let (other_pair, ..) = bls_registration(2);
+
+        assert!(RuntimeCompanion::register_bls_key(
+            RuntimeOrigin::signed(1),
+            other_pair.public(),
+            bls_key,
+            proof_of_possession,
+            other_pair.sign(&bls_registration_message(&pair.public(), 0, &bls_key)),
+        )
+        .is_err());
+
+        assert_eq!(RuntimeCompanion::bls_key(other_pair.public()), None);
+    })
+}
+
+#[test]
+fn fails_to_register_bls_key_without_proof_of_possession() {
+    new_test_ext(&[(1u64, 1u64), (2u64, 2u64)]).execute_with(|| {
+        let (pair, bls_key, _, authority_signature) = bls_registration(1);
+        let (_, _, other_proof_of_possession, _) = bls_registration(2);
+
+        assert!(RuntimeCompanion::register_bls_key(
+            RuntimeOrigin::signed(1),
+            pair.public(),
+            bls_key,
+            other_proof_of_possession,
+            authority_signature,
+        )
+        .is_err());
+
+        assert_eq!(RuntimeCompanion::bls_key(pair.public()), None);
+    })
+}
+
+#[test]
+fn fails_to_register_bls_key_not_signed_by_authority() {
+    new_test_ext(&[(1u64, 1u64), (2u64, 2u64)]).execute_with(|| {
+        let (pair, bls_key, proof_of_possession, _) = bls_registration(1);
+        let (other_pair, ..) = bls_registration(2);
+
+        assert!(RuntimeCompanion::register_bls_key(
+            RuntimeOrigin::signed(1),
+            pair.public(),
+            bls_key,
+            proof_of_possession,
+            other_pair.sign(&bls_registration_message(&pair.public(), 0, &bls_key)),
+        )
+        .is_err());
+
+        assert_eq!(RuntimeCompanion::bls_key(pair.public()), None);
+    })
+}
+
+#[test]
+fn test_bls_authorities() {
+    new_test_ext(&[(1u64, 1u64), (2u64, 2u64)]).execute_with(|| {
+        let accounts = [1u64, 2u64, 3u64];
+        let registrations: Vec<_> = (1..=3).map(bls_registration).collect();
+        for (pair, bls_key, proof_of_possession, authority_signature) in &registrations[..2] {
+            assert!(RuntimeCompanion::register_bls_key(
+                RuntimeOrigin::signed(1),
+                pair.public(),
+                *bls_key,
+                *proof_of_possession,
+                authority_signature.clone(),
+            )
+            .is_ok());
+        }
+        let validators = |indices: &[usize]| {
+            indices
+                .iter()
+                .map(|index| (&accounts[*index], registrations[*index].0.public()))
+                .collect::<Vec<_>>()
+                .into_iter()
+        };
+        let bls_keys = |indices: &[usize]| {
+            indices
+                .iter()
+                .map(|index| registrations[*index].1)
+                .collect::<Vec<_>>()
+        };
+
+        RuntimeCompanion::on_new_session(true, validators(&[0, 1]), validators(&[0, 1]));
+        assert_eq!(RuntimeCompanion::bls_authorities(), None);
+        assert_eq!(
+            RuntimeCompanion::next_bls_authorities(),
+            Some(bls_keys(&[0, 1]))
+        );
+
+        RuntimeCompanion::on_new_session(true, validators(&[0, 1]), validators(&[0, 2]));
+        assert_eq!(RuntimeCompanion::bls_authorities(), Some(bls_keys(&[0, 1])));
+        assert_eq!(RuntimeCompanion::next_bls_authorities(), None);
+
+        RuntimeCompanion::on_new_session(true, validators(&[0, 2]), validators(&[0, 1]));
+        assert_eq!(RuntimeCompanion::bls_authorities(), None);
+        assert_eq!(
+            RuntimeCompanion::next_bls_authorities(),
+            Some(bls_keys(&[0, 1]))
+        );
+        assert_eq!(
+            RuntimeCompanion::authority_data().bls_authorities(),
+            &None
+        );
+        assert_eq!(
+            RuntimeCompanion::next_session_authority_data().bls_authorities(),
+            &Some(bls_keys(&[0, 1]))
+        );
+    })
+}
@@ -1,15 +1,29 @@
+use frame_support::sp_runtime::{traits::OpaqueKeys, RuntimeAppPublic};
 use dagestan_primitives::SessionIndex;
+use sp_std::prelude::*;
+
+use crate::Config;
 
 /// Information provider from `pallet_session`. Loose pallet coupling via traits.
-pub trait SessionInfoProvider<T: frame_system::Config> {
+pub trait SessionInfoProvider<T: Config> {
     fn current_session() -> SessionIndex;
+
+    /// Authorities of the validators queued for the next session.
+    fn queued_authorities() -> Vec<T::AuthorityId>;
 }
 
 impl<T> SessionInfoProvider<T> for pallet_session::Pallet<T>
 where
-    T: pallet_session::Config,
+    T: pallet_session::Config + Config,
 {
     fn current_session() -> SessionIndex {
         pallet_session::CurrentIndex::<T>::get()
     }
+
+    fn queued_authorities() -> Vec<T::AuthorityId> {
+        pallet_session::QueuedKeys::<T>::get()
+            .into_iter()
+            .filter_map(|(_, keys)| keys.get(T::AuthorityId::ID))
+            .collect()
+    }
 }
@@ -14,11 +14,54 @@ pub use sp_staking::{EraIndex, SessionIndex};
 use sp_std::vec::Vec;
 
 pub const KEY_TYPE: KeyTypeId = KeyTypeId(*b"setm");
+pub const BLS_KEY_TYPE: KeyTypeId = KeyTypeId(*b"dbls");
 
-// Same as GRANDPA_ENGINE_ID because as of right now substrate sends only
-// grandpa justifications over the network.
-// TODO: change this once https://github.com/paritytech/substrate/issues/8172 will be resolved.
-pub const DAGESTAN_ENGINE_ID: ConsensusEngineId = *b"FRNK";
+// Same as GRANDPA_ENGINE_ID because substrate used to send only grandpa justifications over the
+// network, see https://github.com/paritytech/substrate/issues/8172. Justifications stored before
+// `DEDICATED_ENGINE_ID_FINALITY_VERSION` use this id, so it has to be accepted forever.
+pub const LEGACY_DAGESTAN_ENGINE_ID: ConsensusEngineId = *b"FRNK";
+pub const DAGESTAN_ENGINE_ID: ConsensusEngineId = *b"DGST";
+
+/// The finality version of runtimes that never scheduled a finality version change.
+pub const DEFAULT_FINALITY_VERSION: Version = 1;
+
+/// The first finality version in which justifications are stored under `DAGESTAN_ENGINE_ID`.
+pub const DEDICATED_ENGINE_ID_FINALITY_VERSION: Version = 3;
+
+/// The first finality version in which justifications are encoded in the compact V4 format.
+pub const COMPACT_JUSTIFICATION_FINALITY_VERSION: Version = 4;
+
+/// The first finality version in which committees with BLS keys aggregate their signatures into a
+/// single BLS signature.
+pub const BLS_AGGREGATION_FINALITY_VERSION: Version = 5;
+
+/// The first finality version in which the maximal length of AlephBFT proposal branches is a
+/// session parameter set in the runtime, rather than `DEFAULT_MAX_DATA_BRANCH_LEN`.
+pub const CONFIGURABLE_BRANCH_LEN_FINALITY_VERSION: Version = 6;
+
+/// The first finality version in which AlephBFT orders the application payloads of the nodes
+/// alongside the head proposals.
+pub const PAYLOAD_FINALITY_VERSION: Version = 7;
+
+/// How many blocks above the last finalized block an AlephBFT proposal may reach, unless
+/// configured otherwise.
+pub const DEFAULT_MAX_DATA_BRANCH_LEN: u32 = 7;
+
+/// The largest maximal length of proposal branches that can be configured.
+pub const MAX_DATA_BRANCH_LEN_LIMIT: u32 = 64;
+
+/// Returns the engine id under which justifications are stored in the given finality version.
+pub fn engine_id(finality_version: Version) -> ConsensusEngineId {
+    match finality_version >= DEDICATED_ENGINE_ID_FINALITY_VERSION {
+        true => DAGESTAN_ENGINE_ID,
+        false => LEGACY_DAGESTAN_ENGINE_ID,
+    }
+}
+
+/// Whether justifications with the given engine id are Dagestan justifications.
+pub fn is_dagestan_engine_id(engine_id: ConsensusEngineId) -> bool {
+    engine_id == DAGESTAN_ENGINE_ID || engine_id == LEGACY_DAGESTAN_ENGINE_ID
+}
 
 mod app {
     use sp_application_crypto::{app_crypto, ed25519};
@@ -31,6 +74,54 @@ sp_application_crypto::with_pair! {
 pub type AuthoritySignature = app::Signature;
 pub type AuthorityId = app::Public;
 
+/// A compressed BLS12-381 public key, a point in G1.
+#[derive(Clone, Copy, Debug, Decode, Encode, PartialEq, Eq, Hash, TypeInfo)]
+pub struct BlsPublic(pub [u8; 48]);
+
+/// A compressed BLS12-381 signature, a point in G2.
+#[derive(Clone, Copy, Debug, Decode, Encode, PartialEq, Eq, Hash, TypeInfo)]
+pub struct BlsSignature(pub [u8; 96]);
+
+/// Domain separation tag of proofs of possession of BLS keys, which are signatures of the public
+/// keys themselves. It differs from the tag of all the other BLS signatures, so that a proof of
+/// possession cannot be mistaken for a signature of a block.
+pub const BLS_POP_DST: &[u8] = b"BLS_POP_BLS12381G2_XMD:SHA-256_SSWU_RO_POP_";
+
+/// The message an authority signs with its authority key when registering a BLS key, which ties
+/// the BLS key to the authority. `nonce` is the number of keys the authority registered so far, so
+/// that an old registration cannot be replayed to bring back a replaced key.
+pub fn bls_registration_message<A: Encode>(
+    authority: &A,
+    nonce: u32,
+    bls_key: &BlsPublic,
+) -> Vec<u8> {
+    (b"dagestan-bls-registration", authority, nonce, bls_key).encode()
+}
+
+/// BLS signature checks that are too expensive to implement in the runtime. Nodes have to register
+/// `bls_crypto::HostFunctions` with their executor.
+#[sp_runtime_interface::runtime_interface]
+pub trait BlsCrypto {
+    /// Verifies that `proof` is a proof of possession of the secret key of `public`, given both in
+    /// their compressed encoding.
+    fn verify_proof_of_possession(public: &[u8], proof: &[u8]) -> bool {
+        use blst::{
+            min_pk::{PublicKey, Signature},
+            BLST_ERROR,
+        };
+        let public_key = match PublicKey::key_validate(public) {
+            Ok(public_key) => public_key,
+            Err(_) => return false,
+        };
+        let proof = match Signature::sig_validate(proof, true) {
+            Ok(proof) => proof,
+            Err(_) => return false,
+        };
+        proof.verify(false, public, BLS_POP_DST, &[], &public_key, false)
+            == BLST_ERROR::BLST_SUCCESS
+    }
+}
+
 pub type BlockNumber = u32;
 pub type SessionCount = u32;
 pub type BlockCount = u32;
@@ -137,11 +228,38 @@ pub enum ApiError {
     DecodeKey,
 }
 
+/// All the data needed to verify block finalization justifications, as returned by
+/// `DagestanSessionApi` before version 2.
+#[derive(Clone, Debug, Decode, Encode, PartialEq, Eq)]
+pub struct LegacySessionAuthorityData {
+    authorities: Vec<AuthorityId>,
+    emergency_finalizer: Option<AuthorityId>,
+}
+
+impl LegacySessionAuthorityData {
+    pub fn new(authorities: Vec<AuthorityId>, emergency_finalizer: Option<AuthorityId>) -> Self {
+        LegacySessionAuthorityData {
+            authorities,
+            emergency_finalizer,
+        }
+    }
+}
+
+impl From<LegacySessionAuthorityData> for SessionAuthorityData {
+    fn from(authority_data: LegacySessionAuthorityData) -> Self {
+        SessionAuthorityData::new(
+            authority_data.authorities,
+            authority_data.emergency_finalizer,
+        )
+    }
+}
+
 /// All the data needed to verify block finalization justifications.
-#[derive(Clone, Debug, Encode, Decode, PartialEq, Eq)]
+#[derive(Clone, Debug, Decode, Encode, PartialEq, Eq)]
 pub struct SessionAuthorityData {
     authorities: Vec<AuthorityId>,
     emergency_finalizer: Option<AuthorityId>,
+    bls_authorities: Option<Vec<BlsPublic>>,
 }
 
 impl SessionAuthorityData {
@@ -149,9 +267,16 @@ impl SessionAuthorityData {
         SessionAuthorityData {
             authorities,
             emergency_finalizer,
+            bls_authorities: None,
         }
     }
 
+    /// Adds the BLS keys of the authorities, in the same order as the authorities themselves.
+    pub fn with_bls_authorities(mut self, bls_authorities: Vec<BlsPublic>) -> Self {
+        self.bls_authorities = Some(bls_authorities);
+        self
+    }
+
     pub fn authorities(&self) -> &Vec<AuthorityId> {
         &self.authorities
     }
@@ -159,6 +284,10 @@ impl SessionAuthorityData {
     pub fn emergency_finalizer(&self) -> &Option<AuthorityId> {
         &self.emergency_finalizer
     }
+
+    pub fn bls_authorities(&self) -> &Option<Vec<BlsPublic>> {
+        &self.bls_authorities
+    }
 }
 
 pub type Version = u32;
@@ -170,15 +299,24 @@ pub struct VersionChange {
 }
 
 sp_api::decl_runtime_apis! {
+    /// Version 2 adds the BLS keys of the authorities to their authority data, version 3 adds the
+    /// maximal data branch length.
+    #[api_version(3)]
     pub trait DagestanSessionApi
     {
         fn next_session_authorities() -> Result<Vec<AuthorityId>, ApiError>;
         fn authorities() -> Vec<AuthorityId>;
+        #[changed_in(2)]
+        fn next_session_authority_data() -> Result<LegacySessionAuthorityData, ApiError>;
         fn next_session_authority_data() -> Result<SessionAuthorityData, ApiError>;
+        #[changed_in(2)]
+        fn authority_data() -> LegacySessionAuthorityData;
         fn authority_data() -> SessionAuthorityData;
         fn session_period() -> u32;
         fn millisecs_per_block() -> u64;
         fn finality_version() -> Version;
         fn next_session_finality_version() -> Version;
+        fn max_data_branch_len() -> u32;
+        fn next_session_max_data_branch_len() -> u32;
     }
 }